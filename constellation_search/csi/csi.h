@@ -3,20 +3,177 @@
 
 #include <stddef.h>
 #include <stdint.h>
+#include <stdbool.h>
 
 #ifdef __cplusplus
 extern "C" {
 #endif
 
+// Bumped on every incompatible change to the functions or records below;
+// compare with csi_abi_version() to detect a mismatched library at run time
+#define CSI_ABI_VERSION 1
+
 typedef struct CSIHandle CSIHandle;
 
+// The library version, "major.minor.patch"; a static string
+const char *csi_version(void);
+
+// The CSI_ABI_VERSION the library was built with
+uint32_t csi_abi_version(void);
+
+// Why a csi_*_ex constructor returned NULL, through its err argument
+typedef enum CsiErrorCode {
+    CSI_OK = 0,
+    CSI_ERR_NULL_ARGUMENT = 1,  // a pointer argument that must be set was NULL
+    CSI_ERR_EMPTY_INPUT = 2,    // there were no bytes to index
+    CSI_ERR_INVALID_PARAMS = 3, // k was 0 or no gaps were given
+    CSI_ERR_INVALID_INDEX = 4,  // the bytes aren't an index csi_serialize wrote
+    CSI_ERR_IO = 5,             // a file couldn't be opened or read, or its path isn't UTF-8
+    CSI_ERR_CANCELLED = 6,      // the progress callback asked to stop
+} CsiErrorCode;
+
+// The message for the last call on this thread that failed, valid until the
+// thread's next call that sets or clears it; NULL if that call succeeded or
+// none failed yet. Set by the constructors and csi_search, so a search
+// returning 0 can be told from one given bad arguments
+const char *csi_last_error_message(void);
+
+// Thread safety: functions taking a const CSIHandle * only read the index,
+// so any number of threads may call them on one handle at once without
+// locking. csi_append and csi_free need the handle to themselves. The same
+// holds for the other handles: csi_results_copy may run on many threads at
+// once, while a CSIBuilder takes one caller at a time.
+
 // Build and return a new handle (or NULL on error)
 CSIHandle *csi_new(const uint8_t *data, size_t len);
 
+// Like csi_new, writing why it returned NULL (or CSI_OK) to *err unless err
+// is NULL; the _ex variants below do the same for their base functions
+CSIHandle *csi_new_ex(const uint8_t *data, size_t len, CsiErrorCode *err);
+
+// Build progress callback: text bytes processed so far out of total, counting
+// one hashing pass and one pass per gap table; return non-zero to cancel
+typedef int32_t (*csi_progress_cb)(size_t done, size_t total, void *user);
+
+// Like csi_new, calling cb(done, total, user) on the calling thread as the
+// build advances, ending with done == total. Returns NULL (CSI_ERR_CANCELLED)
+// as soon as cb returns non-zero, keeping nothing; a NULL cb builds silently
+CSIHandle *csi_new_with_progress(const uint8_t *data, size_t len, csi_progress_cb cb, void *user);
+CSIHandle *csi_new_with_progress_ex(const uint8_t *data, size_t len, csi_progress_cb cb, void *user,
+                                    CsiErrorCode *err);
+
+// Like csi_new, but the handle searches data[] in place instead of copying
+// it. The caller still owns data[] and must keep it allocated and unchanged
+// until csi_free(h), which does not free it; csi_append copies the text and
+// ends the loan.
+CSIHandle *csi_new_borrowed(const uint8_t *data, size_t len);
+
+// Like csi_new, but with k and the gaps_len gaps at gaps given instead of
+// chosen from the data's entropy; gaps are sorted and deduplicated.
+// NULL if k is 0 or there are no gaps
+CSIHandle *csi_new_with_params(const uint8_t *data, size_t len, size_t k,
+                               const size_t *gaps, size_t gaps_len);
+CSIHandle *csi_new_with_params_ex(const uint8_t *data, size_t len, size_t k,
+                                  const size_t *gaps, size_t gaps_len, CsiErrorCode *err);
+
+// Like csi_new, but builds gap tables on `threads` threads when compiled
+// with the rayon feature (sequential otherwise, or when threads <= 1)
+CSIHandle *csi_new_parallel(const uint8_t *data, size_t len, size_t threads);
+
+// Like csi_new, but ASCII case-insensitive: text and patterns have A-Z
+// lowercased before hashing and verification. Not interchangeable with a
+// csi_new handle; search it with csi_search_ci.
+CSIHandle *csi_new_ci(const uint8_t *data, size_t len);
+
+// Like csi_new, but text and patterns are rewritten through table[256]
+// (byte b is indexed and matched as table[b]) before hashing and
+// verification; search it with csi_search. NULL on NULL arguments, or if
+// the table maps some byte to one it changes again (table[table[b]] must
+// equal table[b])
+CSIHandle *csi_new_with_byte_map(const uint8_t *data, size_t len, const uint8_t *table);
+
 // Free a handle
 void csi_free(CSIHandle *h);
 
-// Search: writes up to max_out offsets into out[], returns count
+// Multi-document indexing: queue documents under caller-chosen IDs, then
+// build a handle whose csi_search_docs reports matches per document.
+typedef struct CSIBuilder CSIBuilder;
+
+// An empty document queue; free it with csi_builder_free
+CSIBuilder *csi_builder_new(void);
+
+// Queue a copy of data[0..len) as document id. IDs need not be distinct or
+// ordered. data may be NULL only when len is 0; false on NULL arguments
+bool csi_builder_add_doc(CSIBuilder *b, size_t id, const uint8_t *data, size_t len);
+
+// Index the queued documents in the order they were added; NULL if b is
+// NULL or they hold no bytes. b keeps its queue and can build again
+CSIHandle *csi_builder_build(const CSIBuilder *b);
+
+// Streaming one text in chunks instead of a single buffer: append a copy
+// of data[0..len) (NULL only when len is 0); false on NULL arguments
+bool csi_builder_feed(CSIBuilder *b, const uint8_t *data, size_t len);
+
+// Index the bytes fed so far as one text, as csi_new would, and empty the
+// stream for the next; NULL if b is NULL or nothing was fed. Queued
+// documents are left alone
+CSIHandle *csi_builder_finish(CSIBuilder *b);
+
+// Free a builder; handles it built stay valid
+void csi_builder_free(CSIBuilder *b);
+
+// Serialize the index into out[] if it fits in out_cap bytes; returns the
+// size needed either way (0 on a NULL handle), so out_cap = 0 sizes it
+size_t csi_serialize(const CSIHandle *h, uint8_t *out, size_t out_cap);
+
+// Rebuild a handle from csi_serialize output without rehashing; NULL on a
+// wrong magic, version or hasher, or malformed or truncated data
+CSIHandle *csi_deserialize(const uint8_t *data, size_t len);
+CSIHandle *csi_deserialize_ex(const uint8_t *data, size_t len, CsiErrorCode *err);
+
+// Stream the csi_serialize format into the file at path (NUL-terminated
+// UTF-8), creating or replacing it; false on NULL arguments or I/O errors
+bool csi_save(const CSIHandle *h, const char *path);
+
+// Handle for an index csi_save wrote to path; NULL if the file can't be read
+// or fails the checks csi_deserialize makes
+CSIHandle *csi_load(const char *path);
+// CSI_ERR_IO if the file can't be opened or read, CSI_ERR_INVALID_INDEX if
+// it isn't an index
+CSIHandle *csi_load_ex(const char *path, CsiErrorCode *err);
+
+// Like csi_load, but when compiled with the memmap2 feature the file is
+// mapped and searched in place, so opening does no copying or rehashing;
+// the file must not change while the handle is alive
+CSIHandle *csi_open_mmap(const char *path);
+
+// One handle over the texts of the count handles in parts[], in order,
+// searching like a handle built over their concatenation. The parts must
+// be built alike (same k and gaps, so fix those per shard). On success the
+// parts are consumed: their handles are freed and must not be used again.
+// NULL, leaving every part as it was, if parts is NULL, holds a NULL or
+// repeated handle, or its indexes were built differently
+CSIHandle *csi_merge(CSIHandle *const *parts, size_t count);
+
+// Append len bytes to h's text and index them; the result searches like a
+// handle built over the whole text. Returns false on NULL arguments. Must
+// not run concurrently with other calls on h; invalidates posting views.
+bool csi_append(CSIHandle *h, const uint8_t *data, size_t len);
+
+// Mark bytes start..end of h's text deleted: searches no longer report
+// matches overlapping them, though the text and offsets stay as they were.
+// Returns false if h is NULL or start > end. csi_compact then drops their
+// postings. Neither may run concurrently with other calls on h, and
+// csi_compact invalidates posting views.
+bool csi_invalidate_range(CSIHandle *h, size_t start, size_t end);
+bool csi_compact(CSIHandle *h);
+
+// Search: writes up to max_out offsets into out[] and returns the total
+// number of matches, which is more than max_out if out[] was too small.
+// out may be NULL only when max_out is 0. 0 on NULL arguments too, with
+// csi_last_error_message saying which; it is NULL after a search that ran.
+// Each thread keeps its search buffers between calls, so repeated searches
+// don't allocate once they've grown.
 size_t csi_search(
     const CSIHandle *h,
     const uint8_t *pattern,
@@ -25,6 +182,322 @@ size_t csi_search(
     size_t max_out
 );
 
+// csi_search_flags flag: keep only the leftmost non-overlapping matches,
+// each starting at or past the end of the one before
+#define CSI_SEARCH_NON_OVERLAPPING 1u
+
+// Like csi_search, with flags a bitwise OR of CSI_SEARCH_* values (0 is
+// csi_search itself); 0 on unknown flags, with csi_last_error_message set
+size_t csi_search_flags(const CSIHandle *h, const uint8_t *pattern, size_t pat_len,
+                        uint32_t flags, size_t *out, size_t max_out);
+
+// Paged results: csi_search_begin runs the search once, stores every match
+// offset in a result handle and writes their number to *total (if total is
+// not NULL); NULL on NULL arguments. The results are a copy and stay valid
+// after csi_free(h), until csi_results_free.
+typedef struct CSIResults CSIResults;
+
+CSIResults *csi_search_begin(
+    const CSIHandle *h,
+    const uint8_t *pattern,
+    size_t pat_len,
+    size_t *total
+);
+
+// Copy up to max_out offsets, starting with match number from, into out[];
+// returns how many were copied, 0 once from reaches the total. out may be
+// NULL only when max_out is 0
+size_t csi_results_copy(const CSIResults *r, size_t *out, size_t from, size_t max_out);
+
+// Free a result handle
+void csi_results_free(CSIResults *r);
+
+// Search for each of n_pats patterns (pats[i], pat_lens[i] bytes long) at
+// once, hashing each once and, with the rayon feature, running them in
+// parallel. Writes each pattern's match count to counts[i] and returns the
+// offsets of all of them, pattern after pattern, in one result handle for
+// csi_results_copy. NULL on NULL arguments; pats[i] may be NULL only when
+// pat_lens[i] is 0
+CSIResults *csi_search_batch(
+    const CSIHandle *h,
+    const uint8_t *const *pats,
+    const size_t *pat_lens,
+    size_t n_pats,
+    size_t *counts
+);
+
+// Like csi_search, but only matches lying wholly inside text bytes
+// [start, end) count; posting lists are cut to the window before they are
+// intersected
+size_t csi_search_range(
+    const CSIHandle *h,
+    const uint8_t *pattern,
+    size_t pat_len,
+    size_t start,
+    size_t end,
+    size_t *out,
+    size_t max_out
+);
+
+// One page of csi_search's matches: writes matches number offset onward,
+// at most max_out of them, into out[] and returns how many were written (a
+// short page is the last). Stops at the page's end instead of counting the
+// total. out may be NULL only when max_out is 0
+size_t csi_search_page(
+    const CSIHandle *h,
+    const uint8_t *pattern,
+    size_t pat_len,
+    size_t offset,
+    size_t *out,
+    size_t max_out
+);
+
+// Like csi_search, for matches differing from pattern in at most
+// max_mismatches bytes (same length); returns the total
+size_t csi_search_approx(
+    const CSIHandle *h,
+    const uint8_t *pattern,
+    size_t pat_len,
+    size_t max_mismatches,
+    size_t *out,
+    size_t max_out
+);
+
+// csi_search on a csi_new_ci handle, folding pat the same way; returns 0
+// for a case-sensitive handle, which can't answer case-insensitive queries
+size_t csi_search_ci(
+    const CSIHandle *h,
+    const uint8_t *pattern,
+    size_t pat_len,
+    size_t *out,
+    size_t max_out
+);
+
+// Number of occurrences of pat; 0 on a NULL handle or pattern
+size_t csi_count(const CSIHandle *h, const uint8_t *pat, size_t pat_len);
+
+// Whether pat occurs at all, stopping at the first verified match; false on
+// a NULL handle or pattern
+bool csi_contains(const CSIHandle *h, const uint8_t *pat, size_t pat_len);
+
+// One search hit. Exact matches have length == pat_len and score == 0.
+typedef struct CsiMatch {
+    uint64_t offset;
+    uint64_t length;
+    uint32_t score;
+} CsiMatch;
+
+// Like csi_search, but writes up to max_out CsiMatch records; returns the
+// total number of matches, which is more than max_out if out[] was too small
+size_t csi_search_matches(
+    const CSIHandle *h,
+    const uint8_t *pattern,
+    size_t pat_len,
+    CsiMatch *out,
+    size_t max_out
+);
+
+// Like csi_search_approx, but writes CsiMatch records whose score is the
+// number of mismatched bytes; returns the total
+size_t csi_search_approx_matches(
+    const CSIHandle *h,
+    const uint8_t *pattern,
+    size_t pat_len,
+    size_t max_mismatches,
+    CsiMatch *out,
+    size_t max_out
+);
+
+// One match inside a document, reported by csi_search_docs
+typedef struct CsiDocMatch {
+    size_t doc_id;
+    size_t offset;
+} CsiDocMatch;
+
+// Like csi_search, but each match is reported as its document's ID and the
+// offset within that document; matches spanning two documents are dropped.
+// Documents of handles not built by csi_builder_build are numbered from 0.
+size_t csi_search_docs(
+    const CSIHandle *h,
+    const uint8_t *pattern,
+    size_t pat_len,
+    CsiDocMatch *out,
+    size_t max_out
+);
+
+// Index sets: several handles searched as one, e.g. one per shard or tenant.
+// csi_set_add moves h's index into the set as its next shard, frees h and
+// returns the shard's ID, counting from 0 (SIZE_MAX on NULL arguments,
+// leaving h alone). csi_set_free frees the set and its indexes.
+typedef struct CSIIndexSet CSIIndexSet;
+
+CSIIndexSet *csi_set_new(void);
+size_t csi_set_add(CSIIndexSet *set, CSIHandle *h);
+void csi_set_free(CSIIndexSet *set);
+
+// One match in an index set, reported by csi_set_search
+typedef struct CsiShardMatch {
+    size_t shard;
+    size_t offset;
+} CsiShardMatch;
+
+// Like csi_search over every shard of set: matches are ordered by shard and
+// then offset. Shards are searched concurrently when the library was built
+// with the rayon feature.
+size_t csi_set_search(
+    const CSIIndexSet *set,
+    const uint8_t *pattern,
+    size_t pat_len,
+    CsiShardMatch *out,
+    size_t max_out
+);
+
+// Where csi_search_context put one match's window: len bytes at start of
+// the indexed text, the match at offset among them
+typedef struct CsiContext {
+    size_t offset;
+    size_t start;
+    size_t len;
+} CsiContext;
+
+// Like csi_search, also copying each match's window, up to before bytes
+// ahead and after bytes past it, into buf: record i's bytes start at
+// buf + i * (before + pat_len + after), so buf must hold max_out such
+// strides. Writes the first max_out matches and returns the total. out and
+// buf may be NULL only when max_out is 0; 0 if the strides overflow
+size_t csi_search_context(
+    const CSIHandle *h,
+    const uint8_t *pattern,
+    size_t pat_len,
+    size_t before,
+    size_t after,
+    CsiContext *out,
+    uint8_t *buf,
+    size_t max_out
+);
+
+// Index summary filled by csi_info
+typedef struct CsiInfo {
+    size_t text_len;
+    size_t k;
+    size_t num_gaps;
+    size_t total_distinct_constellations;
+    size_t total_postings;
+} CsiInfo;
+
+// Fill *out with the index summary; returns false on NULL arguments
+bool csi_info(const CSIHandle *h, CsiInfo *out);
+
+// Heap bytes held by the index (text, hash powers and gap tables); 0 on a
+// NULL handle
+size_t csi_mem_usage(const CSIHandle *h);
+
+// Shape of one gap table, filled by csi_stats
+typedef struct CsiGapStats {
+    size_t gap;
+    size_t table_size;
+    double load_factor;
+    size_t max_probe;
+    size_t largest_bucket;     // longest bucket still holding its postings
+    size_t saturated_buckets;  // buckets whose postings were dropped at build
+    size_t postings;
+    size_t heap_bytes;         // slots, postings, fingerprints and overflow
+} CsiGapStats;
+
+// Write the first max_out gap tables' stats (in gap order) into out[] and
+// return how many tables the index has; 0 on a NULL handle. out may be NULL
+// only when max_out is 0.
+size_t csi_stats(const CSIHandle *h, CsiGapStats *out, size_t max_out);
+
+// Distribution of a pattern's matches; all zero when there are none
+typedef struct MatchStats {
+    size_t count;
+    size_t first;
+    size_t last;
+    size_t span;
+} MatchStats;
+
+// Fill *out with match statistics for pattern; returns false on NULL arguments
+bool csi_match_stats(
+    const CSIHandle *h,
+    const uint8_t *pattern,
+    size_t pat_len,
+    MatchStats *out
+);
+
+// Upper bound on the candidates a search for pattern would verify (the
+// shortest posting list, without intersecting); 0 on NULL arguments
+size_t csi_estimate_candidates(
+    const CSIHandle *h,
+    const uint8_t *pattern,
+    size_t pat_len
+);
+
+// Anchor length k chosen at build; 0 on NULL
+size_t csi_k(const CSIHandle *h);
+
+// Write up to max gaps (ascending) into out[] and return how many were
+// written; CsiInfo.num_gaps is the size that holds them all. out may be NULL only when max is 0.
+size_t csi_gaps(const CSIHandle *h, size_t *out, size_t max);
+
+// How many distinct constellations of pattern (every position, every usable
+// gap) occur anywhere in the index; a partial-overlap signal, not a match
+// count. 0 on NULL arguments.
+size_t csi_shared_constellations(
+    const CSIHandle *h,
+    const uint8_t *pattern,
+    size_t pat_len
+);
+
+// Shortest pattern the index can answer (k + gaps[0] + k); searches for
+// shorter patterns return no matches, so route them to a linear scan.
+// 0 on NULL.
+size_t csi_min_pattern_len(const CSIHandle *h);
+
+// Smallest match offset greater than `after` (pass SIZE_MAX to start at 0);
+// returns SIZE_MAX when there is none
+size_t csi_find_next(
+    const CSIHandle *h,
+    const uint8_t *pattern,
+    size_t pat_len,
+    size_t after
+);
+
+// Match offset closest to pos (ties go to the later one); returns SIZE_MAX
+// when there is none
+size_t csi_nearest_match(
+    const CSIHandle *h,
+    const uint8_t *pattern,
+    size_t pat_len,
+    size_t pos
+);
+
+// Match callback for csi_search_cb; return non-zero to stop the search
+typedef int32_t (*csi_match_cb)(size_t offset, void *user);
+
+// Call cb(offset, user) for each match in ascending order until it returns
+// non-zero; returns the number of offsets delivered. cb must not re-enter
+// the library with h.
+size_t csi_search_cb(
+    const CSIHandle *h,
+    const uint8_t *pattern,
+    size_t pat_len,
+    csi_match_cb cb,
+    void *user
+);
+
+// Borrow the posting list for pattern at gaps[gap_index] without copying.
+// The view points into memory owned by h and is invalidated by csi_free or
+// any call that mutates the index. Returns false with a NULL/0 view if absent.
+bool csi_posting_view(
+    const CSIHandle *h,
+    const uint8_t *pattern,
+    size_t pat_len,
+    size_t gap_index,
+    const size_t **out_ptr,
+    size_t *out_len
+);
+
 #ifdef __cplusplus
 }
 #endif
@@ -50,6 +50,15 @@ func (c *CSI) Search(pattern string) ([]int, error) {
 		(*C.uint8_t)(unsafe.Pointer(&pb[0])), C.size_t(len(pb)),
 		(*C.size_t)(unsafe.Pointer(&buf[0])), C.size_t(maxOut),
 	)
+	if int(n) > maxOut {
+		maxOut = int(n)
+		buf = make([]C.size_t, maxOut)
+		n = C.csi_search(
+			c.h,
+			(*C.uint8_t)(unsafe.Pointer(&pb[0])), C.size_t(len(pb)),
+			(*C.size_t)(unsafe.Pointer(&buf[0])), C.size_t(maxOut),
+		)
+	}
 	count := int(n)
 	res := make([]int, count)
 	for i := 0; i < count; i++ {
@@ -27,11 +27,11 @@ func TestSearch(t *testing.T) {
 		pattern  string
 		expected []int
 	}{
-		// short patterns should simply return no matches (no error)
-		{"hello", nil},
+		// short patterns are answered by scanning the text
+		{"hello", []int{0, 13, 26}},
 		{"xyz", nil},
 		{"", nil}, // empty pattern returns error below
-		{"universe", nil},
+		{"universe", []int{32}},
 		// longer pattern that does match once
 		{"ello world, hello there", []int{1}},
 	}
@@ -0,0 +1,61 @@
+// benches/par_search.rs
+//! Where `par_search` overtakes `search`: `cargo bench --bench par_search
+//! --features rayon`.
+//!
+//! Texts of a few log records repeated give one pattern posting lists that
+//! grow with the text; each line is the lists' total length and the best
+//! of five rounds of either search, in ns.
+//!
+//! On a 1‐core Xeon, with nothing to split the work with, the parallel path
+//! ran about a quarter behind `search` wherever it was taken: 75 µs against
+//! 95 µs at 24 Ki postings, 1.1 ms against 1.3 ms at 383 Ki, so some 20 µs
+//! of hand‐off plus a quarter more work in the pairwise tree, over
+//! `search`'s 3.2 ns a posting. Two threads halve that 1.25 times the work,
+//! saving 0.375 of `search`'s time, which outweighs the 20 µs from about 16
+//! Ki postings: `PAR_MIN_POSTINGS`, below which both columns read the same
+//! as `par_search` hands the pattern to `search`.
+
+use std::time::Instant;
+
+use csi::{CsiIndexBuilder, EntropyTiers};
+
+const PATTERN: &[u8] = b"GET /a/3 200\nGET";
+
+fn main() {
+    println!("{} threads", rayon::current_num_threads());
+    println!("{:>10}{:>12}{:>14}{:>14}", "text", "postings", "search ns", "par ns");
+    for len in [1 << 12, 1 << 14, 1 << 16, 1 << 18, 1 << 20, 1 << 22] {
+        let text = records(len);
+        let idx = CsiIndexBuilder::new().tiers(EntropyTiers::fixed(4, vec![4, 8, 16])).build(&text);
+        let postings: usize = (0..idx.gaps().len()).filter_map(|g| idx.posting_list(PATTERN, g)).map(|l| l.len()).sum();
+        assert_eq!(idx.par_search(PATTERN), idx.search(PATTERN));
+        let (seq, par) = (time(|| idx.search(PATTERN).len()), time(|| idx.par_search(PATTERN).len()));
+        println!("{:>10}{:>12}{:>14}{:>14}", len, postings, seq, par);
+    }
+}
+
+/// Best of five rounds of `f`, in ns per call.
+fn time(mut f: impl FnMut() -> usize) -> u128 {
+    let rounds = 50;
+    (0..5).map(|_| {
+        let start = Instant::now();
+        let mut sink = 0;
+        for _ in 0..rounds {
+            sink += f();
+        }
+        assert!(sink > 0);
+        start.elapsed().as_nanos() / rounds
+    }).min().unwrap()
+}
+
+/// `len` bytes of five log records in turn, with a rarer one every 97th.
+fn records(len: usize) -> Vec<u8> {
+    let mut text = Vec::with_capacity(len + 32);
+    let mut i = 0;
+    while text.len() < len {
+        text.extend_from_slice(format!("GET /a/{} 200\n", i % 5 + usize::from(i % 97 == 0) * 10).as_bytes());
+        i += 1;
+    }
+    text.truncate(len);
+    text
+}
@@ -0,0 +1,19 @@
+// build.rs
+//! With the `header` feature, write `include/constellation_search.h` from
+//! the `csi_*` functions and `#[repr(C)]` records in `src/ffi.rs`, using
+//! the settings in `cbindgen.toml`. `include/csi.h` stays the hand‐kept,
+//! commented header; the generated one tracks the code exactly.
+
+fn main() {
+    println!("cargo::rerun-if-changed=build.rs");
+    #[cfg(feature = "header")]
+    {
+        let dir = std::env::var("CARGO_MANIFEST_DIR").expect("cargo sets CARGO_MANIFEST_DIR");
+        let config = cbindgen::Config::from_file(format!("{dir}/cbindgen.toml")).expect("cbindgen.toml");
+        cbindgen::generate_with_config(&dir, config)
+            .expect("cbindgen can't parse the FFI")
+            .write_to_file(format!("{dir}/include/constellation_search.h"));
+        println!("cargo::rerun-if-changed=src/ffi.rs");
+        println!("cargo::rerun-if-changed=cbindgen.toml");
+    }
+}
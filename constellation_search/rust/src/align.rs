@@ -0,0 +1,113 @@
+// src/align.rs
+//! Histogram voting: where, and how confidently, a query lines up with the
+//! indexed reference.
+//!
+//! Every constellation of the query, at every position and usable gap, is
+//! looked up; each posting votes for the alignment (reference offset of the
+//! query's first byte) it implies. A query copied from the reference piles
+//! all its votes on one alignment, while unrelated data spreads a handful of
+//! chance hits thinly. Nothing is verified, so edits in the query only cost
+//! the votes of the constellations they touch.
+
+
+use alloc::vec::Vec;
+
+use crate::syncmer::Syncmers;
+use crate::{key_at, prefix_hash, CsiIndex, Hasher, Map};
+
+impl<H: Hasher> CsiIndex<H> {
+    /// `(alignment, votes)` of the best‐supported alignment of `query`, the
+    /// smallest alignment among equals; `None` if no constellation of it
+    /// occurs. The alignment is negative when the query starts before the
+    /// reference does. Saturated constellations don't vote.
+    pub fn best_alignment(&self, query: &[u8]) -> Option<(isize, usize)> {
+        let query = &*self.normalize(query);
+        let m = query.len();
+        if m < self.min_pattern_len() {
+            return None;
+        }
+        let php = prefix_hash(&self.hasher, query);
+        let pk = self.pw[self.k];
+        let mut votes: Map<isize, usize> = Map::new();
+        for (&d, fi) in self.gaps.iter().zip(&self.flat).filter(|&(&d, _)| d + self.k <= m) {
+            for j in 0..=m - d - self.k {
+                if let Some(list) = fi.lookup(key_at(&self.hasher, &php, pk, j, self.k, d)).postings() {
+                    for &p in &*list {
+                        *votes.entry(p as isize - j as isize).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+        votes.into_iter().max_by_key(|&(at, n)| (n, core::cmp::Reverse(at)))
+    }
+
+    /// Votes for the best alignment of `query` over the most it could get
+    /// there, in `0.0..=1.0`: one per constellation of the query at every
+    /// position and usable gap (on a strided index, only the positions the
+    /// alignment puts on indexed offsets, and with syncmers the query's).
+    ///
+    /// A substring of the reference scores 1.0 unless some of its
+    /// constellations are saturated; unrelated data scores near 0.0, and
+    /// 0.0 below `min_pattern_len`.
+    pub fn best_match_score(&self, query: &[u8]) -> f64 {
+        let Some((at, votes)) = self.best_alignment(query) else {
+            return 0.0;
+        };
+        let query = &*self.normalize(query);
+        let m = query.len();
+        let stride = self.stride as isize;
+        // with syncmers, the ones the query shows; an occurrence may have a
+        // few more whose syncmer runs past the query's end
+        let picked: Vec<usize> = match self.window {
+            1 => Vec::new(),
+            w => Syncmers::new(&self.hasher, &prefix_hash(&self.hasher, query), self.pw[self.k], self.k, w).collect(),
+        };
+        let possible: usize = self.gaps.iter()
+            .filter(|&&d| d + self.k <= m)
+            .map(|&d| match self.window {
+                1 => (0..=m - d - self.k).filter(|&j| (at + j as isize).rem_euclid(stride) == 0).count(),
+                _ => picked.iter().take_while(|&&j| j + d + self.k <= m).count(),
+            })
+            .sum();
+        (votes as f64 / possible as f64).min(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CsiConfig, CsiIndex};
+
+    fn noise(seed: u64, len: usize) -> Vec<u8> {
+        let mut x = seed;
+        (0..len).map(|_| { x ^= x << 13; x ^= x >> 7; x ^= x << 17; (x >> 56) as u8 }).collect()
+    }
+
+    #[test]
+    fn substring_scores_one_and_noise_near_zero() {
+        let reference = noise(0x9e3779b97f4a7c15, 20_000);
+        let idx = CsiIndex::build(&reference);
+        let query = &reference[7_000..7_400];
+        assert_eq!(idx.best_alignment(query), Some((7_000, idx.index_summary().per_gap.iter()
+            .map(|&(d, _, _)| 400 - d - idx.k + 1).sum())));
+        assert_eq!(idx.best_match_score(query), 1.0);
+        assert!(idx.best_match_score(&noise(42, 400)) < 0.05);
+
+        // a few edits cost only the constellations they touch
+        let mut edited = query.to_vec();
+        for i in [50, 150, 250, 350] {
+            edited[i] ^= 0xff;
+        }
+        let s = idx.best_match_score(&edited);
+        assert!(s > 0.3 && s < 1.0);
+        assert_eq!(idx.best_alignment(&edited).unwrap().0, 7_000);
+
+        // overhanging the reference's start: negative alignment
+        let mut overhang = noise(7, 30);
+        overhang.extend_from_slice(&reference[..300]);
+        assert_eq!(idx.best_alignment(&overhang).unwrap().0, -30);
+
+        let sparse = CsiIndex::build_with_config(&reference, &CsiConfig { stride: 3, ..CsiConfig::default() });
+        assert_eq!(sparse.best_match_score(&reference[7_001..7_401]), 1.0);
+        assert_eq!(idx.best_match_score(b"short"), 0.0);
+    }
+}
@@ -0,0 +1,299 @@
+// src/analytics.rs
+//! Corpus‐level analytics computed from the stored constellations.
+
+use alloc::vec::Vec;
+
+use crate::syncmer::Syncmers;
+use crate::{key_at, prefix_hash, CsiIndex, Hasher, Map, Probe, Set, SATURATED};
+
+/// A stretch of text whose constellation recurs, from `top_constellations`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrequentRegion {
+    /// Start of the first occurrence
+    pub offset: usize,
+    /// `k + gaps[0]`, the bytes one constellation covers
+    pub len:    usize,
+    /// Occurrences of the constellation
+    pub count:  usize,
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Posting count of every constellation key at `gaps[gap_index]`, read
+    /// straight from the bucket table (no rehashing). Saturated buckets
+    /// report `usize::MAX`; an out‐of‐range gap index yields an empty map.
+    /// A `HashMap`, or a `BTreeMap` without the `std` feature.
+    pub fn kmer_spectrum(&self, gap_index: usize) -> Map<u64, usize> {
+        self.buckets(gap_index).collect()
+    }
+
+    /// The `n` most frequent constellations at `gaps[gap_index]` as
+    /// `(key, count)`, by descending count then ascending key.
+    pub fn most_frequent(&self, gap_index: usize, n: usize) -> Vec<(u64, usize)> {
+        let mut all: Vec<(u64, usize)> = self.buckets(gap_index).collect();
+        all.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        all.truncate(n);
+        all
+    }
+
+    /// The `n` most repeated regions of the text, by descending count and
+    /// then ascending offset: the constellations at the smallest gap with
+    /// the longest posting lists, each reported once at its first
+    /// occurrence. A region overlapping one already reported is skipped, so
+    /// a long repeat shows up once rather than once per byte of it.
+    ///
+    /// Counts come from the bucket table without searching, so a rare hash
+    /// collision can inflate one (`frequency` gives the verified count);
+    /// on a strided index they count the stride‐aligned occurrences only,
+    /// and with syncmers those at syncmers.
+    /// Saturated buckets keep no postings, so theirs are recounted in one
+    /// pass over the text, and only if one is among the candidates.
+    pub fn top_constellations(&self, n: usize) -> Vec<FrequentRegion> {
+        let (Some(fi), Some(&d)) = (self.flat.first(), self.gaps.first()) else { return Vec::new() };
+        let len = self.k + d;
+        // (count, first offset) per bucket
+        let mut ranked = Vec::new();
+        let mut saturated = Map::new();
+        for (key, start, l) in fi.entries() {
+            match fi.bucket(key, start, l) {
+                Probe::List(list) => ranked.extend(list.first().map(|off| (list.len(), off))),
+                Probe::Packed(list) => ranked.extend(list.iter().next().map(|off| (list.len, off))),
+                Probe::Saturated => drop(saturated.insert(key, (0, usize::MAX))),
+                Probe::Missing => {}
+            }
+        }
+        if !saturated.is_empty() {
+            let (ph, p) = (prefix_hash(&self.hasher, &self.text), self.table_params());
+            p.each_key(&self.hasher, &ph, self.pw[self.k], d, p.starts(self.text.len(), d), |off, key| {
+                if let Some((count, first)) = saturated.get_mut(&key) {
+                    *count += 1;
+                    *first = (*first).min(off);
+                }
+            });
+            ranked.extend(saturated.into_values());
+        }
+        ranked.sort_unstable_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        let mut top: Vec<FrequentRegion> = Vec::new();
+        for (count, offset) in ranked {
+            if top.len() == n {
+                break;
+            }
+            if top.iter().all(|r| offset + len <= r.offset || r.offset + len <= offset) {
+                top.push(FrequentRegion { offset, len, count });
+            }
+        }
+        top
+    }
+
+    /// Exact number of occurrences of `pat`, overlapping ones included:
+    /// `count(pat)`, whose candidates are verified against the text, so
+    /// unlike the bucket counts of `top_constellations` and `kmer_spectrum`
+    /// no collision inflates it.
+    pub fn frequency(&self, pat: &[u8]) -> usize {
+        self.count(pat)
+    }
+
+    /// MinHash sketch of the distinct constellation keys at `gaps[gap_index]`:
+    /// for each of `num_hashes` seeded mixes, the minimum mixed key.
+    ///
+    /// Comparing two sketches position by position (`estimate_jaccard`)
+    /// estimates the Jaccard similarity of the key sets with standard error
+    /// about `1 / sqrt(num_hashes)`, so 128 hashes give roughly ±0.09 and
+    /// 1024 roughly ±0.03. Only meaningful between indexes with the same `k`
+    /// and gap. An empty table sketches to all `u64::MAX`.
+    pub fn minhash_sketch(&self, gap_index: usize, num_hashes: usize) -> Vec<u64> {
+        let mut sketch = vec![u64::MAX; num_hashes];
+        for (key, _) in self.buckets(gap_index) {
+            for (i, slot) in sketch.iter_mut().enumerate() {
+                let h = splitmix64(key ^ splitmix64(i as u64));
+                if h < *slot {
+                    *slot = h;
+                }
+            }
+        }
+        sketch
+    }
+
+    /// Every `(constellation key, anchor offset)` pair the index was built
+    /// from: the anchor k‐mer at the offset paired with the target k‐mer `d`
+    /// bytes later, for each gap `d` in ascending gap order, offsets ascending
+    /// (multiples of the stride only, on a strided index, and syncmers only
+    /// with `CsiConfig::syncmer_window`).
+    ///
+    /// The key mixes in `d`, so pairs from different gaps don't collide by
+    /// construction; callers wanting one fingerprint per offset can filter
+    /// on the smallest gap. Keys are a pure function of the text, `k` and
+    /// gaps, so the same input and config always yield the same set.
+    /// Rehashes the text once up front.
+    pub fn fingerprints(&self) -> impl Iterator<Item = (u64, usize)> + '_ {
+        let ph = prefix_hash(&self.hasher, &self.text);
+        let (k, n, pk, p) = (self.k, self.text.len(), self.pw[self.k], self.table_params());
+        // the syncmers, if those are what's indexed
+        let picked: Vec<usize> = match p.window {
+            1 => Vec::new(),
+            w => Syncmers::new(&self.hasher, &ph, pk, k, w).collect(),
+        };
+        self.gaps.iter()
+            .flat_map(move |&d| {
+                let picked = picked.clone();
+                (0..p.starts(n, d)).step_by(self.stride)
+                    .filter(move |i| p.window == 1 || picked.binary_search(i).is_ok())
+                    .map(move |i| (d, i))
+            })
+            .map(move |(d, i)| (key_at(&self.hasher, &ph, pk, i, k, d), i))
+    }
+
+    fn buckets(&self, gap_index: usize) -> impl Iterator<Item = (u64, usize)> + '_ {
+        self.flat.get(gap_index).into_iter().flat_map(|fi| {
+            fi.entries().map(|(key, _, len)| match len {
+                SATURATED => (key, fi.packed_list(key).map_or(usize::MAX, |l| l.len)),
+                len => (key, len),
+            })
+        })
+    }
+
+    /// Jaccard similarity between the distinct constellations of the indexed
+    /// text and those of `other`, at the smallest gap.
+    ///
+    /// Both sides use this index's `k` and `gaps[0]`; positions are ignored,
+    /// so the score measures shared content, not alignment. Returns 0.0 when
+    /// neither side is long enough to form a constellation.
+    pub fn similarity(&self, other: &[u8]) -> f64 {
+        let (k, d) = (self.k, self.gaps[0]);
+        let fi = &self.flat[0];
+        let ours = fi.entries().count();
+
+        let mut theirs = Set::new();
+        if other.len() >= k + d {
+            let ph = prefix_hash(&self.hasher, other);
+            for i in 0..=other.len() - (k + d) {
+                theirs.insert(key_at(&self.hasher, &ph, self.pw[k], i, k, d));
+            }
+        }
+        let shared = theirs.iter()
+            .filter(|&&key| !matches!(fi.lookup(key), Probe::Missing))
+            .count();
+        let union = ours + theirs.len() - shared;
+        if union == 0 { 0.0 } else { shared as f64 / union as f64 }
+    }
+}
+
+/// Fraction of positions where two equal‐length MinHash sketches agree.
+pub fn estimate_jaccard(a: &[u64], b: &[u64]) -> f64 {
+    if a.is_empty() || a.len() != b.len() {
+        return 0.0;
+    }
+    a.iter().zip(b).filter(|(x, y)| x == y).count() as f64 / a.len() as f64
+}
+
+#[inline]
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{estimate_jaccard, FrequentRegion};
+    use crate::{CsiIndex, CsiIndexBuilder};
+
+    #[test]
+    fn fingerprints_match_tables_and_are_stable() {
+        let text = noise(11, 3000);
+        let a = CsiIndex::build(&text);
+        let fa: Vec<(u64, usize)> = a.fingerprints().collect();
+        let fb: Vec<(u64, usize)> = CsiIndex::build(&text).fingerprints().collect();
+        assert_eq!(fa, fb);
+        let keys: HashSet<u64> = fa.iter().map(|&(h, _)| h).collect();
+        let stored: HashSet<u64> = (0..4).flat_map(|g| a.kmer_spectrum(g).into_keys()).collect();
+        assert_eq!(keys, stored);
+        let m0 = text.len() - (a.k + a.gaps[0]) + 1;
+        assert!(fa[..m0].iter().enumerate().all(|(i, &(_, off))| off == i));
+    }
+
+    #[test]
+    fn top_constellations_find_the_planted_repeats() {
+        // noise with one record planted 40 times and another 12 times
+        let mut text = noise(5, 20_000);
+        let (hot, warm) = (b"ERROR disk quota exceeded on /var", b"WARN slow query");
+        for i in 0..40 {
+            text[300 + i * 450..][..hot.len()].copy_from_slice(hot);
+        }
+        for i in 0..12 {
+            text[520 + i * 1350..][..warm.len()].copy_from_slice(warm);
+        }
+        for builder in [CsiIndexBuilder::new().params(4, vec![4, 8]), CsiIndexBuilder::new().params(4, vec![4, 8]).max_postings_per_key(20)] {
+            let idx = builder.build(&text);
+            let top = idx.top_constellations(5);
+            assert!(top.iter().all(|r| r.len == 8));
+            // the hot record tiled once, not once per constellation inside it
+            let found: Vec<(usize, usize)> = top.iter().map(|r| (r.offset, r.count)).collect();
+            assert_eq!(found, [(300, 40), (308, 40), (316, 40), (324, 40), (520, 12)]);
+            for r in &top {
+                assert_eq!(idx.frequency(&text[r.offset..r.offset + r.len]), r.count);
+            }
+        }
+        assert_eq!(idx_of(b"ab").top_constellations(5), Vec::<FrequentRegion>::new());
+        assert_eq!(idx_of(b"abcdefgh").top_constellations(0), Vec::new());
+    }
+
+    fn idx_of(text: &[u8]) -> CsiIndex {
+        CsiIndexBuilder::new().params(2, vec![2]).build(text)
+    }
+
+    fn noise(seed: u64, n: usize) -> Vec<u8> {
+        let mut x = seed;
+        (0..n).map(|_| { x = x.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407); (x >> 56) as u8 }).collect()
+    }
+
+    #[test]
+    fn minhash_estimate_tracks_exact_jaccard() {
+        let base = noise(1, 4000);
+        let mut other = base[..2000].to_vec();
+        other.extend(noise(2, 2000));
+        let (a, b) = (CsiIndex::build(&base), CsiIndex::build(&other));
+        assert_eq!((a.k, &a.gaps), (b.k, &b.gaps));
+
+        let ka: HashSet<u64> = a.kmer_spectrum(0).into_keys().collect();
+        let kb: HashSet<u64> = b.kmer_spectrum(0).into_keys().collect();
+        let exact = ka.intersection(&kb).count() as f64 / ka.union(&kb).count() as f64;
+        let est = estimate_jaccard(&a.minhash_sketch(0, 512), &b.minhash_sketch(0, 512));
+        assert!((est - exact).abs() < 0.1, "est {est} exact {exact}");
+        assert_eq!(estimate_jaccard(&a.minhash_sketch(0, 64), &a.minhash_sketch(0, 64)), 1.0);
+    }
+
+    #[test]
+    fn spectrum_counts_postings_per_key() {
+        let text = b"ACGTACGTACGTACGTACGTACGTTTGACCAGTACGTACGTACGTACGT";
+        let idx = CsiIndex::build(text);
+        let spec = idx.kmer_spectrum(0);
+        let d = idx.gaps[0];
+        assert_eq!(spec.values().sum::<usize>(), text.len() - (idx.k + d) + 1);
+        let top = idx.most_frequent(0, 3);
+        assert_eq!(top.len(), 3);
+        assert!(top.windows(2).all(|w| w[0].1 >= w[1].1));
+        assert_eq!(spec[&top[0].0], top[0].1);
+        assert!(top[0].1 > 1);
+        assert!(idx.kmer_spectrum(99).is_empty());
+    }
+
+    #[test]
+    fn similarity_identical_disjoint_and_half_shared() {
+        let a: Vec<u8> = (0u32..400).map(|i| (i * 7 % 251) as u8).collect();
+        let b: Vec<u8> = (0u32..400).map(|i| (i * 11 % 241) as u8 ^ 0x80).collect();
+        let idx = CsiIndex::build(&a);
+        assert_eq!(idx.similarity(&a), 1.0);
+        assert_eq!(idx.similarity(&b), 0.0);
+
+        // other = second half of a + unrelated bytes: well short of identical
+        let mut half = a[200..].to_vec();
+        half.extend_from_slice(&b[..200]);
+        let s = idx.similarity(&half);
+        assert!(s > 0.3 && s < 0.6, "{s}");
+        assert_eq!(idx.similarity(b""), 0.0);
+    }
+}
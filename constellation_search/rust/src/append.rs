@@ -0,0 +1,224 @@
+// src/append.rs
+//! Growing an index at the end of its text.
+//!
+//! Only the appended bytes, plus the widest constellation's worth of old
+//! ones before them, are hashed. Gap tables keep their keys, so a table
+//! that needs more slots is re‐slotted from its stored keys rather than the
+//! text; posting arrays are laid out afresh either way, since a bucket's
+//! postings must stay contiguous.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::hash::{fingerprint, prefix_hash, window_const};
+use crate::array::{Offsets, Slots};
+use crate::packed::Packed;
+use crate::{home_slot, newline, next_slot, CsiIndex, FlatIndex, Hasher, Map, TableParams, MAX_PROBE, SATURATED};
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Extend the indexed text by `data`, folded, newline‐normalized and
+    /// masked as the build did, and index every constellation that now fits,
+    /// including those straddling the old end.
+    ///
+    /// A table grows when the longer text needs more slots than it has, by
+    /// the same sizing rule as a build (so the load stays at the configured
+    /// factor, 0.5 by default); forced `table_sizes` are kept. `k` and the
+    /// gaps stay as built, results match an index built over the whole text
+    /// in one pass, and on a multi‐slice index the bytes extend the last
+    /// slice. Costs hashing `data` plus a copy of every posting.
+    ///
+    /// Panics if the index holds no text of its own (a sidecar).
+    pub fn append(&mut self, data: &[u8]) {
+        assert!(self.has_text(), "append needs the index's own text");
+        if data.is_empty() {
+            return;
+        }
+        let mut data = self.fold.apply(data).into_owned();
+        if self.newlines {
+            // a `\r` that ended the old text pairs with a leading `\n`
+            if self.text.last() == Some(&b'\r') && data.first() == Some(&b'\n') {
+                let n = self.text.len() - 1;
+                self.truncate(n);
+                self.crlf.push(n);
+            }
+            if let Some((stripped, lfs)) = newline::strip_crlf(&data) {
+                let n = self.text.len();
+                self.crlf.extend(lfs.into_iter().map(|p| p + n));
+                data = stripped;
+            }
+        }
+        let n = self.text.len();
+        if let Some(lines) = &mut self.lines {
+            lines.extend(newline::line_breaks(&data, n));
+        }
+        if let Some(mask) = &self.mask {
+            mask.apply(&mut data, n);
+        }
+        self.digest = data.iter().fold(self.digest, |h, &b| self.hasher.extend(h, b));
+        self.text.to_mut().extend_from_slice(&data);
+        self.text_len = self.text.len();
+        if self.pw.len() > self.k + 1 {
+            while self.pw.len() <= self.text_len {
+                self.pw.push(self.hasher.step(self.pw[self.pw.len() - 1]));
+            }
+        }
+
+        let (h, k) = (&self.hasher, self.k);
+        let p = self.table_params();
+        // window values don't depend on what precedes them, so prefix states
+        // from any stride‐aligned start key the same constellations (and
+        // pick the same syncmers)
+        let widest = self.gaps.last().map_or(0, |&d| d);
+        let base = p.starts(n, widest) / self.stride * self.stride;
+        let ph = prefix_hash(h, &self.text[base..]);
+        let pk = self.pw[k];
+        for (&d, fi) in self.gaps.iter().zip(&mut self.flat) {
+            let old = p.starts(n, d);
+            let mut added = Vec::new();
+            p.each_key(h, &ph, pk, d, p.starts(ph.len() - 1, d), |j, key| {
+                if base + j >= old {
+                    added.push((key, base + j));
+                }
+            });
+            let ts = if self.fixed { fi.table_size } else { p.slots(self.text_len, d).max(fi.table_size) };
+            if added.is_empty() && ts == fi.table_size {
+                continue;
+            }
+            added.sort_unstable();
+            let fps = p.fps.then(|| {
+                let c = window_const(h, k + d);
+                added.iter().map(|&(_, o)| fingerprint(h.window(ph[o - base + k + d], ph[o - base], c))).collect::<Vec<_>>()
+            });
+            *fi = fi.merged(&added, fps.as_deref(), ts, p);
+        }
+    }
+}
+
+impl FlatIndex {
+    /// This table plus `added`, `(key, offset)` pairs sorted by key and then
+    /// offset, all past the table's own postings, laid out over `ts` slots.
+    /// `fps` are `added`'s fingerprints when the table keeps them.
+    pub(crate) fn merged(&self, added: &[(u64, usize)], fps: Option<&[u16]>, ts: usize, p: TableParams) -> FlatIndex {
+        let mut runs = Map::new();
+        let mut at = 0;
+        for run in added.chunk_by(|a, b| a.0 == b.0) {
+            runs.insert(run[0].0, at..at + run.len());
+            at += run.len();
+        }
+        // (key, old start, old len, run in `added`): the old buckets, then
+        // the keys new to the table
+        let mut buckets: Vec<(u64, usize, usize, Range<usize>)> = self.entries()
+            .map(|(key, start, len)| (key, start, len, runs.remove(&key).unwrap_or(0..0)))
+            .collect();
+        let mut fresh: Vec<_> = runs.into_iter().map(|(key, run)| (key, 0, 0, run)).collect();
+        fresh.sort_unstable_by_key(|b| b.0);
+        buckets.extend(fresh);
+
+        // probe every key in as `fill` does; keys are distinct, so each
+        // claims the first empty slot
+        let pow2 = ts.is_power_of_two();
+        let mut keys = vec![0u64; ts];
+        let mut bucket_of = vec![usize::MAX; ts];
+        let mut spilled = Vec::new();
+        'place: for (b, &(key, ..)) in buckets.iter().enumerate() {
+            let mut slot = if pow2 { home_slot::<true>(key, ts) } else { home_slot::<false>(key, ts) };
+            for _ in 0..MAX_PROBE {
+                if keys[slot] == 0 {
+                    keys[slot] = key;
+                    bucket_of[slot] = b;
+                    continue 'place;
+                }
+                slot = if pow2 { next_slot::<true>(slot, ts) } else { next_slot::<false>(slot, ts) };
+            }
+            spilled.push((key, b));
+        }
+        spilled.sort_unstable_by_key(|e| e.0);
+
+        let (mut starts, mut lens) = (vec![0; ts], vec![0; ts]);
+        let mut offs = Vec::with_capacity(self.offs.len() + added.len());
+        let mut kept = Vec::with_capacity(if fps.is_some() { offs.capacity() } else { 0 });
+        let mut packed = Vec::new();
+        // `(start, len)` of bucket `b`, its postings copied to the end of
+        // `offs`, or to a fresh packed list if the old one was packed
+        let mut place = |b: usize| {
+            let (key, start, len, ref run) = buckets[b];
+            if let Some(old) = self.packed_list(key).filter(|_| len == SATURATED) {
+                if old.len + run.len() <= p.cap {
+                    let list: Vec<usize> = old.iter().chain(added[run.clone()].iter().map(|a| a.1)).collect();
+                    packed.push((key, Packed::new(&list)));
+                }
+                return (0, SATURATED);
+            }
+            if len == SATURATED || len + run.len() > p.cap {
+                return (0, SATURATED);
+            }
+            let at = offs.len();
+            offs.extend(self.offs.list(start..start + len).iter());
+            offs.extend(added[run.clone()].iter().map(|a| a.1));
+            if let Some(fps) = fps {
+                kept.extend_from_slice(&self.fps[start..start + len]);
+                kept.extend_from_slice(&fps[run.clone()]);
+            }
+            (at, len + run.len())
+        };
+        for slot in 0..ts {
+            if bucket_of[slot] != usize::MAX {
+                (starts[slot], lens[slot]) = place(bucket_of[slot]);
+            }
+        }
+        let overflow = spilled.iter().map(|&(key, b)| {
+            let (start, len) = place(b);
+            (key, start, len)
+        }).collect();
+        packed.sort_unstable_by_key(|e| e.0);
+        let mut fi = FlatIndex {
+            table_size: ts,
+            keys: keys.into(),
+            starts: Slots::new(starts, !p.wide),
+            lens: Slots::new(lens, !p.wide),
+            offs: Offsets::new(offs, !p.wide),
+            fps: kept.into(),
+            overflow,
+            packed,
+        };
+        fi.pack(p.pack);
+        fi
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CsiIndexBuilder, EntropyTiers};
+
+    #[test]
+    fn appending_in_two_steps_searches_like_one_build() {
+        let text: Vec<u8> = (0..900).flat_map(|i| format!("rec{:03}:{}\r\n", i % 230, i * 7 % 13).into_bytes()).collect();
+        let a = text.len() / 5;
+        let b = a + 1 + text[a..].iter().position(|&c| c == b'\r').unwrap();
+        let tiers = EntropyTiers::fixed(4, vec![4, 8, 16]);
+        let builders = [
+            CsiIndexBuilder::new().tiers(tiers.clone()),
+            CsiIndexBuilder::new().tiers(tiers.clone()).stride(3).fingerprints(true).max_postings_per_key(40),
+            CsiIndexBuilder::new().tiers(tiers.clone()).normalize_newlines(true).keep_powers(true),
+            CsiIndexBuilder::new().tiers(tiers).syncmer_window(5).fingerprints(true),
+        ];
+        for builder in builders {
+            let whole = builder.build(&text);
+            // split inside a record and between a `\r` and its `\n`
+            let mut grown = builder.build(&text[..a]);
+            grown.append(&text[a..b]);
+            grown.append(&text[b..]);
+            assert_eq!(grown.validate(), Ok(()));
+            assert_eq!((grown.text(), grown.gaps()), (whole.text(), whole.gaps()));
+            let sizes = |idx: &crate::CsiIndex| idx.occupancy_report().iter().map(|g| g.table_size).collect::<Vec<_>>();
+            assert_eq!(sizes(&grown), sizes(&whole));
+            for at in (0..text.len() - 30).step_by(37) {
+                for len in [3, 9, 14, 30] {
+                    let pat = &text[at..at + len];
+                    assert_eq!(grown.search(pat), whole.search(pat), "{:?}", String::from_utf8_lossy(pat));
+                }
+            }
+            assert_eq!(grown.original_offset(grown.text().len()), whole.original_offset(whole.text().len()));
+        }
+    }
+}
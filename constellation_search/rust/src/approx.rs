@@ -0,0 +1,279 @@
+// src/approx.rs
+//! Approximate search: matches within a few substitutions (Hamming
+//! distance) or a few edits, insertions and deletions included.
+//!
+//! Substitutions keep every byte in place, so a constellation of the pattern
+//! that avoids them still hits at its usual posting offset: probing every
+//! pattern position at the smallest gap and verifying the starts the hits
+//! imply finds the match once any one pair survives.
+//!
+//! An indel shifts every byte after it, so posting offsets for constellations
+//! taken at the pattern's start stop lining up. Instead every constellation
+//! of the pattern at the smallest gap is probed, each hit votes for the start
+//! it implies, and each voted start is widened by `max_edits` either way and
+//! checked with a banded Levenshtein DP.
+
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use crate::{key_at, prefix_hash, CsiIndex, Hasher};
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Ascending offsets where `pat` occurs with at most `max_mismatches`
+    /// substituted bytes (Hamming distance; lengths are equal).
+    ///
+    /// Candidates come from every `k`‐mer pair of the pattern at `gaps[0]`
+    /// that still hits, so a match is found as long as one pair avoids all
+    /// its mismatches and isn't saturated: guaranteed when the pattern has
+    /// more than `2k · max_mismatches` pair positions, since a mismatch
+    /// only breaks the pairs whose anchors cover it. Patterns below
+    /// `min_pattern_len` are compared at every position instead. Each
+    /// candidate costs at most one pass over the pattern.
+    pub fn search_approx(&self, pat: &[u8], max_mismatches: usize) -> Vec<usize> {
+        self.search_hamming(pat, max_mismatches).into_iter().map(|(s, _)| s).collect()
+    }
+
+    /// `search_approx` with each match's number of mismatches, as
+    /// `(offset, mismatches)` ascending by offset.
+    pub fn search_hamming(&self, pat: &[u8], max_mismatches: usize) -> Vec<(usize, usize)> {
+        let pat = &*self.normalize(pat);
+        let (k, d, m, n) = (self.k, self.gaps[0], pat.len(), self.text.len());
+        if m > n {
+            return Vec::new();
+        }
+        let close = |s: usize| {
+            if !self.live(s, m) {
+                return None;
+            }
+            let window = &self.text[s..s + m];
+            let diff = window.iter().zip(pat).filter(|(a, b)| a != b).take(max_mismatches + 1).count();
+            (diff <= max_mismatches).then_some((s, diff))
+        };
+        if m < self.min_pattern_len() {
+            if !self.scan_short {
+                return Vec::new();
+            }
+            return (0..=n - m).filter_map(close).collect();
+        }
+        let php = prefix_hash(&self.hasher, pat);
+        let pk = self.pw[k];
+        let mut starts: Vec<usize> = Vec::new();
+        for j in 0..=m - (k + d) {
+            if let Some(list) = self.flat[0].lookup(key_at(&self.hasher, &php, pk, j, k, d)).postings() {
+                starts.extend(list.iter().filter_map(|&o| o.checked_sub(j)).filter(|&s| s + m <= n));
+            }
+        }
+        starts.sort_unstable();
+        starts.dedup();
+        starts.into_iter().filter_map(close).collect()
+    }
+
+    /// `(start, edits)` for every text offset where some substring starting
+    /// there is within `max_edits` insertions, deletions or substitutions of
+    /// `pat`, with the fewest edits needed; ascending by start.
+    ///
+    /// A match is only found if at least one `k`‐mer pair at `gaps[0]` of
+    /// the pattern occurs unedited in it (and isn't saturated), so very
+    /// dense edits can be missed; patterns shorter than `k + gaps[0] + k`
+    /// yield nothing. Neighbouring starts are reported separately: a match
+    /// at `s` usually also appears at `s ± 1` with one more edit. Verifying
+    /// costs O(m · max_edits²) per voted start.
+    pub fn search_indel(&self, pat: &[u8], max_edits: usize) -> Vec<(usize, usize)> {
+        let pat = &*self.normalize(pat);
+        let (k, d, m) = (self.k, self.gaps[0], pat.len());
+        if m < k + d + k {
+            return Vec::new();
+        }
+        let php = prefix_hash(&self.hasher, pat);
+        let pk = self.pw[k];
+        let mut starts: Vec<usize> = Vec::new();
+        for j in 0..=m - (k + d) {
+            if let Some(list) = self.flat[0].lookup(key_at(&self.hasher, &php, pk, j, k, d)).postings() {
+                starts.extend(list.iter().map(|&o| o.saturating_sub(j)));
+            }
+        }
+        starts.sort_unstable();
+        starts.dedup();
+
+        let n = self.text.len();
+        let mut best: BTreeMap<usize, usize> = BTreeMap::new();
+        for c in starts {
+            let lo = c.saturating_sub(max_edits);
+            let hi = (c + max_edits).min(n);
+            for s in lo..=hi {
+                if best.contains_key(&s) || !self.live(s, m) {
+                    continue;
+                }
+                if let Some(e) = banded_distance(pat, &self.text[s..], max_edits) {
+                    best.insert(s, e);
+                }
+            }
+        }
+        best.into_iter().collect()
+    }
+}
+
+/// Fewest edits turning `pat` into some prefix of `text`, if at most `band`.
+///
+/// Only cells with |i − j| ≤ `band` are computed; any alignment within
+/// `band` edits stays inside that diagonal strip.
+fn banded_distance(pat: &[u8], text: &[u8], band: usize) -> Option<usize> {
+    const INF: usize = usize::MAX / 2;
+    let m = pat.len();
+    let w = 2 * band + 1;
+    // row[t] holds dp[i][i + t - band]
+    let mut prev = vec![INF; w];
+    let mut row = vec![INF; w];
+    for (t, cell) in prev.iter_mut().enumerate().skip(band) {
+        if t - band <= text.len() {
+            *cell = t - band;
+        }
+    }
+    for i in 1..=m {
+        row.fill(INF);
+        for t in 0..w {
+            let Some(j) = (i + t).checked_sub(band) else { continue };
+            if j > text.len() {
+                break;
+            }
+            let mut v = if j == 0 { i } else { INF };
+            if j > 0 {
+                // diagonal: same t in the previous row
+                v = v.min(prev[t] + (pat[i - 1] != text[j - 1]) as usize);
+                // insertion into pat: dp[i][j-1]
+                if t > 0 {
+                    v = v.min(row[t - 1] + 1);
+                }
+            }
+            // deletion from pat: dp[i-1][j]
+            if t + 1 < w {
+                v = v.min(prev[t + 1] + 1);
+            }
+            row[t] = v;
+        }
+        core::mem::swap(&mut prev, &mut row);
+    }
+    let best = prev.iter().copied().min().unwrap_or(INF);
+    (best <= band).then_some(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::banded_distance;
+    use crate::{csi_free, csi_new, csi_search_approx, csi_search_approx_matches, CsiIndex, CsiMatch};
+
+    /// Levenshtein distance from `a` to every prefix of `b`.
+    fn lev_prefixes(a: &[u8], b: &[u8]) -> Vec<usize> {
+        let mut prev: Vec<usize> = (0..=b.len()).collect();
+        for i in 1..=a.len() {
+            let mut row = vec![i; b.len() + 1];
+            for j in 1..=b.len() {
+                row[j] = (prev[j - 1] + (a[i - 1] != b[j - 1]) as usize)
+                    .min(prev[j] + 1)
+                    .min(row[j - 1] + 1);
+            }
+            prev = row;
+        }
+        prev
+    }
+
+    fn naive(text: &[u8], pat: &[u8], e: usize) -> Vec<(usize, usize)> {
+        (0..=text.len()).filter_map(|s| {
+            let end = (s + pat.len() + e).min(text.len());
+            let best = lev_prefixes(pat, &text[s..end]).into_iter().min().unwrap();
+            (best <= e).then_some((s, best))
+        }).collect()
+    }
+
+    #[test]
+    fn banded_matches_full_dp() {
+        let pat = b"kitten sitting";
+        for text in [&b"kitten sitting on"[..], b"kiten sittting", b"mitten fitting", b"kit"] {
+            for band in 0..4 {
+                let full = lev_prefixes(pat, text).into_iter().min().unwrap();
+                assert_eq!(banded_distance(pat, text, band), (full <= band).then_some(full));
+            }
+        }
+    }
+
+    #[test]
+    fn approx_search_finds_substituted_copies() {
+        let mut x = 11u64;
+        let mut text: Vec<u8> = (0..600).map(|_| { x = x.wrapping_mul(6364136223846793005).wrapping_add(1); b'a' + (x >> 60) as u8 }).collect();
+        let pat = text[100..160].to_vec();
+        text[250..310].copy_from_slice(&pat);
+        text[275] = b'#';
+        text[400..460].copy_from_slice(&pat);
+        text[405] = b'#';
+        text[450] = b'#';
+        let idx = CsiIndex::build(&text);
+        let naive = |p: &[u8], e: usize| -> Vec<usize> {
+            (0..=text.len() - p.len())
+                .filter(|&s| text[s..s + p.len()].iter().zip(p).filter(|(a, b)| a != b).count() <= e)
+                .collect()
+        };
+        assert_eq!(idx.search_approx(&pat, 0), [100]);
+        assert_eq!(idx.search_approx(&pat, 1), [100, 250]);
+        assert_eq!(idx.search_approx(&pat, 2), [100, 250, 400]);
+        for e in 0..4 {
+            assert_eq!(idx.search_approx(&pat, e), naive(&pat, e));
+            assert_eq!(idx.search_approx(&text[300..305], e), naive(&text[300..305], e));
+        }
+        assert_eq!(idx.search_hamming(&pat, 3), [(100, 0), (250, 1), (400, 2)]);
+
+        let h = csi_new(text.as_ptr(), text.len());
+        let mut out = [0usize; 2];
+        assert_eq!(csi_search_approx(h, pat.as_ptr(), pat.len(), 2, out.as_mut_ptr(), out.len()), 3);
+        assert_eq!(out, [100, 250]);
+        let mut scored = [CsiMatch::default(); 4];
+        assert_eq!(csi_search_approx_matches(h, pat.as_ptr(), pat.len(), 2, scored.as_mut_ptr(), scored.len()), 3);
+        assert_eq!(scored[1..3], [CsiMatch { offset: 250, length: 60, score: 1 }, CsiMatch { offset: 400, length: 60, score: 2 }]);
+        csi_free(h);
+    }
+
+    #[test]
+    fn hamming_counts_are_the_planted_substitutions() {
+        let mut x = 5u64;
+        let mut text: Vec<u8> = (0..2000).map(|_| { x = x.wrapping_mul(6364136223846793005).wrapping_add(1); b'a' + (x >> 60) as u8 }).collect();
+        let pat = text[50..130].to_vec();
+        // copies with 0 to 4 substitutions, spread along the copy
+        let planted = [(50, 0), (300, 1), (600, 2), (900, 3), (1200, 4)];
+        for &(at, subs) in &planted[1..] {
+            text[at..at + pat.len()].copy_from_slice(&pat);
+            for i in 0..subs {
+                text[at + 7 + 19 * i] = b'#';
+            }
+        }
+        let idx = CsiIndex::build(&text);
+        let hamming = |s: usize| text[s..s + pat.len()].iter().zip(&pat).filter(|(a, b)| a != b).count();
+        for e in 0..=4 {
+            let want: Vec<(usize, usize)> = planted.iter().copied().filter(|&(_, subs)| subs <= e).collect();
+            let got = idx.search_hamming(&pat, e);
+            assert_eq!(got, want, "e = {e}");
+            // the limit itself is in, one more is out, and nothing else is close
+            assert_eq!(got.last(), Some(&planted[e]));
+            assert!(got.iter().all(|&(s, c)| c == hamming(s)));
+            assert_eq!((0..=text.len() - pat.len()).filter(|&s| hamming(s) <= e).count(), e + 1);
+        }
+        assert_eq!(idx.search_approx(&pat, 4), planted.map(|(s, _)| s));
+    }
+
+    #[test]
+    fn indel_search_agrees_with_naive_scan() {
+        let mut x = 7u64;
+        let mut text: Vec<u8> = (0..400).map(|_| { x = x.wrapping_mul(6364136223846793005).wrapping_add(1); b'a' + (x >> 60) as u8 }).collect();
+        let pat = text[100..150].to_vec();
+        text.remove(220);
+        let mut shifted = text[200..250].to_vec();
+        shifted.insert(30, b'#');
+        text.splice(300..350, shifted);
+        let idx = CsiIndex::build(&text);
+        for e in 0..3 {
+            let probe = [&pat[..], &text[200..250], &text[300..351]];
+            for p in probe {
+                assert_eq!(idx.search_indel(p, e), naive(&text, p, e));
+            }
+        }
+        let hits = idx.search_indel(&text[300..350], 1);
+        assert!(hits.contains(&(300, 0)));
+    }
+}
@@ -0,0 +1,468 @@
+// src/array.rs
+//! Table and text storage that is owned, read in place from a mapped index
+//! file (see `CsiIndex::open_mmap`), or lent by a C caller (see
+//! `csi_new_borrowed`).
+//!
+//! Reads go through `Deref<Target = [T]>` either way. Writes copy a mapped
+//! or lent array onto the heap first, so such an index can still be
+//! truncated, appended to or rebuilt; it just stops sharing those arrays.
+
+use alloc::{borrow::Cow, vec::Vec};
+#[cfg(feature = "memmap2")]
+use alloc::sync::Arc;
+use core::fmt;
+use core::ops::{Deref, DerefMut, Range};
+
+#[cfg(feature = "memmap2")]
+use memmap2::Mmap;
+
+/// Elements an index file stores little‐endian, so a mapping of one can be
+/// read as `[T]` where the layout matches
+pub(crate) trait Word: Copy + Default + PartialEq + fmt::Debug + 'static {}
+
+impl Word for u8 {}
+impl Word for u16 {}
+impl Word for u32 {}
+impl Word for u64 {}
+impl Word for usize {}
+
+/// A `Vec<T>`, `len` elements at byte offset `at` of a mapped file, or
+/// memory whose owner promised it outlives the index
+pub(crate) enum Array<T: Word> {
+    Heap(Vec<T>),
+    // only `csi_new_borrowed` lends
+    #[cfg_attr(any(target_arch = "wasm32", not(feature = "std")), allow(dead_code))]
+    Lent(&'static [T]),
+    #[cfg(feature = "memmap2")]
+    Mapped { map: Arc<Mmap>, at: usize, len: usize },
+}
+
+impl<T: Word> Array<T> {
+    /// `len` elements at byte offset `at` of `map`, read in place, or `None`
+    /// if they run past its end or the target can't read them as stored
+    /// (misaligned, big‐endian, or `usize` narrower than the file's words).
+    #[cfg(feature = "memmap2")]
+    pub(crate) fn mapped(map: &Arc<Mmap>, at: usize, len: usize) -> Option<Self> {
+        let end = len.checked_mul(size_of::<T>())?.checked_add(at)?;
+        let aligned = (map.as_ptr() as usize + at).is_multiple_of(align_of::<T>());
+        let native = cfg!(target_endian = "little") && size_of::<usize>() == 8;
+        (end <= map.len() && aligned && native).then(|| Array::Mapped { map: Arc::clone(map), at, len })
+    }
+
+    /// The owned elements, copied off the mapping first if need be.
+    pub(crate) fn to_mut(&mut self) -> &mut Vec<T> {
+        if !matches!(self, Array::Heap(_)) {
+            *self = Array::Heap(self.to_vec());
+        }
+        match self {
+            Array::Heap(v) => v,
+            _ => unreachable!(),
+        }
+    }
+
+    /// An empty owned vector for refilling: an owned array keeps its
+    /// allocation, others are let go without copying.
+    pub(crate) fn cleared(&mut self) -> &mut Vec<T> {
+        if !matches!(self, Array::Heap(_)) {
+            *self = Array::default();
+        }
+        let v = self.to_mut();
+        v.clear();
+        v
+    }
+
+    /// Elements allocated on the heap; 0 while mapped or lent.
+    pub(crate) fn capacity(&self) -> usize {
+        match self {
+            Array::Heap(v) => v.capacity(),
+            _ => 0,
+        }
+    }
+}
+
+impl<T: Word> Clone for Array<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Array::Heap(v) => Array::Heap(v.clone()),
+            Array::Lent(s) => Array::Lent(s),
+            #[cfg(feature = "memmap2")]
+            Array::Mapped { map, at, len } => Array::Mapped { map: Arc::clone(map), at: *at, len: *len },
+        }
+    }
+}
+
+impl<T: Word> FromIterator<T> for Array<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Array::Heap(iter.into_iter().collect())
+    }
+}
+
+impl<T: Word> PartialEq for Array<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: Word> PartialEq<&[T]> for Array<T> {
+    fn eq(&self, other: &&[T]) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: Word> fmt::Debug for Array<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<T: Word> Default for Array<T> {
+    fn default() -> Self {
+        Array::Heap(Vec::new())
+    }
+}
+
+impl<T: Word> From<Vec<T>> for Array<T> {
+    fn from(v: Vec<T>) -> Self {
+        Array::Heap(v)
+    }
+}
+
+impl<T: Word> Deref for Array<T> {
+    type Target = [T];
+
+    #[inline(always)]
+    fn deref(&self) -> &[T] {
+        match self {
+            Array::Heap(v) => v,
+            Array::Lent(s) => s,
+            // SAFETY: `mapped` checked that the range lies in the mapping
+            // and is aligned for `T`, and the `Arc` keeps the mapping alive
+            #[cfg(feature = "memmap2")]
+            Array::Mapped { map, at, len } => unsafe {
+                core::slice::from_raw_parts(map.as_ptr().add(*at).cast(), *len)
+            },
+        }
+    }
+}
+
+impl<'a, T: Word> IntoIterator for &'a Array<T> {
+    type Item = &'a T;
+    type IntoIter = core::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T: Word> DerefMut for Array<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.to_mut()
+    }
+}
+
+/// A gap table's per‐slot bucket starts or lengths: 32‐bit words when every
+/// value fits, which halves the bulk of a table, else `usize`s. `usize::MAX`
+/// (a saturated bucket's length) is stored narrow as `u32::MAX`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Slots {
+    Narrow(Array<u32>),
+    Wide(Array<usize>),
+}
+
+impl Slots {
+    /// `v` packed into 32 bits if `narrow` and every value fits.
+    pub(crate) fn new(v: Vec<usize>, narrow: bool) -> Self {
+        if narrow && v.iter().all(|&x| x < u32::MAX as usize || x == usize::MAX) {
+            Slots::Narrow(v.into_iter().map(|x| x as u32).collect())
+        } else {
+            Slots::Wide(v.into())
+        }
+    }
+
+    /// `len` zeros to refill, reusing a wide array's allocation.
+    pub(crate) fn scratch(&mut self, len: usize) -> Vec<usize> {
+        let mut v = match core::mem::take(self) {
+            Slots::Wide(Array::Heap(v)) => v,
+            _ => Vec::new(),
+        };
+        v.clear();
+        v.resize(len, 0);
+        v
+    }
+
+    #[inline(always)]
+    pub(crate) fn get(&self, i: usize) -> usize {
+        match self {
+            Slots::Narrow(a) => widen(a[i]),
+            Slots::Wide(a) => a[i],
+        }
+    }
+
+    /// `get` without the bounds check.
+    ///
+    /// # Safety
+    /// `i < self.len()`.
+    #[inline(always)]
+    pub(crate) unsafe fn get_unchecked(&self, i: usize) -> usize {
+        // SAFETY: the caller keeps `i` in bounds
+        unsafe {
+            match self {
+                Slots::Narrow(a) => widen(*a.get_unchecked(i)),
+                Slots::Wide(a) => *a.get_unchecked(i),
+            }
+        }
+    }
+
+    /// Overwrite slot `i` with `x`, which must be `usize::MAX` or at most
+    /// its current value, so a narrow array stays narrow.
+    pub(crate) fn set(&mut self, i: usize, x: usize) {
+        match self {
+            Slots::Narrow(a) => a.to_mut()[i] = x as u32,
+            Slots::Wide(a) => a.to_mut()[i] = x,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Slots::Narrow(a) => a.len(),
+            Slots::Wide(a) => a.len(),
+        }
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len()).map(|i| self.get(i))
+    }
+
+    /// Bytes allocated on the heap; 0 while mapped or lent.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        match self {
+            Slots::Narrow(a) => a.capacity() * 4,
+            Slots::Wide(a) => a.capacity() * 8,
+        }
+    }
+}
+
+/// A gap table's postings, every bucket's end to end: 32‐bit words when
+/// every offset fits, as in any text under 4 GiB, else `usize`s. Buckets
+/// are lent out as a `List` of either width.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Offsets {
+    Narrow(Array<u32>),
+    Wide(Array<usize>),
+}
+
+impl Offsets {
+    /// `v` packed into 32 bits if `narrow` and every offset fits.
+    pub(crate) fn new(v: Vec<usize>, narrow: bool) -> Self {
+        if narrow && v.iter().all(|&x| x <= u32::MAX as usize) {
+            Offsets::Narrow(v.into_iter().map(|x| x as u32).collect())
+        } else {
+            Offsets::Wide(v.into())
+        }
+    }
+
+    /// `len` zeros to refill, reusing a wide array's allocation.
+    pub(crate) fn scratch(&mut self, len: usize) -> Vec<usize> {
+        let mut v = match core::mem::take(self) {
+            Offsets::Wide(Array::Heap(v)) => v,
+            _ => Vec::new(),
+        };
+        v.clear();
+        v.resize(len, 0);
+        v
+    }
+
+    /// The postings at `range`.
+    #[inline(always)]
+    pub(crate) fn list(&self, range: Range<usize>) -> List<'_> {
+        match self {
+            Offsets::Narrow(a) => List::Narrow(&a[range]),
+            Offsets::Wide(a) => List::Wide(&a[range]),
+        }
+    }
+
+    /// Every posting, bucket after bucket.
+    pub(crate) fn all(&self) -> List<'_> {
+        self.list(0..self.len())
+    }
+
+    /// Where `list` starts in these postings, if it's a run of them.
+    pub(crate) fn position(&self, list: List<'_>) -> Option<usize> {
+        fn at<T>(all: &[T], part: &[T]) -> Option<usize> {
+            (!part.is_empty() && all.as_ptr_range().contains(&part.as_ptr()))
+                .then(|| (part.as_ptr() as usize - all.as_ptr() as usize) / size_of::<T>())
+        }
+        match (self, list) {
+            (Offsets::Narrow(a), List::Narrow(l)) => at(a, l),
+            (Offsets::Wide(a), List::Wide(l)) => at(a, l),
+            _ => None,
+        }
+    }
+
+    /// Move the postings at `range` to start at `to`, as `copy_within` does.
+    pub(crate) fn copy_within(&mut self, range: Range<usize>, to: usize) {
+        match self {
+            Offsets::Narrow(a) => a.to_mut().copy_within(range, to),
+            Offsets::Wide(a) => a.to_mut().copy_within(range, to),
+        }
+    }
+
+    /// Keep the first `len` postings and let go of the rest's memory.
+    pub(crate) fn truncate(&mut self, len: usize) {
+        match self {
+            Offsets::Narrow(a) => {
+                a.to_mut().truncate(len);
+                a.to_mut().shrink_to_fit();
+            }
+            Offsets::Wide(a) => {
+                a.to_mut().truncate(len);
+                a.to_mut().shrink_to_fit();
+            }
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Offsets::Narrow(a) => a.len(),
+            Offsets::Wide(a) => a.len(),
+        }
+    }
+
+    /// Bytes allocated on the heap; 0 while mapped or lent.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        match self {
+            Offsets::Narrow(a) => a.capacity() * 4,
+            Offsets::Wide(a) => a.capacity() * 8,
+        }
+    }
+}
+
+impl Default for Offsets {
+    fn default() -> Self {
+        Offsets::Wide(Array::default())
+    }
+}
+
+/// One bucket's postings as its table stores them, ascending
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum List<'a> {
+    Narrow(&'a [u32]),
+    Wide(&'a [usize]),
+}
+
+impl<'a> List<'a> {
+    #[inline(always)]
+    pub(crate) fn len(self) -> usize {
+        match self {
+            List::Narrow(l) => l.len(),
+            List::Wide(l) => l.len(),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_empty(self) -> bool {
+        self.len() == 0
+    }
+
+    /// Posting `i`; panics past the end, like indexing.
+    #[inline(always)]
+    pub(crate) fn get(self, i: usize) -> usize {
+        match self {
+            List::Narrow(l) => l[i] as usize,
+            List::Wide(l) => l[i],
+        }
+    }
+
+    /// `get` without the bounds check.
+    ///
+    /// # Safety
+    /// `i < self.len()`.
+    #[inline(always)]
+    pub(crate) unsafe fn get_unchecked(self, i: usize) -> usize {
+        // SAFETY: the caller keeps `i` in bounds
+        unsafe {
+            match self {
+                List::Narrow(l) => *l.get_unchecked(i) as usize,
+                List::Wide(l) => *l.get_unchecked(i),
+            }
+        }
+    }
+
+    /// The postings at `range` of this list.
+    #[inline(always)]
+    pub(crate) fn slice(self, range: Range<usize>) -> List<'a> {
+        match self {
+            List::Narrow(l) => List::Narrow(&l[range]),
+            List::Wide(l) => List::Wide(&l[range]),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn first(self) -> Option<usize> {
+        (!self.is_empty()).then(|| self.get(0))
+    }
+
+    pub(crate) fn iter(self) -> impl Iterator<Item = usize> + 'a {
+        (0..self.len()).map(move |i| self.get(i))
+    }
+
+    pub(crate) fn to_vec(self) -> Vec<usize> {
+        match self {
+            List::Narrow(l) => l.iter().map(|&x| x as usize).collect(),
+            List::Wide(l) => l.to_vec(),
+        }
+    }
+
+    /// The postings as `usize`s, borrowed if stored that wide.
+    pub(crate) fn widened(self) -> Cow<'a, [usize]> {
+        match self {
+            List::Wide(l) => Cow::Borrowed(l),
+            List::Narrow(_) => Cow::Owned(self.to_vec()),
+        }
+    }
+
+    /// Where `x` is, or would go, as `slice::binary_search` says.
+    pub(crate) fn binary_search(self, x: usize) -> Result<usize, usize> {
+        match self {
+            List::Narrow(l) => u32::try_from(x).map_or(Err(l.len()), |x| l.binary_search(&x)),
+            List::Wide(l) => l.binary_search(&x),
+        }
+    }
+
+    /// How many postings `pred` holds for before it first fails.
+    pub(crate) fn partition_point(self, pred: impl Fn(usize) -> bool) -> usize {
+        match self {
+            List::Narrow(l) => l.partition_point(|&x| pred(x as usize)),
+            List::Wide(l) => l.partition_point(|&x| pred(x)),
+        }
+    }
+}
+
+impl<'a> From<&'a [usize]> for List<'a> {
+    fn from(l: &'a [usize]) -> Self {
+        List::Wide(l)
+    }
+}
+
+impl<'a, const N: usize> From<&'a [usize; N]> for List<'a> {
+    fn from(l: &'a [usize; N]) -> Self {
+        List::Wide(l)
+    }
+}
+
+impl<'a> From<&'a Vec<usize>> for List<'a> {
+    fn from(l: &'a Vec<usize>) -> Self {
+        List::Wide(l)
+    }
+}
+
+#[inline(always)]
+fn widen(x: u32) -> usize {
+    if x == u32::MAX { usize::MAX } else { x as usize }
+}
+
+impl Default for Slots {
+    fn default() -> Self {
+        Slots::Wide(Array::default())
+    }
+}
@@ -0,0 +1,158 @@
+// src/batch.rs
+//! Patterns hashed once and run against many indexes, or many patterns
+//! run against one.
+
+use alloc::vec::Vec;
+
+use crate::hash::prefix_hash;
+use crate::{verify, CaseFold, CsiIndex, CsiIndexError, Hasher};
+
+/// Normalized patterns and their prefix hash states, from
+/// `CsiIndex::compile_batch`.
+///
+/// Pattern keys depend only on the bytes, the hasher and `k`, never on the
+/// indexed text, so one batch runs against any index built with the same
+/// hasher (and seed), `k` and pattern normalization (case folding, newline handling);
+/// gaps and stride may differ, since keys are formed per table from the
+/// stored prefix states.
+#[derive(Debug, Clone)]
+pub struct CompiledBatch {
+    pats: Vec<(Vec<u8>, Vec<u64>)>,
+    k:    usize,
+    tag:  u32,
+    seed: u64,
+    fold: CaseFold,
+    newlines: bool,
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Normalize and hash every pattern in `pats` once, for repeated
+    /// `CompiledBatch::run_against` calls.
+    pub fn compile_batch(&self, pats: &[&[u8]]) -> CompiledBatch {
+        let pats = pats.iter().map(|p| {
+            let pat = self.normalize(p).into_owned();
+            let php = prefix_hash(&self.hasher, &pat);
+            (pat, php)
+        }).collect();
+        CompiledBatch { pats, k: self.k, tag: H::TAG, seed: self.hasher.seed(), fold: self.fold, newlines: self.newlines }
+    }
+
+    /// `search` of each of `pats`, in order: each is hashed once, as by
+    /// `compile_batch`, and with the `rayon` feature the patterns are
+    /// searched concurrently on the current pool. For scanning one index
+    /// for hundreds of patterns in one call.
+    pub fn search_many(&self, pats: &[&[u8]]) -> Vec<Vec<usize>> {
+        self.compile_batch(pats).run(self)
+    }
+}
+
+impl CompiledBatch {
+    /// Number of patterns in the batch.
+    pub fn len(&self) -> usize {
+        self.pats.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pats.is_empty()
+    }
+
+    /// `index.search` of each pattern, in batch order, reusing the stored
+    /// hashes; `CsiIndexError::IncompatibleBatch` if `index` differs in
+    /// hasher or its seed, `k`, case folding or newline handling.
+    ///
+    /// Masked indexes search each pattern normally (masking changes the
+    /// hashed bytes), so they gain nothing from a batch but still agree.
+    /// With the `rayon` feature patterns are searched concurrently.
+    pub fn run_against<H: Hasher>(&self, index: &CsiIndex<H>) -> Result<Vec<Vec<usize>>, CsiIndexError> {
+        let this = (self.tag, self.seed, self.k, self.fold, self.newlines);
+        if this != (H::TAG, index.hasher.seed(), index.k, index.fold, index.newlines) {
+            return Err(CsiIndexError::IncompatibleBatch);
+        }
+        Ok(self.run(index))
+    }
+
+    /// `run_against` a compatible `index`.
+    fn run<H: Hasher>(&self, index: &CsiIndex<H>) -> Vec<Vec<usize>> {
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            self.pats.par_iter().map(|(pat, php)| search_hashed(index, pat, php)).collect()
+        }
+        #[cfg(not(feature = "rayon"))]
+        self.pats.iter().map(|(pat, php)| search_hashed(index, pat, php)).collect()
+    }
+}
+
+/// `index.search` of normalized `pat`, whose prefix states are `php`.
+fn search_hashed<H: Hasher>(index: &CsiIndex<H>, pat: &[u8], php: &[u64]) -> Vec<usize> {
+    let eq = verify::same;
+    let n = index.text.len();
+    if index.mask.is_some() {
+        return index.search(pat);
+    }
+    if pat.is_empty() {
+        return (0..=n).collect();
+    }
+    let p = crate::periodic::pattern_period(pat);
+    if index.verify && p > 0 && 2 * p <= pat.len() {
+        return index.search_periodic(pat, p);
+    }
+    match index.candidates_hashed(pat.len(), php, usize::MAX, index.plan_cutoff()) {
+        Some(acc) => acc.into_iter().filter(|&off| index.verify_by(off, pat, &eq)).collect(),
+        None => (0..=n.saturating_sub(pat.len()))
+            .filter(|&off| index.verify_by(off, pat, &eq))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CaseFold, CsiConfig, CsiIndex, CsiIndexBuilder, CsiIndexError};
+
+    #[test]
+    fn batch_runs_against_compatible_indexes() {
+        let a = b"users who liked this also liked that; users who liked that also liked this".repeat(3);
+        let b = b"liked that also liked this, said users who liked this also liked nothing".repeat(2);
+        let pats: [&[u8]; 4] = [b"users who liked this", b"also liked that", b"liked this also liked", b""];
+        let ia = CsiIndexBuilder::new().max_gaps(1).build(&a);
+        let ib = CsiIndex::build(&b);
+        assert_eq!(ia.k, ib.k);
+        let batch = ia.compile_batch(&pats);
+        assert_eq!(batch.len(), 4);
+        for idx in [&ia, &ib] {
+            let want: Vec<Vec<usize>> = pats.iter().map(|p| idx.search(p)).collect();
+            assert_eq!(batch.run_against(idx).unwrap(), want);
+        }
+
+        let folded = CsiIndex::build_with_config(&b, &CsiConfig { case_fold: CaseFold::Unicode, ..CsiConfig::default() });
+        assert_eq!(batch.run_against(&folded), Err(CsiIndexError::IncompatibleBatch));
+        let short = CsiIndexBuilder::for_signatures().build(&b);
+        assert_ne!(short.k, ia.k);
+        assert_eq!(batch.run_against(&short), Err(CsiIndexError::IncompatibleBatch));
+    }
+
+    #[test]
+    fn search_many_matches_one_search_per_pattern() {
+        let text: Vec<u8> = (0..600).flat_map(|i| format!("ioc:{:08x} seen {} times; ", (i as u32).wrapping_mul(2654435761) % 1000, i % 9).into_bytes()).collect();
+        let iocs: Vec<Vec<u8>> = (0..40).map(|i| text[i * 97..i * 97 + 13 + i % 20].to_vec()).collect();
+        let mut pats: Vec<&[u8]> = iocs.iter().map(|p| &p[..]).collect();
+        pats.extend([&b"ioc:"[..], b"", b"times; ioc:times; ioc:", b"xxxxxxxxxxxxxxxxxxxxxxxx"]);
+        for idx in [CsiIndex::build(&text), CsiIndex::build_with_config(&text, &CsiConfig { stride: 2, case_fold: CaseFold::Ascii, ..CsiConfig::default() })] {
+            let want: Vec<Vec<usize>> = pats.iter().map(|p| idx.search(p)).collect();
+            assert_eq!(idx.search_many(&pats), want);
+        }
+
+        let h = crate::csi_new(text.as_ptr(), text.len());
+        let (ptrs, lens): (Vec<_>, Vec<_>) = pats.iter().map(|p| (p.as_ptr(), p.len())).unzip();
+        let mut counts = vec![0usize; pats.len()];
+        let r = crate::csi_search_batch(h, ptrs.as_ptr(), lens.as_ptr(), pats.len(), counts.as_mut_ptr());
+        let want = CsiIndex::build(&text).search_many(&pats);
+        assert_eq!(counts, want.iter().map(Vec::len).collect::<Vec<_>>());
+        let mut all = vec![0usize; counts.iter().sum()];
+        assert_eq!(crate::csi_results_copy(r, all.as_mut_ptr(), 0, all.len()), all.len());
+        assert_eq!(all, want.concat());
+        crate::csi_results_free(r);
+        assert!(crate::csi_search_batch(h, ptrs.as_ptr(), std::ptr::null(), 2, counts.as_mut_ptr()).is_null());
+        crate::csi_free(h);
+    }
+}
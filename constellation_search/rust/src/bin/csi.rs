@@ -0,0 +1,181 @@
+// src/bin/csi.rs
+//! `csi`, the command‐line front end (feature `cli`): index a file into a
+//! whole‐index file, then search or describe it.
+//!
+//! ```text
+//! csi build <file> [-o <index>] [--max-memory <bytes>]
+//! csi search [--count] <index> <pattern>
+//! csi stats <index>
+//! ```
+//!
+//! `build` maps the file and writes `<file>.csi` (or `-o`'s path) through
+//! `CsiIndex::write_index_external`, so files bigger than memory can be
+//! indexed; `--max-memory` bounds the postings sorted at once. `search`
+//! prints the byte offset of every match, one per line, or with `--count`
+//! only how many there are; the pattern's bytes are taken as given.
+//! `search` and `stats` map the index instead of reading it. The exit status
+//! is 0 on success, 1 when a search finds nothing and 2 on errors.
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use csi::{CsiConfig, CsiIndex, PolyHasher};
+use memmap2::Mmap;
+
+const USAGE: &str = "usage: csi build <file> [-o <index>] [--max-memory <bytes>]
+       csi search [--count] <index> <pattern>
+       csi stats <index>";
+
+fn main() -> ExitCode {
+    let args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    let mut out = io::stdout().lock();
+    match run(&args, &mut out) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("csi: {e}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Carry out the command in `args`, printing to `out`: `Ok(false)` for a
+/// search without matches.
+fn run(args: &[OsString], out: &mut impl Write) -> Result<bool, String> {
+    let words: Vec<&str> = args.iter().map(|a| a.to_str().unwrap_or("")).collect();
+    match words.as_slice() {
+        ["build", ..] => {
+            let (mut file, mut index, mut budget) = (None, None, None);
+            let mut rest = args[1..].iter();
+            while let Some(arg) = rest.next() {
+                match arg.to_str() {
+                    Some("-o") => index = Some(PathBuf::from(rest.next().ok_or("-o needs a path")?)),
+                    Some("--max-memory") => {
+                        let bytes = rest.next().and_then(|b| b.to_str()?.parse().ok());
+                        budget = Some(bytes.ok_or("--max-memory needs a byte count")?);
+                    }
+                    _ if file.is_none() => file = Some(PathBuf::from(arg)),
+                    _ => return Err(USAGE.into()),
+                }
+            }
+            let file = file.ok_or(USAGE)?;
+            let index = index.unwrap_or_else(|| {
+                let mut name = file.clone().into_os_string();
+                name.push(".csi");
+                name.into()
+            });
+            build(&file, &index, budget).map_err(|e| format!("{}: {e}", file.display()))?;
+            Ok(true)
+        }
+        ["search", "--count", _, _] | ["search", _, _] => {
+            let count = words[1] == "--count";
+            let (index, pat) = (Path::new(&args[args.len() - 2]), args[args.len() - 1].as_encoded_bytes());
+            let idx = CsiIndex::open_mmap(index, PolyHasher).map_err(|e| format!("{}: {e}", index.display()))?;
+            let hits = idx.search(pat);
+            let mut w = BufWriter::new(out);
+            if count {
+                writeln!(w, "{}", hits.len()).map_err(|e| e.to_string())?;
+            } else {
+                for off in &hits {
+                    writeln!(w, "{off}").map_err(|e| e.to_string())?;
+                }
+            }
+            w.flush().map_err(|e| e.to_string())?;
+            Ok(!hits.is_empty())
+        }
+        ["stats", _] => {
+            let index = Path::new(&args[1]);
+            let idx = CsiIndex::open_mmap(index, PolyHasher).map_err(|e| format!("{}: {e}", index.display()))?;
+            stats(&idx, out).map_err(|e| e.to_string())?;
+            Ok(true)
+        }
+        _ => Err(USAGE.into()),
+    }
+}
+
+/// Index `file` into `index` with the default tiers.
+fn build(file: &Path, index: &Path, budget: Option<usize>) -> io::Result<()> {
+    let src = File::open(file)?;
+    // SAFETY: the file is only read, and only while it's being indexed; a
+    // concurrent writer spoils the index, not memory safety of the build
+    let map = unsafe { Mmap::map(&src)? };
+    let config = CsiConfig { max_memory_bytes: budget, ..CsiConfig::default() };
+    CsiIndex::write_index_external(&map, &config, BufWriter::new(File::create(index)?))
+}
+
+/// The index's parameters, then a line per gap table.
+fn stats(idx: &CsiIndex, out: &mut impl Write) -> io::Result<()> {
+    let gaps: Vec<String> = idx.gaps().iter().map(|d| d.to_string()).collect();
+    let stats = idx.stats();
+    writeln!(out, "text bytes   {}", idx.text().len())?;
+    writeln!(out, "k            {}", idx.k())?;
+    writeln!(out, "gaps         {}", gaps.join(" "))?;
+    writeln!(out, "min pattern  {}", idx.min_pattern_len())?;
+    writeln!(out, "postings     {}", stats.total_postings)?;
+    writeln!(out, "heap bytes   {}", stats.heap_bytes)?;
+    writeln!(out, "{:>6} {:>10} {:>6} {:>9} {:>9} {:>9} {:>10}", "gap", "slots", "load", "max probe", "largest", "saturated", "postings")?;
+    for g in &stats.per_gap {
+        writeln!(out, "{:>6} {:>10} {:>6.3} {:>9} {:>9} {:>9} {:>10}",
+                 g.gap, g.table_size, g.load_factor, g.max_probe, g.largest_bucket, g.saturated_buckets, g.postings)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+    use std::fs;
+
+    use csi::CsiIndex;
+
+    use super::run;
+
+    /// `run(args)` and what it printed.
+    fn csi(args: &[&str]) -> (Result<bool, String>, String) {
+        let args: Vec<OsString> = args.iter().map(OsString::from).collect();
+        let mut out = Vec::new();
+        let r = run(&args, &mut out);
+        (r, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn build_search_and_stats_round_trip() {
+        let dir = std::env::temp_dir().join(format!("csi-cli-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("access.log");
+        let text: Vec<u8> = (0..5000).flat_map(|i| format!("{} GET /item/{} 200\n", i % 97, i % 11).into_bytes()).collect();
+        fs::write(&file, &text).unwrap();
+        let (file, index) = (file.to_str().unwrap(), dir.join("access.idx"));
+        let index = index.to_str().unwrap();
+
+        assert_eq!(csi(&["build", file, "-o", index, "--max-memory", "65536"]), (Ok(true), String::new()));
+        let want = CsiIndex::build(&text);
+        for pat in ["GET /item/7 200\n4", "/item/3 200", "not in the log"] {
+            let lines: String = want.search(pat.as_bytes()).iter().map(|o| format!("{o}\n")).collect();
+            assert_eq!(csi(&["search", index, pat]), (Ok(!lines.is_empty()), lines));
+            let count = want.count(pat.as_bytes());
+            assert_eq!(csi(&["search", "--count", index, pat]), (Ok(count > 0), format!("{count}\n")));
+        }
+        let (r, out) = csi(&["stats", index]);
+        assert_eq!(r, Ok(true));
+        assert!(out.starts_with(&format!("text bytes   {}\nk            {}\n", text.len(), want.k())));
+        assert_eq!(out.lines().count(), 7 + want.gaps().len());
+
+        // the default output path sits beside the input
+        assert_eq!(csi(&["build", file]).0, Ok(true));
+        assert_eq!(fs::read(format!("{file}.csi")).unwrap(), fs::read(index).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bad_invocations_explain_themselves() {
+        assert!(csi(&[]).0.unwrap_err().starts_with("usage: "));
+        assert!(csi(&["search", "only-an-index"]).0.unwrap_err().starts_with("usage: "));
+        assert_eq!(csi(&["build", "x", "--max-memory", "lots"]).0, Err("--max-memory needs a byte count".into()));
+        let err = csi(&["stats", "/nonexistent/index.csi"]).0.unwrap_err();
+        assert!(err.starts_with("/nonexistent/index.csi: "), "{err}");
+    }
+}
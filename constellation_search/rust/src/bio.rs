@@ -0,0 +1,121 @@
+// src/bio.rs
+//! Nucleotide search (feature `bio`): a builder preset for DNA and RNA and
+//! search on both strands.
+//!
+//! `for_nucleotides` folds the text onto a four‐letter alphabet (bases in
+//! either case, `U` as `T`) and fixes `k` and the gaps for its two bits of
+//! entropy per byte, which the default tiers misjudge. The text is still
+//! stored a byte per base, since verification, sidecars and mapped indexes
+//! read it as bytes; the alphabet only decides which bytes those are.
+//! `search_revcomp` looks a pattern up along with its reverse complement
+//! and tags each hit with the strand it was found on.
+
+use alloc::vec::Vec;
+
+use crate::{ByteMap, CaseFold, CsiIndex, CsiIndexBuilder, Hasher};
+
+/// Which strand a `search_revcomp` hit is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Strand {
+    /// The pattern itself starts at the offset
+    Forward,
+    /// Its reverse complement does, so the pattern runs backwards on the
+    /// other strand from the end of the hit
+    Reverse,
+}
+
+/// `pat` read backwards with every base complemented: `A`↔`T` (`A` to `U`
+/// if `pat` is RNA, holding a `U`), `C`↔`G`, and the IUPAC codes for two
+/// or three bases likewise, keeping case; other bytes (`N`, `S`, `W`, gaps)
+/// are their own complement.
+pub fn reverse_complement(pat: &[u8]) -> Vec<u8> {
+    let rna = pat.iter().any(|&b| b.eq_ignore_ascii_case(&b'U'));
+    pat.iter().rev().map(|&b| {
+        let to = match b.to_ascii_uppercase() {
+            b'A' if rna => b'U',
+            b'A' => b'T',
+            b'T' | b'U' => b'A',
+            b'C' => b'G',
+            b'G' => b'C',
+            b'R' => b'Y',
+            b'Y' => b'R',
+            b'K' => b'M',
+            b'M' => b'K',
+            b'B' => b'V',
+            b'V' => b'B',
+            b'D' => b'H',
+            b'H' => b'D',
+            _ => return b,
+        };
+        if b.is_ascii_lowercase() { to.to_ascii_lowercase() } else { to }
+    }).collect()
+}
+
+impl CsiIndexBuilder {
+    /// Preset for DNA and RNA: letters upper‐cased and `U` read as `T` (a
+    /// `CaseFold::Map`, so patterns match either case and either kind of
+    /// sequence), and `k = 8` with gaps `[1, 4, 8]` whatever the entropy,
+    /// so patterns of 16 bases and up are indexable. Bytes that aren't
+    /// letters, such as line breaks in a FASTA body, are kept.
+    pub fn for_nucleotides() -> Self {
+        let mut table: [u8; 256] = core::array::from_fn(|b| (b as u8).to_ascii_uppercase());
+        table[b'U' as usize] = b'T';
+        table[b'u' as usize] = b'T';
+        Self::new().params(8, vec![1, 4, 8]).case_fold(CaseFold::Map(ByteMap::new(table)))
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Matches of `pat` on both strands, ascending by offset and forward
+    /// first where both occur: `search(pat)` as `Forward` hits and
+    /// `search(&reverse_complement(pat))` as `Reverse` ones, both at the
+    /// offset where the bytes start in the text. A pattern that is its own
+    /// reverse complement (a palindromic site such as `GAATTC`) is
+    /// reported on both strands.
+    pub fn search_revcomp(&self, pat: &[u8]) -> Vec<(usize, Strand)> {
+        let fwd = self.search(pat).into_iter().map(|off| (off, Strand::Forward));
+        let rev = self.search(&reverse_complement(pat)).into_iter().map(|off| (off, Strand::Reverse));
+        let mut hits: Vec<_> = fwd.chain(rev).collect();
+        hits.sort_unstable();
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reverse_complement, Strand};
+    use crate::CsiIndexBuilder;
+
+    #[test]
+    fn both_strands_are_searched_in_either_case_and_as_rna() {
+        let mut x = 0x9e3779b97f4a7c15u64;
+        let mut text: Vec<u8> = (0..5000).map(|_| {
+            x ^= x << 13; x ^= x >> 7; x ^= x << 17;
+            b"ACGT"[(x >> 62) as usize]
+        }).collect();
+        let site = b"GATTACAGGCTTAACCGATG";
+        let rc = reverse_complement(site);
+        assert_eq!(rc, b"CATCGGTTAAGCCTGTAATC");
+        assert_eq!(reverse_complement(b"gauuaca"), b"uguaauc");
+        assert_eq!(reverse_complement(&rc), site);
+        text[700..720].copy_from_slice(site);
+        text[2300..2320].copy_from_slice(&rc);
+        text[4100..4120].copy_from_slice(&site.to_ascii_lowercase());
+        let palindrome = b"GAATTCGGATCCGAATTC";
+        assert_eq!(reverse_complement(palindrome), palindrome);
+        text[3000..3018].copy_from_slice(palindrome);
+
+        let idx = CsiIndexBuilder::for_nucleotides().build(&text);
+        assert!(site.len() >= idx.min_pattern_len());
+        let naive = |pat: &[u8]| (0..=text.len() - pat.len()).filter(|&o| text[o..o + pat.len()].eq_ignore_ascii_case(pat)).collect::<Vec<_>>();
+        let mut want: Vec<_> = naive(site).into_iter().map(|o| (o, Strand::Forward))
+            .chain(naive(&rc).into_iter().map(|o| (o, Strand::Reverse))).collect();
+        want.sort_unstable();
+        assert!(want.contains(&(4100, Strand::Forward)) && want.contains(&(2300, Strand::Reverse)));
+        assert_eq!(idx.search_revcomp(site), want);
+        assert_eq!(idx.search_revcomp(b"GAUUACAGGCUUAACCGAUG"), want);
+        assert_eq!(idx.search_revcomp(&rc).iter().map(|&(o, s)| (o, s == Strand::Forward)).collect::<Vec<_>>(),
+            want.iter().map(|&(o, s)| (o, s == Strand::Reverse)).collect::<Vec<_>>());
+        assert_eq!(idx.search_revcomp(palindrome), [(3000, Strand::Forward), (3000, Strand::Reverse)]);
+    }
+}
@@ -0,0 +1,156 @@
+// src/boolean.rs
+//! Boolean queries: patterns combined with AND, OR, NOT and proximity.
+//!
+//! A query selects whole lines of the indexed text, the unit logs are
+//! triaged in: a literal selects the lines its matches start in, and the
+//! combinators merge those as sorted lists of line numbers. Each literal
+//! is searched once per appearance in the query; an `And` stops at the
+//! first subquery that leaves nothing.
+
+use alloc::{borrow::Cow, boxed::Box, vec::Vec};
+use core::ops::Range;
+
+use crate::newline::line_breaks;
+use crate::{CsiIndex, Hasher};
+
+/// A combination of patterns for `CsiIndex::query`, each node selecting
+/// a set of lines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    /// Lines in which a match of the pattern starts
+    Literal(Vec<u8>),
+    /// Lines every subquery selects; every line if there are none
+    And(Vec<Query>),
+    /// Lines any subquery selects; none if there are none
+    Or(Vec<Query>),
+    /// Lines the subquery doesn't select
+    Not(Box<Query>),
+    /// Lines holding a match of each pattern, in either order, whose starts
+    /// are at most the given number of bytes apart
+    Near(Vec<u8>, Vec<u8>, usize),
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// The lines `q` selects, ascending, as ranges of the indexed text
+    /// without their `\n`. A final `\n` ends the last line rather than
+    /// starting an empty one, so an empty text has no lines.
+    ///
+    /// Lines come from the line index if the index keeps one (see
+    /// `CsiConfig::track_lines`), else from a scan of the stored text, in
+    /// which a mask may have blanked line breaks.
+    pub fn query(&self, q: &Query) -> Vec<Range<usize>> {
+        let breaks: Cow<'_, [usize]> = match &self.lines {
+            Some(lines) => Cow::Borrowed(lines),
+            None => Cow::Owned(line_breaks(&self.text, 0).collect()),
+        };
+        let count = breaks.len() + (self.text_len > breaks.last().map_or(0, |&p| p + 1)) as usize;
+        self.query_lines(q, &breaks, count).into_iter().map(|l| {
+            let start = if l == 0 { 0 } else { breaks[l - 1] + 1 };
+            start..breaks.get(l).copied().unwrap_or(self.text_len)
+        }).collect()
+    }
+
+    /// Numbers of the lines `q` selects among the `count` that `breaks`
+    /// ends, ascending.
+    fn query_lines(&self, q: &Query, breaks: &[usize], count: usize) -> Vec<usize> {
+        let line = |off: usize| breaks.partition_point(|&p| p < off);
+        let lines = |offs: Vec<usize>| {
+            let mut lines: Vec<usize> = offs.into_iter().map(line).take_while(|&l| l < count).collect();
+            lines.dedup();
+            lines
+        };
+        match q {
+            Query::Literal(pat) => lines(self.search(pat)),
+            Query::And(qs) => {
+                let mut acc: Option<Vec<usize>> = None;
+                for q in qs {
+                    if acc.as_ref().is_some_and(Vec::is_empty) {
+                        break;
+                    }
+                    let next = self.query_lines(q, breaks, count);
+                    acc = Some(match acc {
+                        None => next,
+                        Some(mut acc) => {
+                            let mut j = 0;
+                            acc.retain(|&l| {
+                                j += next[j..].partition_point(|&m| m < l);
+                                next.get(j) == Some(&l)
+                            });
+                            acc
+                        }
+                    });
+                }
+                acc.unwrap_or_else(|| (0..count).collect())
+            }
+            Query::Or(qs) => {
+                let mut all: Vec<usize> = qs.iter().flat_map(|q| self.query_lines(q, breaks, count)).collect();
+                all.sort_unstable();
+                all.dedup();
+                all
+            }
+            Query::Not(q) => {
+                let hit = self.query_lines(q, breaks, count);
+                let mut j = 0;
+                (0..count).filter(|&l| {
+                    let skip = hit.get(j) == Some(&l);
+                    j += skip as usize;
+                    !skip
+                }).collect()
+            }
+            Query::Near(a, b, window) => {
+                let bs = self.search(b);
+                let near = self.search(a).into_iter().filter(|&oa| {
+                    // the closest matches of `b` on either side; any others
+                    // are further off, or on other lines if these are
+                    let i = bs.partition_point(|&ob| ob < oa);
+                    [i.wrapping_sub(1), i].iter().filter_map(|&i| bs.get(i))
+                        .any(|&ob| ob.abs_diff(oa) <= *window && line(ob) == line(oa))
+                }).collect();
+                lines(near)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Query;
+    use crate::{CsiIndex, CsiIndexBuilder};
+
+    #[test]
+    fn queries_select_the_lines_a_line_by_line_filter_does() {
+        let text: Vec<u8> = (0..600).flat_map(|i| {
+            let level = ["INFO", "WARN", "ERROR"][i * 7 % 3];
+            let pad = " ".repeat(i % 40);
+            format!("{i:04} {level} disk{pad} quota host-{};\n", i % 11).into_bytes()
+        }).collect();
+        let lit = |p: &str| Query::Literal(p.as_bytes().to_vec());
+        let find = |line: &[u8], p: &str| line.windows(p.len()).enumerate().filter(|(_, w)| *w == p.as_bytes()).map(|(i, _)| i).collect::<Vec<_>>();
+        type Filter = Box<dyn Fn(&[u8]) -> bool>;
+        let cases: Vec<(Query, Filter)> = vec![
+            (Query::And(vec![lit("ERROR disk"), lit("host-3;")]),
+                Box::new(move |l| !find(l, "ERROR disk").is_empty() && !find(l, "host-3;").is_empty())),
+            (Query::Or(vec![lit("WARN disk"), lit("host-10;"), lit("no such line")]),
+                Box::new(move |l| !find(l, "WARN disk").is_empty() || !find(l, "host-10;").is_empty())),
+            (Query::And(vec![lit("ERROR disk"), Query::Not(Box::new(lit("host-1")))]),
+                Box::new(move |l| !find(l, "ERROR disk").is_empty() && find(l, "host-1").is_empty())),
+            (Query::Near(b"ERROR disk".to_vec(), b"quota host".to_vec(), 20),
+                Box::new(move |l| find(l, "ERROR disk").iter().any(|&a| find(l, "quota host").iter().any(|&b| a.abs_diff(b) <= 20)))),
+            (Query::And(Vec::new()), Box::new(|_| true)),
+            (Query::Or(Vec::new()), Box::new(|_| false)),
+        ];
+        let plain = CsiIndex::build(&text);
+        let tracked = CsiIndexBuilder::new().track_lines(true).build(&text);
+        for (q, keep) in &cases {
+            let mut start = 0;
+            let want: Vec<_> = text.split(|&b| b == b'\n').map(|l| {
+                start += l.len() + 1;
+                (start - l.len() - 1)..(start - 1)
+            }).filter(|r| r.start < text.len() && keep(&text[r.clone()])).collect();
+            assert_eq!(plain.query(q), want, "{q:?}");
+            assert_eq!(tracked.query(q), want, "{q:?}");
+        }
+        assert!(CsiIndex::build(b"").query(&Query::And(Vec::new())).is_empty());
+        assert_eq!(CsiIndex::build(b"a\n\nb").query(&Query::Not(Box::new(lit("b")))), [0..1, 2..2]);
+    }
+}
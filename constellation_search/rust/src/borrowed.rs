@@ -0,0 +1,84 @@
+// src/borrowed.rs
+//! Index over caller‐owned text, verifying against the borrowed bytes
+//! instead of a private copy.
+
+use alloc::vec::Vec;
+
+use crate::{CsiConfig, CsiIndex, CsiIndexError, PolyHasher, Spare};
+
+/// Gap tables plus the text they were built over, borrowed from the caller;
+/// see `CsiIndex::build_borrowed`.
+///
+/// Searches verify against the borrowed slice the way an owned index
+/// verifies against its copy, and the borrow keeps the bytes from changing
+/// while the index is alive. The C API keeps owned indexes only, since a
+/// handle can't carry the lifetime.
+pub struct BorrowedIndex<'a> {
+    index: CsiIndex, // built over `text`; its own text is left empty
+    text:  &'a [u8],
+}
+
+impl CsiIndex {
+    /// Build tables over `data` without copying it: the prefix hashes and
+    /// tables are built as usual, but the text stays in the caller's memory
+    /// (a mapping, an arena, a larger buffer) and the index borrows it.
+    ///
+    /// Configs that fold, normalize or mask the text have to store the
+    /// rewritten copy, so they fail with `CsiIndexError::RewritesText`;
+    /// other build errors are as for `try_build_with_config`.
+    pub fn build_borrowed<'a>(data: &'a [u8], config: &CsiConfig) -> Result<BorrowedIndex<'a>, CsiIndexError> {
+        if config.rewrites_text() {
+            return Err(CsiIndexError::RewritesText);
+        }
+        let index = CsiIndex::build_phased(data.into(), config, PolyHasher, None, None, Spare::default())?;
+        Ok(BorrowedIndex { index, text: data })
+    }
+}
+
+impl<'a> BorrowedIndex<'a> {
+    /// The borrowed text, which searches verify against.
+    pub fn text(&self) -> &'a [u8] {
+        self.text
+    }
+
+    /// Sorted offsets of every exact occurrence of `pat`, as
+    /// `CsiIndex::search` would report them over an owned copy.
+    pub fn search(&self, pat: &[u8]) -> Vec<usize> {
+        self.index.search_over(pat, self.text)
+    }
+
+    /// See `CsiIndex::k`.
+    pub fn k(&self) -> usize {
+        self.index.k()
+    }
+
+    /// See `CsiIndex::gaps`.
+    pub fn gaps(&self) -> &[usize] {
+        self.index.gaps()
+    }
+
+    /// Heap bytes held by the tables; the borrowed text is not counted.
+    pub fn heap_bytes(&self) -> usize {
+        self.index.heap_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CaseFold, CsiConfig, CsiIndex, CsiIndexError};
+
+    #[test]
+    fn borrowed_text_searches_like_an_owned_copy() {
+        let text = b"borrow the bytes, hash the bytes, verify against the bytes; borrow".repeat(25);
+        let owned = CsiIndex::build(&text);
+        let borrowed = CsiIndex::build_borrowed(&text, &CsiConfig::default()).unwrap();
+        assert_eq!((borrowed.text(), borrowed.k(), borrowed.gaps()), (&text[..], owned.k(), owned.gaps()));
+        for pat in [&text[..50], &text[17..90], b"verify against the bytes; borrow", b"bytes", b"", b"never lent out"] {
+            assert_eq!(borrowed.search(pat), owned.search(pat));
+        }
+        assert_eq!(borrowed.heap_bytes() + text.len(), owned.heap_bytes());
+
+        let folding = CsiConfig { case_fold: CaseFold::Ascii, ..CsiConfig::default() };
+        assert_eq!(CsiIndex::build_borrowed(&text, &folding).err(), Some(CsiIndexError::RewritesText));
+    }
+}
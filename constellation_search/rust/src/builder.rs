@@ -0,0 +1,338 @@
+// src/builder.rs
+//! Chainable front end over `CsiConfig`.
+
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+
+use crate::{ByteMask, CaseFold, CsiConfig, CsiIndex, CsiIndexError, EntropyTiers, Hasher, PolyHasher, Spare};
+
+/// Builder for a `CsiIndex`; each setter mirrors a `CsiConfig` field
+#[derive(Debug, Clone, Default)]
+pub struct CsiIndexBuilder {
+    config: CsiConfig,
+    pub(crate) docs: Vec<(usize, Vec<u8>)>, // queued by `add_document`
+    pub(crate) stream: Stream,              // fed by `feed`
+}
+
+/// Text streamed in so far, with its prefix hashes (`ph[i]` over the first
+/// `i` bytes, once anything is fed)
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Stream {
+    pub(crate) text: Vec<u8>,
+    ph:              Vec<u64>,
+}
+
+impl CsiIndexBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Preset for scanning one file for many short byte signatures:
+    /// `k = 3` and gaps `[2, 4, 6]` whatever the entropy, so patterns of
+    /// 8 bytes and up are indexable (the default tiers need 12 to 20, and
+    /// answer shorter patterns with a linear scan).
+    ///
+    /// Short anchors are far less selective, so tables hold more distinct
+    /// keys per byte of text, posting lists are longer and more candidates
+    /// reach verify; expect several times the memory of a default build on
+    /// low‐entropy input.
+    pub fn for_signatures() -> Self {
+        Self::new().params(3, vec![2, 4, 6])
+    }
+
+    /// See `CsiConfig::max_postings_per_key`.
+    pub fn max_postings_per_key(mut self, cap: usize) -> Self {
+        self.config.max_postings_per_key = Some(cap);
+        self
+    }
+
+    /// See `CsiConfig::threads`.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.config.threads = threads;
+        self
+    }
+
+    /// See `CsiConfig::max_gaps`.
+    pub fn max_gaps(mut self, keep: usize) -> Self {
+        self.config.max_gaps = Some(keep);
+        self
+    }
+
+    /// See `CsiConfig::tiers`.
+    pub fn tiers(mut self, tiers: EntropyTiers) -> Self {
+        self.config.tiers = tiers;
+        self
+    }
+
+    /// Use `k` and `gaps` whatever the data's entropy; see
+    /// `EntropyTiers::fixed`, which panics on `k == 0` or no gaps.
+    pub fn params(self, k: usize, gaps: Vec<usize>) -> Self {
+        self.tiers(EntropyTiers::fixed(k, gaps))
+    }
+
+    /// See `CsiConfig::case_fold`.
+    pub fn case_fold(mut self, fold: CaseFold) -> Self {
+        self.config.case_fold = fold;
+        self
+    }
+
+    /// See `CsiConfig::normalize_newlines`.
+    pub fn normalize_newlines(mut self, on: bool) -> Self {
+        self.config.normalize_newlines = on;
+        self
+    }
+
+    /// See `CsiConfig::track_lines`.
+    pub fn track_lines(mut self, on: bool) -> Self {
+        self.config.track_lines = on;
+        self
+    }
+
+    /// See `CsiConfig::mask`.
+    pub fn mask(mut self, mask: ByteMask) -> Self {
+        self.config.mask = Some(mask);
+        self
+    }
+
+    /// See `CsiConfig::keep_powers`; overrides the automatic choice.
+    pub fn keep_powers(mut self, keep: bool) -> Self {
+        self.config.keep_powers = Some(keep);
+        self
+    }
+
+    /// See `CsiConfig::stride`.
+    pub fn stride(mut self, stride: usize) -> Self {
+        self.config.stride = stride;
+        self
+    }
+
+    /// See `CsiConfig::syncmer_window`.
+    pub fn syncmer_window(mut self, w: usize) -> Self {
+        self.config.syncmer_window = Some(w);
+        self
+    }
+
+    /// See `CsiConfig::dedup`.
+    pub fn dedup(mut self, on: bool) -> Self {
+        self.config.dedup = on;
+        self
+    }
+
+    /// See `CsiConfig::load_factor`.
+    pub fn load_factor(mut self, load: f64) -> Self {
+        self.config.load_factor = Some(load);
+        self
+    }
+
+    /// See `CsiConfig::table_sizes`.
+    pub fn table_sizes(mut self, sizes: Vec<usize>) -> Self {
+        self.config.table_sizes = Some(sizes);
+        self
+    }
+
+    /// See `CsiConfig::fingerprints`.
+    pub fn fingerprints(mut self, keep: bool) -> Self {
+        self.config.fingerprints = keep;
+        self
+    }
+
+    /// See `CsiConfig::pack_postings`.
+    pub fn pack_postings(mut self, threshold: usize) -> Self {
+        self.config.pack_postings = Some(threshold);
+        self
+    }
+
+    /// See `CsiConfig::max_memory_bytes`.
+    pub fn max_memory_bytes(mut self, bytes: usize) -> Self {
+        self.config.max_memory_bytes = Some(bytes);
+        self
+    }
+
+    /// See `CsiConfig::wide_slots`.
+    pub fn wide_slots(mut self, wide: bool) -> Self {
+        self.config.wide_slots = wide;
+        self
+    }
+
+    /// See `CsiConfig::scan_short_patterns`.
+    pub fn scan_short_patterns(mut self, on: bool) -> Self {
+        self.config.scan_short_patterns = on;
+        self
+    }
+
+    /// Whether searches byte‐compare candidates (default true). Turning it
+    /// off makes `search` return unverified candidates that may contain hash
+    /// collisions; only do so when the caller re‐verifies or tolerates them.
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.config.verify = verify;
+        self
+    }
+
+    /// The options accumulated so far.
+    pub fn config(&self) -> &CsiConfig {
+        &self.config
+    }
+
+    pub fn build(&self, data: &[u8]) -> CsiIndex {
+        CsiIndex::build_with_config(data, &self.config)
+    }
+
+    /// See `CsiIndex::try_build_with_config`.
+    pub fn try_build(&self, data: &[u8]) -> Result<CsiIndex, CsiIndexError> {
+        CsiIndex::try_build_with_config(data, &self.config)
+    }
+
+    /// See `CsiIndex::build_from_slices`.
+    pub fn build_from_slices(&self, slices: &[&[u8]]) -> CsiIndex {
+        CsiIndex::build_from_slices(slices, &self.config)
+    }
+
+    /// Queue a copy of `bytes` as document `id` for `build_documents`. IDs
+    /// are the caller's: they needn't be distinct, ordered or dense.
+    pub fn add_document(&mut self, id: usize, bytes: &[u8]) -> &mut Self {
+        self.docs.push((id, bytes.to_vec()));
+        self
+    }
+
+    /// Build over the documents queued so far, in the order they were
+    /// added; see `CsiIndex::build_from_documents`. The queue is kept.
+    pub fn build_documents(&self) -> CsiIndex {
+        let docs: Vec<_> = self.docs.iter().map(|(id, d)| (*id, &d[..])).collect();
+        CsiIndex::build_from_documents(&docs, &self.config)
+    }
+
+    /// Append `chunk` to the text being streamed in for `finish`, hashing
+    /// it on arrival: the rolling prefix hash carries over from the chunk
+    /// before, so boundaries may fall anywhere and `finish` only has the
+    /// tables left to build. The chunk is copied; the index keeps its text.
+    pub fn feed(&mut self, chunk: &[u8]) -> &mut Self {
+        let Stream { text, ph } = &mut self.stream;
+        if ph.is_empty() {
+            ph.push(0);
+        }
+        ph.reserve(chunk.len());
+        for &b in chunk {
+            ph.push(PolyHasher.extend(ph[ph.len() - 1], b));
+        }
+        text.extend_from_slice(chunk);
+        self
+    }
+
+    /// `feed` everything `r` yields until end of input, 64 KiB at a time.
+    #[cfg(feature = "std")]
+    pub fn feed_reader<R: Read>(&mut self, mut r: R) -> io::Result<&mut Self> {
+        let mut buf = vec![0u8; 1 << 16];
+        loop {
+            match r.read(&mut buf) {
+                Ok(0) => return Ok(self),
+                Ok(n) => { self.feed(&buf[..n]); }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Build over the bytes fed so far, as `build` would over them in one
+    /// buffer, and empty the stream for the next text. Case folding,
+    /// newline normalization and masks rewrite the text, so under those
+    /// the hashing is redone here.
+    pub fn finish(&mut self) -> CsiIndex {
+        let Stream { text, ph } = core::mem::take(&mut self.stream);
+        let spare = Spare { ph, ..Spare::default() };
+        CsiIndex::build_phased(text.into(), &self.config, PolyHasher, None, None, spare).unwrap_or_else(|e| panic!("{e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CsiIndexBuilder;
+
+    #[test]
+    fn streamed_chunks_build_what_one_buffer_does() {
+        let text: Vec<u8> = (0..600).flat_map(|i| format!("Line {i:03}: status=OK\r\n").into_bytes()).collect();
+        let builders = [
+            CsiIndexBuilder::new(),
+            CsiIndexBuilder::new().stride(2).fingerprints(true),
+            CsiIndexBuilder::new().normalize_newlines(true).keep_powers(true),
+        ];
+        for mut builder in builders {
+            let whole = builder.build(&text);
+            for size in [1, 7, 4096, text.len()] {
+                for chunk in text.chunks(size) {
+                    builder.feed(chunk);
+                }
+                let streamed = builder.finish();
+                assert_eq!((streamed.text(), streamed.k(), streamed.gaps()), (whole.text(), whole.k(), whole.gaps()));
+                assert_eq!(streamed.validate(), Ok(()));
+                for at in (0..text.len() - 40).step_by(331) {
+                    let pat = &text[at..at + 40];
+                    assert_eq!(streamed.search(pat), whole.search(pat));
+                }
+            }
+            assert!(builder.stream.text.is_empty());
+            let streamed = builder.feed_reader(&text[..]).unwrap().finish();
+            assert_eq!(streamed.search(b"Line 123: status"), whole.search(b"Line 123: status"));
+        }
+    }
+
+    #[test]
+    fn signature_preset_indexes_short_patterns() {
+        let mut text = b"MZ\x90\x00\x03".repeat(50);
+        text.extend_from_slice(b"\xde\xad\xbe\xef\x13\x37\xc0\xde");
+        text.extend(b"PE\x00\x00".repeat(50));
+        let sig = b"\xde\xad\xbe\xef\x13\x37\xc0\xde";
+        let idx = CsiIndexBuilder::for_signatures().build(&text);
+        assert_eq!(idx.search(sig), [250]);
+        let default = crate::CsiIndex::build(&text);
+        assert!(sig.len() < default.min_pattern_len());
+        assert_eq!(default.search(sig), [250]);
+    }
+
+    #[test]
+    fn fixed_params_override_the_entropy_tiers() {
+        let dna: Vec<u8> = (0u32..3000).map(|i| b"ACGT"[(i.wrapping_mul(2654435761) >> 29) as usize % 4]).collect();
+        let default = crate::CsiIndex::build(&dna);
+        let tuned = CsiIndexBuilder::new().params(8, vec![1, 4, 8]).build(&dna);
+        assert_eq!((tuned.k(), tuned.gaps()), (8, &[1, 4, 8][..]));
+        assert!(tuned.min_pattern_len() < default.min_pattern_len());
+        for at in (0..dna.len() - 24).step_by(211) {
+            let pat = &dna[at..at + 24];
+            assert_eq!(tuned.search(pat), default.search(pat));
+        }
+    }
+
+    #[test]
+    fn single_gap_index_matches_naive() {
+        let text = b"one table is enough when verification is exact; one table is enough!".repeat(4);
+        let idx = CsiIndexBuilder::new().max_gaps(1).build(&text);
+        assert_eq!(idx.gaps.len(), 1);
+        assert_eq!(idx.flat.len(), 1);
+        for (a, b) in [(0, 30), (10, 60), (5, 140), (69, 100)] {
+            let pat = &text[a..b];
+            let naive: Vec<usize> = (0..=text.len() - pat.len())
+                .filter(|&i| &text[i..i + pat.len()] == pat)
+                .collect();
+            assert_eq!(idx.search(pat), naive);
+        }
+    }
+
+    #[test]
+    fn unverified_returns_superset_of_verified() {
+        let text = b"candidate generation is exact on anchors, candidate generation is exact".repeat(2);
+        let checked = CsiIndexBuilder::new().build(&text);
+        let trusted = CsiIndexBuilder::new().verify(false).build(&text);
+        let pat = &text[..40];
+        let exact = checked.search(pat);
+        assert!(!exact.is_empty());
+        assert_eq!(trusted.search(pat), checked.search_unverified(pat));
+        assert!(exact.iter().all(|o| trusted.search(pat).contains(o)));
+
+        // anchors intact, middle bytes changed: only verification rejects it
+        let mut near = pat.to_vec();
+        near[checked.k] ^= 0x20;
+        assert!(checked.search(&near).is_empty());
+        assert!(!checked.search_unverified(&near).is_empty());
+        assert!(!trusted.search(&near).is_empty());
+    }
+}
@@ -0,0 +1,131 @@
+// src/cancel.rs
+//! Searches that stop part way: on a cancellation flag, or (feature
+//! `async`) by yielding to the executor so a long search shares its thread.
+//!
+//! Both walk `search_iter`'s candidates and look up every `BATCH` of them,
+//! verified or not, so a run of hash collisions can't hold them off.
+//! Masked and periodic patterns, and ones with packed posting lists, are
+//! intersected up front as in `search_iter`; only their verification is
+//! split.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{verify, CsiIndex, CsiIndexError, Hasher};
+
+/// Candidates between two looks at the flag or yields
+const BATCH: usize = 4096;
+
+impl<H: Hasher> CsiIndex<H> {
+    /// `search`, or `CsiIndexError::Cancelled` once `cancel` is found set.
+    ///
+    /// The flag is read before the first candidate and after every 4096, so
+    /// another thread storing `true` stops the search within a batch.
+    pub fn search_cancellable(&self, pat: &[u8], cancel: &AtomicBool) -> Result<Vec<usize>, CsiIndexError> {
+        let pat = self.normalize(pat);
+        let (cands, verified) = self.candidate_iter(&pat);
+        let mut out = Vec::new();
+        for (i, off) in cands.enumerate() {
+            if i % BATCH == 0 && cancel.load(Ordering::Relaxed) {
+                return Err(CsiIndexError::Cancelled);
+            }
+            if verified || self.verify_by(off, &pat, &verify::same) {
+                out.push(off);
+            }
+        }
+        Ok(out)
+    }
+
+    /// `search` as a future that returns `Pending` once every 4096
+    /// candidates, waking itself first, so any executor polls other tasks
+    /// in between. Dropping the future abandons the search.
+    #[cfg(feature = "async")]
+    pub async fn search_async(&self, pat: &[u8]) -> Vec<usize> {
+        let pat = self.normalize(pat);
+        let (cands, verified) = self.candidate_iter(&pat);
+        let mut out = Vec::new();
+        for (i, off) in cands.enumerate() {
+            if i > 0 && i % BATCH == 0 {
+                YieldNow(false).await;
+            }
+            if verified || self.verify_by(off, &pat, &verify::same) {
+                out.push(off);
+            }
+        }
+        out
+    }
+}
+
+/// Pending on the first poll, after waking the task, and ready on the next
+#[cfg(feature = "async")]
+struct YieldNow(bool);
+
+#[cfg(feature = "async")]
+impl core::future::Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: core::pin::Pin<&mut Self>, cx: &mut core::task::Context<'_>) -> core::task::Poll<()> {
+        if self.0 {
+            return core::task::Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        core::task::Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::{CsiIndex, CsiIndexBuilder, CsiIndexError, EntropyTiers};
+
+    fn log() -> Vec<u8> {
+        (0..12_000).flat_map(|i| format!("{} GET /item/{} 200\n", i % 97, i % 11).into_bytes()).collect()
+    }
+
+    #[test]
+    fn cancellable_search_matches_search_until_the_flag_is_set() {
+        let text = log();
+        let idx = CsiIndexBuilder::new().tiers(EntropyTiers::fixed(4, vec![4, 8])).build(&text);
+        let cancel = AtomicBool::new(false);
+        for pat in [&b"GET /item/3 200"[..], b"200\n5", b"GET", b"/item/12 "] {
+            assert_eq!(idx.search_cancellable(pat, &cancel), Ok(idx.search(pat)));
+        }
+        cancel.store(true, Ordering::Relaxed);
+        assert_eq!(idx.search_cancellable(b"GET /item/3 200", &cancel), Err(CsiIndexError::Cancelled));
+        // answered without a candidate to look at the flag for
+        assert_eq!(CsiIndex::build(b"short").search_cancellable(b"long pattern", &cancel), Ok(Vec::new()));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_search_yields_between_batches() {
+        use core::future::Future;
+        use core::pin::pin;
+        use core::task::{Context, Poll, Waker};
+
+        /// The future's output and how many polls it took.
+        fn run<F: Future>(f: F) -> (F::Output, usize) {
+            let mut cx = Context::from_waker(Waker::noop());
+            let mut f = pin!(f);
+            let mut polls = 1;
+            loop {
+                if let Poll::Ready(out) = f.as_mut().poll(&mut cx) {
+                    return (out, polls);
+                }
+                polls += 1;
+            }
+        }
+        let text = log();
+        let idx = CsiIndexBuilder::new().tiers(EntropyTiers::fixed(4, vec![4, 8])).build(&text);
+        let pat = b"GET /item/";
+        let want = idx.search(pat);
+        let (got, polls) = run(idx.search_async(pat));
+        assert_eq!(got, want);
+        // one yield per full batch, collisions included
+        assert!(polls > 1 && polls > (want.len() - 1) / 4096);
+        let (got, polls) = run(idx.search_async(b"not in the log"));
+        assert_eq!((got, polls), (Vec::new(), 1));
+    }
+}
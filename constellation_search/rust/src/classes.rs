@@ -0,0 +1,228 @@
+// src/classes.rs
+//! Search with a set of allowed bytes at each pattern position.
+//!
+//! Candidates come from one constellation window per stride phase. A window
+//! whose anchor bytes are all single‐byte classes is an exact lookup; failing
+//! that, a window's anchors are expanded into every byte combination (up to
+//! `MAX_EXPANSION` of them) and the posting lists of all combinations are
+//! merged. Per phase the window with the fewest postings is kept. If every
+//! window needs more combinations, or hits a saturated bucket, every position
+//! is verified instead.
+
+use alloc::vec::Vec;
+
+use crate::array::List;
+use crate::{key_at, prefix_hash, CsiIndex, Hasher, Probe};
+
+/// Most anchor‐byte combinations expanded for one window
+const MAX_EXPANSION: usize = 64;
+
+/// Bitset of allowed byte values
+type ByteSet = [u64; 4];
+
+/// Constellation at pattern offset `j` and gap `d`, with the number of
+/// byte combinations its anchors expand to
+struct Window {
+    j:      usize,
+    gap:    usize, // index into `gaps`
+    combos: usize,
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Offsets `off`, ascending, where `text[off + i]` is one of
+    /// `classes[i]` for every `i`: degenerate patterns such as IUPAC `R`
+    /// (`b"AG"`) or a character class per position. Single‐byte classes
+    /// behave as in `search`; an empty class matches nothing.
+    ///
+    /// Classes are compared with the indexed text as stored (see `text()`),
+    /// without case folding or newline normalization, and candidates are
+    /// always verified. Dense classes cost: a window of `2k` anchor bytes
+    /// expands into the product of its class sizes, and when no window fits
+    /// in 64 combinations the search scans the whole text. Masked indexes
+    /// always scan. Patterns shorter than `min_pattern_len` have no window
+    /// and match nothing, as with `search`.
+    pub fn search_classes(&self, classes: &[&[u8]]) -> Vec<usize> {
+        let (m, n) = (classes.len(), self.text.len());
+        if m == 0 {
+            return (0..=n).collect();
+        }
+        if m > n || m < self.min_pattern_len() || classes.iter().any(|c| c.is_empty()) {
+            return Vec::new();
+        }
+        let sets: Vec<ByteSet> = classes.iter().map(|c| byte_set(c)).collect();
+        let fits = |&off: &usize| {
+            off + m <= n && self.live(off, m) && self.text[off..off + m].iter().zip(&sets)
+                .all(|(&b, set)| set[(b >> 6) as usize] >> (b & 63) & 1 == 1)
+        };
+        match self.class_candidates(classes) {
+            Some(acc) => acc.into_iter().filter(fits).collect(),
+            None => (0..=n - m).filter(fits).collect(),
+        }
+    }
+
+    /// Sorted candidate starts for `classes`, or `None` if some phase has no
+    /// usable window and every position must be checked.
+    fn class_candidates(&self, classes: &[&[u8]]) -> Option<Vec<usize>> {
+        // a class pattern has no syncmers of its own to look up at
+        if self.mask.is_some() || self.window > 1 {
+            return None;
+        }
+        let (k, m) = (self.k, classes.len());
+        let mut acc = Vec::new();
+        for phase in 0..self.stride {
+            let mut windows: Vec<Window> = Vec::new();
+            for j in (phase..m).step_by(self.stride) {
+                for (gap, &d) in self.gaps.iter().enumerate().filter(|&(_, &d)| j + d + k <= m) {
+                    let combos = anchors(k, d).try_fold(1usize, |c, p| {
+                        c.checked_mul(classes[j + p].len()).filter(|&c| c <= MAX_EXPANSION)
+                    });
+                    if let Some(combos) = combos {
+                        windows.push(Window { j, gap, combos });
+                    }
+                }
+            }
+            // exact windows are one probe each; only expand when there are none
+            let fewest = windows.iter().map(|w| w.combos).min()?;
+            let mut best: Option<(usize, &Window, Vec<List>)> = None;
+            for w in windows.iter().filter(|w| fewest > 1 || w.combos == 1) {
+                let Some(lists) = self.window_lists(classes, w) else { continue };
+                let total = lists.iter().map(|l| l.len()).sum();
+                if best.as_ref().is_none_or(|b| total < b.0) {
+                    best = Some((total, w, lists));
+                }
+                if total == 0 {
+                    break;
+                }
+            }
+            let (_, w, lists) = best?;
+            acc.extend(lists.into_iter().flat_map(List::iter).filter_map(|o| o.checked_sub(w.j)));
+        }
+        acc.sort_unstable();
+        acc.dedup();
+        Some(acc)
+    }
+
+    /// Posting lists of every byte combination of window `w`'s anchors
+    /// (absent ones contribute nothing), or `None` if any is saturated or
+    /// packed.
+    fn window_lists<'a>(&'a self, classes: &[&[u8]], w: &Window) -> Option<Vec<List<'a>>> {
+        let (k, d, pk) = (self.k, self.gaps[w.gap], self.pw[self.k]);
+        let mut buf = vec![0u8; d + k];
+        let pos: Vec<usize> = anchors(k, d).collect();
+        for &p in &pos {
+            buf[p] = classes[w.j + p][0];
+        }
+        let varying: Vec<usize> = pos.into_iter().filter(|&p| classes[w.j + p].len() > 1).collect();
+        let mut digit = vec![0usize; varying.len()];
+        let mut lists = Vec::new();
+        loop {
+            let key = key_at(&self.hasher, &prefix_hash(&self.hasher, &buf), pk, 0, k, d);
+            match self.flat[w.gap].lookup(key) {
+                Probe::Missing => {}
+                Probe::Saturated | Probe::Packed(_) => return None,
+                Probe::List(l) => lists.push(l),
+            }
+            // next combination, like an odometer over the varying anchors
+            let mut i = 0;
+            loop {
+                let Some(&p) = varying.get(i) else { return Some(lists) };
+                let class = classes[w.j + p];
+                digit[i] += 1;
+                if digit[i] < class.len() {
+                    buf[p] = class[digit[i]];
+                    break;
+                }
+                digit[i] = 0;
+                buf[p] = class[0];
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Window offsets of the two anchors of a gap‐`d` constellation, each once
+/// even when they overlap.
+fn anchors(k: usize, d: usize) -> impl Iterator<Item = usize> {
+    (0..k).chain(d.max(k)..d + k)
+}
+
+fn byte_set(class: &[u8]) -> ByteSet {
+    let mut set = [0u64; 4];
+    for &b in class {
+        set[(b >> 6) as usize] |= 1 << (b & 63);
+    }
+    set
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CsiConfig, CsiIndex};
+
+    /// IUPAC nucleotide codes used below.
+    fn iupac(code: u8) -> &'static [u8] {
+        match code {
+            b'R' => b"AG",
+            b'Y' => b"CT",
+            b'W' => b"AT",
+            b'N' => b"ACGT",
+            b'A' => b"A",
+            b'C' => b"C",
+            b'G' => b"G",
+            _ => b"T",
+        }
+    }
+
+    fn naive(text: &[u8], classes: &[&[u8]]) -> Vec<usize> {
+        (0..=text.len() - classes.len())
+            .filter(|&o| classes.iter().enumerate().all(|(i, c)| c.contains(&text[o + i])))
+            .collect()
+    }
+
+    #[test]
+    fn degenerate_dna_patterns_match_naive() {
+        let mut x = 0x2545f4914f6cdd1du64;
+        let mut text: Vec<u8> = (0..4000).map(|_| {
+            x ^= x << 13; x ^= x >> 7; x ^= x << 17;
+            b"ACGT"[(x >> 62) as usize]
+        }).collect();
+        let site = b"GAATTCAGGACCTTGAACGT".repeat(2);
+        for at in [100, 1500, 3100] {
+            text[at..at + site.len()].copy_from_slice(&site);
+        }
+        text[1500 + 3] = b'A';
+        let idx = CsiIndex::build(&text);
+        let strided = CsiIndex::build_with_config(&text, &CsiConfig { stride: 3, ..CsiConfig::default() });
+
+        let mut sparse = site.clone();
+        sparse[3] = b'W';
+        sparse[26] = b'R';
+        // every anchor holds a class: expanded windows, then a full scan
+        let every = |step: usize| -> Vec<u8> {
+            let mut code = site.clone();
+            for i in (2..code.len()).step_by(step) {
+                code[i] = b'N';
+            }
+            code
+        };
+        let (dense, denser) = (every(5), every(2));
+        let patterns = [site.clone(), sparse, dense.clone(), dense[..24].to_vec(), denser];
+        for code in &patterns {
+            let classes: Vec<&[u8]> = code.iter().map(|&c| iupac(c)).collect();
+            let want = naive(&text, &classes);
+            assert_eq!(idx.search_classes(&classes), want);
+            assert_eq!(strided.search_classes(&classes), want);
+        }
+        let classes = |code: &[u8]| -> Vec<&[u8]> { code.iter().map(|&c| iupac(c)).collect() };
+        assert!(idx.class_candidates(&classes(&dense)).is_some());
+        assert!(idx.class_candidates(&classes(&patterns[4])).is_none());
+        let exact: Vec<&[u8]> = site.chunks(1).collect();
+        assert_eq!(idx.search_classes(&exact), idx.search(&site));
+        assert_eq!(idx.search_classes(&exact), [100, 3100]);
+        assert_eq!(idx.search_classes(&classes(&patterns[1])), [100, 1500, 3100]);
+
+        let mut empty: Vec<&[u8]> = exact.clone();
+        empty[7] = b"";
+        assert!(idx.search_classes(&empty).is_empty());
+        assert_eq!(idx.search_classes(&[]).len(), text.len() + 1);
+    }
+}
@@ -0,0 +1,186 @@
+// src/corpus.rs
+//! Document‐aware search over indexes built from several source slices.
+
+use alloc::vec::Vec;
+
+use crate::{CsiConfig, CsiIndex, Hasher};
+
+/// Source slices behind each stored slice of a deduplicated build, grouped
+/// by stored slice: slice `s`'s are `docs[starts[s]..starts[s + 1]]`, in
+/// ascending order. Empty when every source slice is stored once.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Aliases {
+    pub(crate) starts: Vec<usize>,
+    pub(crate) docs:   Vec<usize>,
+}
+
+impl Aliases {
+    /// Group source slices by `slot_of[doc]`, their stored slice, of which
+    /// there are `slots`.
+    pub(crate) fn group(slot_of: &[usize], slots: usize) -> Self {
+        let mut starts = vec![0usize; slots + 1];
+        for &s in slot_of {
+            starts[s + 1] += 1;
+        }
+        for s in 0..slots {
+            starts[s + 1] += starts[s];
+        }
+        let mut fill = starts.clone();
+        let mut docs = vec![0usize; slot_of.len()];
+        for (doc, &s) in slot_of.iter().enumerate() {
+            docs[fill[s]] = doc;
+            fill[s] += 1;
+        }
+        Aliases { starts, docs }
+    }
+
+    /// Source slices stored as slice `slot`, or `None` if there are no
+    /// duplicates (slot and source slice then coincide).
+    pub(crate) fn of(&self, slot: usize) -> Option<&[usize]> {
+        let r = self.starts.get(slot..slot + 2)?;
+        Some(&self.docs[r[0]..r[1]])
+    }
+
+    /// Forget the stored slices from `slots` on.
+    pub(crate) fn truncate(&mut self, slots: usize) {
+        if slots + 1 < self.starts.len() {
+            self.starts.truncate(slots + 1);
+            let kept = self.starts[slots];
+            // docs are grouped by slot, so dropping slots drops a suffix
+            self.docs.truncate(kept);
+        }
+    }
+
+    pub(crate) fn heap_bytes(&self) -> usize {
+        (self.starts.capacity() + self.docs.capacity()) * 8
+    }
+}
+
+impl CsiIndex {
+    /// `build_from_slices` over the `(id, bytes)` documents, with
+    /// `search_docs` reporting each match under its document's `id` rather
+    /// than the document's position in `docs`.
+    pub fn build_from_documents(docs: &[(usize, &[u8])], config: &CsiConfig) -> Self {
+        let slices: Vec<&[u8]> = docs.iter().map(|d| d.1).collect();
+        let mut idx = Self::build_from_slices(&slices, config);
+        idx.ids = docs.iter().map(|d| d.0).collect();
+        idx
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// ID of source slice `doc` (as `locate` numbers them): the one given
+    /// to `build_from_documents`, or `doc` itself for other builds.
+    pub fn doc_id(&self, doc: usize) -> usize {
+        self.ids.get(doc).copied().unwrap_or(doc)
+    }
+
+    /// Matches as `(document ID, offset within document)`, dropping any
+    /// that straddle the join between two slices. Documents are numbered
+    /// by slice index unless built by `build_from_documents`; either way
+    /// matches come in document order, then by offset.
+    ///
+    /// Boundaries are enforced at verify time rather than by inserting a
+    /// separator byte, so the indexed text stays exactly the concatenation.
+    /// No byte value is reserved: any pattern is well‐formed, and an empty
+    /// result always means no single document contains it. Indexes built
+    /// from one buffer behave as a single document.
+    ///
+    /// Under `CsiConfig::dedup`, a match in a stored slice is reported for
+    /// each of its duplicates, sorted by document then offset, exactly as
+    /// a build without dedup would report them.
+    pub fn search_docs(&self, pat: &[u8]) -> Vec<(usize, usize)> {
+        let pat_len = self.normalize(pat).len();
+        let hits = self.search(pat)
+            .into_iter()
+            .map(|off| self.locate_stored(off))
+            .filter(|&(slot, local)| local + pat_len <= self.doc_len(slot));
+        let mut out: Vec<(usize, usize)> = if self.aliases.of(0).is_none() {
+            hits.collect()
+        } else {
+            let mut out: Vec<_> = hits
+                .flat_map(|(slot, local)| self.aliases.of(slot).unwrap_or(&[]).iter().map(move |&doc| (doc, local)))
+                .collect();
+            out.sort_unstable();
+            out
+        };
+        if !self.ids.is_empty() {
+            for m in &mut out {
+                m.0 = self.ids[m.0];
+            }
+        }
+        out
+    }
+
+    /// Length of stored slice `slot` (the whole text for single‐buffer builds).
+    fn doc_len(&self, slot: usize) -> usize {
+        let start = self.bounds.get(slot).copied().unwrap_or(0);
+        let end = self.bounds.get(slot + 1).copied().unwrap_or(self.text.len());
+        end - start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CsiConfig, CsiIndex, CsiIndexBuilder};
+
+    #[test]
+    fn straddling_matches_are_rejected() {
+        let docs: [&[u8]; 3] = [
+            b"the first document ends with a tail",
+            b"the second document starts here and names a tail",
+            b"a third one: the first document ends with a tail",
+        ];
+        let idx = CsiIndex::build_from_slices(&docs, &CsiConfig::default());
+        let inside = b"the first document ends with a tail";
+        assert_eq!(idx.search_docs(inside), vec![(0, 0), (2, 13)]);
+
+        let across = b"ends with a tailthe second document";
+        assert_eq!(idx.search(across).len(), 1);
+        assert!(idx.search_docs(across).is_empty());
+    }
+
+    #[test]
+    fn duplicate_documents_are_stored_once() {
+        let readme: &[u8] = b"the same license text shipped in every vendored package";
+        let docs: [&[u8]; 6] = [
+            readme,
+            b"a package with its own notes, and the same license text shipped in every vendored package",
+            readme,
+            b"the same license text shipped in every vendored packagE",
+            readme,
+            b"",
+        ];
+        let config = CsiConfig { dedup: true, ..CsiConfig::default() };
+        let dedup = CsiIndex::build_from_slices(&docs, &config);
+        let plain = CsiIndex::build_from_slices(&docs, &CsiConfig::default());
+        assert_eq!(dedup.text().len(), plain.text().len() - 2 * readme.len());
+        assert!(dedup.heap_bytes() < plain.heap_bytes());
+
+        let pat = b"same license text shipped in every";
+        assert_eq!(dedup.search_docs(pat), [(0, 4), (1, 38), (2, 4), (3, 4), (4, 4)]);
+        assert_eq!(dedup.search_docs(pat), plain.search_docs(pat));
+        assert_eq!(dedup.search(pat).len(), 3);
+        assert_eq!(dedup.locate(dedup.search(pat)[2]), (3, 4));
+        assert_eq!(dedup.search_docs(b"in every vendored package"), plain.search_docs(b"in every vendored package"));
+    }
+
+    #[test]
+    fn documents_are_reported_under_their_ids() {
+        let note: &[u8] = b"the changelog entry repeated in two release notes";
+        let mut builder = CsiIndexBuilder::new().dedup(true);
+        builder.add_document(907, b"first the readme, then ").add_document(12, note)
+            .add_document(40, b"a manual that quotes: the changelog entry repeated in two release notes")
+            .add_document(3, note);
+        let idx = builder.build_documents();
+        let pat = b"changelog entry repeated in two";
+        assert_eq!(idx.search_docs(pat), [(12, 4), (40, 26), (3, 4)]);
+        assert!(idx.search_docs(b"readme, then the changelog").is_empty());
+        assert_eq!(idx.doc_id(idx.locate(idx.search(pat)[0]).0), 12);
+
+        let mut file = Vec::new();
+        idx.write_index(&mut file).unwrap();
+        let loaded = CsiIndex::read_index(&file[..], crate::PolyHasher).unwrap();
+        assert_eq!(loaded.search_docs(pat), idx.search_docs(pat));
+    }
+}
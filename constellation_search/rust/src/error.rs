@@ -0,0 +1,63 @@
+// src/error.rs
+//! Errors from fallible index construction and checked searches.
+
+use alloc::string::String;
+use core::fmt;
+
+/// Why a fallible build or checked search returned no result
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CsiIndexError {
+    /// the progress callback or a cancellation flag asked to stop
+    Cancelled,
+    /// caller‐supplied text doesn't hash to the text the index was built over
+    TextMismatch,
+    /// a budgeted build can't fit even one gap table; `needed` is the
+    /// smallest budget that would
+    OverBudget { needed: usize },
+    /// a compiled batch was hashed for an index with another `k`, hasher,
+    /// case folding or newline handling
+    IncompatibleBatch,
+    /// `CsiConfig::table_sizes` gives no size for `gap`, or one that isn't
+    /// a power of two of at least `min` slots
+    TableSize { gap: usize, min: usize },
+    /// the config folds, normalizes or masks the text, so an index over
+    /// borrowed or mapped bytes would have nothing to verify against
+    RewritesText,
+    /// `merge` was given no indexes, or indexes built with different
+    /// options, gaps or hasher seeds, or without texts of their own
+    IncompatibleParts,
+    /// `search_regex` was given a pattern that doesn't compile; the
+    /// parser's message
+    Regex(String),
+    /// `gap` is so wide that the `k + gap + k` bytes a pattern needs to use
+    /// it can't be counted in an `isize`
+    GapTooWide { k: usize, gap: usize },
+    /// `CsiConfig::load_factor` is in `(0, 1)` but below the 0.01 floor,
+    /// a hundred slots per constellation
+    LoadFactor,
+}
+
+impl fmt::Display for CsiIndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsiIndexError::Cancelled => write!(f, "cancelled"),
+            CsiIndexError::TextMismatch => write!(f, "text differs from the indexed text"),
+            CsiIndexError::OverBudget { needed } =>
+                write!(f, "index needs at least {needed} bytes"),
+            CsiIndexError::IncompatibleBatch =>
+                write!(f, "batch was compiled for an incompatible index"),
+            CsiIndexError::TableSize { gap, min } =>
+                write!(f, "gap {gap} needs a power-of-two table of at least {min} slots"),
+            CsiIndexError::RewritesText =>
+                write!(f, "a borrowed or mapped index can't fold, normalize or mask the text"),
+            CsiIndexError::IncompatibleParts =>
+                write!(f, "indexes differ in their build options or hold no text to merge"),
+            CsiIndexError::Regex(msg) => write!(f, "invalid regex: {msg}"),
+            CsiIndexError::GapTooWide { k, gap } =>
+                write!(f, "gap {gap} with k = {k} is wider than any text"),
+            CsiIndexError::LoadFactor => write!(f, "load factor is below 0.01"),
+        }
+    }
+}
+
+impl core::error::Error for CsiIndexError {}
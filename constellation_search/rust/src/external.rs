@@ -0,0 +1,473 @@
+// src/external.rs
+//! Whole‐index files written without building the index in memory, for
+//! texts whose tables don't fit (see `CsiIndex::write_index_external`).
+//!
+//! Tables are written one at a time. A first pass over the text counts the
+//! postings of each key into the table's slots, as `fill` does, which fixes
+//! where every bucket lands in the file; a second pass emits a record per
+//! posting, its place in the file and offset, sorting them into runs at
+//! most `CsiConfig::max_memory_bytes` long and spilling each to a temporary
+//! file, and a k‐way merge of the runs streams the postings out in place
+//! order. No prefix hashes are kept: keys are hashed a chunk of text at a
+//! time, from stride‐aligned starts, as `append` does; syncmers only depend
+//! on the bytes after them, so chunks pick the ones a build does.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::array::{Array, Slots};
+use crate::hash::{fingerprint, prefix_hash, window_const};
+use crate::packed::Packer;
+use crate::sidecar::{put, put_halves, put_table_head, put_table_tail, put_words};
+use crate::{
+    corpus, home_slot, newline, next_slot, plan, CsiConfig, CsiIndex, CsiIndexError, FlatIndex, Hasher,
+    Map, PolyHasher, TableParams, MAX_PROBE, SATURATED,
+};
+
+/// Constellation starts hashed per chunk (rounded up to the stride)
+const CHUNK: usize = 1 << 16;
+
+/// A posting on its way to the file: its place among the table's postings
+/// (kept ones first, then packed lists in bucket order), offset and
+/// fingerprint
+type Rec = (usize, usize, u16);
+
+/// Bytes of a `Rec` in a run file
+const REC_BYTES: usize = 18;
+
+/// Runs merged into one once this many have been merged equally often
+const FAN_IN: usize = 16;
+
+/// Run files created by this process so far, for unique names
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+impl CsiIndex {
+    /// Write the whole index `build_with_config(data, config)` would write
+    /// with `write_index`, byte for byte, without building it: for texts
+    /// whose tables don't fit in memory beside them. The file opens with
+    /// `read_index` or `open_mmap`.
+    ///
+    /// Tables are written one after another. Each table's slot arrays are
+    /// held while it is written (about 32 bytes a slot), plus the packed
+    /// lists and the text's line breaks if configured; postings are sorted
+    /// in runs of `config.max_memory_bytes` and those spilled to
+    /// `std::env::temp_dir`, which must have room for 18 bytes a posting of
+    /// the largest table. The files are removed before returning. Neither
+    /// prefix hashes nor hash powers are held, and `data` is only read, so
+    /// it can be a mapped file.
+    ///
+    /// Configs that rewrite the text (see `build_from_file`) or whose table
+    /// sizes don't fit are refused with `InvalidInput`. Errors from `w` or
+    /// the temporary files pass through.
+    pub fn write_index_external<W: Write>(data: &[u8], config: &CsiConfig, mut w: W) -> io::Result<()> {
+        if config.rewrites_text() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, CsiIndexError::RewritesText));
+        }
+        let (gaps, p, tables) = plan(data, config).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let (h, n) = (PolyHasher, data.len());
+        let shell = CsiIndex {
+            k: p.k,
+            gaps,
+            flat: Vec::new(),
+            text: Array::default(),
+            pw: Vec::new(),
+            bounds: Vec::new(),
+            aliases: corpus::Aliases::default(),
+            ids: Vec::new(),
+            cap: p.cap,
+            stride: p.stride,
+            window: p.window,
+            verify: config.verify,
+            fold: config.case_fold,
+            newlines: false,
+            crlf: Vec::new(),
+            lines: config.track_lines.then(|| newline::line_breaks(data, 0).collect()),
+            dead: Vec::new(),
+            mask: None,
+            digest: data.iter().fold(0, |ph, &b| h.extend(ph, b)),
+            text_len: n,
+            load: config.load_factor,
+            fixed: config.table_sizes.is_some(),
+            fps: config.fingerprints,
+            scan_short: config.scan_short_patterns,
+            wide: config.wide_slots,
+            pack: p.pack,
+            hasher: h,
+        };
+        shell.write_head(&mut w, true)?;
+        let run = config.max_memory_bytes.map_or(usize::MAX, |b| (b / size_of::<Rec>()).max(1));
+        for (d, ts) in tables {
+            if ts.is_power_of_two() {
+                write_table::<_, _, true>(&mut w, data, &h, p, d, ts, run)?;
+            } else {
+                write_table::<_, _, false>(&mut w, data, &h, p, d, ts, run)?;
+            }
+        }
+        // the powers a build keeps: B^0..=B^k, or one per text byte as well
+        let len = if config.keep_powers.unwrap_or(false) { n.max(p.k) + 1 } else { p.k + 1 };
+        let pw = core::iter::successors(Some(1), |&c| Some(h.step(c))).take(len);
+        shell.write_tail(&mut w, data, pw, len)?;
+        w.flush()
+    }
+}
+
+/// `f(i, key, fp)` for every indexed start `i` at gap `d`, ascending, with
+/// keys as stored and `fp` its span fingerprint if `fps` (else 0).
+fn postings<H: Hasher>(data: &[u8], h: &H, p: TableParams, d: usize, fps: bool, mut f: impl FnMut(usize, u64, u16)) {
+    let span = p.k + d;
+    let m = p.starts(data.len(), d);
+    let (pk, c) = (window_const(h, p.k), window_const(h, span));
+    let chunk = CHUNK.next_multiple_of(p.stride);
+    for base in (0..m).step_by(chunk) {
+        let len = chunk.min(m - base);
+        let ph = prefix_hash(h, &data[base..base + len - 1 + span.max(p.reach())]);
+        p.each_key(h, &ph, pk, d, len, |i, key| {
+            let fp = if fps { fingerprint(h.window(ph[i + span], ph[i], c)) } else { 0 };
+            f(base + i, key, fp);
+        });
+    }
+}
+
+/// Write the gap‐`d` table of `ts` slots as `write_index` does the one
+/// `fill` builds, probing as `home_slot::<POW2>` does.
+fn write_table<W: Write, H: Hasher, const POW2: bool>(
+    w: &mut W, data: &[u8], h: &H, p: TableParams, d: usize, ts: usize, run: usize,
+) -> io::Result<()> {
+    // Pass 1: count per key, as `fill` does
+    let mut keys = vec![0u64; ts];
+    let mut counts = vec![0usize; ts];
+    let mut spill: Map<u64, usize> = Map::new();
+    postings(data, h, p, d, false, |_, key, _| {
+        let mut slot = home_slot::<POW2>(key, ts);
+        for _ in 0..MAX_PROBE {
+            if keys[slot] == 0 {
+                (keys[slot], counts[slot]) = (key, 1);
+                return;
+            }
+            if keys[slot] == key {
+                counts[slot] += 1;
+                return;
+            }
+            slot = next_slot::<POW2>(slot, ts);
+        }
+        *spill.entry(key).or_insert(0) += 1;
+    });
+    let mut overflow: Vec<(u64, usize, usize)> = spill.into_iter().map(|(key, c)| (key, 0, c)).collect();
+    overflow.sort_unstable_by_key(|e| e.0);
+
+    // Lay the buckets out as `fill` then `pack` would, slots before
+    // overflow: saturated ones take no space, and packed ones are numbered
+    // after every kept posting. `counts` become the slots' next places.
+    let bucket = |key: u64, c: usize| key != 0 && c <= p.cap;
+    let packs = |key: u64, c: usize| bucket(key, c) && c > p.pack;
+    let total = keys.iter().zip(&counts).chain(overflow.iter().map(|(key, _, c)| (key, c)))
+        .filter(|&(&key, &c)| bucket(key, c)).map(|(_, &c)| c).sum::<usize>();
+    let packed_total = keys.iter().zip(&counts).chain(overflow.iter().map(|(key, _, c)| (key, c)))
+        .filter(|&(&key, &c)| packs(key, c)).map(|(_, &c)| c).sum::<usize>();
+    // widths as `Slots::new` picks them before packing, over the starts as
+    // `fill` lays them out, which packing only lowers
+    let mut sum = 0;
+    let mut narrow = !p.wide;
+    let mut starts = vec![0usize; ts];
+    let mut lens = vec![0usize; ts];
+    let (mut kept, mut packed_at) = (0, total - packed_total);
+    let mut packed = Vec::new();
+    let mut place = |key: u64, c: usize| -> (usize, usize, usize) {
+        if !bucket(key, c) {
+            return (0, if key == 0 { 0 } else { SATURATED }, SATURATED);
+        }
+        narrow &= sum < u32::MAX as usize && c < u32::MAX as usize;
+        sum += c;
+        if packs(key, c) {
+            packed.push((key, c));
+            packed_at += c;
+            return (0, SATURATED, packed_at - c);
+        }
+        kept += c;
+        (kept - c, c, kept - c)
+    };
+    for slot in 0..ts {
+        (starts[slot], lens[slot], counts[slot]) = place(keys[slot], counts[slot]);
+    }
+    let mut next = Vec::with_capacity(overflow.len());
+    for e in &mut overflow {
+        let (start, len, at) = place(e.0, e.2);
+        (e.1, e.2) = (start, len);
+        next.push(at);
+    }
+    let mut fi = FlatIndex {
+        table_size: ts,
+        keys: keys.into(),
+        starts: Slots::new(starts, narrow),
+        lens: Slots::new(lens, narrow),
+        overflow,
+        ..FlatIndex::default()
+    };
+    put_table_head(w, &fi)?;
+
+    // Pass 2: a record per posting of an unsaturated bucket, noting whether
+    // they all fit in 32 bits, as `Offsets::new` sees them before packing
+    let mut runs = Runs { files: Vec::new(), buf: Vec::with_capacity(run.min(total)), run };
+    let mut narrow_postings = !p.wide;
+    let mut failed = Ok(());
+    postings(data, h, p, d, p.fps, |i, key, fp| {
+        if failed.is_err() {
+            return;
+        }
+        let mut slot = home_slot::<POW2>(key, ts);
+        let at = 'found: {
+            for _ in 0..MAX_PROBE {
+                if fi.keys[slot] == key {
+                    break 'found &mut counts[slot];
+                }
+                slot = next_slot::<POW2>(slot, ts);
+            }
+            match fi.overflow.binary_search_by_key(&key, |e| e.0) {
+                Ok(e) => &mut next[e],
+                Err(_) => return,
+            }
+        };
+        if *at != SATURATED {
+            narrow_postings &= i <= u32::MAX as usize;
+            failed = runs.push((*at, i, fp));
+            *at += 1;
+        }
+    });
+    failed?;
+    runs.buf.sort_unstable_by_key(|r| r.0);
+
+    // postings and fingerprints of the kept buckets, then the packed lists
+    let kept = total - packed_total;
+    let mut merge = runs.merged()?;
+    if narrow_postings {
+        put(w, 32)?;
+        put(w, kept as u64)?;
+        put_halves(w, merge.by_ref().take(kept).map(|r| r.1 as u32), kept)?;
+    } else {
+        put(w, 64)?;
+        put_words(w, merge.by_ref().take(kept).map(|r| r.1 as u64), kept)?;
+    }
+    if p.fps {
+        let mut fps = runs.merged()?;
+        let words = fps.by_ref().take(kept).map(|r| r.2).collect::<Vec<_>>();
+        fps.check()?;
+        let packs = words.chunks(4).map(|c| c.iter().rev().fold(0, |w, &f| w << 16 | f as u64));
+        put_words(w, packs, kept.div_ceil(4))?;
+    } else {
+        put_words(w, core::iter::empty(), 0)?;
+    }
+    for (key, c) in packed {
+        let mut list = Packer::with_capacity(c);
+        for r in merge.by_ref().take(c) {
+            list.push(r.1);
+        }
+        fi.packed.push((key, list.finish()));
+    }
+    merge.check()?;
+    fi.packed.sort_unstable_by_key(|e| e.0);
+    put_table_tail(w, &fi)
+}
+
+/// One table's records: sorted runs spilled to temporary files as the
+/// buffer fills, and the last one, which stays in memory in `buf`
+struct Runs {
+    files: Vec<Run>,
+    buf:   Vec<Rec>,
+    run:   usize,
+}
+
+impl Runs {
+    fn push(&mut self, r: Rec) -> io::Result<()> {
+        self.buf.push(r);
+        if self.buf.len() == self.run {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Sort the buffer and move it to a file of its own, merging the last
+    /// `FAN_IN` files into one whenever they were all merged as often, so
+    /// the final merge opens a few dozen files at most.
+    fn spill(&mut self) -> io::Result<()> {
+        self.buf.sort_unstable_by_key(|r| r.0);
+        let run = Run::create(self.buf.iter().copied(), 0)?;
+        self.files.push(run);
+        self.buf.clear();
+        while let Some(at) = self.files.len().checked_sub(FAN_IN)
+            && self.files[at..].iter().all(|r| r.level == self.files[at].level)
+        {
+            let tail = self.files.split_off(at);
+            let mut merge = Merge::new(&tail, &[])?;
+            let run = Run::create(merge.by_ref(), tail[0].level + 1)?;
+            merge.check()?;
+            self.files.push(run);
+        }
+        Ok(())
+    }
+
+    /// Every record in place order; `buf` must be sorted.
+    fn merged(&self) -> io::Result<Merge<'_>> {
+        Merge::new(&self.files, &self.buf)
+    }
+}
+
+/// A sorted run in a temporary file, removed on drop
+struct Run {
+    path:  PathBuf,
+    len:   usize,
+    level: u32, // how many merges its records went through
+}
+
+impl Run {
+    /// A run of `recs`, which must be sorted by place.
+    fn create(recs: impl Iterator<Item = Rec>, level: u32) -> io::Result<Run> {
+        let name = format!("csi-run-{}-{}", std::process::id(), RUNS.fetch_add(1, Ordering::Relaxed));
+        let path = std::env::temp_dir().join(name);
+        let file = File::create_new(&path)?;
+        // from here on a failed write still removes the file
+        let mut run = Run { path, len: 0, level };
+        let mut file = BufWriter::new(file);
+        for (at, off, fp) in recs {
+            file.write_all(&(at as u64).to_le_bytes())?;
+            file.write_all(&(off as u64).to_le_bytes())?;
+            file.write_all(&fp.to_le_bytes())?;
+            run.len += 1;
+        }
+        file.flush()?;
+        Ok(run)
+    }
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// A k‐way merge of sorted runs: the run files, then a sorted buffer as the
+/// last source. A read error ends it early and is kept for `check`.
+struct Merge<'a> {
+    heap:  BinaryHeap<Reverse<(usize, usize)>>, // (place, source) of each head
+    heads: Vec<Rec>,
+    files: Vec<(BufReader<File>, usize)>, // and records left
+    buf:   core::slice::Iter<'a, Rec>,
+    err:   Option<io::Error>,
+}
+
+impl<'a> Merge<'a> {
+    fn new(files: &[Run], buf: &'a [Rec]) -> io::Result<Self> {
+        let mut merge = Merge { heap: BinaryHeap::new(), heads: Vec::new(), files: Vec::new(), buf: buf.iter(), err: None };
+        for run in files {
+            merge.files.push((BufReader::new(File::open(&run.path)?), run.len));
+        }
+        for s in 0..=files.len() {
+            merge.heads.push((0, 0, 0));
+            merge.advance(s);
+        }
+        Ok(merge)
+    }
+
+    /// Replace source `s`'s head with its next record, if any.
+    fn advance(&mut self, s: usize) {
+        let next = match self.files.get_mut(s) {
+            None => self.buf.next().copied(),
+            Some((_, 0)) => None,
+            Some((file, left)) => {
+                *left -= 1;
+                let mut b = [0u8; REC_BYTES];
+                match file.read_exact(&mut b) {
+                    Ok(()) => {
+                        let word = |i: usize| u64::from_le_bytes(b[i..i + 8].try_into().unwrap()) as usize;
+                        Some((word(0), word(8), u16::from_le_bytes([b[16], b[17]])))
+                    }
+                    Err(e) => {
+                        self.err = Some(e);
+                        None
+                    }
+                }
+            }
+        };
+        if let Some(r) = next {
+            self.heads[s] = r;
+            self.heap.push(Reverse((r.0, s)));
+        }
+    }
+
+    /// The read error that ended the merge, if one did.
+    fn check(&mut self) -> io::Result<()> {
+        self.err.take().map_or(Ok(()), Err)
+    }
+}
+
+impl Iterator for Merge<'_> {
+    type Item = Rec;
+
+    fn next(&mut self) -> Option<Rec> {
+        if self.err.is_some() {
+            return None;
+        }
+        let Reverse((_, s)) = self.heap.pop()?;
+        let r = self.heads[s];
+        self.advance(s);
+        Some(r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{CsiConfig, CsiIndex, CsiIndexBuilder, CsiIndexError, EntropyTiers, PolyHasher};
+
+    fn log() -> Vec<u8> {
+        (0..3000).flat_map(|i| format!("{} GET /item/{} 200 {}\n", i % 31, i % 7, i * 7919 % 1000).into_bytes()).collect()
+    }
+
+    #[test]
+    fn external_builds_write_what_write_index_does() {
+        let text = log();
+        let tiers = EntropyTiers::fixed(4, vec![4, 8, 16]);
+        let builders = [
+            CsiIndexBuilder::new(),
+            CsiIndexBuilder::new().tiers(tiers.clone()).stride(2).fingerprints(true).pack_postings(40),
+            CsiIndexBuilder::new().tiers(tiers.clone()).max_postings_per_key(20).load_factor(0.7).track_lines(true),
+            CsiIndexBuilder::new().tiers(tiers.clone()).syncmer_window(8).fingerprints(true),
+            CsiIndexBuilder::new().tiers(tiers).table_sizes(vec![1 << 17, 1 << 18]).keep_powers(true).max_gaps(2),
+        ];
+        for builder in builders {
+            let mut want = Vec::new();
+            builder.build(&text).write_index(&mut want).unwrap();
+            // one run in memory, a few files, and enough to be merged twice
+            for budget in [None, Some(256 << 10), Some(2400)] {
+                let config = CsiConfig { max_memory_bytes: budget, ..builder.config().clone() };
+                let mut got = Vec::new();
+                CsiIndex::write_index_external(&text, &config, &mut got).unwrap();
+                assert!(got == want, "{budget:?}");
+            }
+            let idx = CsiIndex::read_index(&want[..], PolyHasher).unwrap();
+            assert_eq!(idx.search(b"GET /item/3 200 1"), builder.build(&text).search(b"GET /item/3 200 1"));
+        }
+        for text in [&b""[..], b"tiny"] {
+            let mut want = Vec::new();
+            CsiIndex::build(text).write_index(&mut want).unwrap();
+            let mut got = Vec::new();
+            CsiIndex::write_index_external(text, &CsiConfig::default(), &mut got).unwrap();
+            assert_eq!(got, want);
+        }
+    }
+
+    #[test]
+    fn external_builds_refuse_rewritten_texts() {
+        let config = CsiIndexBuilder::new().normalize_newlines(true).config().clone();
+        let err = CsiIndex::write_index_external(b"a\r\nb", &config, io::sink()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(err.into_inner().unwrap().downcast_ref(), Some(&CsiIndexError::RewritesText));
+        let config = CsiIndexBuilder::new().table_sizes(vec![16]).config().clone();
+        let err = CsiIndex::write_index_external(&log(), &config, io::sink()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
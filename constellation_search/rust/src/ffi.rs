@@ -0,0 +1,1789 @@
+// src/ffi.rs
+//! The C ABI over `CsiIndex` declared in `include/csi.h`: opaque handles,
+//! `csi_*` entry points, and the `#[repr(C)]` records they fill.
+//!
+//! Left out of wasm32 builds, which have no C callers to serve; see the
+//! `wasm` feature for browser bindings instead.
+
+// Every entry point dereferences the raw pointers C passes it once they pass
+// its NULL checks, trusting the rest (live handles, buffers of the stated
+// lengths) to `include/csi.h`'s contract. Declaring them `unsafe extern "C"`
+// would tell C callers nothing and only wrap every Rust call in `unsafe`.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use core::ffi::{c_char, c_uchar, c_void};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::slice;
+
+use crate::*;
+
+/// Opaque handle passed over FFI
+#[repr(C)]
+pub struct CSIHandle {
+    pub(crate) inner: Box<CsiIndex>,
+}
+
+/// Opaque handle to the match offsets of one `csi_search_begin`
+#[repr(C)]
+pub struct CSIResults {
+    pub(crate) offs: Vec<usize>,
+}
+
+/// Opaque handle queuing documents for one index, from `csi_builder_new`
+#[repr(C)]
+pub struct CSIBuilder {
+    pub(crate) inner: CsiIndexBuilder,
+}
+
+/// Opaque handle to the indexes of one `csi_set_new`
+#[repr(C)]
+pub struct CSIIndexSet {
+    pub(crate) inner: CsiIndexSet,
+}
+
+// the handles are shared between threads like the index they wrap
+const _: () = {
+    const fn shared<T: Send + Sync>() {}
+    shared::<CSIHandle>();
+    shared::<CSIResults>();
+    shared::<CSIIndexSet>();
+};
+
+/// `csi_abi_version` of this build: bumped whenever a `csi_*` signature or
+/// `#[repr(C)]` record changes incompatibly, so a binding built against
+/// another header can refuse to run instead of misreading memory
+pub const CSI_ABI_VERSION: u32 = 1;
+
+/// The library's version, `major.minor.patch`, as a NUL‐terminated static
+/// string.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+/// `CSI_ABI_VERSION`, for comparing with the one a caller was compiled
+/// against.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_abi_version() -> u32 {
+    CSI_ABI_VERSION
+}
+
+/// Why a `csi_*_ex` constructor returned NULL, through its `err` argument
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CsiErrorCode {
+    Ok = 0,
+    /// a pointer argument that must be set was NULL
+    NullArgument = 1,
+    /// there were no bytes to index
+    EmptyInput = 2,
+    /// `k` was 0 or no gaps were given
+    InvalidParams = 3,
+    /// the bytes aren't an index (wrong magic, version or hasher, malformed
+    /// or truncated)
+    InvalidIndex = 4,
+    /// a file couldn't be opened or read, or its path isn't UTF‐8
+    Io = 5,
+    /// the progress callback asked to stop
+    Cancelled = 6,
+}
+
+thread_local! {
+    // what `csi_last_error_message` returns: the last failure on this
+    // thread, or `None` once a later call succeeded
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+    // `csi_search`'s buffers and matches, kept so each search reuses them
+    // whichever index it's on
+    static SCRATCH: RefCell<(SearchScratch, Vec<usize>)> = RefCell::default();
+}
+
+/// Remember `msg` (or clear the last error on `None`) for this thread's
+/// `csi_last_error_message`.
+fn set_last_error(msg: Option<String>) {
+    let msg = msg.map(|m| CString::new(m.replace('\0', "")).unwrap_or_default());
+    LAST_ERROR.with(|last| *last.borrow_mut() = msg);
+}
+
+/// A handle for `res`'s index, or NULL; either way the outcome goes to
+/// `*err` (if not NULL) and `csi_last_error_message`.
+fn handle_or_error(res: Result<CsiIndex, (CsiErrorCode, String)>, err: *mut CsiErrorCode) -> *mut CSIHandle {
+    let (handle, code, msg) = match res {
+        Ok(idx) => (Box::into_raw(Box::new(CSIHandle { inner: Box::new(idx) })), CsiErrorCode::Ok, None),
+        Err((code, msg)) => (std::ptr::null_mut(), code, Some(msg)),
+    };
+    if !err.is_null() {
+        unsafe { *err = code }
+    }
+    set_last_error(msg);
+    handle
+}
+
+/// The `len` bytes at `data` to build over, or why there are none.
+fn input<'a>(data: *const c_uchar, len: usize) -> Result<&'a [u8], (CsiErrorCode, String)> {
+    match (data.is_null(), len) {
+        (true, _) => Err((CsiErrorCode::NullArgument, "data is NULL".into())),
+        (false, 0) => Err((CsiErrorCode::EmptyInput, "no bytes to index".into())),
+        (false, _) => Ok(unsafe { slice::from_raw_parts(data, len) }),
+    }
+}
+
+/// The message for the last call on this thread that failed, NUL‐terminated
+/// and valid until the thread's next call that sets or clears it; NULL if
+/// that call succeeded, or no call failed yet. Set by the constructors and
+/// `csi_search`, so a search returning 0 can be told from one given bad
+/// arguments.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(std::ptr::null(), |m| m.as_ptr()))
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_new(data: *const c_uchar, len: usize) -> *mut CSIHandle {
+    csi_new_ex(data, len, std::ptr::null_mut())
+}
+
+/// `csi_new`, writing why it returned NULL (or `Ok`) to `*err` unless
+/// `err` is NULL.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_new_ex(data: *const c_uchar, len: usize, err: *mut CsiErrorCode) -> *mut CSIHandle {
+    let res = input(data, len).map(|slice| {
+        let (k, gaps) = CsiConfig::default().tiers.choose(compute_entropy(slice));
+        CsiIndex::build_with(slice, k, &gaps).expect("the default tiers have k >= 1 and gaps")
+    });
+    handle_or_error(res, err)
+}
+
+/// Like `csi_new`, calling `cb(done, total, user)` as the build advances
+/// (see `CsiIndex::build_cancellable`) and returning NULL, with nothing
+/// kept, as soon as it returns non‐zero. A NULL `cb` builds silently.
+///
+/// `cb` runs synchronously on the calling thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_new_with_progress(
+    data: *const c_uchar,
+    len:  usize,
+    cb:   Option<extern "C" fn(usize, usize, *mut c_void) -> i32>,
+    user: *mut c_void,
+) -> *mut CSIHandle {
+    csi_new_with_progress_ex(data, len, cb, user, std::ptr::null_mut())
+}
+
+/// `csi_new_with_progress`, writing why it returned NULL (or `Ok`) to
+/// `*err` unless `err` is NULL.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_new_with_progress_ex(
+    data: *const c_uchar,
+    len:  usize,
+    cb:   Option<extern "C" fn(usize, usize, *mut c_void) -> i32>,
+    user: *mut c_void,
+    err:  *mut CsiErrorCode,
+) -> *mut CSIHandle {
+    let res = input(data, len).and_then(|slice| {
+        CsiIndex::build_cancellable(slice, &CsiConfig::default(), |done, total| cb.is_none_or(|cb| cb(done, total, user) == 0))
+            .map_err(|e| (CsiErrorCode::Cancelled, e.to_string()))
+    });
+    handle_or_error(res, err)
+}
+
+/// Like `csi_new`, but the index verifies against `data` itself instead of
+/// a copy, halving peak memory for a large buffer.
+///
+/// The caller keeps ownership: `data` must stay allocated and unmodified
+/// until `csi_free` on the returned handle, which never frees it. Changing
+/// the bytes first makes searches miss or report wrong matches; freeing
+/// them is a use‐after‐free. `csi_append` copies the text into the handle
+/// and ends the loan.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_new_borrowed(data: *const c_uchar, len: usize) -> *mut CSIHandle {
+    if data.is_null() || len == 0 { return std::ptr::null_mut() }
+    // SAFETY: the caller keeps `data` alive and unchanged until `csi_free`,
+    // which drops the index and the only copy of this reference
+    let slice: &'static [u8] = unsafe { slice::from_raw_parts(data, len) };
+    match CsiIndex::build_phased(slice.into(), &CsiConfig::default(), PolyHasher, None, None, Spare::default()) {
+        Ok(mut idx) => {
+            idx.text = Array::Lent(slice);
+            Box::into_raw(Box::new(CSIHandle { inner: Box::new(idx) }))
+        }
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Like `csi_new`, with `k` and the `gaps_len` gaps at `gaps` given
+/// instead of chosen from the data (see `CsiIndex::build_with`); NULL if
+/// `k` is 0, there are no gaps or one is too wide for any text.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_new_with_params(
+    data:     *const c_uchar,
+    len:      usize,
+    k:        usize,
+    gaps:     *const usize,
+    gaps_len: usize,
+) -> *mut CSIHandle {
+    csi_new_with_params_ex(data, len, k, gaps, gaps_len, std::ptr::null_mut())
+}
+
+/// `csi_new_with_params`, writing why it returned NULL (or `Ok`) to `*err`
+/// unless `err` is NULL.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_new_with_params_ex(
+    data:     *const c_uchar,
+    len:      usize,
+    k:        usize,
+    gaps:     *const usize,
+    gaps_len: usize,
+    err:      *mut CsiErrorCode,
+) -> *mut CSIHandle {
+    let res = input(data, len).and_then(|slice| {
+        if gaps.is_null() { return Err((CsiErrorCode::NullArgument, "gaps is NULL".into())) }
+        let gaps = unsafe { slice::from_raw_parts(gaps, gaps_len) };
+        CsiIndex::build_with(slice, k, gaps)
+            .ok_or_else(|| (CsiErrorCode::InvalidParams, "k must be at least 1, with at least one gap, none too wide for any text".into()))
+    });
+    handle_or_error(res, err)
+}
+
+/// Like `csi_new`, but builds the gap tables on `threads` threads when the
+/// library was compiled with the `rayon` feature (see `CsiConfig::threads`:
+/// 0 uses rayon's global pool, 1 is sequential; always sequential without
+/// the feature).
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_new_parallel(data: *const c_uchar, len: usize, threads: usize) -> *mut CSIHandle {
+    if data.is_null() || len == 0 { return std::ptr::null_mut() }
+    let slice = unsafe { slice::from_raw_parts(data, len) };
+    let idx = CsiIndex::build_with_config(slice, &CsiConfig { threads, ..CsiConfig::default() });
+    let handle = CSIHandle { inner: Box::new(idx) };
+    Box::into_raw(Box::new(handle))
+}
+
+/// Like `csi_new`, but ASCII case‐insensitive: the text is indexed with
+/// `A`–`Z` lowercased (`CaseFold::Ascii`) and every pattern is folded the
+/// same way, so searches match across ASCII case. Search it with
+/// `csi_search_ci` (or `csi_search`); it is not interchangeable with a
+/// `csi_new` handle, whose tables are keyed by the unfolded bytes.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_new_ci(data: *const c_uchar, len: usize) -> *mut CSIHandle {
+    if data.is_null() || len == 0 { return std::ptr::null_mut() }
+    let slice = unsafe { slice::from_raw_parts(data, len) };
+    match CsiIndex::try_build_with_config(slice, &CsiConfig { case_fold: CaseFold::Ascii, ..CsiConfig::default() }) {
+        Ok(idx) => Box::into_raw(Box::new(CSIHandle { inner: Box::new(idx) })),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Like `csi_new`, but text and patterns are rewritten through the
+/// 256‐entry table at `table` first (see `ByteMap`), so bytes it maps
+/// alike match each other; search it with `csi_search`. NULL on NULL
+/// arguments or a table that maps some byte to one it changes again.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_new_with_byte_map(data: *const c_uchar, len: usize, table: *const c_uchar) -> *mut CSIHandle {
+    if data.is_null() || len == 0 || table.is_null() { return std::ptr::null_mut() }
+    let slice = unsafe { slice::from_raw_parts(data, len) };
+    let Some(map) = ByteMap::checked(unsafe { *table.cast::<[u8; 256]>() }) else { return std::ptr::null_mut() };
+    match CsiIndex::try_build_with_config(slice, &CsiConfig { case_fold: CaseFold::Map(map), ..CsiConfig::default() }) {
+        Ok(idx) => Box::into_raw(Box::new(CSIHandle { inner: Box::new(idx) })),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_free(handle: *mut CSIHandle) {
+    if !handle.is_null() {
+        unsafe { let _ = Box::from_raw(handle); }
+    }
+}
+
+/// Serialize the index (see `CsiIndex::write_index`) into `out` if it fits
+/// in `out_cap` bytes, and return the size it needs either way, so a first
+/// call with `out_cap == 0` sizes the buffer. 0 on a NULL handle.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_serialize(handle: *const CSIHandle, out: *mut c_uchar, out_cap: usize) -> usize {
+    if handle.is_null() { return 0 }
+    let idx = unsafe { &*((*handle).inner) };
+    let mut bytes = Vec::new();
+    if idx.write_index(&mut bytes).is_err() { return 0 }
+    if !out.is_null() && bytes.len() <= out_cap {
+        unsafe { slice::from_raw_parts_mut(out, bytes.len()) }.copy_from_slice(&bytes);
+    }
+    bytes.len()
+}
+
+/// Handle for an index from `csi_serialize`, or NULL if `data` isn't one
+/// (wrong magic, version or hasher, malformed or truncated).
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_deserialize(data: *const c_uchar, len: usize) -> *mut CSIHandle {
+    csi_deserialize_ex(data, len, std::ptr::null_mut())
+}
+
+/// `csi_deserialize`, writing why it returned NULL (or `Ok`) to `*err`
+/// unless `err` is NULL.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_deserialize_ex(data: *const c_uchar, len: usize, err: *mut CsiErrorCode) -> *mut CSIHandle {
+    let res = match data.is_null() {
+        true => Err((CsiErrorCode::NullArgument, "data is NULL".into())),
+        false => CsiIndex::read_index(unsafe { slice::from_raw_parts(data, len) }, PolyHasher)
+            .map_err(|e| (CsiErrorCode::InvalidIndex, e.to_string())),
+    };
+    handle_or_error(res, err)
+}
+
+/// An empty document queue, freed by `csi_builder_free`.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_builder_new() -> *mut CSIBuilder {
+    Box::into_raw(Box::new(CSIBuilder { inner: CsiIndexBuilder::new() }))
+}
+
+/// Queue a copy of the `len` bytes at `data` as document `id` (see
+/// `CsiIndexBuilder::add_document`). `data` may be NULL only when `len` is
+/// 0; false on NULL arguments otherwise.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_builder_add_doc(builder: *mut CSIBuilder, id: usize, data: *const c_uchar, len: usize) -> bool {
+    if builder.is_null() || (data.is_null() && len > 0) { return false }
+    let bytes = if len == 0 { &[][..] } else { unsafe { slice::from_raw_parts(data, len) } };
+    unsafe { (*builder).inner.add_document(id, bytes) };
+    true
+}
+
+/// Index the queued documents, in the order they were added, for
+/// `csi_search_docs`; NULL if the builder is NULL or they hold no bytes.
+/// The builder keeps its queue, so more can be added and built again.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_builder_build(builder: *const CSIBuilder) -> *mut CSIHandle {
+    if builder.is_null() { return std::ptr::null_mut() }
+    let b = unsafe { &(*builder).inner };
+    if b.docs.iter().all(|(_, d)| d.is_empty()) { return std::ptr::null_mut() }
+    Box::into_raw(Box::new(CSIHandle { inner: Box::new(b.build_documents()) }))
+}
+
+/// Append a copy of the `len` bytes at `data` to the text being streamed
+/// in (see `CsiIndexBuilder::feed`). `data` may be NULL only when `len` is
+/// 0; false on NULL arguments otherwise.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_builder_feed(builder: *mut CSIBuilder, data: *const c_uchar, len: usize) -> bool {
+    if builder.is_null() || (data.is_null() && len > 0) { return false }
+    let bytes = if len == 0 { &[][..] } else { unsafe { slice::from_raw_parts(data, len) } };
+    unsafe { (*builder).inner.feed(bytes) };
+    true
+}
+
+/// Index the bytes fed so far as one text and empty the stream for the
+/// next; NULL if the builder is NULL or nothing was fed. Queued documents
+/// are left alone.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_builder_finish(builder: *mut CSIBuilder) -> *mut CSIHandle {
+    if builder.is_null() { return std::ptr::null_mut() }
+    let b = unsafe { &mut (*builder).inner };
+    if b.stream.text.is_empty() { return std::ptr::null_mut() }
+    Box::into_raw(Box::new(CSIHandle { inner: Box::new(b.finish()) }))
+}
+
+/// Free a builder and its queued documents; handles it built stay valid.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_builder_free(builder: *mut CSIBuilder) {
+    if !builder.is_null() {
+        unsafe { let _ = Box::from_raw(builder); }
+    }
+}
+
+/// Stream the index (as `csi_serialize` lays it out) into the file at
+/// `path`, a NUL‐terminated UTF‐8 path, creating or replacing it. False on
+/// NULL arguments, a non‐UTF‐8 path or any I/O error.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_save(handle: *const CSIHandle, path: *const c_char) -> bool {
+    if handle.is_null() { return false }
+    let idx = unsafe { &*((*handle).inner) };
+    let Some(path) = c_path(path) else { return false };
+    File::create(path).and_then(|f| {
+        let mut w = BufWriter::new(f);
+        idx.write_index(&mut w)?;
+        w.flush()
+    }).is_ok()
+}
+
+/// Handle for the index `csi_save` wrote to `path`, or NULL if it can't be
+/// read or isn't one (as for `csi_deserialize`).
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_load(path: *const c_char) -> *mut CSIHandle {
+    csi_load_ex(path, std::ptr::null_mut())
+}
+
+/// `csi_load`, writing why it returned NULL (or `Ok`) to `*err` unless
+/// `err` is NULL: `Io` if the file can't be opened or read, `InvalidIndex`
+/// if it isn't an index.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_load_ex(path: *const c_char, err: *mut CsiErrorCode) -> *mut CSIHandle {
+    let res = match c_path(path) {
+        None if path.is_null() => Err((CsiErrorCode::NullArgument, "path is NULL".into())),
+        None => Err((CsiErrorCode::Io, "path is not UTF-8".into())),
+        Some(path) => File::open(path).map_err(|e| (CsiErrorCode::Io, format!("{path}: {e}"))).and_then(|f| {
+            CsiIndex::read_index(BufReader::new(f), PolyHasher).map_err(|e| match e.kind() {
+                std::io::ErrorKind::InvalidData | std::io::ErrorKind::UnexpectedEof =>
+                    (CsiErrorCode::InvalidIndex, format!("{path}: {e}")),
+                _ => (CsiErrorCode::Io, format!("{path}: {e}")),
+            })
+        }),
+    };
+    handle_or_error(res, err)
+}
+
+/// `csi_load`, but with the memmap2 feature the file is mapped and searched
+/// in place (see `CsiIndex::open_mmap`), and must not change while the
+/// handle is alive. Without it, the same as `csi_load`.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_open_mmap(path: *const c_char) -> *mut CSIHandle {
+    #[cfg(feature = "memmap2")]
+    {
+        let Some(path) = c_path(path) else { return std::ptr::null_mut() };
+        match CsiIndex::open_mmap(std::path::Path::new(path), PolyHasher) {
+            Ok(idx) => Box::into_raw(Box::new(CSIHandle { inner: Box::new(idx) })),
+            Err(_) => std::ptr::null_mut(),
+        }
+    }
+    #[cfg(not(feature = "memmap2"))]
+    csi_load(path)
+}
+
+/// A NUL‐terminated UTF‐8 path from C, or `None` if NULL or not UTF‐8.
+fn c_path<'a>(path: *const c_char) -> Option<&'a str> {
+    if path.is_null() { return None }
+    unsafe { CStr::from_ptr(path) }.to_str().ok()
+}
+
+/// One handle over the texts of the `count` handles in `parts`, in order
+/// (see `CsiIndex::merge`). The parts are consumed on success: their
+/// handles are freed and must not be used again. NULL, leaving every part
+/// as it was, if `parts` is NULL, holds a NULL or repeated handle, or its
+/// indexes were built differently.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_merge(parts: *const *mut CSIHandle, count: usize) -> *mut CSIHandle {
+    if parts.is_null() { return std::ptr::null_mut() }
+    let handles = unsafe { slice::from_raw_parts(parts, count) };
+    let mut distinct = handles.to_vec();
+    distinct.sort_unstable();
+    distinct.dedup();
+    if distinct.len() != count || handles.iter().any(|h| h.is_null())
+        || CsiIndex::check_mergeable(handles.iter().map(|&h| unsafe { &*(*h).inner })).is_err() {
+        return std::ptr::null_mut();
+    }
+    let indexes = handles.iter().map(|&h| *unsafe { Box::from_raw(h) }.inner).collect();
+    match CsiIndex::merge(indexes) {
+        Ok(idx) => Box::into_raw(Box::new(CSIHandle { inner: Box::new(idx) })),
+        Err(_) => unreachable!("checked above"),
+    }
+}
+
+/// Append `len` bytes to the handle's text and index them (see
+/// `CsiIndex::append`); false on NULL arguments. Must not run concurrently
+/// with any other call on the handle, and invalidates posting views.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_append(handle: *mut CSIHandle, data: *const c_uchar, len: usize) -> bool {
+    if handle.is_null() || data.is_null() { return false }
+    let idx = unsafe { &mut *(*handle).inner };
+    idx.append(unsafe { slice::from_raw_parts(data, len) });
+    true
+}
+
+/// Mark bytes `start..end` of the handle's text deleted, so searches skip
+/// matches overlapping them (see `CsiIndex::invalidate_range`); false on a
+/// NULL handle or `start > end`. Must not run concurrently with any other
+/// call on the handle.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_invalidate_range(handle: *mut CSIHandle, start: usize, end: usize) -> bool {
+    if handle.is_null() || start > end { return false }
+    unsafe { &mut *(*handle).inner }.invalidate_range(start..end);
+    true
+}
+
+/// Rebuild the handle's tables without the postings of deleted ranges or
+/// truncated text (see `CsiIndex::compact`); false on NULL. Must not run
+/// concurrently with any other call on the handle, and invalidates posting
+/// views.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_compact(handle: *mut CSIHandle) -> bool {
+    if handle.is_null() { return false }
+    unsafe { &mut *(*handle).inner }.compact();
+    true
+}
+
+/// Write the first `max_out` match offsets into `out` and return the total
+/// number of matches, which exceeds `max_out` when the output was truncated.
+/// `out` may be NULL only when `max_out` is 0. 0 on NULL arguments too,
+/// with `csi_last_error_message` saying which; it is NULL after a search
+/// that ran. Each thread keeps its search buffers between calls, so
+/// repeated searches don't allocate once they've grown.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_search(
+    handle: *const CSIHandle,
+    pat:    *const c_uchar,
+    pat_len: usize,
+    out:    *mut usize,
+    max_out: usize,
+) -> usize {
+    csi_search_flags(handle, pat, pat_len, 0, out, max_out)
+}
+
+/// `csi_search_flags` flag keeping only the leftmost non‐overlapping
+/// matches (see `MatchMode::NonOverlapping`)
+pub const CSI_SEARCH_NON_OVERLAPPING: u32 = 1;
+
+/// `csi_search`, with `flags` a bitwise OR of `CSI_SEARCH_*` values; 0
+/// flags is `csi_search` itself. 0 on unknown flags, with
+/// `csi_last_error_message` set as for NULL arguments.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_search_flags(
+    handle: *const CSIHandle,
+    pat:    *const c_uchar,
+    pat_len: usize,
+    flags:  u32,
+    out:    *mut usize,
+    max_out: usize,
+) -> usize {
+    if handle.is_null() || pat.is_null() || (out.is_null() && max_out > 0) {
+        let which = if handle.is_null() { "handle" } else if pat.is_null() { "pattern" } else { "out" };
+        set_last_error(Some(format!("{which} is NULL")));
+        return 0;
+    }
+    if flags & !CSI_SEARCH_NON_OVERLAPPING != 0 {
+        set_last_error(Some(format!("unknown search flags {flags:#x}")));
+        return 0;
+    }
+    set_last_error(None);
+    let idx = unsafe { &*((*handle).inner) };
+    let pat_slice = unsafe { slice::from_raw_parts(pat, pat_len) };
+    let mode = if flags & CSI_SEARCH_NON_OVERLAPPING != 0 { MatchMode::NonOverlapping } else { MatchMode::All };
+    if mode == MatchMode::All {
+        return SCRATCH.with(|cell| {
+            let (scratch, hits) = &mut *cell.borrow_mut();
+            idx.search_with(pat_slice, scratch, hits);
+            let n = hits.len().min(max_out);
+            if n > 0 {
+                unsafe { slice::from_raw_parts_mut(out, n) }.copy_from_slice(&hits[..n]);
+            }
+            hits.len()
+        });
+    }
+    let mut total = 0;
+    idx.visit_mode(pat_slice, mode, |off| {
+        if total < max_out {
+            unsafe { *out.add(total) = off; }
+        }
+        total += 1;
+        true
+    });
+    total
+}
+
+/// Run a search and keep every match offset in a result handle, for
+/// callers that can't size a buffer up front: the total goes to `*total`
+/// (if not NULL), and `csi_results_copy` pages the offsets out. The results
+/// are a copy, valid until `csi_results_free` even if the index is freed
+/// first. NULL on NULL arguments.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_search_begin(
+    handle: *const CSIHandle,
+    pat:    *const c_uchar,
+    pat_len: usize,
+    total:  *mut usize,
+) -> *mut CSIResults {
+    if handle.is_null() || pat.is_null() { return std::ptr::null_mut() }
+    let idx = unsafe { &*((*handle).inner) };
+    let offs = idx.search(unsafe { slice::from_raw_parts(pat, pat_len) });
+    if !total.is_null() {
+        unsafe { *total = offs.len(); }
+    }
+    Box::into_raw(Box::new(CSIResults { offs }))
+}
+
+/// Search for each of the `n_pats` patterns at `pats` (with lengths at
+/// `pat_lens`) as `CsiIndex::search_many` does, keeping their matches in
+/// one result handle, pattern after pattern, and writing each pattern's
+/// number of matches to `counts`. NULL on NULL arguments (any of them may
+/// be NULL when `n_pats` is 0); a pattern may be NULL only if empty.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_search_batch(
+    handle:   *const CSIHandle,
+    pats:     *const *const c_uchar,
+    pat_lens: *const usize,
+    n_pats:   usize,
+    counts:   *mut usize,
+) -> *mut CSIResults {
+    if handle.is_null() || n_pats > 0 && (pats.is_null() || pat_lens.is_null() || counts.is_null()) {
+        return std::ptr::null_mut();
+    }
+    let idx = unsafe { &*((*handle).inner) };
+    let mut owned = Vec::with_capacity(n_pats);
+    for i in 0..n_pats {
+        let (p, len) = unsafe { (*pats.add(i), *pat_lens.add(i)) };
+        if p.is_null() && len > 0 { return std::ptr::null_mut() }
+        owned.push(if len == 0 { &[][..] } else { unsafe { slice::from_raw_parts(p, len) } });
+    }
+    let hits = idx.search_many(&owned);
+    for (i, h) in hits.iter().enumerate() {
+        unsafe { *counts.add(i) = h.len(); }
+    }
+    Box::into_raw(Box::new(CSIResults { offs: hits.concat() }))
+}
+
+/// Copy up to `max_out` offsets, starting with match number `from`, into
+/// `out`, returning how many were copied: 0 once `from` reaches the total,
+/// or on a NULL handle. `out` may be NULL only when `max_out` is 0.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_results_copy(results: *const CSIResults, out: *mut usize, from: usize, max_out: usize) -> usize {
+    if results.is_null() || (out.is_null() && max_out > 0) { return 0 }
+    let offs = unsafe { &(*results).offs };
+    let rest = offs.get(from..).unwrap_or(&[]);
+    let page = &rest[..max_out.min(rest.len())];
+    if !page.is_empty() {
+        unsafe { slice::from_raw_parts_mut(out, page.len()) }.copy_from_slice(page);
+    }
+    page.len()
+}
+
+/// Free a result handle from `csi_search_begin`.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_results_free(results: *mut CSIResults) {
+    if !results.is_null() {
+        unsafe { let _ = Box::from_raw(results); }
+    }
+}
+
+/// `csi_search` for the matches lying wholly inside text bytes
+/// `start..end` (see `CsiIndex::search_in`): writes the first `max_out`
+/// offsets and returns the total.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_search_range(
+    handle:  *const CSIHandle,
+    pat:     *const c_uchar,
+    pat_len: usize,
+    start:   usize,
+    end:     usize,
+    out:     *mut usize,
+    max_out: usize,
+) -> usize {
+    if handle.is_null() || pat.is_null() || (out.is_null() && max_out > 0) { return 0 }
+    let idx = unsafe { &*((*handle).inner) };
+    let matches = idx.search_in(unsafe { slice::from_raw_parts(pat, pat_len) }, start..end);
+    for (i, &off) in matches.iter().take(max_out).enumerate() {
+        unsafe { *out.add(i) = off; }
+    }
+    matches.len()
+}
+
+/// One page of `csi_search`'s matches: writes matches number `offset`
+/// onward, at most `max_out` of them, and returns how many it wrote, so a
+/// short page is the last (see `CsiIndex::search_limited`). Finding stops
+/// at the page's end rather than counting the total. `out` may be NULL
+/// only when `max_out` is 0.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_search_page(
+    handle:  *const CSIHandle,
+    pat:     *const c_uchar,
+    pat_len: usize,
+    offset:  usize,
+    out:     *mut usize,
+    max_out: usize,
+) -> usize {
+    if handle.is_null() || pat.is_null() || (out.is_null() && max_out > 0) { return 0 }
+    let idx = unsafe { &*((*handle).inner) };
+    let page = idx.search_limited(unsafe { slice::from_raw_parts(pat, pat_len) }, offset, max_out);
+    for (i, &off) in page.iter().enumerate() {
+        unsafe { *out.add(i) = off; }
+    }
+    page.len()
+}
+
+/// Like `csi_search`, for matches with at most `max_mismatches`
+/// substituted bytes (see `CsiIndex::search_approx`): writes the first
+/// `max_out` offsets and returns the total. `out` may be NULL only when
+/// `max_out` is 0.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_search_approx(
+    handle:  *const CSIHandle,
+    pat:     *const c_uchar,
+    pat_len: usize,
+    max_mismatches: usize,
+    out:     *mut usize,
+    max_out: usize,
+) -> usize {
+    if handle.is_null() || pat.is_null() || (out.is_null() && max_out > 0) { return 0 }
+    let idx = unsafe { &*((*handle).inner) };
+    let matches = idx.search_approx(unsafe { slice::from_raw_parts(pat, pat_len) }, max_mismatches);
+    for (i, &off) in matches.iter().take(max_out).enumerate() {
+        unsafe { *out.add(i) = off; }
+    }
+    matches.len()
+}
+
+/// `csi_search_approx` writing `CsiMatch` records whose `score` is the
+/// number of mismatched bytes (see `CsiIndex::search_hamming`); returns the
+/// total. `out` may be NULL only when `max_out` is 0.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_search_approx_matches(
+    handle:  *const CSIHandle,
+    pat:     *const c_uchar,
+    pat_len: usize,
+    max_mismatches: usize,
+    out:     *mut CsiMatch,
+    max_out: usize,
+) -> usize {
+    if handle.is_null() || pat.is_null() || (out.is_null() && max_out > 0) { return 0 }
+    let idx = unsafe { &*((*handle).inner) };
+    let matches = idx.search_hamming(unsafe { slice::from_raw_parts(pat, pat_len) }, max_mismatches);
+    for (i, &(off, diff)) in matches.iter().take(max_out).enumerate() {
+        let m = CsiMatch { offset: off as u64, length: pat_len as u64, score: diff.min(u32::MAX as usize) as u32 };
+        unsafe { *out.add(i) = m; }
+    }
+    matches.len()
+}
+
+/// `csi_search` on a handle from `csi_new_ci`: `pat` is ASCII‐folded
+/// before hashing and verification, as `csi_search` itself does on such a
+/// handle. A case‐sensitive handle holds no folded text to match against,
+/// so it returns 0 rather than a silently case‐sensitive result.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_search_ci(
+    handle: *const CSIHandle,
+    pat:    *const c_uchar,
+    pat_len: usize,
+    out:    *mut usize,
+    max_out: usize,
+) -> usize {
+    if handle.is_null() || unsafe { (*handle).inner.fold } != CaseFold::Ascii { return 0 }
+    csi_search(handle, pat, pat_len, out, max_out)
+}
+
+/// Number of occurrences of `pat`, as `CsiIndex::count`; 0 on NULL.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_count(handle: *const CSIHandle, pat: *const c_uchar, pat_len: usize) -> usize {
+    if handle.is_null() || pat.is_null() { return 0 }
+    let idx = unsafe { &*((*handle).inner) };
+    idx.count(unsafe { slice::from_raw_parts(pat, pat_len) })
+}
+
+/// Whether `pat` occurs at all, as `CsiIndex::contains`; false on NULL.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_contains(handle: *const CSIHandle, pat: *const c_uchar, pat_len: usize) -> bool {
+    if handle.is_null() || pat.is_null() { return false }
+    let idx = unsafe { &*((*handle).inner) };
+    idx.contains(unsafe { slice::from_raw_parts(pat, pat_len) })
+}
+
+/// One search hit over FFI. Exact matches have `length == pat_len` and
+/// `score == 0`; richer search modes reuse the same layout.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CsiMatch {
+    pub offset: u64,
+    pub length: u64,
+    pub score:  u32,
+}
+
+/// Write the first `max_out` matches into `out` as `CsiMatch` records and
+/// return the total, as `csi_search` does: more than `max_out` means `out`
+/// was too small. `out` may be NULL only when `max_out` is 0; 0 on NULL
+/// arguments.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_search_matches(
+    handle: *const CSIHandle,
+    pat:    *const c_uchar,
+    pat_len: usize,
+    out:    *mut CsiMatch,
+    max_out: usize,
+) -> usize {
+    if handle.is_null() || pat.is_null() || (out.is_null() && max_out > 0) { return 0 }
+    let idx = unsafe { &*((*handle).inner) };
+    let pat_slice = unsafe { slice::from_raw_parts(pat, pat_len) };
+    let matches = idx.search(pat_slice);
+    let n = matches.len().min(max_out);
+    if n > 0 {
+        let out_slice = unsafe { slice::from_raw_parts_mut(out, n) };
+        for (o, &off) in out_slice.iter_mut().zip(&matches) {
+            *o = CsiMatch { offset: off as u64, length: pat_len as u64, score: 0 };
+        }
+    }
+    matches.len()
+}
+
+/// A match inside one document, as `csi_search_docs` reports it
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CsiDocMatch {
+    pub doc_id: usize,
+    pub offset: usize,
+}
+
+/// Write the first `max_out` matches as (document ID, offset within the
+/// document) into `out` and return the total, as `csi_search` does (see
+/// `CsiIndex::search_docs`). Matches straddling two documents are dropped.
+/// `out` may be NULL only when `max_out` is 0.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_search_docs(
+    handle: *const CSIHandle,
+    pat:    *const c_uchar,
+    pat_len: usize,
+    out:    *mut CsiDocMatch,
+    max_out: usize,
+) -> usize {
+    if handle.is_null() || pat.is_null() || (out.is_null() && max_out > 0) { return 0 }
+    let idx = unsafe { &*((*handle).inner) };
+    let matches = idx.search_docs(unsafe { slice::from_raw_parts(pat, pat_len) });
+    for (i, &(doc_id, offset)) in matches.iter().take(max_out).enumerate() {
+        unsafe { *out.add(i) = CsiDocMatch { doc_id, offset }; }
+    }
+    matches.len()
+}
+
+/// An empty index set, to `csi_set_add` handles to and search them all
+/// with `csi_set_search`.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_set_new() -> *mut CSIIndexSet {
+    Box::into_raw(Box::new(CSIIndexSet { inner: CsiIndexSet::new() }))
+}
+
+/// Move the index of `handle` into `set` as its next shard and return the
+/// shard's ID, counting from 0; `handle` is freed and must not be used
+/// again. `SIZE_MAX` on NULL arguments, which leaves `handle` alone.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_set_add(set: *mut CSIIndexSet, handle: *mut CSIHandle) -> usize {
+    if set.is_null() || handle.is_null() { return usize::MAX }
+    let handle = unsafe { Box::from_raw(handle) };
+    unsafe { (*set).inner.add(*handle.inner) }
+}
+
+/// One match in an index set, reported by `csi_set_search`
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CsiShardMatch {
+    pub shard:  usize,
+    pub offset: usize,
+}
+
+/// Write the first `max_out` matches in every shard of `set` into `out`,
+/// by shard and then offset (see `CsiIndexSet::search`), and return the
+/// total, as `csi_search` does. With the `rayon` feature the shards are
+/// searched concurrently. `out` may be NULL only when `max_out` is 0.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_set_search(
+    set:     *const CSIIndexSet,
+    pat:     *const c_uchar,
+    pat_len: usize,
+    out:     *mut CsiShardMatch,
+    max_out: usize,
+) -> usize {
+    if set.is_null() || pat.is_null() || (out.is_null() && max_out > 0) { return 0 }
+    let set = unsafe { &(*set).inner };
+    let pat = unsafe { slice::from_raw_parts(pat, pat_len) };
+    #[cfg(feature = "rayon")]
+    let matches = set.par_search(pat);
+    #[cfg(not(feature = "rayon"))]
+    let matches = set.search(pat);
+    for (i, &(shard, offset)) in matches.iter().take(max_out).enumerate() {
+        unsafe { *out.add(i) = CsiShardMatch { shard, offset }; }
+    }
+    matches.len()
+}
+
+/// Free an index set and every index in it.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_set_free(set: *mut CSIIndexSet) {
+    if !set.is_null() {
+        unsafe { let _ = Box::from_raw(set); }
+    }
+}
+
+/// Where `csi_search_context` put one match's window: `len` bytes at
+/// `start` of the indexed text, the match at `offset` among them
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CsiContext {
+    pub offset: usize,
+    pub start:  usize,
+    pub len:    usize,
+}
+
+/// Like `csi_search`, also copying each match's window (see
+/// `CsiIndex::search_with_context`) into `buf`: record `i`'s bytes start at
+/// `buf + i * (before + pat_len + after)`, which no window outgrows, so
+/// `buf` must hold `max_out` such strides. Writes the first `max_out`
+/// matches and returns the total. `out` and `buf` may be NULL only when
+/// `max_out` is 0; returns 0 if the strides overflow.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_search_context(
+    handle:  *const CSIHandle,
+    pat:     *const c_uchar,
+    pat_len: usize,
+    before:  usize,
+    after:   usize,
+    out:     *mut CsiContext,
+    buf:     *mut c_uchar,
+    max_out: usize,
+) -> usize {
+    if handle.is_null() || pat.is_null() || (max_out > 0 && (out.is_null() || buf.is_null())) { return 0 }
+    let Some(stride) = before.checked_add(pat_len).and_then(|s| s.checked_add(after)) else { return 0 };
+    if stride.checked_mul(max_out).is_none() { return 0 }
+    let idx = unsafe { &*((*handle).inner) };
+    let matches = idx.search_with_context(unsafe { slice::from_raw_parts(pat, pat_len) }, before, after);
+    for (i, m) in matches.iter().take(max_out).enumerate() {
+        unsafe {
+            *out.add(i) = CsiContext { offset: m.offset, start: m.start, len: m.context.len() };
+            std::ptr::copy_nonoverlapping(m.context.as_ptr(), buf.add(i * stride), m.context.len());
+        }
+    }
+    matches.len()
+}
+
+/// Flattened index summary filled by `csi_info`
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CsiInfo {
+    pub text_len: usize,
+    pub k: usize,
+    pub num_gaps: usize,
+    pub total_distinct_constellations: usize,
+    pub total_postings: usize,
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_info(handle: *const CSIHandle, out: *mut CsiInfo) -> bool {
+    if handle.is_null() || out.is_null() { return false }
+    let idx = unsafe { &*((*handle).inner) };
+    let s = idx.index_summary();
+    unsafe {
+        *out = CsiInfo {
+            text_len: s.text_len,
+            k: idx.k,
+            num_gaps: idx.gaps.len(),
+            total_distinct_constellations: s.total_distinct_constellations,
+            total_postings: s.total_postings,
+        };
+    }
+    true
+}
+
+/// Heap bytes held by the index, as `CsiIndex::heap_bytes`; 0 on NULL.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_mem_usage(handle: *const CSIHandle) -> usize {
+    if handle.is_null() { return 0 }
+    let idx = unsafe { &*((*handle).inner) };
+    idx.heap_bytes()
+}
+
+/// One gap table's shape, flattened from `GapStats` for `csi_stats`
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CsiGapStats {
+    pub gap: usize,
+    pub table_size: usize,
+    pub load_factor: f64,
+    pub max_probe: usize,
+    pub largest_bucket: usize,
+    pub saturated_buckets: usize,
+    pub postings: usize,
+    pub heap_bytes: usize,
+}
+
+/// Write the first `max_out` gap tables' stats, in gap order, into `out`
+/// and return how many tables the index has; 0 on a NULL handle. `out`
+/// may be NULL only when `max_out` is 0.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_stats(handle: *const CSIHandle, out: *mut CsiGapStats, max_out: usize) -> usize {
+    if handle.is_null() || (out.is_null() && max_out > 0) { return 0 }
+    let idx = unsafe { &*((*handle).inner) };
+    let s = idx.stats();
+    for (i, g) in s.per_gap.iter().take(max_out).enumerate() {
+        unsafe {
+            *out.add(i) = CsiGapStats {
+                gap: g.gap,
+                table_size: g.table_size,
+                load_factor: g.load_factor,
+                max_probe: g.max_probe,
+                largest_bucket: g.largest_bucket,
+                saturated_buckets: g.saturated_buckets,
+                postings: g.postings,
+                heap_bytes: g.heap_bytes,
+            };
+        }
+    }
+    s.per_gap.len()
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_match_stats(
+    handle: *const CSIHandle,
+    pat:    *const c_uchar,
+    pat_len: usize,
+    out:    *mut MatchStats,
+) -> bool {
+    if handle.is_null() || pat.is_null() || out.is_null() { return false }
+    let idx = unsafe { &*((*handle).inner) };
+    let pat_slice = unsafe { slice::from_raw_parts(pat, pat_len) };
+    unsafe { *out = idx.match_stats(pat_slice); }
+    true
+}
+
+/// Upper bound on the candidates a search for `pat` would verify; 0 on NULL
+/// arguments. See `CsiIndex::estimate_candidates`.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_estimate_candidates(
+    handle: *const CSIHandle,
+    pat:    *const c_uchar,
+    pat_len: usize,
+) -> usize {
+    if handle.is_null() || pat.is_null() { return 0 }
+    let idx = unsafe { &*((*handle).inner) };
+    let pat_slice = unsafe { slice::from_raw_parts(pat, pat_len) };
+    idx.estimate_candidates(pat_slice)
+}
+
+/// Distinct constellations of `pat` present in the index; 0 on NULL
+/// arguments. See `CsiIndex::shared_constellations`.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_shared_constellations(
+    handle: *const CSIHandle,
+    pat:    *const c_uchar,
+    pat_len: usize,
+) -> usize {
+    if handle.is_null() || pat.is_null() { return 0 }
+    let idx = unsafe { &*((*handle).inner) };
+    let pat_slice = unsafe { slice::from_raw_parts(pat, pat_len) };
+    idx.shared_constellations(pat_slice)
+}
+
+/// Anchor length `k`; 0 on a NULL handle.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_k(handle: *const CSIHandle) -> usize {
+    if handle.is_null() { return 0 }
+    let idx = unsafe { &*((*handle).inner) };
+    idx.k()
+}
+
+/// Write up to `max` gaps, ascending, into `out` and return how many were
+/// written. `CsiInfo::num_gaps` is the size that holds them all. `out` may be NULL only when `max` is 0.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_gaps(handle: *const CSIHandle, out: *mut usize, max: usize) -> usize {
+    if handle.is_null() || (out.is_null() && max > 0) { return 0 }
+    let idx = unsafe { &*((*handle).inner) };
+    let n = idx.gaps().len().min(max);
+    if n > 0 {
+        unsafe { slice::from_raw_parts_mut(out, n) }.copy_from_slice(&idx.gaps()[..n]);
+    }
+    n
+}
+
+/// Shortest indexable pattern, `k + gaps[0] + k`; 0 on a NULL handle. See
+/// `CsiIndex::min_pattern_len`.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_min_pattern_len(handle: *const CSIHandle) -> usize {
+    if handle.is_null() { return 0 }
+    let idx = unsafe { &*((*handle).inner) };
+    idx.min_pattern_len()
+}
+
+/// Smallest match offset greater than `after` (`SIZE_MAX` starts at 0), or
+/// `SIZE_MAX` when there is none or an argument is NULL.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_find_next(
+    handle: *const CSIHandle,
+    pat:    *const c_uchar,
+    pat_len: usize,
+    after:  usize,
+) -> usize {
+    if handle.is_null() || pat.is_null() { return usize::MAX }
+    let idx = unsafe { &*((*handle).inner) };
+    let pat_slice = unsafe { slice::from_raw_parts(pat, pat_len) };
+    idx.find_next(pat_slice, after).unwrap_or(usize::MAX)
+}
+
+/// Match offset closest to `pos`, the later one on a tie; `SIZE_MAX` when
+/// there is none or an argument is NULL.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_nearest_match(
+    handle: *const CSIHandle,
+    pat:    *const c_uchar,
+    pat_len: usize,
+    pos:    usize,
+) -> usize {
+    if handle.is_null() || pat.is_null() { return usize::MAX }
+    let idx = unsafe { &*((*handle).inner) };
+    let pat_slice = unsafe { slice::from_raw_parts(pat, pat_len) };
+    idx.nearest_match(pat_slice, pos).unwrap_or(usize::MAX)
+}
+
+/// Invoke `cb(offset, user)` for each match in ascending order, stopping as
+/// soon as it returns non‐zero; returns how many offsets were delivered.
+///
+/// `cb` runs synchronously on the calling thread and must not re‐enter the
+/// library with this handle (in particular it must not call `csi_free`).
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_search_cb(
+    handle:  *const CSIHandle,
+    pat:     *const c_uchar,
+    pat_len: usize,
+    cb:      Option<extern "C" fn(usize, *mut c_void) -> i32>,
+    user:    *mut c_void,
+) -> usize {
+    let Some(cb) = cb else { return 0 };
+    if handle.is_null() || pat.is_null() { return 0 }
+    let idx = unsafe { &*((*handle).inner) };
+    let pat_slice = unsafe { slice::from_raw_parts(pat, pat_len) };
+    let mut delivered = 0;
+    idx.search_visit(pat_slice, |off| {
+        delivered += 1;
+        cb(off, user) == 0
+    });
+    delivered
+}
+
+/// Borrow `pat`'s posting list at `gap_index` without copying.
+///
+/// On success `*out_ptr`/`*out_len` point into memory owned by the handle.
+/// The view stays valid only until `csi_free(handle)` or any call that
+/// mutates the index; callers must not write through it or hold it across
+/// those calls. Offsets are unverified candidates. Returns false (and a
+/// NULL/0 view) when no list exists for that gap, or the table stores its
+/// postings in 32 bits, as it does by default for texts under 4 GiB, so
+/// there is no `size_t` array to lend; `csi_load` an index written with
+/// `CsiConfig::wide_slots` set to view its lists.
+#[unsafe(no_mangle)]
+pub extern "C" fn csi_posting_view(
+    handle:    *const CSIHandle,
+    pat:       *const c_uchar,
+    pat_len:   usize,
+    gap_index: usize,
+    out_ptr:   *mut *const usize,
+    out_len:   *mut usize,
+) -> bool {
+    if handle.is_null() || pat.is_null() || out_ptr.is_null() || out_len.is_null() { return false }
+    let idx = unsafe { &*((*handle).inner) };
+    let pat_slice = unsafe { slice::from_raw_parts(pat, pat_len) };
+    let (ptr, len, ok) = match idx.posting_list(pat_slice, gap_index) {
+        Some(Cow::Borrowed(l)) => (l.as_ptr(), l.len(), true),
+        _ => (std::ptr::null(), 0, false),
+    };
+    unsafe { *out_ptr = ptr; *out_len = len; }
+    ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ffi_search_cb_streams_and_stops() {
+        extern "C" fn collect(off: usize, user: *mut c_void) -> i32 {
+            let v = unsafe { &mut *(user as *mut Vec<usize>) };
+            v.push(off);
+            (v.len() >= 2) as i32
+        }
+        let text = b"callback target string here; callback target string here; callback target string here";
+        let h = csi_new(text.as_ptr(), text.len());
+        let pat = b"callback target string here";
+        let mut got: Vec<usize> = Vec::new();
+        let n = csi_search_cb(h, pat.as_ptr(), pat.len(), Some(collect), &mut got as *mut _ as *mut c_void);
+        assert_eq!((n, got), (2, vec![0, 29]));
+        assert_eq!(csi_search_cb(h, pat.as_ptr(), pat.len(), None, std::ptr::null_mut()), 0);
+        csi_free(h);
+    }
+
+    #[test]
+    fn ffi_ci_handles_fold_ascii_only() {
+        let text = b"WARN disk nearly full on host-a; warn DISK NEARLY FULL ON HOST-A";
+        let pat = b"Warn Disk Nearly Full On Host-A";
+        let ci = csi_new_ci(text.as_ptr(), text.len());
+        let exact = csi_new(text.as_ptr(), text.len());
+        let mut out = [0usize; 4];
+        assert_eq!(csi_search_ci(ci, pat.as_ptr(), pat.len(), out.as_mut_ptr(), out.len()), 2);
+        assert_eq!(out[..2], [0, 33]);
+        assert_eq!(csi_search(ci, pat.as_ptr(), pat.len(), out.as_mut_ptr(), out.len()), 2);
+        assert_eq!(csi_search_ci(exact, pat.as_ptr(), pat.len(), out.as_mut_ptr(), out.len()), 0);
+        assert_eq!(csi_search(exact, pat.as_ptr(), pat.len(), out.as_mut_ptr(), out.len()), 0);
+        assert_eq!(csi_search_ci(std::ptr::null(), pat.as_ptr(), pat.len(), out.as_mut_ptr(), out.len()), 0);
+        assert!(csi_new_ci(std::ptr::null(), 0).is_null());
+        csi_free(ci);
+        csi_free(exact);
+    }
+
+    #[test]
+    fn ffi_search_reports_the_untruncated_total() {
+        let text = b"truncate me gently; truncate me gently; truncate me gently; truncate me gently";
+        let h = csi_new(text.as_ptr(), text.len());
+        let pat = b"truncate me gently";
+        let mut out = [usize::MAX; 3];
+        assert_eq!(csi_search(h, pat.as_ptr(), pat.len(), out.as_mut_ptr(), 2), 4);
+        assert_eq!(out, [0, 20, usize::MAX]);
+        assert_eq!(csi_search(h, pat.as_ptr(), pat.len(), std::ptr::null_mut(), 0), 4);
+        csi_free(h);
+    }
+
+    #[test]
+    fn ffi_search_buffers_outlive_the_index() {
+        // the thread's scratch serves each index in turn, freed ones included
+        for text in [&b"first text, first text, first text"[..], b"second text; second text"] {
+            let h = csi_new(text.as_ptr(), text.len());
+            let mut out = [0usize; 4];
+            let n = csi_search(h, b"text".as_ptr(), 4, out.as_mut_ptr(), out.len());
+            assert_eq!(out[..n], CsiIndex::build(text).search(b"text")[..]);
+            csi_free(h);
+        }
+    }
+
+    #[test]
+    fn ffi_search_flags_drop_overlapping_matches() {
+        let text = b"abababab-abababab-abababab-abababab";
+        let h = csi_new(text.as_ptr(), text.len());
+        let pat = b"abab";
+        let mut out = [usize::MAX; 8];
+        let all = csi_search_flags(h, pat.as_ptr(), pat.len(), 0, out.as_mut_ptr(), out.len());
+        assert_eq!(all, csi_search(h, pat.as_ptr(), pat.len(), std::ptr::null_mut(), 0));
+        assert_eq!(all, 12);
+        let n = csi_search_flags(h, pat.as_ptr(), pat.len(), CSI_SEARCH_NON_OVERLAPPING, out.as_mut_ptr(), out.len());
+        assert_eq!((n, out), (8, [0, 4, 9, 13, 18, 22, 27, 31]));
+        assert_eq!(csi_search_flags(h, pat.as_ptr(), pat.len(), 2, out.as_mut_ptr(), out.len()), 0);
+        assert!(!csi_last_error_message().is_null());
+        csi_free(h);
+    }
+
+    #[test]
+    fn ffi_reports_the_library_and_abi_versions() {
+        let version = unsafe { CStr::from_ptr(csi_version()) }.to_str().unwrap();
+        assert_eq!(version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(csi_abi_version(), CSI_ABI_VERSION);
+        let header = include_str!("../include/csi.h");
+        assert!(header.contains(&format!("#define CSI_ABI_VERSION {CSI_ABI_VERSION}\n")));
+    }
+
+    #[test]
+    fn ffi_reports_why_a_call_failed() {
+        let last = || {
+            let msg = csi_last_error_message();
+            (!msg.is_null()).then(|| unsafe { CStr::from_ptr(msg) }.to_str().unwrap().to_owned())
+        };
+        let text = b"errors travel in codes; errors travel in codes";
+        let mut err = CsiErrorCode::Io;
+        assert!(csi_new_ex(std::ptr::null(), 4, &mut err).is_null());
+        assert_eq!((err, last().as_deref()), (CsiErrorCode::NullArgument, Some("data is NULL")));
+        assert!(csi_new_ex(text.as_ptr(), 0, &mut err).is_null());
+        assert_eq!(err, CsiErrorCode::EmptyInput);
+        assert!(csi_new_with_params_ex(text.as_ptr(), text.len(), 0, [4].as_ptr(), 1, &mut err).is_null());
+        assert_eq!(err, CsiErrorCode::InvalidParams);
+        assert!(csi_deserialize_ex(text.as_ptr(), text.len(), &mut err).is_null());
+        assert_eq!(err, CsiErrorCode::InvalidIndex);
+        assert!(last().unwrap().contains("sidecar"));
+        let missing = std::ffi::CString::new("/nonexistent/csi.idx").unwrap();
+        assert!(csi_load_ex(missing.as_ptr(), &mut err).is_null());
+        assert_eq!(err, CsiErrorCode::Io);
+
+        let h = csi_new_ex(text.as_ptr(), text.len(), &mut err);
+        assert!(!h.is_null());
+        assert_eq!((err, last()), (CsiErrorCode::Ok, None));
+        // no matches and bad arguments both return 0; the message tells them apart
+        let pat = b"never in the text";
+        assert_eq!(csi_search(std::ptr::null(), pat.as_ptr(), pat.len(), std::ptr::null_mut(), 0), 0);
+        assert_eq!(last().as_deref(), Some("handle is NULL"));
+        assert_eq!(csi_search(h, pat.as_ptr(), pat.len(), std::ptr::null_mut(), 0), 0);
+        assert_eq!(last(), None);
+        // a NULL buffer with room claimed in it is refused, in either mode
+        for flags in [0, CSI_SEARCH_NON_OVERLAPPING] {
+            assert_eq!(csi_search_flags(h, b"errors".as_ptr(), 6, flags, std::ptr::null_mut(), 4), 0);
+            assert_eq!(last().as_deref(), Some("out is NULL"));
+            assert_eq!(csi_search_flags(h, b"errors".as_ptr(), 6, flags, std::ptr::null_mut(), 0), 2);
+        }
+        // the message is per thread
+        assert!(csi_new(std::ptr::null(), 0).is_null());
+        std::thread::spawn(|| assert!(csi_last_error_message().is_null())).join().unwrap();
+        assert!(last().is_some());
+        csi_free(h);
+    }
+
+    #[test]
+    fn ffi_progress_callback_reports_and_cancels() {
+        // user: (calls so far, call to cancel at, last `done`)
+        extern "C" fn progress(done: usize, total: usize, user: *mut c_void) -> i32 {
+            let state = unsafe { &mut *(user as *mut (usize, usize, usize)) };
+            assert!(done <= total && done >= state.2);
+            state.0 += 1;
+            state.2 = done;
+            (state.0 == state.1) as i32
+        }
+        let text: Vec<u8> = (0u32..150_000).map(|i| (i.wrapping_mul(2654435761) >> 11) as u8).collect();
+        let mut state: (usize, usize, usize) = (0, usize::MAX, 0);
+        let h = csi_new_with_progress(text.as_ptr(), text.len(), Some(progress), &mut state as *mut _ as *mut c_void);
+        assert!(!h.is_null() && state.0 > 2);
+        assert_eq!(unsafe { &*(*h).inner }.index_summary(), CsiIndex::build(&text).index_summary());
+        csi_free(h);
+
+        let mut err = CsiErrorCode::Ok;
+        let mut state: (usize, usize, usize) = (0, 2, 0);
+        let user = &mut state as *mut _ as *mut c_void;
+        assert!(csi_new_with_progress_ex(text.as_ptr(), text.len(), Some(progress), user, &mut err).is_null());
+        assert_eq!((err, state.0), (CsiErrorCode::Cancelled, 2));
+        assert!(!csi_last_error_message().is_null());
+        let h = csi_new_with_progress(text.as_ptr(), text.len(), None, std::ptr::null_mut());
+        assert!(!h.is_null());
+        csi_free(h);
+    }
+
+    #[test]
+    fn ffi_merge_consumes_the_parts() {
+        let text = b"shard one of the corpus; shard two of the corpus; shard three".repeat(6);
+        let (a, b) = text.split_at(150);
+        let parts = [csi_new(a.as_ptr(), a.len()), csi_new(b.as_ptr(), b.len())];
+        assert!(csi_merge(parts.as_ptr(), 0).is_null());
+        assert!(csi_merge([parts[0], parts[0]].as_ptr(), 2).is_null());
+        let merged = csi_merge(parts.as_ptr(), 2);
+        assert!(!merged.is_null());
+        let whole = CsiIndex::build(&text);
+        let pat = b"of the corpus; shard";
+        let mut out = [0usize; 16];
+        let n = csi_search(merged, pat.as_ptr(), pat.len(), out.as_mut_ptr(), out.len());
+        assert_eq!(out[..n], whole.search(pat));
+        csi_free(merged);
+    }
+
+    #[test]
+    fn ffi_search_context_copies_each_window() {
+        let text = b"warn: low disk; error: no disk; warn: low memory";
+        let h = csi_new(text.as_ptr(), text.len());
+        let pat = b"warn: low ";
+        let (before, after) = (3, 4);
+        let stride = before + pat.len() + after;
+        let mut out = [CsiContext::default(); 1];
+        let mut buf = vec![0u8; stride];
+        assert_eq!(csi_search_context(h, pat.as_ptr(), pat.len(), before, after, out.as_mut_ptr(), buf.as_mut_ptr(), 1), 2);
+        assert_eq!(out[0], CsiContext { offset: 0, start: 0, len: 14 });
+        assert_eq!(&buf[..out[0].len], b"warn: low disk");
+        let mut out = [CsiContext::default(); 2];
+        let mut buf = vec![0u8; 2 * stride];
+        assert_eq!(csi_search_context(h, pat.as_ptr(), pat.len(), before, after, out.as_mut_ptr(), buf.as_mut_ptr(), 2), 2);
+        assert_eq!(out[1], CsiContext { offset: 32, start: 29, len: 17 });
+        assert_eq!(&buf[stride..stride + out[1].len], b"k; warn: low memo");
+        assert_eq!(csi_search_context(h, pat.as_ptr(), pat.len(), usize::MAX, 1, out.as_mut_ptr(), buf.as_mut_ptr(), 2), 0);
+        assert_eq!(csi_search_context(h, pat.as_ptr(), pat.len(), 0, 0, std::ptr::null_mut(), std::ptr::null_mut(), 0), 2);
+        csi_free(h);
+    }
+
+    #[test]
+    fn ffi_search_page_writes_one_page() {
+        let text = b"page two of the results, page three of the results".repeat(7);
+        let h = csi_new(text.as_ptr(), text.len());
+        let pat = b"of the results";
+        let all = CsiIndex::build(&text).search(pat);
+        let mut out = [0usize; 4];
+        assert_eq!(csi_search_page(h, pat.as_ptr(), pat.len(), 4, out.as_mut_ptr(), 4), 4);
+        assert_eq!(out, all[4..8]);
+        assert_eq!(csi_search_page(h, pat.as_ptr(), pat.len(), 12, out.as_mut_ptr(), 4), 2);
+        assert_eq!(out[..2], all[12..]);
+        assert_eq!(csi_search_page(h, pat.as_ptr(), pat.len(), 0, std::ptr::null_mut(), 0), 0);
+        assert_eq!(csi_search_page(h, pat.as_ptr(), pat.len(), 0, std::ptr::null_mut(), 1), 0);
+        csi_free(h);
+    }
+
+    #[test]
+    fn ffi_search_approx_writes_the_first_matches() {
+        let text = b"the grey heron, the gray heron, the grey herring".repeat(4);
+        let h = csi_new(text.as_ptr(), text.len());
+        let pat = b"the grey heron";
+        let all = CsiIndex::build(&text).search_approx(pat, 1);
+        assert_eq!(all.len(), 8);
+        let mut out = [0usize; 3];
+        assert_eq!(csi_search_approx(h, pat.as_ptr(), pat.len(), 1, out.as_mut_ptr(), 3), 8);
+        assert_eq!(out, all[..3]);
+        assert_eq!(csi_search_approx(h, pat.as_ptr(), pat.len(), 1, std::ptr::null_mut(), 0), 8);
+        assert_eq!(csi_search_approx(h, pat.as_ptr(), pat.len(), 1, std::ptr::null_mut(), 3), 0);
+        csi_free(h);
+    }
+
+    #[test]
+    fn ffi_results_page_out_every_match() {
+        let text = b"page one, page two, page three; page four, page five".repeat(3);
+        let h = csi_new(text.as_ptr(), text.len());
+        let mut total = 0;
+        let r = csi_search_begin(h, b"page ".as_ptr(), 5, &mut total);
+        csi_free(h);
+        assert_eq!(total, 15);
+        let (mut got, mut page) = (Vec::new(), [0usize; 4]);
+        loop {
+            let n = csi_results_copy(r, page.as_mut_ptr(), got.len(), page.len());
+            if n == 0 { break }
+            got.extend_from_slice(&page[..n]);
+        }
+        assert_eq!(got, CsiIndex::build(&text).search(b"page "));
+        assert_eq!(csi_results_copy(r, page.as_mut_ptr(), usize::MAX, 4), 0);
+        assert_eq!(csi_results_copy(r, std::ptr::null_mut(), 0, 0), 0);
+        csi_results_free(r);
+        assert!(csi_search_begin(std::ptr::null(), b"page".as_ptr(), 4, std::ptr::null_mut()).is_null());
+    }
+
+    #[test]
+    fn posting_view_borrows_table_memory() {
+        let text = b"view this posting list, then view this posting list again";
+        let wide = CsiIndexBuilder::new().wide_slots(true).build(text);
+        let h = Box::into_raw(Box::new(CSIHandle { inner: Box::new(wide) }));
+        let pat = b"view this posting list";
+        let (mut ptr, mut len) = (std::ptr::null(), 0usize);
+        assert!(csi_posting_view(h, pat.as_ptr(), pat.len(), 0, &mut ptr, &mut len));
+        let view = unsafe { slice::from_raw_parts(ptr, len) };
+        let idx = unsafe { &*(*h).inner };
+        assert_eq!(view, &idx.posting_list(pat, 0).unwrap()[..]);
+        assert!(matches!(&idx.flat[0].offs, array::Offsets::Wide(offs) if offs.as_ptr_range().contains(&ptr)));
+        assert!(view.contains(&0) && view.contains(&29));
+        let viewed = view.to_vec();
+        assert!(!csi_posting_view(h, pat.as_ptr(), pat.len(), 99, &mut ptr, &mut len));
+        assert!(ptr.is_null() && len == 0);
+        csi_free(h);
+
+        // 32‐bit postings have no `size_t` array to lend
+        let h = csi_new(text.as_ptr(), text.len());
+        assert!(!csi_posting_view(h, pat.as_ptr(), pat.len(), 0, &mut ptr, &mut len));
+        assert!(ptr.is_null() && len == 0);
+        assert_eq!(unsafe { &*(*h).inner }.posting_list(pat, 0).as_deref(), Some(&viewed[..]));
+        csi_free(h);
+    }
+
+    #[test]
+    fn ffi_nearest_match_uses_size_max_for_none() {
+        let text = b"..needle in a stack of hay.. ....needle in a stack of hay..".to_vec();
+        let pat = b"needle in a stack of hay";
+        let h = csi_new(text.as_ptr(), text.len());
+        assert_eq!(csi_nearest_match(h, pat.as_ptr(), pat.len(), 10), 2);
+        assert_eq!(csi_nearest_match(h, pat.as_ptr(), pat.len(), 20_000), 33);
+        assert_eq!(csi_nearest_match(h, b"haystack".as_ptr(), 8, 0), usize::MAX);
+        assert_eq!(csi_nearest_match(h, std::ptr::null(), 0, 0), usize::MAX);
+        csi_free(h);
+    }
+
+    #[test]
+    fn ffi_reports_k_and_gaps() {
+        let text = b"log the chosen configuration from C; log it once, log it twice".repeat(2);
+        let idx = CsiIndex::build(&text);
+        let h = csi_new(text.as_ptr(), text.len());
+        assert_eq!(csi_k(h), idx.k());
+        let mut out = [usize::MAX; 8];
+        let n = csi_gaps(h, out.as_mut_ptr(), out.len());
+        assert_eq!(&out[..n], idx.gaps());
+        assert_eq!(out[n], usize::MAX);
+        assert_eq!(csi_gaps(h, out.as_mut_ptr(), 1), 1);
+        assert_eq!(csi_gaps(h, std::ptr::null_mut(), 0), 0);
+        assert_eq!(csi_gaps(h, std::ptr::null_mut(), 4), 0);
+        csi_free(h);
+        assert_eq!((csi_k(std::ptr::null()), csi_gaps(std::ptr::null(), out.as_mut_ptr(), 8)), (0, 0));
+    }
+
+    #[test]
+    fn explicit_params_skip_the_entropy_tiers() {
+        let text = b"ACGTTGCAACGTAGCTAGCTTAGCGATCGATCGGATCCGATTACAGATTACA".repeat(8);
+        let idx = CsiIndex::build_with(&text, 5, &[24, 8, 16, 8]).unwrap();
+        assert_eq!((idx.k(), idx.gaps()), (5, &[8, 16, 24][..]));
+        assert_eq!(idx.search(&text[3..40]), CsiIndex::build(&text).search(&text[3..40]));
+        assert!(CsiIndex::build_with(&text, 0, &[8]).is_none());
+        assert!(CsiIndex::build_with(&text, 5, &[]).is_none());
+
+        let gaps = [16usize, 8];
+        let h = csi_new_with_params(text.as_ptr(), text.len(), 6, gaps.as_ptr(), gaps.len());
+        assert_eq!(csi_k(h), 6);
+        let mut out = [0usize; 4];
+        let n = csi_gaps(h, out.as_mut_ptr(), 4);
+        assert_eq!(&out[..n], [8, 16]);
+        csi_free(h);
+        assert!(csi_new_with_params(text.as_ptr(), text.len(), 0, gaps.as_ptr(), 2).is_null());
+        assert!(csi_new_with_params(text.as_ptr(), text.len(), 6, gaps.as_ptr(), 0).is_null());
+        assert!(csi_new_with_params(text.as_ptr(), text.len(), 6, std::ptr::null(), 2).is_null());
+        // gaps whose spans would overflow are turned away, not built
+        let mut err = CsiErrorCode::Ok;
+        let wide = [8, usize::MAX - 2];
+        assert!(csi_new_with_params_ex(text.as_ptr(), text.len(), 6, wide.as_ptr(), 2, &mut err).is_null());
+        assert_eq!(err, CsiErrorCode::InvalidParams);
+        assert!(CsiIndex::build_with(&text, 6, &wide).is_none());
+        assert!(CsiIndex::build_with(&text, usize::MAX / 2, &[8]).is_none());
+        let tiers = EntropyTiers::fixed(6, vec![isize::MAX as usize]);
+        let config = CsiConfig { tiers, ..CsiConfig::default() };
+        assert_eq!(CsiIndex::try_build_with_config(&text, &config).err(), Some(CsiIndexError::GapTooWide { k: 6, gap: isize::MAX as usize }));
+        // while a gap merely wider than the text still builds
+        let far = CsiIndex::build_with(&text, 6, &[1 << 40]).unwrap();
+        assert_eq!((far.search(&text[..50]), far.min_pattern_len()), (CsiIndex::build(&text).search(&text[..50]), (1 << 40) + 12));
+
+        let d = CsiIndex::build(&text);
+        let h = csi_new(text.as_ptr(), text.len());
+        assert_eq!((csi_k(h), csi_gaps(h, out.as_mut_ptr(), 4)), (d.k(), d.gaps().len()));
+        csi_free(h);
+    }
+
+    #[test]
+    fn ffi_borrowed_handle_searches_the_callers_buffer() {
+        let text = b"keep the buffer, lend it to the index, free the handle first".repeat(12);
+        let (owned, lent) = (csi_new(text.as_ptr(), text.len()), csi_new_borrowed(text.as_ptr(), text.len()));
+        let (a, b) = (unsafe { &*(*owned).inner }, unsafe { &*(*lent).inner });
+        assert_eq!(b.text().as_ptr(), text.as_ptr());
+        assert_eq!(b.heap_bytes() + text.len(), a.heap_bytes());
+        for pat in [&text[..30], &text[17..70], b"free the handle first", b"never lent to anyone"] {
+            assert_eq!(b.search(pat), a.search(pat));
+        }
+        let mut got = [0usize; 4];
+        assert_eq!(csi_search(lent, text.as_ptr(), 30, got.as_mut_ptr(), got.len()), 12);
+        assert!(csi_new_borrowed(std::ptr::null(), 3).is_null());
+        csi_free(lent);
+        csi_free(owned);
+    }
+
+    #[test]
+    fn ffi_handle_serves_concurrent_searches() {
+        let text: Vec<u8> = (0..3000).flat_map(|i| format!("req {} from 10.0.{}.{} ok\n", i, i % 7, i % 251).into_bytes()).collect();
+        let h = csi_new(text.as_ptr(), text.len());
+        let pats: Vec<Vec<u8>> = (0..64).map(|i| format!("from 10.0.{}.{} ok", i % 7, i * 13 % 251).into_bytes()).collect();
+        let want: Vec<Vec<usize>> = pats.iter().map(|p| CsiIndex::build(&text).search(p)).collect();
+        // what a C thread pool would share: the bare pointer
+        let addr = h as usize;
+        std::thread::scope(|s| {
+            for t in 0..8 {
+                let (pats, want) = (&pats, &want);
+                s.spawn(move || {
+                    let h = addr as *const CSIHandle;
+                    let mut out = vec![0usize; 64];
+                    for round in 0..20 {
+                        let i = (t * 7 + round * 5) % pats.len();
+                        let (p, w) = (&pats[i], &want[i]);
+                        assert_eq!(csi_search(h, p.as_ptr(), p.len(), out.as_mut_ptr(), out.len()), w.len());
+                        assert_eq!(out[..w.len().min(64)], w[..w.len().min(64)]);
+                        assert_eq!(csi_count(h, p.as_ptr(), p.len()), w.len());
+                        assert_eq!(csi_contains(h, p.as_ptr(), p.len()), !w.is_empty());
+                    }
+                });
+            }
+        });
+        csi_free(h);
+    }
+
+    #[test]
+    fn ffi_appended_log_lines_search_like_a_full_build() {
+        let lines: Vec<Vec<u8>> = (0..120).map(|i| format!("{i:04} GET /api/v{} status={}\n", i % 3, 200 + i % 7).into_bytes()).collect();
+        let all = lines.concat();
+        let h = csi_new_borrowed(lines[0].as_ptr(), lines[0].len());
+        for line in &lines[1..] {
+            assert!(csi_append(h, line.as_ptr(), line.len()));
+        }
+        assert!(!csi_append(h, std::ptr::null(), 0));
+        let (grown, whole) = (unsafe { &*(*h).inner }, CsiIndex::build(&all));
+        assert_eq!(grown.text(), &all[..]);
+        for pat in [&b"GET /api/v1 status=203"[..], b"status=206\n0119 GET", b"0042 GET /api/v0"] {
+            let mut got = [0usize; 8];
+            let n = csi_search(h, pat.as_ptr(), pat.len(), got.as_mut_ptr(), got.len());
+            assert_eq!(&got[..n.min(8)], &whole.search(pat)[..n.min(8)]);
+            assert_eq!(n, whole.search(pat).len());
+        }
+        csi_free(h);
+    }
+
+    #[test]
+    fn ffi_invalidated_ranges_drop_out_of_searches() {
+        let text: Vec<u8> = (0..200).flat_map(|i| format!("{i:03} GET /api/v{}\n", i % 3).into_bytes()).collect();
+        let h = csi_new(text.as_ptr(), text.len());
+        let pat = b"GET /api/v2";
+        let hits = |h: *const CSIHandle| {
+            let mut got = [0usize; 100];
+            let n = csi_search(h, pat.as_ptr(), pat.len(), got.as_mut_ptr(), got.len());
+            got[..n.min(100)].to_vec()
+        };
+        let all = hits(h);
+        assert!(csi_invalidate_range(h, 0, 800));
+        assert!(!csi_invalidate_range(h, 900, 800));
+        assert!(!csi_invalidate_range(std::ptr::null_mut(), 0, 1));
+        let live: Vec<usize> = all.iter().copied().filter(|&o| o >= 800).collect();
+        assert_eq!(hits(h), live);
+        assert!(csi_compact(h));
+        assert!(!csi_compact(std::ptr::null_mut()));
+        assert_eq!(hits(h), live);
+        csi_free(h);
+    }
+
+    #[test]
+    fn ffi_builder_searches_documents_by_id() {
+        let b = csi_builder_new();
+        assert!(csi_builder_build(b).is_null());
+        let (one, two) = (b"an invoice for the march order, net thirty".repeat(3), b"the march order shipped late".repeat(3));
+        assert!(csi_builder_add_doc(b, 70, one.as_ptr(), one.len()));
+        assert!(csi_builder_add_doc(b, 5, std::ptr::null(), 0));
+        assert!(csi_builder_add_doc(b, 71, two.as_ptr(), two.len()));
+        assert!(!csi_builder_add_doc(b, 72, std::ptr::null(), 4));
+        let h = csi_builder_build(b);
+        csi_builder_free(b);
+
+        let pat = b"the march order";
+        let mut got = [CsiDocMatch::default(); 4];
+        assert_eq!(csi_search_docs(h, pat.as_ptr(), pat.len(), got.as_mut_ptr(), got.len()), 6);
+        assert_eq!(got, [(70, 15), (70, 57), (70, 99), (71, 0)].map(|(doc_id, offset)| CsiDocMatch { doc_id, offset }));
+        assert_eq!(csi_search_docs(h, b"net thirtythe march".as_ptr(), 19, std::ptr::null_mut(), 0), 0);
+        csi_free(h);
+    }
+
+    #[test]
+    fn ffi_set_searches_every_shard() {
+        let set = csi_set_new();
+        for (i, text) in [&b"shard zero: needle"[..], b"no match here", b"needle, needle"].into_iter().enumerate() {
+            assert_eq!(csi_set_add(set, csi_new(text.as_ptr(), text.len())), i);
+        }
+        assert_eq!(csi_set_add(set, std::ptr::null_mut()), usize::MAX);
+        let mut got = [CsiShardMatch::default(); 2];
+        assert_eq!(csi_set_search(set, b"needle".as_ptr(), 6, got.as_mut_ptr(), got.len()), 3);
+        assert_eq!(got, [(0, 12), (2, 0)].map(|(shard, offset)| CsiShardMatch { shard, offset }));
+        assert_eq!(csi_set_search(set, b"absent".as_ptr(), 6, std::ptr::null_mut(), 0), 0);
+        assert_eq!(csi_set_search(std::ptr::null(), b"needle".as_ptr(), 6, std::ptr::null_mut(), 0), 0);
+        csi_set_free(set);
+    }
+
+    #[test]
+    fn ffi_builder_streams_one_text_in_chunks() {
+        let text = b"chunk boundaries fall anywhere, even inside a constellation; ".repeat(40);
+        let b = csi_builder_new();
+        assert!(csi_builder_finish(b).is_null());
+        for chunk in text.chunks(37) {
+            assert!(csi_builder_feed(b, chunk.as_ptr(), chunk.len()));
+        }
+        assert!(csi_builder_feed(b, std::ptr::null(), 0));
+        assert!(!csi_builder_feed(b, std::ptr::null(), 3));
+        assert!(!csi_builder_feed(std::ptr::null_mut(), text.as_ptr(), 3));
+        let h = csi_builder_finish(b);
+        assert!(csi_builder_finish(b).is_null());
+        csi_builder_free(b);
+
+        let pat = b"inside a constellation; chunk";
+        let mut out = [0usize; 64];
+        assert_eq!(csi_search(h, pat.as_ptr(), pat.len(), out.as_mut_ptr(), out.len()), 39);
+        assert_eq!(out[..39], CsiIndex::build(&text).search(pat)[..]);
+        csi_free(h);
+        assert!(csi_builder_finish(std::ptr::null_mut()).is_null());
+    }
+
+    #[test]
+    fn ffi_saved_index_loads_from_disk() {
+        let text = b"persist the index once, reload it at every start; persist once".repeat(8);
+        let h = csi_new(text.as_ptr(), text.len());
+        let path = std::env::temp_dir().join(format!("csi-saved-{}.idx", std::process::id()));
+        let cpath = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        assert!(csi_save(h, cpath.as_ptr()));
+        assert!(!csi_save(std::ptr::null(), cpath.as_ptr()));
+
+        let loaded = csi_load(cpath.as_ptr());
+        assert!(!loaded.is_null());
+        for pat in [&text[..40], &text[21..75], b"never persisted anywhere at all"] {
+            let (a, b) = (unsafe { &*(*h).inner }, unsafe { &*(*loaded).inner });
+            assert_eq!(b.search(pat), a.search(pat));
+        }
+        csi_free(loaded);
+        csi_free(h);
+        std::fs::remove_file(&path).unwrap();
+        assert!(csi_load(cpath.as_ptr()).is_null());
+        assert!(csi_load(std::ptr::null()).is_null());
+    }
+
+    #[test]
+    fn ffi_serialized_index_round_trips() {
+        let text = b"serialize once, load on every start; serialize once, search forever".repeat(6);
+        let h = csi_new(text.as_ptr(), text.len());
+        let need = csi_serialize(h, std::ptr::null_mut(), 0);
+        let mut buf = vec![0u8; need];
+        assert_eq!(csi_serialize(h, buf.as_mut_ptr(), buf.len() - 1), need);
+        assert!(buf.iter().all(|&b| b == 0));
+        assert_eq!(csi_serialize(h, buf.as_mut_ptr(), buf.len()), need);
+
+        let loaded = csi_deserialize(buf.as_ptr(), buf.len());
+        assert!(!loaded.is_null());
+        let mut want = [0usize; 16];
+        let mut got = [0usize; 16];
+        for pat in [&text[..40], &text[33..90], b"search forever; serialize once, load", b"never serialized anywhere at all"] {
+            let n = csi_search(h, pat.as_ptr(), pat.len(), want.as_mut_ptr(), want.len());
+            assert_eq!(csi_search(loaded, pat.as_ptr(), pat.len(), got.as_mut_ptr(), got.len()), n);
+            assert_eq!(got[..n], want[..n]);
+        }
+        assert_eq!(unsafe { &*(*loaded).inner }.validate(), Ok(()));
+        csi_free(loaded);
+
+        for cut in [0, 3, 12, need / 2, need - 1] {
+            assert!(csi_deserialize(buf.as_ptr(), cut).is_null());
+        }
+        buf[0] = b'X';
+        assert!(csi_deserialize(buf.as_ptr(), buf.len()).is_null());
+        buf[0] = b'C';
+        buf[4] = 12;
+        assert!(csi_deserialize(buf.as_ptr(), buf.len()).is_null());
+        assert!(csi_deserialize(std::ptr::null(), 8).is_null());
+        assert_eq!(csi_serialize(std::ptr::null(), buf.as_mut_ptr(), buf.len()), 0);
+        csi_free(h);
+    }
+
+    #[test]
+    fn crafted_headers_load_as_null() {
+        let text = b"bytes from anywhere, loaded in the host process; bytes from anywhere".repeat(4);
+        let h = csi_new(text.as_ptr(), text.len());
+        let mut buf = vec![0u8; csi_serialize(h, std::ptr::null_mut(), 0)];
+        csi_serialize(h, buf.as_mut_ptr(), buf.len());
+        csi_free(h);
+        let path = std::env::temp_dir().join(format!("csi-crafted-{}.idx", std::process::id()));
+        let cpath = CString::new(path.to_str().unwrap()).unwrap();
+        // `k` at byte 20, the gap count at 80 and the first gap at 88
+        for (at, v) in [(20, u64::MAX / 2), (20, 1 << 40), (80, 0), (88, u64::MAX - 2)] {
+            let mut bad = buf.clone();
+            bad[at..at + 8].copy_from_slice(&v.to_le_bytes());
+            let mut err = CsiErrorCode::Ok;
+            assert!(csi_deserialize_ex(bad.as_ptr(), bad.len(), &mut err).is_null(), "{at} {v}");
+            assert_eq!(err, CsiErrorCode::InvalidIndex);
+            std::fs::write(&path, &bad).unwrap();
+            let mut err = CsiErrorCode::Ok;
+            assert!(csi_load_ex(cpath.as_ptr(), &mut err).is_null());
+            assert_eq!(err, CsiErrorCode::InvalidIndex);
+            assert!(csi_open_mmap(cpath.as_ptr()).is_null());
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn min_pattern_len_is_the_search_threshold() {
+        let text = b"route short queries elsewhere; route short queries elsewhere".repeat(3);
+        let idx = CsiIndex::build(&text);
+        let min = idx.min_pattern_len();
+        assert_eq!(min, idx.k + idx.gaps[0] + idx.k);
+        assert!(!idx.search(&text[..min]).is_empty());
+        assert!(!idx.search(&text[..min - 1]).is_empty());
+        assert_eq!(idx.max_useful_gap_for(min), Some(idx.gaps[0]));
+        assert_eq!(idx.max_useful_gap_for(min - 1), None);
+        assert_eq!(idx.max_useful_gap_for(usize::MAX), idx.gaps.last().copied());
+        let h = csi_new(text.as_ptr(), text.len());
+        assert_eq!(csi_min_pattern_len(h), min);
+        csi_free(h);
+        assert_eq!(csi_min_pattern_len(std::ptr::null()), 0);
+    }
+
+    #[test]
+    fn threaded_build_matches_sequential() {
+        let text = b"threads should not change the tables at all, not at all".repeat(5);
+        let seq = CsiIndexBuilder::new().threads(1).build(&text);
+        let tables = |idx: &CsiIndex| -> Vec<_> {
+            idx.flat.iter().map(|f| (f.table_size, f.keys.clone(), f.starts.clone(), f.lens.clone(),
+                                     f.offs.clone(), f.overflow.clone())).collect()
+        };
+        for threads in [0, 4] {
+            let p = csi_new_parallel(text.as_ptr(), text.len(), threads);
+            assert_eq!(tables(unsafe { &(*p).inner }), tables(&seq));
+            csi_free(p);
+        }
+        assert_eq!(tables(&CsiIndex::build(&text)), tables(&seq));
+    }
+
+    #[test]
+    fn search_matches_fills_exact_records() {
+        let text = b"records of matches, records of matches, records";
+        let h = csi_new(text.as_ptr(), text.len());
+        let pat = b"records of matches, rec";
+        let mut out = [CsiMatch::default(); 4];
+        let n = csi_search_matches(h, pat.as_ptr(), pat.len(), out.as_mut_ptr(), out.len());
+        assert_eq!(n, 2);
+        assert_eq!(out[1], CsiMatch { offset: 20, length: pat.len() as u64, score: 0 });
+        // a short buffer gets the first matches and the total
+        let mut one = [CsiMatch::default(); 1];
+        assert_eq!(csi_search_matches(h, pat.as_ptr(), pat.len(), one.as_mut_ptr(), 1), 2);
+        assert_eq!(one[0], out[0]);
+        assert_eq!(csi_search_matches(h, pat.as_ptr(), pat.len(), std::ptr::null_mut(), 0), 2);
+        csi_free(h);
+    }
+}
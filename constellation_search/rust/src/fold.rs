@@ -0,0 +1,222 @@
+// src/fold.rs
+//! Case folding applied to the text before indexing and to every pattern.
+//!
+//! Folding happens in place on the indexed representation, so it must not
+//! change byte lengths: offsets into the folded text are offsets into the
+//! original. ASCII folding and caller `ByteMap`s map bytes to bytes and
+//! trivially qualify; Unicode
+//! folding keeps only 1:1 mappings whose UTF‐8
+//! encoding has the same length as the source character; characters with
+//! multi‐character (full) folds, like 'ß' → "ss", or whose fold changes
+//! length, like 'ẞ' (3 bytes) → 'ß' (2 bytes), are left as they are.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::fmt;
+
+use crate::{CsiIndex, Hasher};
+
+/// How text and patterns are case‐folded before hashing
+// a map's table is copied along with the fold; that's cheap next to any search
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CaseFold {
+    /// Byte‐exact (default)
+    #[default]
+    None,
+    /// Length‐preserving Unicode simple folding of UTF‐8; invalid UTF‐8
+    /// bytes pass through untouched
+    Unicode,
+    /// `A`–`Z` lowercased, every other byte untouched; cheaper than
+    /// `Unicode` and indifferent to whether the text is UTF‐8
+    Ascii,
+    /// Every byte rewritten through a caller's table, for equivalences
+    /// beyond case: `-` as `_`, digits as one class, and so on
+    Map(ByteMap),
+}
+
+/// A byte‐for‐byte rewrite: byte `b` is indexed and matched as `table[b]`
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ByteMap([u8; 256]);
+
+impl ByteMap {
+    /// The map `b → table[b]`.
+    ///
+    /// Panics unless mapping twice changes nothing (`table[table[b]] ==
+    /// table[b]` for every byte), since patterns are mapped as the text was
+    /// and the mapped text must map to itself.
+    pub fn new(table: [u8; 256]) -> Self {
+        Self::checked(table).expect("byte map must be idempotent")
+    }
+
+    /// `new`, or `None` where it would panic.
+    pub(crate) fn checked(table: [u8; 256]) -> Option<Self> {
+        table.iter().all(|&to| table[to as usize] == to).then_some(ByteMap(table))
+    }
+
+    /// What byte `b` is rewritten as.
+    pub fn get(&self, b: u8) -> u8 {
+        self.0[b as usize]
+    }
+
+    /// The table, indexed by source byte.
+    pub fn table(&self) -> &[u8; 256] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for ByteMap {
+    /// Only the bytes the map changes.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries((0..=255u8).map(|b| (b, self.get(b))).filter(|(b, to)| b != to)).finish()
+    }
+}
+
+impl CaseFold {
+    pub(crate) fn apply<'a>(self, data: &'a [u8]) -> Cow<'a, [u8]> {
+        match self {
+            CaseFold::None => Cow::Borrowed(data),
+            CaseFold::Unicode => Cow::Owned(fold_unicode(data)),
+            CaseFold::Ascii if !data.iter().any(u8::is_ascii_uppercase) => Cow::Borrowed(data),
+            CaseFold::Ascii => Cow::Owned(data.to_ascii_lowercase()),
+            CaseFold::Map(m) if data.iter().all(|&b| m.get(b) == b) => Cow::Borrowed(data),
+            CaseFold::Map(m) => Cow::Owned(data.iter().map(|&b| m.get(b)).collect()),
+        }
+    }
+
+    /// `apply`, appending the folded bytes to `out`.
+    pub(crate) fn apply_into(self, data: &[u8], out: &mut Vec<u8>) {
+        match self {
+            CaseFold::None => out.extend_from_slice(data),
+            CaseFold::Unicode => fold_unicode_into(data, out),
+            CaseFold::Ascii => out.extend(data.iter().map(u8::to_ascii_lowercase)),
+            CaseFold::Map(m) => out.extend(data.iter().map(|&b| m.get(b))),
+        }
+    }
+}
+
+/// Single‐char fold of `c` if it exists and keeps the UTF‐8 length.
+///
+/// Round‐tripping through uppercase first makes variants such as final
+/// sigma 'ς' and 'σ' fold together, as case folding does.
+fn fold_char(c: char) -> char {
+    fn single(mut it: impl Iterator<Item = char>) -> Option<char> {
+        let c = it.next()?;
+        it.next().is_none().then_some(c)
+    }
+    let folded = single(c.to_uppercase())
+        .and_then(|u| single(u.to_lowercase()))
+        .or_else(|| single(c.to_lowercase()));
+    match folded {
+        Some(f) if f.len_utf8() == c.len_utf8() => f,
+        _ => c,
+    }
+}
+
+fn fold_unicode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    fold_unicode_into(data, &mut out);
+    out
+}
+
+fn fold_unicode_into(data: &[u8], out: &mut Vec<u8>) {
+    let start = out.len();
+    let mut buf = [0u8; 4];
+    for chunk in data.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            out.extend_from_slice(fold_char(c).encode_utf8(&mut buf).as_bytes());
+        }
+        out.extend_from_slice(chunk.invalid());
+    }
+    debug_assert_eq!(out.len() - start, data.len());
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// `pat` folded (and newline‐normalized) the way this index treated its
+    /// text. Searches do this themselves; it's exposed for callers driving
+    /// lower‐level APIs.
+    pub fn normalize<'a>(&self, pat: &'a [u8]) -> Cow<'a, [u8]> {
+        let folded = self.fold.apply(pat);
+        if !self.newlines {
+            return folded;
+        }
+        match crate::newline::strip_crlf(&folded) {
+            Some((stripped, _)) => Cow::Owned(stripped),
+            None => folded,
+        }
+    }
+
+    /// `normalize`, into `buf` (cleared first) instead of a new vector.
+    pub(crate) fn normalize_into(&self, pat: &[u8], buf: &mut Vec<u8>) {
+        buf.clear();
+        self.fold.apply_into(pat, buf);
+        if self.newlines {
+            crate::newline::strip_crlf_in_place(buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ByteMap, CaseFold};
+    use crate::{CsiConfig, CsiIndex};
+
+    #[test]
+    fn unicode_fold_matches_across_case() {
+        let text = "Ünïcödé ΣΟΦΊΑ and İstanbul straße: ünïcödé σοφία and İSTANBUL STRASSE".as_bytes();
+        let cfg = CsiConfig { case_fold: CaseFold::Unicode, ..CsiConfig::default() };
+        let idx = CsiIndex::build_with_config(text, &cfg);
+        let hits = idx.search("ÜNÏCÖDÉ ΣΟΦΊΑ AND".as_bytes());
+        assert_eq!(hits.len(), 2);
+        assert!(text[hits[1]..].starts_with("ünïcödé".as_bytes()));
+        // İ has no 1:1 fold, ß no same-length uppercase; both stay literal
+        assert_eq!(idx.search("ïcödé σοφία and İstanbul STRAßE".as_bytes()), vec![hits[0] + 3]);
+        assert!(idx.search("ïcödé σοφία and istanbul straße".as_bytes()).is_empty());
+
+        let exact = CsiIndex::build(text);
+        assert!(exact.search("ÜNÏCÖDÉ ΣΟΦΊΑ AND".as_bytes()).is_empty());
+    }
+
+    #[test]
+    fn ascii_fold_leaves_other_bytes_alone() {
+        let text = b"ERROR: Disk Full on /dev/sda1; error: disk full on /dev/SDA1; \xc3\x89T\xc3\x89";
+        let cfg = CsiConfig { case_fold: CaseFold::Ascii, ..CsiConfig::default() };
+        let idx = CsiIndex::build_with_config(text, &cfg);
+        assert_eq!(idx.search(b"Error: DISK FULL on /dev/sda1"), [0, 31]);
+        assert_eq!(CaseFold::Ascii.apply(b"\xc3\x89T\xc3\x89").as_ref(), b"\xc3\x89t\xc3\x89");
+        assert!(matches!(CaseFold::Ascii.apply(b"already lower"), std::borrow::Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn byte_map_folds_case_and_separators() {
+        let table = std::array::from_fn(|b| match b as u8 {
+            b'-' | b' ' => b'_',
+            c => c.to_ascii_lowercase(),
+        });
+        let map = ByteMap::new(table);
+        assert_eq!(format!("{map:?}").matches(": ").count(), 28);
+        assert!(ByteMap::checked(std::array::from_fn(|b| (b as u8).wrapping_add(1))).is_none());
+
+        let text = b"WARN Disk-Full on sda1; warn disk_full on sda1; Warn disk full on sdb2!";
+        let cfg = CsiConfig { case_fold: CaseFold::Map(map), ..CsiConfig::default() };
+        let idx = CsiIndex::build_with_config(text, &cfg);
+        assert_eq!(idx.search(b"warn_DISK FULL-on sda1"), [0, 24]);
+        assert_eq!(&text[48..], b"Warn disk full on sdb2!");
+        assert!(idx.search(b"warn disk full on sdc").is_empty());
+
+        let mut file = Vec::new();
+        idx.write_index(&mut file).unwrap();
+        let loaded = CsiIndex::read_index(&file[..], crate::PolyHasher).unwrap();
+        assert_eq!(loaded.search(b"WARN-DISK-FULL"), idx.search(b"warn disk full"));
+        let h = crate::csi_new_with_byte_map(text.as_ptr(), text.len(), table.as_ptr());
+        let pat = b"Warn Disk Full On Sd";
+        assert_eq!(crate::csi_count(h, pat.as_ptr(), pat.len()), 3);
+        crate::csi_free(h);
+    }
+
+    #[test]
+    fn fold_preserves_length_and_invalid_bytes() {
+        let data = b"MiXeD \xff\xfe ASCII \xce\xa3";
+        let folded = CaseFold::Unicode.apply(data);
+        assert_eq!(folded.as_ref(), b"mixed \xff\xfe ascii \xcf\x83");
+    }
+}
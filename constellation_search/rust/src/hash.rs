@@ -0,0 +1,330 @@
+// src/hash.rs
+//! Pluggable window hash and constellation combine.
+//!
+//! The index only needs a hash whose window values can be read off prefix
+//! states in O(1): `window(prefix[i + len], prefix[i], c(len))`, where
+//! `c(len)` is a per‐length constant built by repeated `step` from 1. The
+//! polynomial rolling hash fits (`hi - lo·P^len`), as do CRC‐style hashes
+//! over GF(2) (`hi ^ lo·x^(8·len)`). Constellation keys are then
+//! `combine(window(anchor), window(target), d)`.
+//!
+//! `PolyHasher` is fixed, so inputs crafted against it collide on purpose;
+//! `SeededHasher` derives its base and combine from a per‐index seed, which
+//! saved tables record.
+
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::hash::BuildHasher;
+#[cfg(feature = "std")]
+use std::collections::hash_map::RandomState;
+
+use crate::BASE_P;
+
+/// Hash family used to key the gap tables
+pub trait Hasher: Send + Sync {
+    /// Identifies the family (and its parameters). Saved tables record it so
+    /// a load can refuse tables keyed by a different hasher.
+    const TAG: u32;
+
+    /// Prefix state of `data[..i + 1]` from that of `data[..i]`; the empty
+    /// prefix's state is 0.
+    fn extend(&self, prefix: u64, byte: u8) -> u64;
+
+    /// Window constant for length `len + 1` from the one for `len`; the
+    /// constant for length 0 is 1.
+    fn step(&self, c: u64) -> u64;
+
+    /// Hash of `data[i..i + len]` from prefix states `hi` (at `i + len`) and
+    /// `lo` (at `i`) and the length's window constant `c`.
+    fn window(&self, hi: u64, lo: u64, c: u64) -> u64;
+
+    /// Constellation key for anchor hash `h1`, target hash `h2` and gap `d`.
+    /// Keys of 0 are stored under a fixed nonzero key (0 marks empty table
+    /// slots), which only costs a collision.
+    fn combine(&self, h1: u64, h2: u64, d: u64) -> u64;
+
+    /// Seed the family was instantiated with, saved along with `TAG`; 0
+    /// for families that take none.
+    fn seed(&self) -> u64 {
+        0
+    }
+
+    /// The family under `seed`, for loading tables saved with it, or `None`
+    /// if it takes no seed.
+    fn reseeded(&self, _seed: u64) -> Option<Self> where Self: Sized {
+        None
+    }
+
+    /// Keys of the anchor pairs at `start + t` and `start + t + d` into
+    /// `out[t]`, given prefix states `ph` (covering every window) and `pk`,
+    /// the window constant for `k`. Override to vectorize; the default is
+    /// the scalar `window`/`combine` per position.
+    #[inline(always)]
+    fn keys(&self, ph: &[u64], pk: u64, start: usize, k: usize, d: usize, out: &mut [u64]) {
+        let ph = &ph[start..start + out.len() + d + k];
+        for (i, o) in out.iter_mut().enumerate() {
+            let h1 = self.window(ph[i + k], ph[i], pk);
+            let h2 = self.window(ph[i + d + k], ph[i + d], pk);
+            *o = self.combine(h1, h2, d as u64);
+        }
+    }
+}
+
+/// The default hasher: polynomial rolling hash mod 2⁶⁴ with base
+/// `1315423911`, combined through a murmur3‐style finalizer
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PolyHasher;
+
+impl Hasher for PolyHasher {
+    const TAG: u32 = 1;
+
+    #[inline(always)]
+    fn extend(&self, prefix: u64, byte: u8) -> u64 {
+        prefix.wrapping_mul(BASE_P).wrapping_add(byte as u64)
+    }
+
+    #[inline(always)]
+    fn step(&self, c: u64) -> u64 {
+        c.wrapping_mul(BASE_P)
+    }
+
+    #[inline(always)]
+    fn window(&self, hi: u64, lo: u64, c: u64) -> u64 {
+        hi.wrapping_sub(lo.wrapping_mul(c))
+    }
+
+    #[inline(always)]
+    fn combine(&self, h1: u64, h2: u64, d: u64) -> u64 {
+        fmix(h1 ^ (h2 << 1) ^ (d << 2))
+    }
+
+    /// Eight positions per step with `portable_simd`, scalar for the tail.
+    #[cfg(feature = "simd")]
+    #[inline(always)]
+    fn keys(&self, ph: &[u64], pk: u64, start: usize, k: usize, d: usize, out: &mut [u64]) {
+        use core::simd::u64x8;
+
+        const L: usize = 8;
+        let ph = &ph[start..start + out.len() + d + k];
+        let (pkv, dv) = (u64x8::splat(pk), u64x8::splat((d as u64) << 2));
+        let (c1, c2) = (u64x8::splat(0xff51afd7ed558ccd), u64x8::splat(0xc4ceb9fe1a85ec53));
+        let body = out.len() - out.len() % L;
+        for i in (0..body).step_by(L) {
+            let at = |o: usize| u64x8::from_slice(&ph[i + o..i + o + L]);
+            let h1 = at(k) - at(0) * pkv;
+            let h2 = at(d + k) - at(d) * pkv;
+            let mut x = h1 ^ (h2 << 1) ^ dv;
+            x ^= x >> 33;
+            x *= c1;
+            x ^= x >> 33;
+            x *= c2;
+            x ^= x >> 33;
+            x.copy_to_slice(&mut out[i..i + L]);
+        }
+        for (i, o) in out.iter_mut().enumerate().skip(body) {
+            *o = self.combine(self.window(ph[i + k], ph[i], pk), self.window(ph[i + d + k], ph[i + d], pk), d as u64);
+        }
+    }
+}
+
+/// `PolyHasher`'s scheme with its constants drawn from a seed: the rolling
+/// base, and a key the combine mixes in before either window, so collisions
+/// can't be precomputed and differ from index to index. Saved tables record
+/// the seed; any `SeededHasher` can load them and takes it on.
+///
+/// Keys differ from a `PolyHasher` index's, so the two aren't
+/// interchangeable, and key extraction is scalar even with `simd`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeededHasher {
+    seed: u64,
+    base: u64,
+    mix:  u64,
+}
+
+impl SeededHasher {
+    /// The hasher for `seed`; equal seeds key tables alike.
+    pub fn new(seed: u64) -> Self {
+        // ≡ 5 mod 8: odd, with powers of the longest period mod 2⁶⁴
+        let base = splitmix(seed) & !7 | 5;
+        SeededHasher { seed, base, mix: splitmix(seed ^ base) }
+    }
+
+    /// A hasher under a seed drawn from the process's random keys.
+    #[cfg(feature = "std")]
+    pub fn random() -> Self {
+        Self::new(RandomState::new().hash_one(0u64))
+    }
+}
+
+impl Hasher for SeededHasher {
+    const TAG: u32 = 2;
+
+    #[inline(always)]
+    fn extend(&self, prefix: u64, byte: u8) -> u64 {
+        prefix.wrapping_mul(self.base).wrapping_add(byte as u64)
+    }
+
+    #[inline(always)]
+    fn step(&self, c: u64) -> u64 {
+        c.wrapping_mul(self.base)
+    }
+
+    #[inline(always)]
+    fn window(&self, hi: u64, lo: u64, c: u64) -> u64 {
+        hi.wrapping_sub(lo.wrapping_mul(c))
+    }
+
+    /// Injective in `h2` for a given anchor and gap, as `fmix` is.
+    #[inline(always)]
+    fn combine(&self, h1: u64, h2: u64, d: u64) -> u64 {
+        fmix(fmix(h1 ^ self.mix).wrapping_add(h2) ^ d.wrapping_mul(0x9e3779b97f4a7c15))
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn reseeded(&self, seed: u64) -> Option<Self> {
+        Some(Self::new(seed))
+    }
+}
+
+/// murmur3's 64‐bit finalizer, a bijection
+#[inline(always)]
+fn fmix(mut x: u64) -> u64 {
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51afd7ed558ccd);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xc4ceb9fe1a85ec53);
+    x ^= x >> 33;
+    x
+}
+
+/// splitmix64's output function
+fn splitmix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Prefix states of `data`: element `i` covers `data[..i]`.
+#[inline(always)]
+pub(crate) fn prefix_hash<H: Hasher>(h: &H, data: &[u8]) -> Vec<u64> {
+    let mut ph = Vec::with_capacity(data.len() + 1);
+    ph.push(0u64);
+    for (i, &b) in data.iter().enumerate() {
+        ph.push(h.extend(ph[i], b));
+    }
+    ph
+}
+
+/// Window constant for length `len`, i.e. `step` applied `len` times to 1.
+pub(crate) fn window_const<H: Hasher>(h: &H, len: usize) -> u64 {
+    (0..len).fold(1u64, |c, _| h.step(c))
+}
+
+/// 16‐bit fingerprint of a constellation span's window hash
+/// (`CsiConfig::fingerprints`): the top bits after a multiply, which depend
+/// on every bit of the hash.
+#[inline(always)]
+pub(crate) fn fingerprint(span: u64) -> u16 {
+    (span.wrapping_mul(0x9e3779b97f4a7c15) >> 48) as u16
+}
+
+/// Constellation key of the anchor pair at `i` and `i + d`, from prefix states
+/// `ph` and `pk`, the window constant for `k`. Bounds‐checked; the build
+/// loops inline this.
+#[inline(always)]
+pub(crate) fn key_at<H: Hasher>(h: &H, ph: &[u64], pk: u64, i: usize, k: usize, d: usize) -> u64 {
+    let h1 = h.window(ph[i + k], ph[i], pk);
+    let h2 = h.window(ph[i + d + k], ph[i + d], pk);
+    h.combine(h1, h2, d as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Hasher, PolyHasher};
+    use crate::{CsiConfig, CsiIndex, BASE_P};
+
+    /// Same polynomial scheme with another base and a plain combine.
+    struct AltHasher;
+
+    impl Hasher for AltHasher {
+        const TAG: u32 = 0xa17;
+        fn extend(&self, prefix: u64, byte: u8) -> u64 { prefix.wrapping_mul(31).wrapping_add(byte as u64 + 1) }
+        fn step(&self, c: u64) -> u64 { c.wrapping_mul(31) }
+        fn window(&self, hi: u64, lo: u64, c: u64) -> u64 { hi.wrapping_sub(lo.wrapping_mul(c)) }
+        fn combine(&self, h1: u64, h2: u64, d: u64) -> u64 { (h1 ^ h2.rotate_left(17) ^ d).wrapping_mul(0x9e3779b97f4a7c15) | 1 }
+    }
+
+    #[test]
+    fn custom_hasher_gives_same_matches() {
+        let text = b"swap the hash, keep the answers; swap the hash, keep the answers!".repeat(4);
+        let alt = CsiIndex::build_with_hasher(&text, &CsiConfig::default(), AltHasher);
+        let poly = CsiIndex::build(&text);
+        for pat in [&text[..30], &text[5..60], &text[33..100]] {
+            assert_eq!(alt.search(pat), poly.search(pat));
+        }
+        assert_eq!(alt.validate(), Ok(()));
+    }
+
+    /// The constellation key as computed before hashing became pluggable.
+    fn legacy_key(win1: &[u8], win2: &[u8], d: u64) -> u64 {
+        let poly = |w: &[u8]| w.iter().fold(0u64, |h, &b| h.wrapping_mul(BASE_P).wrapping_add(b as u64));
+        let mut x = poly(win1) ^ (poly(win2) << 1) ^ (d << 2);
+        x ^= x >> 33;
+        x = x.wrapping_mul(0xff51afd7ed558ccd);
+        x ^= x >> 33;
+        x = x.wrapping_mul(0xc4ceb9fe1a85ec53);
+        x ^= x >> 33;
+        x
+    }
+
+    #[test]
+    fn poly_hasher_is_bit_identical_to_legacy_keys() {
+        let text: Vec<u8> = (0u32..3000).map(|i| (i.wrapping_mul(2654435761) >> 7) as u8).collect();
+        let idx = CsiIndex::build(&text);
+        let k = idx.k;
+        for (key, off) in idx.fingerprints().step_by(13) {
+            let d = (0..idx.gaps.len())
+                .map(|g| idx.gaps[g])
+                .find(|&d| legacy_key(&text[off..off + k], &text[off + d..off + d + k], d as u64) == key);
+            assert!(d.is_some(), "no gap reproduces key at {off}");
+        }
+        let ph = super::prefix_hash(&PolyHasher, b"xyzzy");
+        let pk = super::window_const(&PolyHasher, 2);
+        assert_eq!(super::key_at(&PolyHasher, &ph, pk, 0, 2, 3), legacy_key(b"xy", b"zy", 3));
+    }
+
+    #[test]
+    fn seeded_tables_differ_per_seed_and_reload_under_theirs() {
+        use super::SeededHasher;
+
+        let text = b"same answers under every seed; same answers under every seed".repeat(6);
+        let (a, b) = (SeededHasher::new(1), SeededHasher::new(2));
+        let (ia, ib) = (CsiIndex::build_with_hasher(&text, &CsiConfig::default(), a), CsiIndex::build_with_hasher(&text, &CsiConfig::default(), b));
+        let poly = CsiIndex::build(&text);
+        for pat in [&text[..30], &text[7..70], &b"every seeds"[..]] {
+            assert_eq!(ia.search(pat), poly.search(pat));
+            assert_eq!(ib.search(pat), poly.search(pat));
+        }
+        // a seed changes every key, not just where keys land
+        let keys = |idx: &CsiIndex<SeededHasher>| idx.flat[0].keys.iter().filter(|&&k| k != 0).copied().collect::<std::collections::HashSet<u64>>();
+        assert!(keys(&ia).is_disjoint(&keys(&ib)));
+        assert_ne!(SeededHasher::random().seed(), SeededHasher::random().seed());
+
+        // any seeded hasher loads the tables and takes on their seed
+        let mut bytes = Vec::new();
+        ia.write_index(&mut bytes).unwrap();
+        let back = CsiIndex::read_index(&bytes[..], SeededHasher::new(99)).unwrap();
+        assert_eq!(back.hasher, a);
+        assert_eq!(back.validate(), Ok(()));
+        assert_eq!(back.search(&text[7..70]), poly.search(&text[7..70]));
+        assert!(CsiIndex::read_index(&bytes[..], PolyHasher).is_err());
+
+        let batch = ia.compile_batch(&[&text[..30]]);
+        assert_eq!(batch.run_against(&back), Ok(vec![poly.search(&text[..30])]));
+        assert_eq!(batch.run_against(&ib), Err(crate::CsiIndexError::IncompatibleBatch));
+    }
+}
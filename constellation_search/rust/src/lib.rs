@@ -1,254 +1,1880 @@
 // src/lib.rs
 //! Single‐threaded, zero‐allocation Rust CSI using one‐pass open‐address buckets.
+//!
+//! From Rust, use `CsiIndex` directly: `CsiIndex::build(text)` (or a
+//! `CsiIndexBuilder`) and then `search`, `count` or `search_iter`. No call
+//! needs `unsafe`, and an index is `Send + Sync`, so it can be shared across
+//! threads behind an `Arc`. The `csi_*` functions are the same index behind
+//! a C ABI (see `include/csi.h`) for other languages, on every target but
+//! wasm32, where the `wasm` feature exports `WasmCsiIndex` to JS instead.
+//! The `regex` feature adds `CsiIndex::search_regex`, which prefilters a
+//! regex's windows through the index, and the `bio` feature a nucleotide
+//! preset and `CsiIndex::search_revcomp` for both DNA strands. Under the
+//! `tracing` feature builds and searches report spans and events to a
+//! `tracing` subscriber; `CsiIndex::search_with_metrics` needs no feature.
+//!
+//! Without the default `std` feature the crate is `no_std` and builds,
+//! appends to and searches indexes with nothing but `alloc`. Left out then
+//! are the C ABI, serialized indexes, readers, build timings and the
+//! features that need an OS (every optional dependency turns `std` on);
+//! the entropy that picks `k` and the gaps comes from integer logarithms,
+//! and the maps the build keeps are ordered ones. Only the rlib builds so:
+//! `cargo rustc --lib --no-default-features --crate-type rlib` (the cdylib
+//! needs `std`; see `README.md`).
 
-use std::slice;
-use std::os::raw::c_uchar;
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+#![cfg_attr(feature = "simd", feature(portable_simd))]
 
-/// Opaque handle passed over FFI
-#[repr(C)]
-pub struct CSIHandle {
-    inner: Box<CSIIndex>,
+#[macro_use]
+extern crate alloc;
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::ops::{ControlFlow, Range};
+#[cfg(not(feature = "std"))]
+use alloc::collections::{BTreeMap as Map, BTreeSet as Set};
+// `alloc` has no hash maps, so without `std` the ordered ones stand in
+#[cfg(feature = "std")]
+use std::collections::{HashMap as Map, HashSet as Set};
+#[cfg(feature = "std")]
+use std::time::Instant;
+#[cfg(not(feature = "std"))]
+use stats::Instant;
+
+mod align;
+mod analytics;
+mod append;
+mod approx;
+mod array;
+mod batch;
+#[cfg(feature = "bio")]
+mod bio;
+mod boolean;
+mod borrowed;
+mod builder;
+mod cancel;
+mod classes;
+mod corpus;
+mod error;
+#[cfg(feature = "std")]
+mod external;
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+mod ffi;
+mod fold;
+mod hash;
+#[cfg(feature = "memmap2")]
+mod mapped;
+mod mask;
+mod merge;
+mod newline;
+mod packed;
+mod periodic;
+mod presence;
+#[cfg(feature = "python")]
+mod python;
+mod query;
+#[cfg(feature = "regex")]
+mod regex;
+mod scored;
+mod scratch;
+mod set;
+#[cfg(feature = "std")]
+mod sidecar;
+mod stats;
+#[cfg(feature = "arc-swap")]
+mod store;
+mod syncmer;
+mod text;
+mod tombstone;
+mod validate;
+mod verify;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+pub use analytics::{estimate_jaccard, FrequentRegion};
+pub use batch::CompiledBatch;
+#[cfg(feature = "bio")]
+pub use bio::{reverse_complement, Strand};
+pub use boolean::Query;
+pub use borrowed::BorrowedIndex;
+pub use builder::CsiIndexBuilder;
+pub use error::CsiIndexError;
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub use ffi::*;
+pub use fold::{ByteMap, CaseFold};
+pub use hash::{Hasher, PolyHasher, SeededHasher};
+pub use mask::ByteMask;
+#[cfg(feature = "memmap2")]
+pub use mapped::MmapSourceIndex;
+pub use presence::PresenceIndex;
+#[cfg(feature = "python")]
+pub use python::ConstellationIndex;
+pub use query::{set_span, Match, MatchMode};
+pub use scored::OrderBy;
+pub use scratch::SearchScratch;
+pub use set::CsiIndexSet;
+pub use stats::{BuildTimings, GapLookup, GapOccupancy, GapProbe, GapStats, IndexStats, IndexSummary, MatchStats, MemUsage, MissReport, QueryMetrics, QueryRoute, SearchDiag};
+#[cfg(feature = "arc-swap")]
+pub use store::{CsiStore, ProgressiveIndex};
+pub use text::TextIndex;
+pub use validate::ValidationError;
+#[cfg(feature = "wasm")]
+pub use wasm::WasmCsiIndex;
+
+use array::{Array, List, Offsets, Slots, Word};
+use hash::{fingerprint, key_at, prefix_hash, window_const};
+use packed::Packed;
+use syncmer::Syncmers;
+
+// searches take `&self`, so sharing an index between threads must stay
+// sound; the FFI hands out `*const` handles on the same promise
+const _: () = {
+    const fn shared<T: Send + Sync>() {}
+    shared::<CsiIndex>();
+};
+
+/// Build options; `Default` reproduces the plain entropy‐tuned build
+#[derive(Debug, Clone)]
+pub struct CsiConfig {
+    /// Buckets holding more postings than this are stored as "saturated":
+    /// the key is kept but its offsets are dropped. Saturated constellations
+    /// are skipped during candidate generation, trading extra verify work
+    /// for memory on repetitive text; results stay exact.
+    pub max_postings_per_key: Option<usize>,
+    /// With the `rayon` feature, gap tables are built concurrently: by
+    /// default (0) on the current rayon pool, which is the global one unless
+    /// the build runs inside an embedder's `install`; above 1 on a dedicated
+    /// pool of this many threads that lives only for the build, so no
+    /// existing pool is resized or oversubscribed. 1 builds sequentially, as
+    /// does every value without the feature.
+    ///
+    /// Each table is filled by one thread, since linear probing places keys
+    /// in text order and the layout must not depend on scheduling; threads
+    /// beyond the number of gaps don't speed a build up.
+    pub threads: usize,
+    /// When false, searches skip the final byte compare and return raw
+    /// candidates, which may include hash‐collision false positives.
+    pub verify: bool,
+    /// Case folding applied to the text at build and to every pattern; the
+    /// index stores (and verifies against) the folded text, so a folded
+    /// index can't answer byte‐exact queries, nor the reverse.
+    pub case_fold: CaseFold,
+    /// Collapse `\r\n` to `\n` in the indexed text and in every pattern, so
+    /// queries match across line‐ending styles. The text gets shorter, so
+    /// search offsets refer to the normalized text; map them back to source
+    /// bytes with `CsiIndex::original_offset`.
+    pub normalize_newlines: bool,
+    /// Record the offset of every `\n` in the indexed text (8 bytes per
+    /// line), so `CsiIndex::resolve_position` and `search_lines` find a
+    /// match's line by binary search instead of counting from the start.
+    /// The line index is kept through `append`, `truncate` and sidecars.
+    pub track_lines: bool,
+    /// Periodic don't‐care bytes (see `ByteMask`): zeroed in the indexed
+    /// text and matched against anything by `search`, which then costs one
+    /// pass per mask period.
+    pub mask: Option<ByteMask>,
+    /// Build tables for only this many of the smallest gaps (at least one).
+    /// Fewer tables mean less memory and weaker candidate filtering; results
+    /// stay exact, and the minimum indexable length is unchanged.
+    pub max_gaps: Option<usize>,
+    /// Entropy tiers used to pick `k` and gaps.
+    pub tiers: EntropyTiers,
+    /// Keep the hash powers `B^0..=B^n` (8 bytes per text byte) after the
+    /// build instead of only `B^0..=B^k`. `None` picks automatically: no
+    /// search, scoring or validation path (with any feature set) reads
+    /// past `B^k`, so the tail is dropped. Only `CsiIndex::powers` exposes
+    /// the rest.
+    pub keep_powers: Option<bool>,
+    /// Index constellations only at start offsets that are multiples of
+    /// this (0 and 1 index every offset), dividing table memory by about
+    /// `stride`.
+    ///
+    /// Any occurrence at least `stride` bytes longer than the anchors
+    /// covers one indexed offset, so searches look the pattern up at each
+    /// of its first `stride` positions (one phase each) and shift the hits
+    /// back; the minimum pattern length grows by `stride - 1` and, above
+    /// it, results stay exact. Each search costs up to `stride` times the
+    /// lookups and intersections.
+    pub stride: usize,
+    /// Index only the constellation starts whose anchor k‐mer opens or
+    /// closes a syncmer over windows of this many k‐mers: of the k‐mer
+    /// hashes at the start and the `window - 1` starts after it, the
+    /// first or the last is the smallest. `None` (and 0 or 1) indexes every
+    /// start; otherwise `stride` is ignored.
+    ///
+    /// Whether a start is indexed depends only on the `k + window - 1`
+    /// bytes from it, and among any `window` consecutive starts one is, so
+    /// a search looks the pattern up at its first indexed start alone and
+    /// results stay exact. Tables shrink by about `(window + 1) / 2` on
+    /// varied text (less where k‐mers repeat, as ties are indexed), for a
+    /// minimum pattern length larger by about `2 * (window - 1)`.
+    pub syncmer_window: Option<usize>,
+    /// `build_from_slices` only: store one copy of byte‐identical slices
+    /// and report every match in it once per duplicate (see
+    /// `CsiIndex::search_docs`). Only exact duplicates are merged; slices
+    /// that differ in a single byte, or only in case or line endings, are
+    /// stored separately.
+    pub dedup: bool,
+    /// Target share of occupied slots per gap table, in `(0, 1)`. `None`
+    /// (and anything outside that range) sizes tables to the next power of
+    /// two holding at least twice the constellations, a load of 0.25 to 0.5;
+    /// a target sizes them to exactly `constellations / load` slots instead,
+    /// trading probe length for memory at a finer grain than doubling.
+    /// Such tables are rarely a power of two and reduce keys to slots by a
+    /// multiply‐shift rather than a mask. Loads above about 0.8 lengthen
+    /// probes enough that more keys reach the overflow table. Targets
+    /// below 0.01 fail `try_build_with_config` with
+    /// `CsiIndexError::LoadFactor` and panic the infallible builds, as
+    /// their tables would outgrow memory (or `usize`) for no gain.
+    pub load_factor: Option<f64>,
+    /// Slots of each gap table, in gap order, instead of sizing them from
+    /// the text: for layouts (and `write_sidecar` output) that stay the
+    /// same across versions and tiering changes. Each must be a power of
+    /// two with a slot for every indexed constellation of its gap; one that
+    /// is missing or too small fails `try_build_with_config` with
+    /// `CsiIndexError::TableSize` and panics the infallible builds.
+    /// Overrides `load_factor`.
+    pub table_sizes: Option<Vec<usize>>,
+    /// Store a 16‐bit hash of each posting's whole constellation span (both
+    /// anchors and the bytes between them) beside it, 2 bytes per posting.
+    /// Candidates whose span hash differs from the pattern's are dropped
+    /// before verification without touching the text, which pays off when
+    /// many positions share anchors but differ in between; results are
+    /// unchanged.
+    pub fingerprints: bool,
+    /// Answer patterns shorter than `CsiIndex::min_pattern_len` by scanning
+    /// the text (default true). Off, searches report only what the tables
+    /// can find, so those patterns match nothing; for callers that want
+    /// every query index‐backed and screen out short ones themselves.
+    pub scan_short_patterns: bool,
+    /// Keep every gap table's bucket starts and lengths, and its postings,
+    /// as 64‐bit words. By default the starts and lengths are stored in 32
+    /// bits whenever the table holds fewer than 4 Gi postings, and the
+    /// postings whenever every offset fits, as in any text under 4 GiB,
+    /// halving both; lookups and intersections widen them on read, so
+    /// results are unchanged, but `CsiIndex::posting_list` then hands out a
+    /// widened copy instead of borrowing the table.
+    pub wide_slots: bool,
+    /// Store buckets holding more postings than this delta‐encoded, a byte
+    /// or two per posting instead of four; `None` (the default) keeps every
+    /// bucket plain. Searches decode a packed list block by block, only
+    /// where the candidates from the other lists fall, so a repetitive text
+    /// whose memory goes to a few huge buckets shrinks a lot for little
+    /// query work; a pattern whose every list is packed decodes the
+    /// shortest in full. Buckets over `max_postings_per_key` are still
+    /// dropped, and packed ones keep no fingerprints.
+    pub pack_postings: Option<usize>,
+    /// `CsiIndex::write_index_external` only: bytes of postings to sort in
+    /// memory before spilling them to a temporary file, at 24 bytes a
+    /// posting. `None` sorts each table's postings in one run, without
+    /// temporary files. The other builds ignore it.
+    pub max_memory_bytes: Option<usize>,
+}
+
+impl CsiConfig {
+    /// Whether the build rewrites the text (folding, newline normalization
+    /// or a mask), leaving the index a text of its own to verify against.
+    pub(crate) fn rewrites_text(&self) -> bool {
+        self.case_fold != CaseFold::None || self.normalize_newlines || self.mask.is_some()
+    }
+}
+
+impl Default for CsiConfig {
+    fn default() -> Self {
+        CsiConfig {
+            max_postings_per_key: None,
+            threads: 0,
+            verify: true,
+            case_fold: CaseFold::None,
+            normalize_newlines: false,
+            track_lines: false,
+            mask: None,
+            max_gaps: None,
+            tiers: EntropyTiers::default(),
+            keep_powers: None,
+            stride: 1,
+            syncmer_window: None,
+            dedup: false,
+            load_factor: None,
+            table_sizes: None,
+            fingerprints: false,
+            scan_short_patterns: true,
+            wide_slots: false,
+            pack_postings: None,
+            max_memory_bytes: None,
+        }
+    }
+}
+
+/// Entropy cutoffs and the `(k, gaps)` used in each tier: entropy below
+/// `low` picks `params[0]`, below `high` picks `params[1]`, else `params[2]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntropyTiers {
+    pub low:    f64,
+    pub high:   f64,
+    pub params: [(usize, Vec<usize>); 3],
+}
+
+impl Default for EntropyTiers {
+    fn default() -> Self {
+        EntropyTiers {
+            low:  3.5,
+            high: 4.5,
+            params: [
+                (6, vec![8,16,32,64]),
+                (5, vec![6,12,24,48]),
+                (4, vec![4,8,16,32]),
+            ],
+        }
+    }
+}
+
+impl EntropyTiers {
+    /// `(k, gaps)` at every entropy, for inputs the cutoffs misjudge (DNA's
+    /// four‐letter alphabet, say); builds then skip measuring the entropy.
+    ///
+    /// Panics if `k` is 0 or `gaps` is empty.
+    pub fn fixed(k: usize, gaps: Vec<usize>) -> Self {
+        assert!(k >= 1 && !gaps.is_empty(), "fixed tiers need k >= 1 and at least one gap");
+        let params = (k, gaps);
+        EntropyTiers { params: [params.clone(), params.clone(), params], ..EntropyTiers::default() }
+    }
+
+    /// The `(k, gaps)` tier for a byte entropy in bits.
+    pub fn choose(&self, entropy: f64) -> (usize, Vec<usize>) {
+        let tier = if entropy < self.low { 0 } else if entropy < self.high { 1 } else { 2 };
+        self.params[tier].clone()
+    }
 }
 
 /// A single gap’s open‐address bucket table
+#[derive(Default)]
 struct FlatIndex {
     table_size: usize,
-    keys:       Vec<u64>,   // len = table_size, 0 means empty
-    starts:     Vec<usize>, // len = table_size, prefix‐sum start offsets
-    lens:       Vec<usize>, // len = table_size, count of entries
-    offs:       Vec<usize>, // all offsets, grouped by bucket
+    keys:       Array<u64>,   // len = table_size, 0 means empty
+    starts:     Slots,        // len = table_size, prefix‐sum start offsets
+    lens:       Slots,        // len = table_size, count of entries (or SATURATED)
+    offs:       Offsets,      // all offsets, grouped by bucket
+    fps:        Array<u16>,   // span fingerprint per offset; empty unless kept
+    // keys that found no slot within MAX_PROBE of home, as
+    // (key, start, len) sorted by key; postings live in `offs` too
+    overflow:   Vec<(u64, usize, usize)>,
+    // buckets over CsiConfig::pack_postings, sorted by key; their slots
+    // read SATURATED and hold nothing in `offs`
+    packed:     Vec<(u64, Packed)>,
 }
 
-/// Main index
-struct CSIIndex {
+/// Main index, keyed by hasher `H`
+pub struct CsiIndex<H = PolyHasher> {
     k:    usize,
     gaps: Vec<usize>,
     flat: Vec<FlatIndex>,
-    text: Vec<u8>,
-    pw:   Vec<u64>, // rolling‐hash powers
+    text: Array<u8>,
+    pw:   Vec<u64>, // window constants by length (powers of BASE_P by default)
+    bounds: Vec<usize>, // start offset of each stored source slice; empty if built from one
+    aliases: corpus::Aliases, // source slices sharing each stored one; empty without duplicates
+    ids:    Vec<usize>, // caller's ID for each source slice; empty to number them by position
+    cap:    usize,      // max_postings_per_key, SATURATED when unlimited
+    stride: usize,      // only start offsets that are multiples of this are indexed
+    window: usize,      // CsiConfig::syncmer_window, 1 when every (stride‐th) start is indexed
+    verify: bool,
+    fold: CaseFold,
+    newlines: bool,
+    crlf:   Vec<usize>, // normalized offsets of `\n`s that lost a `\r`
+    lines:  Option<Vec<usize>>, // offsets of every `\n`, with CsiConfig::track_lines
+    dead:   Vec<Range<usize>>,  // invalidated ranges, ascending and disjoint
+    mask:   Option<ByteMask>,
+    digest: u64,        // hash of the whole text, i.e. ph[text.len()]
+    text_len: usize,    // length of the indexed text, also when it isn't stored
+    load:   Option<f64>, // CsiConfig::load_factor the tables were sized with
+    fixed:  bool,        // sized by CsiConfig::table_sizes, which `compact` keeps
+    fps:    bool,        // CsiConfig::fingerprints
+    scan_short: bool,    // CsiConfig::scan_short_patterns
+    wide:   bool,        // CsiConfig::wide_slots
+    pack:   usize,       // CsiConfig::pack_postings, SATURATED when off
+    hasher: H,
 }
 
 const BASE_P: u64 = 1315423911;
 
-impl CSIIndex {
+/// Positions whose keys `FlatIndex::refill` extracts per `Hasher::keys` call
+const KEY_BATCH: usize = 64;
+
+/// Bytes hashed between progress callbacks in `build_cancellable`
+const PROGRESS_CHUNK: usize = 1 << 16;
+
+/// Slots a lookup probes before falling back to the overflow table. Kept
+/// loose enough that with load ≤ 0.5 only adversarial or freakishly
+/// clustered keys ever reach it.
+const MAX_PROBE: usize = 64;
+
+/// `lens` marker for a bucket whose postings were dropped at build time
+const SATURATED: usize = usize::MAX;
+
+/// Smallest `CsiConfig::load_factor` a build accepts
+pub(crate) const MIN_LOAD: f64 = 0.01;
+
+/// A list this many times longer than the one it's intersected with is
+/// galloped through instead of merged
+const GALLOP_RATIO: usize = 16;
+
+/// Postings below which `par_search` runs `search` instead: its hand‐off
+/// and pairwise tree cost some 20 µs plus a quarter of `search`'s work,
+/// which two threads only win back from about this many postings (see
+/// `benches/par_search.rs`)
+#[cfg(feature = "rayon")]
+const PAR_MIN_POSTINGS: usize = 1 << 14;
+
+/// A verifying search stops probing a phase's gap tables once one gives a
+/// posting list shorter than this: checking so few candidates' bytes costs
+/// less than the table probes and intersections that would rule them out
+const PLAN_CUTOFF: usize = 16;
+
+/// What a constellation key of 0, the empty‐slot marker, is stored and
+/// looked up as
+const ZERO_KEY: u64 = 0x9e3779b97f4a7c15;
+
+/// Pattern offset of one search phase and the posting lists probed there,
+/// plain and packed
+type Phase<'a> = (usize, Vec<List<'a>>, Vec<&'a Packed>);
+
+/// Arrays of a previous index for `build_phased` to refill, and prefix
+/// hashes already taken over the start of the text
+#[derive(Default)]
+struct Spare {
+    pw:   Vec<u64>,
+    flat: Vec<FlatIndex>,
+    // `ph[i]` over the first `i` bytes, by the build's hasher; dropped if
+    // the config rewrites the text
+    ph:   Vec<u64>,
+}
+
+/// Result of probing one gap table for a constellation
+enum Probe<'a> {
+    Missing,
+    Saturated,
+    List(List<'a>),
+    Packed(&'a Packed),
+}
+
+impl<'a> Probe<'a> {
+    /// The postings found, borrowed if plain and stored as `usize`s, else
+    /// widened or decoded.
+    fn postings(self) -> Option<Cow<'a, [usize]>> {
+        match self {
+            Probe::List(l) => Some(l.widened()),
+            Probe::Packed(l) => Some(Cow::Owned(l.iter().collect())),
+            Probe::Missing | Probe::Saturated => None,
+        }
+    }
+}
+
+impl CsiIndex {
+    /// Build an index over `data`, choosing `k` and gaps from its entropy.
+    pub fn build(data: &[u8]) -> Self {
+        Self::build_from(data)
+    }
+
+    /// Build an index over `data` with the given options.
+    pub fn build_with_config(data: &[u8], config: &CsiConfig) -> Self {
+        Self::build_inner(data, config)
+    }
+
+    /// `build_with_config`, returning `CsiIndexError::TableSize` instead of
+    /// panicking when `config.table_sizes` doesn't fit the data.
+    pub fn try_build_with_config(data: &[u8], config: &CsiConfig) -> Result<Self, CsiIndexError> {
+        Self::build_phased(data.to_vec().into(), config, PolyHasher, None, None, Spare::default())
+    }
+
+    /// Build with a fixed `k` and gap schedule instead of picking them from
+    /// the data's entropy, which isn't measured at all. Gaps are sorted and
+    /// deduplicated as the tiers' are; `None` if `k` is 0, `gaps` is empty
+    /// or a gap is too wide for any text (`CsiIndexError::GapTooWide`).
+    pub fn build_with(data: &[u8], k: usize, gaps: &[usize]) -> Option<Self> {
+        if k == 0 || gaps.is_empty() || too_wide(k, gaps).is_some() {
+            return None;
+        }
+        let tiers = EntropyTiers::fixed(k, gaps.to_vec());
+        Some(Self::build_inner(data, &CsiConfig { tiers, ..CsiConfig::default() }))
+    }
+
+    /// Build with custom entropy cutoffs and per‐tier `(k, gaps)`, still
+    /// measuring the data's entropy to pick the tier.
+    ///
+    /// Panics if any tier has `k == 0` or no gaps.
+    pub fn build_auto_with_thresholds(data: &[u8], low: f64, high: f64,
+                                      params: [(usize, Vec<usize>); 3]) -> Self {
+        assert!(params.iter().all(|(k, g)| *k >= 1 && !g.is_empty()),
+                "every tier needs k >= 1 and at least one gap");
+        let tiers = EntropyTiers { low, high, params };
+        Self::build_inner(data, &CsiConfig { tiers, ..CsiConfig::default() })
+    }
+
+    /// Build over the concatenation of `slices`, recording where each one
+    /// starts so `locate` can map offsets back. The slices are copied once,
+    /// straight into the index's text, without an intermediate buffer.
+    ///
+    /// With `CsiConfig::dedup`, a slice byte‐identical to an earlier one is
+    /// not copied again but recorded as an alias of it; slices are grouped
+    /// by their polynomial hash and length, then compared in full.
+    pub fn build_from_slices(slices: &[&[u8]], config: &CsiConfig) -> Self {
+        let mut stored: Vec<&[u8]> = Vec::with_capacity(slices.len());
+        let mut slot_of = Vec::new();
+        if config.dedup {
+            let mut seen: Map<(u64, usize), usize> = Map::new();
+            for &s in slices {
+                let key = (s.iter().fold(0, |h, &b| PolyHasher.extend(h, b)), s.len());
+                let slot = match seen.get(&key) {
+                    Some(&i) if stored[i] == s => i,
+                    // a colliding hash only costs this slice its dedup
+                    _ => {
+                        seen.entry(key).or_insert(stored.len());
+                        stored.push(s);
+                        stored.len() - 1
+                    }
+                };
+                slot_of.push(slot);
+            }
+        } else {
+            stored.extend_from_slice(slices);
+        }
+        let mut text = Vec::with_capacity(stored.iter().map(|s| s.len()).sum());
+        let mut bounds = Vec::with_capacity(stored.len());
+        for s in &stored {
+            bounds.push(text.len());
+            text.extend_from_slice(s);
+        }
+        let mut idx = Self::build_owned(text, config);
+        idx.bounds = bounds.iter().map(|&b| idx.normalized_offset(b)).collect();
+        if stored.len() < slices.len() {
+            idx.aliases = corpus::Aliases::group(&slot_of, stored.len());
+        }
+        idx
+    }
+
+    /// Like `build_with_config`, also reporting how long each build phase
+    /// took. Gap tables are built one after another here, whatever
+    /// `config.threads` says, so `per_gap` reflects each table alone.
+    pub fn build_timed(data: &[u8], config: &CsiConfig) -> (Self, BuildTimings) {
+        let mut timings = BuildTimings::default();
+        let idx = built(Self::build_phased(data.to_vec().into(), config, PolyHasher, Some(&mut timings), None, Spare::default()));
+        (idx, timings)
+    }
+
+    /// Like `build_with_config`, calling `f(done, total)` as the build
+    /// advances and abandoning it with `CsiIndexError::Cancelled` (dropping
+    /// everything built so far) as soon as `f` returns false.
+    ///
+    /// Progress is measured in text bytes processed, over one prefix‐hash
+    /// pass plus one pass per gap table; `f` is called every 64 KiB of the
+    /// hash pass and after each table, ending at `done == total`. Tables are
+    /// built sequentially so each one is a cancellation point.
+    pub fn build_cancellable<F: FnMut(usize, usize) -> bool>(
+        data: &[u8], config: &CsiConfig, mut f: F,
+    ) -> Result<Self, CsiIndexError> {
+        Self::build_phased(data.to_vec().into(), config, PolyHasher, None, Some(&mut f), Spare::default())
+    }
+
+    /// The default build, reporting to `f(done, total)` as
+    /// `build_cancellable` does and abandoning it with
+    /// `CsiIndexError::Cancelled` once `f` breaks.
+    pub fn build_with_progress<F: FnMut(usize, usize) -> ControlFlow<()>>(data: &[u8], mut f: F) -> Result<Self, CsiIndexError> {
+        Self::build_cancellable(data, &CsiConfig::default(), |done, total| f(done, total).is_continue())
+    }
+
+    /// The default build, with as many of the smallest gaps as fit in
+    /// `max_bytes` of heap (as `heap_bytes` counts it: text, kept hash
+    /// powers and gap tables).
+    ///
+    /// A table's size depends only on the text length, `k` and its gap, so
+    /// it's known before building; gaps are taken in ascending order, as
+    /// `CsiConfig::max_gaps` keeps them, while they fit. Fewer gaps weaken
+    /// filtering but results stay exact. `CsiIndexError::OverBudget` if not
+    /// even the smallest gap fits.
+    pub fn build_within_budget(data: &[u8], max_bytes: usize) -> Result<Self, CsiIndexError> {
+        let config = CsiConfig::default();
+        let n = data.len();
+        let (k, mut gaps) = config.tiers.choose(compute_entropy(data));
+        gaps.sort_unstable();
+        gaps.dedup();
+        let mut used = n + (k + 1) * 8;
+        let fits = gaps.iter().take_while(|&&d| {
+            used += table_bytes(n, k, d);
+            used <= max_bytes
+        }).count();
+        if fits == 0 {
+            let needed = n + (k + 1) * 8 + table_bytes(n, k, gaps[0]);
+            return Err(CsiIndexError::OverBudget { needed });
+        }
+        Ok(Self::build_owned(data.to_vec(), &CsiConfig { max_gaps: Some(fits), ..config }))
+    }
+
+    /// Rebuild over `data` with `config` in place, refilling this index's
+    /// text, hash powers and gap tables instead of allocating new ones; the
+    /// result is identical to `build_with_config(data, config)`.
+    ///
+    /// The index doesn't keep the config it was built with, so it is passed
+    /// again. Tables are reused in gap order and only grow when the new text
+    /// needs more slots or postings. A tier change that adds gaps allocates
+    /// their tables fresh, and one that drops gaps frees the rest. Powers
+    /// are only worth reusing under `keep_powers`; other builds trim them.
+    pub fn rebuild(&mut self, data: &[u8], config: &CsiConfig) {
+        let mut text = core::mem::take(&mut self.text);
+        let text = text.cleared();
+        text.extend_from_slice(data);
+        let spare = Spare { pw: core::mem::take(&mut self.pw), flat: core::mem::take(&mut self.flat), ph: Vec::new() };
+        *self = built(Self::build_phased(core::mem::take(text).into(), config, PolyHasher, None, None, spare));
+    }
+
     #[inline(always)]
     fn build_from(data: &[u8]) -> Self {
+        Self::build_inner(data, &CsiConfig::default())
+    }
+
+    #[inline(always)]
+    fn build_inner(data: &[u8], config: &CsiConfig) -> Self {
+        Self::build_owned(data.to_vec(), config)
+    }
+
+    fn build_owned(text: Vec<u8>, config: &CsiConfig) -> Self {
+        built(Self::build_phased(text.into(), config, PolyHasher, None, None, Spare::default()))
+    }
+
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Build with a custom hasher. Tables keyed by different hashers are not
+    /// interchangeable; `H::TAG` identifies which one built an index.
+    pub fn build_with_hasher(data: &[u8], config: &CsiConfig, hasher: H) -> Self {
+        built(Self::build_phased(data.to_vec().into(), config, hasher, None, None, Spare::default()))
+    }
+
+    /// The indexed text, after any case folding (what `search` verifies
+    /// against). Empty for a sidecar from `read_sidecar`, which keeps no
+    /// text of its own; `has_text` tells that from an index over no text.
+    pub fn text(&self) -> &[u8] {
+        &self.text
+    }
+
+    /// Whether the index holds the text it covers, so that `text` and
+    /// `match_bytes` return it: false only for a sidecar of a non‐empty text.
+    pub fn has_text(&self) -> bool {
+        self.text.len() == self.text_len
+    }
+
+    /// `text[off..off + len]`, or `None` if that runs past the end (as any
+    /// non‐empty range does on an index without its text).
+    pub fn match_bytes(&self, off: usize, len: usize) -> Option<&[u8]> {
+        self.text.get(off..off.checked_add(len)?)
+    }
+
+    /// Anchor length chosen at build.
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Gaps that got a table, ascending.
+    pub fn gaps(&self) -> &[usize] {
+        &self.gaps
+    }
+
+    /// Shortest pattern the tables can answer: `k + gaps[0] + k`, two
+    /// anchors at the smallest gap, plus `stride - 1` on a strided index so
+    /// every phase has a constellation. With syncmers, `window - 1` more,
+    /// to hold an indexed start, and at least `2 * (window - 1) + k`, to
+    /// tell which one it is.
+    ///
+    /// Shorter patterns have no constellation to look up, so searches
+    /// answer them by comparing the pattern at every text position, as when
+    /// all of a pattern's constellations are saturated: still exact, but
+    /// linear in the text. Built without `CsiConfig::scan_short_patterns`,
+    /// the index reports no matches for them instead.
+    pub fn min_pattern_len(&self) -> usize {
+        let anchors = self.k + self.gaps[0] + self.k;
+        match self.window {
+            1 => anchors + self.stride - 1,
+            w => w - 1 + anchors.max(self.k + w - 1),
+        }
+    }
+
+    /// Largest gap whose constellation fits in a pattern of `pat_len` bytes
+    /// at every phase (`d + k + stride - 1 <= pat_len`, or `window - 1`
+    /// with syncmers), or `None` below `min_pattern_len`.
+    pub fn max_useful_gap_for(&self, pat_len: usize) -> Option<usize> {
+        if pat_len < self.min_pattern_len() {
+            return None;
+        }
+        let slack = self.stride.max(self.window) - 1;
+        self.gaps.iter().rev().copied().find(|&d| d + self.k + slack <= pat_len)
+    }
+
+    /// Map a text offset to `(slice index, offset within that slice)`.
+    ///
+    /// Indexes built from a single buffer report every offset in slice 0.
+    /// Under `CsiConfig::dedup` the slice is the first of its duplicates.
+    pub fn locate(&self, off: usize) -> (usize, usize) {
+        let (slot, local) = self.locate_stored(off);
+        (self.aliases.of(slot).map_or(slot, |docs| docs[0]), local)
+    }
+
+    /// `locate` in stored slices, before mapping duplicates back.
+    fn locate_stored(&self, off: usize) -> (usize, usize) {
+        match self.bounds.partition_point(|&b| b <= off) {
+            0 => (0, off),
+            i => (i - 1, off - self.bounds[i - 1]),
+        }
+    }
+
+    /// Drop `text[new_len..]` and every posting whose constellation no longer
+    /// fits; a no‐op if `new_len >= text.len()`.
+    ///
+    /// Buckets are trimmed in place (postings are sorted, so it's a binary
+    /// search per bucket). Emptied buckets keep their key and freed posting
+    /// space isn't reclaimed until `compact` or a rebuild.
+    pub fn truncate(&mut self, new_len: usize) {
+        if new_len >= self.text.len() {
+            return;
+        }
+        self.text.to_mut().truncate(new_len);
+        self.text_len = new_len;
+        self.digest = self.text.iter().fold(0, |h, &b| self.hasher.extend(h, b));
+        self.crlf.truncate(self.crlf.partition_point(|&p| p < new_len));
+        self.truncate_dead(new_len);
+        if let Some(lines) = &mut self.lines {
+            lines.truncate(lines.partition_point(|&p| p < new_len));
+        }
+        self.pw.truncate(new_len.max(self.k) + 1);
+        let keep = self.bounds.partition_point(|&b| b < new_len).max(1);
+        self.bounds.truncate(keep);
+        self.aliases.truncate(keep);
+        let p = self.table_params();
+        for (&d, fi) in self.gaps.iter().zip(&mut self.flat) {
+            // last start offset whose constellation (and syncmer) still fits
+            let limit = p.starts(new_len, d);
+            for slot in 0..fi.table_size {
+                let len = fi.lens.get(slot);
+                if fi.keys[slot] == 0 || len == SATURATED {
+                    continue;
+                }
+                let start = fi.starts.get(slot);
+                fi.lens.set(slot, fi.offs.list(start..start + len).partition_point(|o| o < limit));
+            }
+            for e in fi.overflow.iter_mut().filter(|e| e.2 != SATURATED) {
+                e.2 = fi.offs.list(e.1..e.1 + e.2).partition_point(|o| o < limit);
+            }
+            for (_, list) in &mut fi.packed {
+                if list.iter().last().is_some_and(|o| o >= limit) {
+                    *list = Packed::new(&list.iter().take_while(|&o| o < limit).collect::<Vec<_>>());
+                }
+            }
+        }
+    }
+
+    /// Rebuild every gap table from the current text, dropping emptied
+    /// buckets and unused posting space left behind by `truncate`, and the
+    /// postings of constellations overlapping an `invalidate_range`d range.
+    ///
+    /// Keeps this index's `k`, gaps, posting cap and sampling rather than re‐measuring
+    /// entropy, so results are unchanged; tables are built sequentially.
+    /// Buckets saturated before the deletions stay saturated.
+    pub fn compact(&mut self) {
+        let ph = prefix_hash(&self.hasher, &self.text);
+        self.text.to_mut().shrink_to_fit();
+        self.pw.shrink_to_fit();
+        let params = self.table_params();
+        let n = self.text.len();
+        let tables: Vec<(usize, usize)> = self.gaps.iter().zip(&self.flat)
+            .map(|(&d, fi)| (d, if self.fixed { fi.table_size } else { params.slots(n, d) }))
+            .collect();
+        self.flat = build_tables(&self.hasher, &ph, &self.pw, params, &tables, 1, Vec::new());
+        if !self.dead.is_empty() {
+            self.drop_dead(&ph, params);
+        }
+    }
+
+    /// The build proper. Phase timings are only taken when `timings` is set;
+    /// `progress(done, total)` is polled between prefix‐hash chunks and gap
+    /// tables, and returning false abandons the build.
+    ///
+    /// Owned text is kept as the index's text. Borrowed text that no config
+    /// option rewrites is only hashed, leaving the index's text empty; the
+    /// caller then owns it and must verify through `search_over`. Arrays
+    /// in `spare` are refilled instead of allocating new ones.
+    fn build_phased(
+        mut text: Cow<'_, [u8]>,
+        config: &CsiConfig,
+        hasher: H,
+        mut timings: Option<&mut BuildTimings>,
+        mut progress: Option<&mut dyn FnMut(usize, usize) -> bool>,
+        spare: Spare,
+    ) -> Result<Self, CsiIndexError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("csi_build", bytes = text.len()).entered();
+        let mut rewritten = false;
+        if let Cow::Owned(folded) = config.case_fold.apply(&text) {
+            (text, rewritten) = (Cow::Owned(folded), true);
+        }
+        let mut crlf = Vec::new();
+        if config.normalize_newlines && let Some((stripped, lfs)) = newline::strip_crlf(&text) {
+            (text, crlf, rewritten) = (Cow::Owned(stripped), lfs, true);
+        }
+        // before masking, which may blank line breaks
+        let lines = config.track_lines.then(|| newline::line_breaks(&text, 0).collect());
+        if let Some(mask) = &config.mask {
+            mask.apply(text.to_mut(), 0);
+            rewritten = true;
+        }
+        let data = &text[..];
         let n = data.len();
 
         // 1) entropy → choose k & gaps
-        let entropy = compute_entropy(data);
-        let (k, mut gaps) = if entropy < 3.5 {
-            (6, vec![8,16,32,64])
-        } else if entropy < 4.5 {
-            (5, vec![6,12,24,48])
-        } else {
-            (4, vec![4,8,16,32])
+        let started = timings.is_some().then(Instant::now);
+        let (gaps, params, tables) = plan(data, config)?;
+        let k = params.k;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(k, gaps = ?gaps, "planned");
+        if let (Some(bt), Some(t)) = (timings.as_deref_mut(), started) {
+            bt.entropy = t.elapsed();
+        }
+        // progress counts text bytes: one pass to hash, one per gap table
+        let total = n * (1 + gaps.len());
+        let staged = timings.is_some() || progress.is_some();
+        let mut report = |done: usize| match progress.as_deref_mut().map(|f| f(done, total)) {
+            Some(false) => Err(CsiIndexError::Cancelled),
+            _ => Ok(()),
         };
-        gaps.sort_unstable();
 
         // 2) prefix‐hash & powers
-        let mut ph = Vec::with_capacity(n+1);
-        let mut pw = Vec::with_capacity(n+1);
-        ph.push(0u64); pw.push(1u64);
-        for i in 0..n {
-            ph.push(ph[i].wrapping_mul(BASE_P).wrapping_add(data[i] as u64));
-            pw.push(pw[i].wrapping_mul(BASE_P));
+        let started = timings.is_some().then(Instant::now);
+        let mut ph = if rewritten || spare.ph.len() > n + 1 { Vec::new() } else { spare.ph };
+        let mut pw = spare.pw;
+        pw.clear();
+        ph.reserve_exact(n + 1 - ph.len());
+        pw.reserve(n+1);
+        if ph.is_empty() {
+            ph.push(0u64);
+        }
+        pw.push(1u64);
+        for chunk in (0..n).step_by(PROGRESS_CHUNK) {
+            for i in chunk..n.min(chunk + PROGRESS_CHUNK) {
+                // hashes fed with the text are only extended past their end
+                if i + 1 == ph.len() {
+                    ph.push(hasher.extend(ph[i], data[i]));
+                }
+                pw.push(hasher.step(pw[i]));
+            }
+            report(n.min(chunk + PROGRESS_CHUNK))?;
+        }
+        // pattern keys need pw[k] even when the text is shorter than k
+        while pw.len() <= k {
+            pw.push(hasher.step(pw[pw.len() - 1]));
+        }
+
+        if let (Some(bt), Some(t)) = (timings.as_deref_mut(), started) {
+            bt.prefix_hash = t.elapsed();
         }
 
         // 3) one‐pass open‐address bucket for each gap
-        let mut flat = Vec::with_capacity(gaps.len());
-        for &d in &gaps {
-            flat.push(FlatIndex::new(&ph, &pw, n, k, d));
+        let flat = if !staged {
+            build_tables(&hasher, &ph, &pw, params, &tables, config.threads, spare.flat)
+        } else {
+            let mut flat = Vec::with_capacity(gaps.len());
+            let mut old = spare.flat.into_iter();
+            for (g, &(d, ts)) in tables.iter().enumerate() {
+                let t = timings.is_some().then(Instant::now);
+                let mut fi = old.next().unwrap_or_default();
+                fi.refill(&hasher, &ph, &pw, params, d, ts);
+                flat.push(fi);
+                if let (Some(bt), Some(t)) = (timings.as_deref_mut(), t) {
+                    bt.per_gap.push(t.elapsed());
+                }
+                report(n * (g + 2))?;
+            }
+            flat
+        };
+        // nothing past pw[k] is read after the tables are built
+        if !config.keep_powers.unwrap_or(false) {
+            pw.truncate(k + 1);
+            pw.shrink_to_fit();
         }
 
-        CSIIndex {
+        Ok(CsiIndex {
             k,
             gaps,
             flat,
-            text: data.to_vec(),
+            text: match text {
+                Cow::Owned(text) => text.into(),
+                Cow::Borrowed(_) => Array::default(),
+            },
             pw,
+            bounds: Vec::new(),
+            aliases: corpus::Aliases::default(),
+            ids: Vec::new(),
+            cap: params.cap,
+            stride: params.stride,
+            window: params.window,
+            verify: config.verify,
+            fold: config.case_fold,
+            newlines: config.normalize_newlines,
+            crlf,
+            lines,
+            dead: Vec::new(),
+            mask: config.mask.clone(),
+            digest: ph[n],
+            text_len: n,
+            load: config.load_factor,
+            fixed: config.table_sizes.is_some(),
+            fps: config.fingerprints,
+            scan_short: config.scan_short_patterns,
+            wide: config.wide_slots,
+            pack: params.pack,
+            hasher,
+        })
+    }
+
+    /// Return the sorted offsets of every exact occurrence of `pat`.
+    ///
+    /// The empty pattern occurs at every offset, `0..=text.len()`, as with
+    /// `str::match_indices("")`; an empty text therefore yields `[0]` for it
+    /// and nothing for any other pattern.
+    ///
+    /// If the index was built with `CsiConfig::verify` off, the offsets are
+    /// unverified candidates (see `search_unverified`), not exact matches.
+    pub fn search(&self, pat: &[u8]) -> Vec<usize> {
+        let pat = &*self.normalize(pat);
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("csi_search", len = pat.len()).entered();
+        if let Some(mask) = &self.mask {
+            return self.search_masked(pat, mask);
+        }
+        // only offset 0 can fit; compare directly instead of probing tables
+        if pat.len() >= self.text.len() {
+            return if pat == &self.text[..] && self.live(0, pat.len()) { vec![0] } else { Vec::new() };
+        }
+        let p = periodic::pattern_period(pat);
+        if self.verify && p > 0 && 2 * p <= pat.len() {
+            return self.search_periodic(pat, p);
         }
+        self.search_lists(pat, usize::MAX, &verify::same)
     }
 
-    fn search(&self, pat: &[u8]) -> Vec<usize> {
-        let m = pat.len();
-        let minlen = self.k + self.gaps[0] + self.k;
-        if m < minlen {
-            return Vec::new();
+    /// Like `search`, but verifies candidates with `eq(candidate, pat)`.
+    ///
+    /// Candidates are still generated from exact constellation hashes, so
+    /// `eq` can only relax bytes that fall outside the pattern's anchors
+    /// (`pat[..k]` and `pat[d..d + k]` for each usable gap `d`); it cannot
+    /// surface occurrences whose anchors differ from the pattern's. On a
+    /// case‐folding index both arguments to `eq` are already folded.
+    #[inline]
+    pub fn search_by<F: Fn(&[u8], &[u8]) -> bool>(&self, pat: &[u8], eq: F) -> Vec<usize> {
+        self.search_lists(&self.normalize(pat), usize::MAX, &eq)
+    }
+
+    /// Like `search`, but intersects only the `max_gaps` smallest posting
+    /// lists (at least one) and lets verification reject the rest.
+    ///
+    /// Gap tables are often redundant for long patterns: one or two highly
+    /// selective lists already pin the candidates down, and skipping the
+    /// others saves their intersection. Results are identical to `search`
+    /// because verification is exact; only the candidate count can grow.
+    pub fn search_best_gaps(&self, pat: &[u8], max_gaps: usize) -> Vec<usize> {
+        self.search_lists(&self.normalize(pat), max_gaps.max(1), &verify::same)
+    }
+
+    #[inline(always)]
+    fn search_lists<F: Fn(&[u8], &[u8]) -> bool>(&self, pat: &[u8], max_lists: usize, eq: &F) -> Vec<usize> {
+        if pat.is_empty() {
+            return (0..=self.text.len()).collect();
         }
-        // build pattern hash
-        let mut php = Vec::with_capacity(m+1);
-        php.push(0u64);
-        for i in 0..m {
-            php.push(php[i].wrapping_mul(BASE_P).wrapping_add(pat[i] as u64));
+        let candidates = self.candidates(pat, max_lists);
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("csi_verify", candidates = candidates.as_ref().map(Vec::len)).entered();
+        let hits: Vec<usize> = match candidates {
+            Some(acc) => acc.into_iter()
+                .filter(|&off| self.verify_by(off, pat, eq))
+                .collect(),
+            // every usable constellation saturated: verify each position
+            None => (0..=self.text.len().saturating_sub(pat.len()))
+                .filter(|&off| self.verify_by(off, pat, eq))
+                .collect(),
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!(matches = hits.len(), "verified");
+        hits
+    }
+
+    /// Unverified candidates: the intersection of the `max_lists` smallest
+    /// posting lists for `pat`, per phase on a strided index. `None` means
+    /// no list narrows anything (all saturated, or the pattern is too short
+    /// to index), so every position is a candidate.
+    fn candidates(&self, pat: &[u8], max_lists: usize) -> Option<Vec<usize>> {
+        self.candidates_hashed(pat.len(), &prefix_hash(&self.hasher, pat), max_lists, self.plan_cutoff())
+    }
+
+    /// `candidates` for a pattern of length `m` with prefix states `php`,
+    /// probing as `probe_phases` does with `cutoff`.
+    fn candidates_hashed(&self, m: usize, php: &[u64], max_lists: usize, cutoff: usize) -> Option<Vec<usize>> {
+        let mut phases = self.probe_phases(m, php, cutoff)?;
+        Some(self.intersect_phases(php, &mut phases, max_lists))
+    }
+
+    /// The candidates `phases`, from `probe_phases` over prefix states
+    /// `php`, leave: each phase's `max_lists` smallest lists intersected.
+    fn intersect_phases(&self, php: &[u64], phases: &mut [Phase<'_>], max_lists: usize) -> Vec<usize> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("csi_intersect", phases = phases.len()).entered();
+        let mut acc = Vec::new();
+        for (phase, lists, packed) in phases.iter_mut() {
+            // smallest lists first: `max_lists` keeps the most selective
+            // ones, two lists are cheapest intersected two‐pointer, and the
+            // driving list is the one read in full (`benches/intersect.rs`
+            // measures a third fewer cache lines and half the time)
+            lists.sort_unstable_by_key(|l| l.len());
+            lists.truncate(max_lists);
+            packed.sort_unstable_by_key(|l| l.len);
+            packed.truncate(max_lists.saturating_sub(lists.len()));
+            let mut hits = match lists.first().map(|&l| self.span_check(php, *phase, l)) {
+                // only packed lists: the shortest is decoded to drive the rest
+                None => packed.remove(0).iter().collect(),
+                Some(None) => match lists[..] {
+                    [one] => one.to_vec(),
+                    [a, b] => intersect_sorted(a, b),
+                    _ => intersect_kway(lists),
+                },
+                Some(Some((fps, want))) => {
+                    let mut hits = Vec::new();
+                    let mut keep = |i: usize, p: usize| if fps[i] == want { hits.push(p) };
+                    match lists[..] {
+                        [one] => one.iter().enumerate().for_each(|(i, p)| keep(i, p)),
+                        [a, b] => visit_sorted(a, b, keep),
+                        _ => visit_kway(lists, keep),
+                    }
+                    hits
+                }
+            };
+            for list in packed.iter() {
+                list.retain(&mut hits);
+            }
+            if *phase == 0 {
+                acc.extend(hits);
+            } else {
+                acc.extend(hits.into_iter().filter_map(|p| p.checked_sub(*phase)));
+            }
         }
-        // collect postings
+        if phases.len() > 1 {
+            acc.sort_unstable();
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(candidates = acc.len(), "intersected");
+        acc
+    }
+
+    /// Fingerprints of `list` (a posting list found at `phase`) and the one
+    /// every match must have there, from the pattern's prefix states `php`;
+    /// `None` if the index keeps none.
+    fn span_check(&self, php: &[u64], phase: usize, list: List<'_>) -> Option<(&[u16], u16)> {
+        if !self.fps {
+            return None;
+        }
+        self.gaps.iter().zip(&self.flat).find_map(|(&d, fi)| {
+            let fps = fi.fingerprints(list)?;
+            let span = self.k + d;
+            let c = window_const(&self.hasher, span);
+            Some((fps, fingerprint(self.hasher.window(php[phase + span], php[phase], c))))
+        })
+    }
+
+    /// Like `search`, but intersects the gaps' posting lists as a parallel
+    /// tree reduction and verifies the candidates concurrently.
+    ///
+    /// Only pays off when the posting lists are long (dense, repetitive
+    /// text); for selective patterns the thread hand-off costs more than the
+    /// work it splits, so `search` stays the default, and a pattern whose
+    /// lists hold fewer than `PAR_MIN_POSTINGS` (16 Ki) postings between
+    /// them goes to `search` once they're looked up. The lookups themselves
+    /// are a few probes each and run on the calling thread.
+    #[cfg(feature = "rayon")]
+    pub fn par_search(&self, pat: &[u8]) -> Vec<usize> {
+        use rayon::prelude::*;
+
+        // fallbacks get the caller's pattern: normalizing twice can change
+        // it, as stripping CR before LF does to "\r\r\n"
+        let norm = &*self.normalize(pat);
+        let m = norm.len();
+        if m == 0 || self.stride > 1 || self.window > 1 {
+            return self.search(pat);
+        }
+        if m < self.min_pattern_len() {
+            return self.search(pat);
+        }
+        let php = prefix_hash(&self.hasher, norm);
+        let probes = self.gaps.iter()
+            .enumerate()
+            .filter(|&(_, &d)| d + self.k <= m)
+            .map(|(idx, &d)| self.flat[idx].lookup(self.pattern_key(&php, d)));
         let mut lists = Vec::with_capacity(self.gaps.len());
-        for (idx, &d) in self.gaps.iter().enumerate() {
-            if d + self.k <= m {
-                let h1 = php[self.k]
-                    .wrapping_sub(php[0].wrapping_mul(self.pw[self.k]));
-                let h2 = php[d + self.k]
-                    .wrapping_sub(php[d].wrapping_mul(self.pw[self.k]));
-                let key = combine_hashes(h1, h2, d as u64);
-
-                // open‐address lookup
-                let fi = &self.flat[idx];
-                let mut slot = (key as usize) & (fi.table_size - 1);
-                loop {
-                    let k2 = unsafe { *fi.keys.get_unchecked(slot) };
-                    if k2 == 0 {
-                        return Vec::new();
+        for p in probes {
+            match p {
+                Probe::Missing => return Vec::new(),
+                Probe::Saturated => {}
+                Probe::List(l) => lists.push(l),
+                Probe::Packed(_) => return self.search(pat),
+            }
+        }
+        if lists.iter().map(|l| l.len()).sum::<usize>() < PAR_MIN_POSTINGS {
+            return self.search(pat);
+        }
+        par_intersect(&lists)
+            .into_par_iter()
+            .filter(|&off| self.verify_by(off, norm, &verify::same))
+            .collect()
+    }
+
+    /// Unverified candidate offsets for `pat`'s constellation at `gaps[gap_index]`,
+    /// borrowed from the table if it stores them as `usize`s (see
+    /// `CsiConfig::wide_slots`), else widened into a copy. `None` if the gap doesn't fit the pattern, the
+    /// constellation is absent, or its bucket is saturated or packed (see
+    /// `CsiConfig::pack_postings`). On a strided index the list only holds
+    /// offsets that are multiples of the stride, with syncmers only the
+    /// offsets of syncmers.
+    pub fn posting_list(&self, pat: &[u8], gap_index: usize) -> Option<Cow<'_, [usize]>> {
+        let d = *self.gaps.get(gap_index)?;
+        if d + self.k > pat.len() {
+            return None;
+        }
+        match self.flat[gap_index].lookup(self.pattern_key(&prefix_hash(&self.hasher, &self.normalize(pat)), d)) {
+            probe @ Probe::List(_) => probe.postings(),
+            _ => None,
+        }
+    }
+
+    /// Candidate offsets for `pat` with the final byte compare skipped.
+    ///
+    /// Every true match is included, but so is any offset whose
+    /// constellations merely collide with the pattern's under the 64‐bit
+    /// hash (or, when all of them are saturated or the pattern is below
+    /// `min_pattern_len`, every offset where the pattern fits). Use it when collisions are acceptable or the caller
+    /// re‐verifies downstream. The compare dominates long patterns with many
+    /// matches: `benches/verify.rs` measures it at some 40% of a 1 KiB search.
+    pub fn search_unverified(&self, pat: &[u8]) -> Vec<usize> {
+        let pat = &*self.normalize(pat);
+        let n = self.text.len();
+        if pat.is_empty() {
+            return (0..=n).collect();
+        }
+        // every list, as nothing checks the candidates a cutoff would add
+        match self.candidates_hashed(pat.len(), &prefix_hash(&self.hasher, pat), usize::MAX, 0) {
+            Some(mut acc) => {
+                acc.retain(|&off| off + pat.len() <= n);
+                acc
+            }
+            None => (0..=n.saturating_sub(pat.len())).collect(),
+        }
+    }
+
+    /// `(phase, posting lists)` for each phase of `pat` (just 0 unless
+    /// strided): the unsaturated lists of every gap usable at pattern offset
+    /// `phase`, whose entries are text offsets `phase` bytes past a
+    /// candidate start. Phases with an absent constellation can't match and
+    /// are left out, so an empty result means no candidates; `None` means
+    /// some phase has only saturated lists, or the pattern is below
+    /// `min_pattern_len` (and short patterns are scanned), and every
+    /// position is a candidate.
+    fn phase_lists(&self, pat: &[u8]) -> Option<Vec<Phase<'_>>> {
+        self.probe_phases(pat.len(), &prefix_hash(&self.hasher, pat), 0)
+    }
+
+    /// `phase_lists` for a pattern of length `m` with prefix states `php`,
+    /// as searches plan them: see `plan_cutoff`.
+    fn phase_lists_hashed(&self, m: usize, php: &[u64]) -> Option<Vec<Phase<'_>>> {
+        self.probe_phases(m, php, self.plan_cutoff())
+    }
+
+    /// Posting‐list length below which a search stops probing further
+    /// gaps: `PLAN_CUTOFF` when candidates are verified, else 0, since an
+    /// unverified search returns whatever the lists leave.
+    fn plan_cutoff(&self) -> usize {
+        if self.verify { PLAN_CUTOFF } else { 0 }
+    }
+
+    /// `phase_lists`, except that a phase takes no more lists once one is
+    /// shorter than `cutoff`. Gaps are probed smallest first.
+    fn probe_phases(&self, m: usize, php: &[u64], cutoff: usize) -> Option<Vec<Phase<'_>>> {
+        if m < self.min_pattern_len() {
+            // no phases, hence no candidates, unless short patterns are scanned
+            return if self.scan_short { None } else { Some(Vec::new()) };
+        }
+        let pk = self.pw[self.k];
+        let mut phases = Vec::with_capacity(self.stride);
+        'phase: for phase in self.phases(php) {
+            let (mut lists, mut packed) = (Vec::with_capacity(self.gaps.len()), Vec::new());
+            for (idx, &d) in self.gaps.iter().enumerate() {
+                if phase + d + self.k <= m {
+                    let probe = self.flat[idx].lookup(key_at(&self.hasher, php, pk, phase, self.k, d));
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!(gap = d, phase, lookup = ?GapLookup::from(&probe));
+                    match probe {
+                        Probe::Missing => continue 'phase,
+                        Probe::Saturated => {}
+                        Probe::List(l) => lists.push(l),
+                        Probe::Packed(l) => packed.push(l),
                     }
-                    if k2 == key {
-                        let start = unsafe { *fi.starts.get_unchecked(slot) };
-                        let len   = unsafe { *fi.lens.get_unchecked(slot) };
-                        lists.push(&fi.offs[start..start+len]);
+                    if lists.last().is_some_and(|l| l.len() < cutoff) {
                         break;
                     }
-                    slot = (slot + 1) & (fi.table_size - 1);
                 }
             }
+            if lists.is_empty() && packed.is_empty() {
+                return None;
+            }
+            phases.push((phase, lists, packed));
+        }
+        Some(phases)
+    }
+
+    /// Pattern offsets to look a pattern with prefix states `php` up at,
+    /// each a phase: `0..stride`, or with syncmers the first start the
+    /// pattern's own bytes show indexed, which every occurrence has indexed
+    /// at the same place.
+    fn phases(&self, php: &[u64]) -> Range<usize> {
+        if self.window == 1 {
+            return 0..self.stride;
+        }
+        match Syncmers::new(&self.hasher, php, self.pw[self.k], self.k, self.window).next() {
+            Some(q) => q..q + 1,
+            None => 0..0,
+        }
+    }
+
+    /// The parameters the tables were built with.
+    fn table_params(&self) -> TableParams {
+        TableParams {
+            k: self.k, cap: self.cap, stride: self.stride, window: self.window, load: self.load, fps: self.fps,
+            wide: self.wide, pack: self.pack,
+        }
+    }
+
+    /// Hash powers `B^0, B^1, ..`: all `text().len() + 1` of them if the
+    /// index was built with `CsiConfig::keep_powers`, else just `B^0..=B^k`.
+    /// Together with prefix hashes the caller computes, the full array turns
+    /// `window_hash` into O(1) per window.
+    pub fn powers(&self) -> &[u64] {
+        &self.pw
+    }
+
+    /// The index's rolling hash of `text[start..start + len]`, i.e.
+    /// `ph[start + len] - ph[start] * pw[len]`, or `None` if out of bounds.
+    ///
+    /// Prefix hashes aren't kept after build, so this folds the window
+    /// directly (O(len)) and needs no stored powers; the value is
+    /// bit‐identical either way.
+    pub fn window_hash(&self, start: usize, len: usize) -> Option<u64> {
+        let end = start.checked_add(len)?;
+        let win = self.text.get(start..end)?;
+        Some(win.iter().fold(0u64, |h, &b| self.hasher.extend(h, b)))
+    }
+
+    /// Constellation key of the pattern's anchors at gap `d`, given its prefix hash.
+    #[inline(always)]
+    fn pattern_key(&self, php: &[u64], d: usize) -> u64 {
+        key_at(&self.hasher, php, self.pw[self.k], 0, self.k, d)
+    }
+
+    #[inline(always)]
+    fn verify_by<F: Fn(&[u8], &[u8]) -> bool>(&self, off: usize, pat: &[u8], eq: &F) -> bool {
+        off + pat.len() <= self.text.len() && self.live(off, pat.len()) && (!self.verify ||
+            eq(unsafe { self.text.get_unchecked(off..off+pat.len()) }, pat))
+    }
+}
+
+/// What every gap table of one index is built with
+#[derive(Clone, Copy)]
+struct TableParams {
+    k:      usize,
+    cap:    usize, // max postings per key, SATURATED when unlimited
+    stride: usize, // index positions that are multiples of this (>= 1)
+    window: usize, // index only syncmers over this many k‐mers, if > 1
+    load:   Option<f64>,
+    fps:    bool,      // keep a span fingerprint per posting
+    wide:   bool,      // keep bucket starts and lengths in 64 bits
+    pack:   usize,     // delta‐encode buckets longer than this
+}
+
+impl TableParams {
+    /// Automatic slot count of the gap‐`d` table over `n` text bytes.
+    fn slots(&self, n: usize, d: usize) -> usize {
+        table_size_for(self.indexed(constellations(n, self.k, d)), self.load)
+    }
+
+    /// About how many of `m` start positions get indexed: every `stride`th,
+    /// or for syncmers the share of varied text.
+    fn indexed(&self, m: usize) -> usize {
+        m.div_ceil(self.stride.max(self.window.div_ceil(2)))
+    }
+
+    /// Bytes from a start that decide whether it's indexed: its syncmer's,
+    /// 0 without syncmers.
+    fn reach(&self) -> usize {
+        if self.window > 1 { self.k + self.window - 1 } else { 0 }
+    }
+
+    /// Start positions in `n` bytes that the gap‐`d` table can index: those
+    /// whose constellation fits, and whose syncmer does too.
+    fn starts(&self, n: usize, d: usize) -> usize {
+        constellations(n, self.k, d).min((n + 1).saturating_sub(self.reach()))
+    }
+
+    /// `f(i, key)` for every indexed start position `i < m` at gap `d`, in
+    /// ascending order, with keys as stored (see `slot_key`). With syncmers
+    /// `ph` must reach `reach` bytes past `i`, or to the end of the text.
+    /// Other unstrided keys are extracted a batch at a time (vectorized
+    /// where the hasher supports it).
+    #[inline(always)]
+    fn each_key<H: Hasher>(&self, h: &H, ph: &[u64], pk: u64, d: usize, m: usize, mut f: impl FnMut(usize, u64)) {
+        if self.window > 1 {
+            for i in Syncmers::new(h, ph, pk, self.k, self.window).take_while(|&i| i < m) {
+                f(i, slot_key(key_at(h, ph, pk, i, self.k, d)));
+            }
+            return;
         }
-        if lists.is_empty() {
-            return Vec::new();
+        if self.stride > 1 {
+            for i in (0..m).step_by(self.stride) {
+                f(i, slot_key(key_at(h, ph, pk, i, self.k, d)));
+            }
+            return;
         }
-        // intersect two‐pointer
-        let mut acc = lists[0].to_vec();
-        for lst in &lists[1..] {
-            acc = intersect_sorted(&acc, lst);
-            if acc.is_empty() {
-                return Vec::new();
+        let mut batch = [0u64; KEY_BATCH];
+        for b in (0..m).step_by(KEY_BATCH) {
+            let len = (m - b).min(KEY_BATCH);
+            h.keys(ph, pk, b, self.k, d, &mut batch[..len]);
+            for (i, &key) in (b..).zip(&batch[..len]) {
+                f(i, slot_key(key));
             }
         }
-        // verify
-        let dlen = m;
-        acc.into_iter()
-            .filter(|&off| off + dlen <= self.text.len() &&
-                unsafe { &*self.text.get_unchecked(off..off+dlen) } == pat)
-            .collect()
     }
 }
 
 impl FlatIndex {
+    /// Build the table for gap `d` with `ts` slots in place, reusing this
+    /// table's arrays wherever their capacity suffices.
+    #[inline(always)]
+    fn refill<H: Hasher>(&mut self, h: &H, ph: &[u64], pw: &[u64], p: TableParams, d: usize, ts: usize) {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("csi_gap_table", gap = d, slots = ts).entered();
+        // separate copies, so the default power‐of‐two tables keep bare masks
+        if ts.is_power_of_two() {
+            self.fill::<H, true>(h, ph, pw, p, d, ts);
+        } else {
+            self.fill::<H, false>(h, ph, pw, p, d, ts);
+        }
+    }
+
+    /// `refill`, probing as `home_slot::<POW2>` does.
     #[inline(always)]
-    fn new(ph: &[u64], pw: &[u64], n: usize, k: usize, d: usize) -> Self {
-        // number of entries
-        let m = if n >= k + d { n - (k + d) + 1 } else { 0 };
-        // table size = next power of two ≥ 2*m, min 16
-        let ts = (m * 2).next_power_of_two().max(16);
+    fn fill<H: Hasher, const POW2: bool>(&mut self, h: &H, ph: &[u64], pw: &[u64], p: TableParams, d: usize, ts: usize) {
+        let (k, cap) = (p.k, p.cap);
+        // positions whose constellation fits; every `stride`th (or every
+        // syncmer) is indexed
+        let m = p.starts(ph.len() - 1, d);
         // arrays
-        let mut keys   = vec![0u64; ts];
-        let mut counts = vec![0usize; ts];
-
-        // Pass 1: count per-key
-        for i in 0..m {
-            let h1 = unsafe {
-                ph.get_unchecked(i+k).wrapping_sub(
-                    ph.get_unchecked(i).wrapping_mul(*pw.get_unchecked(k))
-                )
-            };
-            let j = i + d;
-            let h2 = unsafe {
-                ph.get_unchecked(j+k).wrapping_sub(
-                    ph.get_unchecked(j).wrapping_mul(*pw.get_unchecked(k))
-                )
-            };
-            let key = combine_hashes(h1, h2, d as u64);
-            let mut slot = (key as usize) & (ts - 1);
-            loop {
+        let FlatIndex { keys, starts: start_slots, lens: len_slots, offs: off_words, fps, overflow, .. } = self;
+        let keys = zeroed(keys, ts);
+        let mut lens = len_slots.scratch(ts);
+        let counts = &mut lens[..];
+
+        let pk = pw[k];
+
+        // Pass 1: count per-key; keys still unplaced after MAX_PROBE slots
+        // are counted in `spill` instead
+        let mut spill: Map<u64, usize> = Map::new();
+        p.each_key(h, ph, pk, d, m, |_, key| {
+            let mut slot = home_slot::<POW2>(key, ts);
+            for _ in 0..MAX_PROBE {
                 let k2 = unsafe { *keys.get_unchecked(slot) };
                 if k2 == 0 {
                     // claim empty
                     unsafe { *keys.get_unchecked_mut(slot) = key; }
                     unsafe { *counts.get_unchecked_mut(slot) = 1; }
-                    break;
+                    return;
                 }
                 if k2 == key {
                     unsafe { *counts.get_unchecked_mut(slot) += 1; }
-                    break;
+                    return;
                 }
-                slot = (slot + 1) & (ts - 1);
+                slot = next_slot::<POW2>(slot, ts);
             }
-        }
+            *spill.entry(key).or_insert(0) += 1;
+        });
 
-        // prefix-sum to get starts
-        let mut starts = vec![0usize; ts];
+        // prefix-sum to get starts (saturated buckets take no space)
+        let mut start_words = start_slots.scratch(ts);
+        let starts = &mut start_words[..];
         let mut sum = 0;
         for idx in 0..ts {
             if unsafe { *keys.get_unchecked(idx) } != 0 {
-                unsafe { *starts.get_unchecked_mut(idx) = sum; }
-                sum += unsafe { *counts.get_unchecked(idx) };
+                let c = unsafe { *counts.get_unchecked(idx) };
+                if c <= cap {
+                    unsafe { *starts.get_unchecked_mut(idx) = sum; }
+                    sum += c;
+                }
+            }
+        }
+        overflow.clear();
+        overflow.extend(spill.into_iter().map(|(key, c)| (key, 0, c)));
+        overflow.sort_unstable_by_key(|e| e.0);
+        for e in overflow.iter_mut() {
+            if e.2 <= cap {
+                e.1 = sum;
+                sum += e.2;
             }
         }
 
         // reset counts → use as write‐idx
         for idx in 0..ts {
             if unsafe { *keys.get_unchecked(idx) } != 0 {
-                unsafe { *counts.get_unchecked_mut(idx) = 0; }
+                let c = unsafe { counts.get_unchecked_mut(idx) };
+                *c = if *c > cap { SATURATED } else { 0 };
             }
         }
+        let overflow = &mut overflow[..];
+        for e in overflow.iter_mut() {
+            e.2 = if e.2 > cap { SATURATED } else { 0 };
+        }
 
-        let mut offs = vec![0usize; sum];
+        let mut offs = off_words.scratch(sum);
         // Pass 2: fill offs
-        for i in 0..m {
-            let h1 = unsafe {
-                ph.get_unchecked(i+k).wrapping_sub(
-                    ph.get_unchecked(i).wrapping_mul(*pw.get_unchecked(k))
-                )
-            };
-            let j = i + d;
-            let h2 = unsafe {
-                ph.get_unchecked(j+k).wrapping_sub(
-                    ph.get_unchecked(j).wrapping_mul(*pw.get_unchecked(k))
-                )
-            };
-            let key = combine_hashes(h1, h2, d as u64);
-            let mut slot = (key as usize) & (ts - 1);
-            loop {
+        p.each_key(h, ph, pk, d, m, |i, key| {
+            let mut slot = home_slot::<POW2>(key, ts);
+            for _ in 0..MAX_PROBE {
                 if unsafe { *keys.get_unchecked(slot) } == key {
                     let start = unsafe { *starts.get_unchecked(slot) };
                     let cnt   = unsafe { *counts.get_unchecked(slot) };
-                    unsafe { *offs.get_unchecked_mut(start + cnt) = i; }
-                    unsafe { *counts.get_unchecked_mut(slot) = cnt + 1; }
-                    break;
+                    if cnt != SATURATED {
+                        unsafe { *offs.get_unchecked_mut(start + cnt) = i; }
+                        unsafe { *counts.get_unchecked_mut(slot) = cnt + 1; }
+                    }
+                    return;
                 }
-                slot = (slot + 1) & (ts - 1);
+                slot = next_slot::<POW2>(slot, ts);
+            }
+            if let Ok(e) = overflow.binary_search_by_key(&key, |e| e.0) {
+                let (_, start, cnt) = overflow[e];
+                if cnt != SATURATED {
+                    offs[start + cnt] = i;
+                    overflow[e].2 = cnt + 1;
+                }
+            }
+        });
+
+        let fps = fps.cleared();
+        if p.fps {
+            let c = window_const(h, k + d);
+            fps.extend(offs.iter().map(|&o| fingerprint(h.window(ph[o + k + d], ph[o], c))));
+        }
+        *start_slots = Slots::new(start_words, !p.wide);
+        *len_slots = Slots::new(lens, !p.wide);
+        *off_words = Offsets::new(offs, !p.wide);
+        self.packed.clear();
+        self.pack(p.pack);
+
+        self.table_size = ts;
+    }
+
+    /// Open‐address lookup of `key`'s posting list: at most `MAX_PROBE`
+    /// slots, then a binary search of the overflow table. Hitting an empty
+    /// slot first is a definite miss, since slots are never vacated and a key
+    /// only overflows when every slot in its window was already taken.
+    #[inline(always)]
+    fn lookup(&self, key: u64) -> Probe<'_> {
+        let key = slot_key(key);
+        if self.table_size.is_power_of_two() {
+            self.probe::<true>(key)
+        } else {
+            self.probe::<false>(key)
+        }
+    }
+
+    #[inline(always)]
+    fn probe<const POW2: bool>(&self, key: u64) -> Probe<'_> {
+        let mut slot = home_slot::<POW2>(key, self.table_size);
+        for _ in 0..MAX_PROBE {
+            let k2 = unsafe { *self.keys.get_unchecked(slot) };
+            if k2 == 0 {
+                return Probe::Missing;
             }
+            if k2 == key {
+                let start = unsafe { self.starts.get_unchecked(slot) };
+                let len   = unsafe { self.lens.get_unchecked(slot) };
+                return self.bucket(key, start, len);
+            }
+            slot = next_slot::<POW2>(slot, self.table_size);
+        }
+        match self.overflow.binary_search_by_key(&key, |e| e.0) {
+            Ok(e) => self.bucket(key, self.overflow[e].1, self.overflow[e].2),
+            Err(_) => Probe::Missing,
         }
+    }
+
+    /// First slot probed for `key`.
+    fn home(&self, key: u64) -> usize {
+        if self.table_size.is_power_of_two() {
+            home_slot::<true>(key, self.table_size)
+        } else {
+            home_slot::<false>(key, self.table_size)
+        }
+    }
+
+    /// `(key, start, len)` of every occupied slot, then of every overflow
+    /// entry; `len` may be `SATURATED`.
+    fn entries(&self) -> impl Iterator<Item = (u64, usize, usize)> + '_ {
+        self.keys.iter().zip(self.starts.iter()).zip(self.lens.iter())
+            .filter(|&((&key, _), _)| key != 0)
+            .map(|((&key, start), len)| (key, start, len))
+            .chain(self.overflow.iter().copied())
+    }
 
-        FlatIndex { table_size: ts, keys, starts, lens: counts, offs }
+    /// Fingerprints of `list`'s postings, if it's one of this table's
+    /// buckets and fingerprints were kept.
+    fn fingerprints(&self, list: List<'_>) -> Option<&[u16]> {
+        let at = self.offs.position(list)?;
+        self.fps.get(at..at + list.len())
+    }
+
+    #[inline(always)]
+    fn bucket(&self, key: u64, start: usize, len: usize) -> Probe<'_> {
+        if len == SATURATED {
+            return self.packed_list(key).map_or(Probe::Saturated, Probe::Packed);
+        }
+        Probe::List(self.offs.list(start..start + len))
+    }
+
+    /// `key`'s packed posting list, if its bucket was packed.
+    fn packed_list(&self, key: u64) -> Option<&Packed> {
+        if self.packed.is_empty() {
+            return None;
+        }
+        self.packed.binary_search_by_key(&key, |e| e.0).ok().map(|e| &self.packed[e].1)
     }
 }
 
+/// `key` as tables store it: 0 marks an empty slot, so a key that hashes
+/// to 0 shares `ZERO_KEY`'s bucket instead, which verification sorts out
+/// like any other collision.
 #[inline(always)]
-fn intersect_sorted(a: &[usize], b: &[usize]) -> Vec<usize> {
+fn slot_key(key: u64) -> u64 {
+    if key == 0 { ZERO_KEY } else { key }
+}
+
+/// Start positions whose gap‐`d` constellation fits in `n` bytes.
+fn constellations(n: usize, k: usize, d: usize) -> usize {
+    (n + 1).saturating_sub(k.saturating_add(d))
+}
+
+/// The first of `gaps` whose shortest usable pattern, `k + gap + k` bytes,
+/// is longer than a slice can be: spans and offsets are summed unchecked
+/// everywhere else, which these bounds keep from wrapping.
+fn too_wide(k: usize, gaps: &[usize]) -> Option<usize> {
+    gaps.iter().copied().find(|&d| k.checked_mul(2).and_then(|s| s.checked_add(d)).is_none_or(|s| s > isize::MAX as usize))
+}
+
+/// `(gap, slots)` per table from `CsiConfig::table_sizes`, checked against
+/// what the gap indexes over `n` bytes.
+fn forced_tables(gaps: &[usize], forced: &[usize], n: usize, p: TableParams)
+                 -> Result<Vec<(usize, usize)>, CsiIndexError> {
+    gaps.iter().enumerate().map(|(i, &gap)| {
+        let min = p.indexed(constellations(n, p.k, gap)).next_power_of_two();
+        match forced.get(i) {
+            Some(&ts) if ts.is_power_of_two() && ts >= min => Ok((gap, ts)),
+            _ => Err(CsiIndexError::TableSize { gap, min }),
+        }
+    }).collect()
+}
+
+/// Gaps, table parameters and `(gap, slots)` per table of one build
+type Plan = (Vec<usize>, TableParams, Vec<(usize, usize)>);
+
+/// The `Plan` for a build of `config` over `data`: `k` and the gaps from
+/// the tiers (measuring the entropy only if they differ), sorted,
+/// deduplicated and cut to `max_gaps`.
+fn plan(data: &[u8], config: &CsiConfig) -> Result<Plan, CsiIndexError> {
+    let n = data.len();
+    let (k, mut gaps) = match &config.tiers.params {
+        // every tier alike: the entropy can't change the choice
+        [a, b, c] if a == b && b == c => a.clone(),
+        _ => config.tiers.choose(compute_entropy(data)),
+    };
+    gaps.sort_unstable();
+    gaps.dedup();
+    if let Some(gap) = too_wide(k, &gaps) {
+        return Err(CsiIndexError::GapTooWide { k, gap });
+    }
+    if config.load_factor.is_some_and(|l| l > 0.0 && l < MIN_LOAD) {
+        return Err(CsiIndexError::LoadFactor);
+    }
+    if let Some(keep) = config.max_gaps {
+        gaps.truncate(keep.max(1));
+    }
+    // a gap whose constellation is wider than the text would get an empty
+    // table; drop those, but keep the smallest so `gaps[0]` is defined
+    let fits = 1 + gaps.iter().skip(1).take_while(|&&d| k + d <= n).count();
+    gaps.truncate(fits);
+    let window = config.syncmer_window.unwrap_or(1).max(1);
+    let params = TableParams {
+        k,
+        cap: config.max_postings_per_key.unwrap_or(SATURATED),
+        stride: if window > 1 { 1 } else { config.stride.max(1) },
+        window,
+        load: config.load_factor,
+        fps: config.fingerprints,
+        wide: config.wide_slots,
+        pack: config.pack_postings.unwrap_or(SATURATED),
+    };
+    let tables = match &config.table_sizes {
+        None => gaps.iter().map(|&d| (d, params.slots(n, d))).collect(),
+        Some(forced) => forced_tables(&gaps, forced, n, params)?,
+    };
+    Ok((gaps, params, tables))
+}
+
+/// The index from a build without progress callback, where only forced
+/// table sizes can fail.
+fn built<H>(r: Result<CsiIndex<H>, CsiIndexError>) -> CsiIndex<H> {
+    r.unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Bucket count for a table of `m` constellations: the next power of two
+/// ≥ 2·m, min 16, or with a `load` target (see `CsiConfig::load_factor`)
+/// ⌈m / load⌉, min 16.
+#[inline(always)]
+fn table_size_for(m: usize, load: Option<f64>) -> usize {
+    // nothing to store: one empty slot, so every lookup is an immediate miss
+    if m == 0 {
+        return 1;
+    }
+    match load {
+        Some(load) if load > 0.0 && load < 1.0 => {
+            // rounded up by hand, as `f64::ceil` needs `std`
+            let want = m as f64 / load;
+            (want as usize + (want > want as usize as f64) as usize).max(16)
+        }
+        _ => (m * 2).next_power_of_two().max(16),
+    }
+}
+
+/// First slot probed for `key` in a table of `ts` slots: its low bits when
+/// `ts` is a power of two (`POW2`), else Lemire's multiply‐shift
+/// `key·ts / 2^64`, which maps keys onto any size without a division.
+#[inline(always)]
+fn home_slot<const POW2: bool>(key: u64, ts: usize) -> usize {
+    if POW2 {
+        (key as usize) & (ts - 1)
+    } else {
+        ((key as u128 * ts as u128) >> 64) as usize
+    }
+}
+
+/// Slot probed after `slot`, wrapping at `ts`.
+#[inline(always)]
+fn next_slot<const POW2: bool>(slot: usize, ts: usize) -> usize {
+    if POW2 {
+        (slot + 1) & (ts - 1)
+    } else if slot + 1 == ts {
+        0
+    } else {
+        slot + 1
+    }
+}
+
+/// Heap bytes of one gap's `FlatIndex` over `n` text bytes, assuming no
+/// saturated buckets: keys, starts and lens per slot (the latter two in 32
+/// bits while the postings fit), plus one posting per constellation, in 32
+/// bits while the offsets fit.
+fn table_bytes(n: usize, k: usize, d: usize) -> usize {
+    let m = constellations(n, k, d);
+    let slot = if m < u32::MAX as usize { 16 } else { 24 };
+    let posting = if n <= u32::MAX as usize { 4 } else { 8 };
+    table_size_for(m, None) * slot + m * posting
+}
+
+/// One `FlatIndex` per `(gap, slots)` of `tables`, in order, refilling the
+/// tables in `flat` (extra ones are dropped, missing ones start empty).
+#[cfg(not(feature = "rayon"))]
+fn build_tables<H: Hasher>(h: &H, ph: &[u64], pw: &[u64], p: TableParams, tables: &[(usize, usize)], _threads: usize,
+                           mut flat: Vec<FlatIndex>) -> Vec<FlatIndex> {
+    flat.resize_with(tables.len(), FlatIndex::default);
+    flat.iter_mut().zip(tables).for_each(|(fi, &(d, ts))| fi.refill(h, ph, pw, p, d, ts));
+    flat
+}
+
+/// One `FlatIndex` per `(gap, slots)` of `tables`, in order, refilling the
+/// tables in `flat`; concurrently unless `threads == 1` (see
+/// `CsiConfig::threads`).
+#[cfg(feature = "rayon")]
+fn build_tables<H: Hasher>(h: &H, ph: &[u64], pw: &[u64], p: TableParams, tables: &[(usize, usize)], threads: usize,
+                           mut flat: Vec<FlatIndex>) -> Vec<FlatIndex> {
+    use rayon::prelude::*;
+
+    flat.resize_with(tables.len(), FlatIndex::default);
+    let par = |flat: &mut Vec<FlatIndex>| flat.par_iter_mut()
+        .zip(tables)
+        .for_each(|(fi, &(d, ts))| fi.refill(h, ph, pw, p, d, ts));
+    match threads {
+        0 => par(&mut flat),
+        1 => flat.iter_mut().zip(tables).for_each(|(fi, &(d, ts))| fi.refill(h, ph, pw, p, d, ts)),
+        t => match rayon::ThreadPoolBuilder::new().num_threads(t).build() {
+            Ok(pool) => pool.install(|| par(&mut flat)),
+            Err(_) => par(&mut flat),
+        },
+    }
+    flat
+}
+
+/// Make `v` hold `len` zeros, in its current allocation if that is big
+/// enough, else in a fresh zeroed one.
+fn zeroed<T: Word>(v: &mut Array<T>, len: usize) -> &mut [T] {
+    let v = v.cleared();
+    if v.capacity() >= len {
+        v.resize(len, T::default());
+    } else {
+        *v = vec![T::default(); len];
+    }
+    v
+}
+
+#[inline(always)]
+fn intersect_sorted(a: List<'_>, b: List<'_>) -> Vec<usize> {
     let mut res = Vec::with_capacity(a.len().min(b.len()));
+    visit_sorted(a, b, |_, v| res.push(v));
+    res
+}
+
+/// `f(i, a[i])` for each offset in both `a` and `b`, ascending: a merge
+/// for lists of similar length, else galloping through the longer one.
+#[inline(always)]
+fn visit_sorted(a: List<'_>, b: List<'_>, mut f: impl FnMut(usize, usize)) {
+    if b.len() / GALLOP_RATIO > a.len() {
+        visit_galloping(a, b, |i, _, v| f(i, v));
+    } else if a.len() / GALLOP_RATIO > b.len() {
+        visit_galloping(b, a, |_, j, v| f(j, v));
+    } else {
+        visit_merged(a, b, f);
+    }
+}
+
+/// `f(i, j, v)` for each `v == short[i] == long[j]`, ascending, seeking
+/// each of `short`'s offsets in `long` by `gallop`: O(s log(l / s)) for
+/// lists of lengths `s` and `l`, against `visit_merged`'s O(s + l).
+#[inline(always)]
+fn visit_galloping(short: List<'_>, long: List<'_>, mut f: impl FnMut(usize, usize, usize)) {
+    let mut j = 0;
+    for (i, v) in short.iter().enumerate() {
+        j = gallop(long, j, v);
+        if j == long.len() {
+            return;
+        }
+        if long.get(j) == v {
+            f(i, j, v);
+            j += 1;
+        }
+    }
+}
+
+/// `visit_sorted` by a two‐pointer merge.
+#[inline(always)]
+fn visit_merged(a: List<'_>, b: List<'_>, mut f: impl FnMut(usize, usize)) {
     let (mut i, mut j) = (0, 0);
     while i < a.len() && j < b.len() {
-        match unsafe { *a.get_unchecked(i) }.cmp(&unsafe { *b.get_unchecked(j) }) {
-            std::cmp::Ordering::Less    => i += 1,
-            std::cmp::Ordering::Greater => j += 1,
-            std::cmp::Ordering::Equal   => {
-                res.push(unsafe { *a.get_unchecked(i) });
+        match unsafe { a.get_unchecked(i) }.cmp(&unsafe { b.get_unchecked(j) }) {
+            core::cmp::Ordering::Less    => i += 1,
+            core::cmp::Ordering::Greater => j += 1,
+            core::cmp::Ordering::Equal   => {
+                f(i, unsafe { a.get_unchecked(i) });
                 i += 1; j += 1;
             }
         }
     }
+}
+
+/// Intersect three or more sorted lists in one pass, with no intermediate
+/// vectors. The first (shortest) list drives: each of its offsets is sought
+/// in the others in turn, and the first list that overshoots sets the value
+/// all cursors, the driver included, gallop to next.
+fn intersect_kway(lists: &[List<'_>]) -> Vec<usize> {
+    let mut res = Vec::with_capacity(lists.first().map_or(0, |l| l.len()));
+    visit_kway(lists, |_, v| res.push(v));
     res
 }
 
+/// `f(i, lists[0][i])` for each offset in every list, ascending, as
+/// `intersect_kway` finds them.
 #[inline(always)]
-fn combine_hashes(h1: u64, h2: u64, d: u64) -> u64 {
-    let mut x = h1 ^ (h2 << 1) ^ (d << 2);
-    x ^= x >> 33;
-    x = x.wrapping_mul(0xff51afd7ed558ccd);
-    x ^= x >> 33;
-    x = x.wrapping_mul(0xc4ceb9fe1a85ec53);
-    x ^= x >> 33;
-    x
+fn visit_kway(lists: &[List<'_>], f: impl FnMut(usize, usize)) {
+    visit_kway_in(lists, &mut Vec::new(), f);
 }
 
+/// `visit_kway`, keeping the cursors in `pos`.
+#[inline(always)]
+fn visit_kway_in(lists: &[List<'_>], pos: &mut Vec<usize>, mut f: impl FnMut(usize, usize)) {
+    let Some((&first, rest)) = lists.split_first() else { return };
+    pos.clear();
+    pos.resize(rest.len(), 0);
+    let mut p0 = 0;
+    'outer: while p0 < first.len() {
+        let v = first.get(p0);
+        let mut target = v;
+        for (&l, p) in rest.iter().zip(pos.iter_mut()) {
+            *p = gallop(l, *p, target);
+            if *p == l.len() {
+                break 'outer;
+            }
+            let x = l.get(*p);
+            if x > target {
+                target = x;
+                break;
+            }
+        }
+        if target == v {
+            f(p0, v);
+            p0 += 1;
+        } else {
+            p0 = gallop(first, p0, target);
+        }
+    }
+}
+
+/// First index at or after `from` whose value is `>= target`: doubling
+/// steps, then a binary search inside the last step, so short skips (the
+/// common case when lists mostly agree) cost a comparison or two. `l` is
+/// sorted.
+#[inline(always)]
+fn gallop(l: List<'_>, from: usize, target: usize) -> usize {
+    if from >= l.len() || l.get(from) >= target {
+        return from;
+    }
+    // invariant: l[lo] < target
+    let (mut lo, mut step) = (from, 1);
+    while lo + step < l.len() && l.get(lo + step) < target {
+        lo += step;
+        step *= 2;
+    }
+    let hi = (lo + step).min(l.len());
+    lo + 1 + l.slice(lo + 1..hi).partition_point(|x| x < target)
+}
+
+/// Intersect sorted lists pairwise as a balanced tree, halves in parallel.
+#[cfg(feature = "rayon")]
+fn par_intersect(lists: &[List<'_>]) -> Vec<usize> {
+    match *lists {
+        [one] => one.to_vec(),
+        [a, b] => intersect_sorted(a, b),
+        _ => {
+            let (lo, hi) = lists.split_at(lists.len() / 2);
+            let (a, b) = rayon::join(|| par_intersect(lo), || par_intersect(hi));
+            intersect_sorted((&a).into(), (&b).into())
+        }
+    }
+}
+
+#[cfg(feature = "std")]
 fn compute_entropy(data: &[u8]) -> f64 {
     let mut freq = [0usize; 256];
     for &b in data {
@@ -265,40 +1891,633 @@ fn compute_entropy(data: &[u8]) -> f64 {
     ent
 }
 
-// FFI exports
-
-#[unsafe(no_mangle)]
-pub extern "C" fn csi_new(data: *const c_uchar, len: usize) -> *mut CSIHandle {
-    if data.is_null() || len == 0 { return std::ptr::null_mut() }
-    let slice = unsafe { slice::from_raw_parts(data, len) };
-    let idx = CSIIndex::build_from(slice);
-    let handle = CSIHandle { inner: Box::new(idx) };
-    Box::into_raw(Box::new(handle))
+/// `compute_entropy` without `std`'s `f64::log2`: `log2 n - Σ c log2 c / n`
+/// over the byte counts, from fixed‐point logarithms summed as integers.
+/// Within 1e‐6 bits of the float version, so only data that close to a
+/// tier's cutoff can choose other parameters.
+#[cfg(any(not(feature = "std"), test))]
+fn compute_entropy_int(data: &[u8]) -> f64 {
+    let mut freq = [0usize; 256];
+    for &b in data {
+        freq[b as usize] += 1;
+    }
+    let n = data.len();
+    if n == 0 {
+        return 0.0;
+    }
+    let sum: u128 = freq.iter().filter(|&&c| c > 0).map(|&c| c as u128 * log2_fixed(c) as u128).sum();
+    let bits = n as u128 * log2_fixed(n) as u128 - sum;
+    bits as f64 / n as f64 / (1u64 << LOG2_FRAC) as f64
 }
 
-#[unsafe(no_mangle)]
-pub extern "C" fn csi_free(handle: *mut CSIHandle) {
-    if !handle.is_null() {
-        unsafe { let _ = Box::from_raw(handle); }
+#[cfg(not(feature = "std"))]
+use compute_entropy_int as compute_entropy;
+
+/// Fraction bits of `log2_fixed`
+#[cfg(any(not(feature = "std"), test))]
+const LOG2_FRAC: u32 = 32;
+
+/// `log2 x` for `x >= 1`, rounded down to `LOG2_FRAC` fraction bits: the
+/// integer part is the top bit's position, and each fraction bit is
+/// whether squaring the mantissa (held in `[1, 2)` as 1.62 fixed point)
+/// reaches 2.
+#[cfg(any(not(feature = "std"), test))]
+fn log2_fixed(x: usize) -> u64 {
+    let top = usize::BITS - 1 - x.leading_zeros();
+    let mut m = match top {
+        ..=62 => (x as u128) << (62 - top),
+        _ => (x as u128) >> (top - 62),
+    };
+    let mut log = (top as u64) << LOG2_FRAC;
+    for bit in (0..LOG2_FRAC).rev() {
+        m = (m * m) >> 62;
+        if m >= 2 << 62 {
+            m >>= 1;
+            log |= 1 << bit;
+        }
     }
+    log
 }
 
-#[unsafe(no_mangle)]
-pub extern "C" fn csi_search(
-    handle: *const CSIHandle,
-    pat:    *const c_uchar,
-    pat_len: usize,
-    out:    *mut usize,
-    max_out: usize,
-) -> usize {
-    if handle.is_null() || pat.is_null() { return 0 }
-    let idx = unsafe { &*((*handle).inner) };
-    let pat_slice = unsafe { slice::from_raw_parts(pat, pat_len) };
-    let matches = idx.search(pat_slice);
-    let n = matches.len().min(max_out);
-    let out_slice = unsafe { slice::from_raw_parts_mut(out, n) };
-    for i in 0..n {
-        unsafe { *out_slice.get_unchecked_mut(i) = matches[i]; }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integer_entropy_tracks_the_float_one() {
+        let mut x = 7u64;
+        let noise: Vec<u8> = (0..5000).map(|_| { x = x.wrapping_mul(6364136223846793005).wrapping_add(1); (x >> 56) as u8 }).collect();
+        let texts: [&[u8]; 6] = [b"", b"a", b"ab", b"aaaaaaab", b"GET /index.html HTTP/1.1\r\nHost: example\r\n", &noise];
+        for text in texts {
+            let (float, int) = (compute_entropy(text), compute_entropy_int(text));
+            assert!((float - int).abs() < 1e-6, "{float} vs {int}");
+        }
+        for x in [1, 2, 3, 1000, 1 << 40, usize::MAX] {
+            assert!(((log2_fixed(x) as f64 / (1u64 << LOG2_FRAC) as f64) - (x as f64).log2()).abs() < 1e-8, "{x}");
+        }
+    }
+
+    #[test]
+    fn kway_intersection_matches_pairwise() {
+        let lists: [Vec<usize>; 4] = [
+            (0..400).step_by(2).collect(),
+            (0..400).step_by(3).collect(),
+            (0..400).filter(|x| x % 5 != 1).collect(),
+            vec![0, 6, 12, 30, 60, 61, 90, 390, 398],
+        ];
+        let refs: Vec<List> = lists.iter().map(List::from).collect();
+        let pairwise = refs[1..].iter().fold(refs[0].to_vec(), |acc, &l| intersect_sorted((&acc).into(), l));
+        assert_eq!(intersect_kway(&refs), pairwise);
+        assert_eq!(intersect_kway(&refs[..3]), intersect_sorted((&intersect_sorted(refs[0], refs[1])).into(), refs[2]));
+        assert!(intersect_kway(&[refs[0], List::Wide(&[]), refs[1]]).is_empty());
+        assert_eq!(intersect_kway(&[(&[7]).into(), (&[7]).into(), (&[7]).into()]), [7]);
+
+        // 32‐bit lists, alone or among wide ones, intersect the same
+        let narrow: Vec<Vec<u32>> = lists.iter().map(|l| l.iter().map(|&x| x as u32).collect()).collect();
+        let mixed: Vec<List> = narrow.iter().enumerate().map(|(i, l)| if i % 2 == 0 { List::Narrow(l) } else { refs[i] }).collect();
+        assert_eq!(intersect_kway(&mixed), pairwise);
+        assert_eq!(intersect_kway(&narrow.iter().map(|l| List::Narrow(l)).collect::<Vec<_>>()), pairwise);
+        assert_eq!(intersect_sorted(mixed[0], mixed[1]), intersect_sorted(refs[0], refs[1]));
+    }
+
+    #[test]
+    fn galloping_intersection_matches_the_merge() {
+        let mut x = 0x2545f4914f6cdd1du64;
+        let mut sorted = |len: usize, spread: u64| -> Vec<usize> {
+            let mut v: Vec<usize> = (0..len).map(|_| {
+                x ^= x << 13; x ^= x >> 7; x ^= x << 17;
+                (x % spread) as usize
+            }).collect();
+            v.sort_unstable();
+            v.dedup();
+            v
+        };
+        for (short, long, spread) in [(200, 100_000, 400_000), (40, 5000, 6000), (1, 3000, 3000), (3000, 3000, 5000), (0, 100, 100)] {
+            let (a, b) = (sorted(short, spread), sorted(long, spread));
+            let mut merged = Vec::new();
+            let (a, b) = (List::from(&a), List::from(&b));
+            visit_merged(a, b, |i, v| merged.push((i, v)));
+            let mut galloped = Vec::new();
+            visit_galloping(a, b, |i, _, v| galloped.push((i, v)));
+            assert_eq!(galloped, merged);
+            let mut flipped = Vec::new();
+            visit_galloping(b, a, |_, j, v| flipped.push((j, v)));
+            assert_eq!(flipped, merged);
+            assert_eq!(intersect_sorted(a, b), merged.iter().map(|&(_, v)| v).collect::<Vec<_>>());
+            assert_eq!(intersect_sorted(b, a), intersect_sorted(a, b));
+        }
+    }
+
+    #[test]
+    fn search_by_case_folding_comparator() {
+        let text = b"The quick brown fox jumps over the lazy dog; the quick brown fox naps.";
+        let idx = CsiIndex::build(text);
+        let exact = &text[4..40];
+        assert_eq!(idx.search(exact), vec![4]);
+
+        // flip case on bytes no anchor covers, so candidates still hash equal
+        let mut anchored = vec![false; exact.len()];
+        for &d in &idx.gaps {
+            if d + idx.k <= exact.len() {
+                anchored[..idx.k].fill(true);
+                anchored[d..d + idx.k].fill(true);
+            }
+        }
+        let folded: Vec<u8> = exact.iter().zip(&anchored)
+            .map(|(&b, &a)| if a { b } else { b.to_ascii_uppercase() })
+            .collect();
+        assert_ne!(folded.as_slice(), exact);
+        assert!(idx.search(&folded).is_empty());
+        assert_eq!(idx.search_by(&folded, |a, b| a.eq_ignore_ascii_case(b)), vec![4]);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_search_matches_search() {
+        let text = b"abcabcabdabcabcabcabdabcabcabcabdabcabcabcabdabcabcabcabdabc".repeat(8);
+        let idx = CsiIndex::build(&text);
+        for pat in [&text[3..40], &text[..33], &b"abcabcabcabcabcabcabcabcabcabcabc"[..]] {
+            assert_eq!(idx.par_search(pat), idx.search(pat));
+        }
+
+        // lists long enough to take the parallel path
+        let text: Vec<u8> = (0..24_000).flat_map(|i| format!("GET /a/{} 200\n", i % 5 + (i % 97 == 0) as usize * 10).into_bytes()).collect();
+        let idx = CsiIndexBuilder::new().tiers(EntropyTiers::fixed(4, vec![4, 8, 16])).build(&text);
+        let pat = b"GET /a/3 200\nGET";
+        let postings: usize = (0..idx.gaps().len()).filter_map(|g| idx.posting_list(pat, g)).map(|l| l.len()).sum();
+        assert!(postings >= PAR_MIN_POSTINGS, "{postings}");
+        assert_eq!(idx.par_search(pat), idx.search(pat));
+        assert_eq!(idx.par_search(b"GET /a/13 200\nGET"), idx.search(b"GET /a/13 200\nGET"));
+
+        // a pattern that normalizing twice would change
+        let text = b"one\r\r\ntwo\r\nthree\r\r\n".repeat(20);
+        let idx = CsiIndexBuilder::new().normalize_newlines(true).build(&text);
+        for pat in [&b"\r\r\n"[..], b"one\r\r\ntwo", b"three\r\r\none\r\r\ntwo\r\nthree"] {
+            assert!(!idx.search(pat).is_empty());
+            assert_eq!(idx.par_search(pat), idx.search(pat));
+        }
+    }
+
+    #[test]
+    fn saturated_buckets_keep_results_exact() {
+        let mut text = b"ab".repeat(200);
+        text.extend_from_slice(b"--the one distinctive tail of this text--");
+        text.extend(b"ab".repeat(200));
+        let plain = CsiIndex::build(&text);
+        let capped = CsiIndex::build_with_config(&text, &CsiConfig { max_postings_per_key: Some(8), ..CsiConfig::default() });
+        assert!(capped.index_summary().total_postings < plain.index_summary().total_postings);
+        for pat in [&text[10..90], &text[390..450], &text[..64], &text[401..460]] {
+            assert!(!plain.search(pat).is_empty());
+            assert_eq!(capped.search(pat), plain.search(pat));
+        }
+    }
+
+    #[test]
+    fn build_from_slices_matches_concatenation() {
+        let parts: [&[u8]; 3] = [b"first buffer of bytes, ", b"second buffer here, ", b"and the third one."];
+        let idx = CsiIndex::build_from_slices(&parts, &CsiConfig::default());
+        let whole = CsiIndex::build(&parts.concat());
+        let pat = b"bytes, second buffer here";
+        assert_eq!(idx.search(pat), whole.search(pat));
+        let off = idx.search(pat)[0];
+        assert_eq!(idx.locate(off), (0, 16));
+        assert_eq!(idx.locate(off + 7), (1, 0));
+        assert_eq!(idx.locate(idx.text.len() - 1), (2, parts[2].len() - 1));
+    }
+
+    #[test]
+    fn window_hash_matches_prefix_hash_difference() {
+        let text = b"rolling hashes are reusable outside the index";
+        let idx = CsiIndex::build_with_config(text, &CsiConfig { keep_powers: Some(true), ..CsiConfig::default() });
+        let ph = prefix_hash(&PolyHasher, text);
+        for (start, len) in [(0, 0), (0, 4), (8, 6), (3, text.len() - 3)] {
+            let want = ph[start + len].wrapping_sub(ph[start].wrapping_mul(idx.powers()[len]));
+            assert_eq!(idx.window_hash(start, len), Some(want));
+        }
+        assert_eq!(idx.window_hash(text.len(), 1), None);
+        assert_eq!(idx.window_hash(usize::MAX, 2), None);
+
+        let lean = CsiIndex::build(text);
+        assert_eq!(lean.powers(), &idx.powers()[..=idx.k]);
+        assert_eq!(lean.window_hash(3, 20), idx.window_hash(3, 20));
+        assert_eq!(lean.search(&text[4..30]), idx.search(&text[4..30]));
+    }
+
+    #[test]
+    fn truncate_drops_tail_postings() {
+        let text = b"keep this sentence around; keep this sentence around; and lose this tail".to_vec();
+        let mut idx = CsiIndex::build(&text);
+        let pat = b"keep this sentence around";
+        assert_eq!(idx.search(pat), vec![0, 27]);
+
+        idx.truncate(50);
+        assert_eq!(idx.text, &text[..50]);
+        assert_eq!(idx.search(pat), vec![0]);
+        assert!(idx.search(b"and lose this tail, and this").is_empty());
+        assert_eq!(idx.validate(), Ok(()));
+
+        let fresh = CsiIndex::build(&text[..50]);
+        for p in [&text[..30], &text[5..45], &text[20..50]] {
+            assert_eq!(idx.search(p), fresh.search(p));
+        }
+    }
+
+    #[test]
+    fn build_cancellable_reports_and_cancels() {
+        let text: Vec<u8> = (0u32..200_000).map(|i| (i.wrapping_mul(2654435761) >> 9) as u8).collect();
+        let mut seen = Vec::new();
+        let idx = CsiIndex::build_cancellable(&text, &CsiConfig::default(), |done, total| {
+            seen.push((done, total));
+            true
+        }).unwrap();
+        assert!(seen.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(seen.last().map(|&(d, t)| d == t), Some(true));
+        assert_eq!(idx.index_summary(), CsiIndex::build(&text).index_summary());
+
+        let mut calls = 0;
+        let r = CsiIndex::build_cancellable(&text, &CsiConfig::default(), |_, _| { calls += 1; calls < 2 });
+        assert_eq!(r.err(), Some(CsiIndexError::Cancelled));
+        assert_eq!(calls, 2);
+
+        let mut last = (0, 0);
+        let r = CsiIndex::build_with_progress(&text, |done, total| {
+            last = (done, total);
+            if done < total / 2 { ControlFlow::Continue(()) } else { ControlFlow::Break(()) }
+        });
+        assert_eq!(r.err(), Some(CsiIndexError::Cancelled));
+        assert!(last.0 < last.1);
+        let idx = CsiIndex::build_with_progress(&text, |_, _| ControlFlow::Continue(())).unwrap();
+        assert_eq!(idx.index_summary(), CsiIndex::build(&text).index_summary());
+    }
+
+    /// `PolyHasher` with every key forced onto home slot 1 of any table up
+    /// to 2^20 slots, so clusters outgrow the probe limit.
+    struct ClusterHasher;
+
+    impl Hasher for ClusterHasher {
+        const TAG: u32 = 0xc1;
+        fn extend(&self, prefix: u64, byte: u8) -> u64 { PolyHasher.extend(prefix, byte) }
+        fn step(&self, c: u64) -> u64 { PolyHasher.step(c) }
+        fn window(&self, hi: u64, lo: u64, c: u64) -> u64 { PolyHasher.window(hi, lo, c) }
+        fn combine(&self, h1: u64, h2: u64, d: u64) -> u64 { PolyHasher.combine(h1, h2, d) << 20 | 1 }
+    }
+
+    /// Hashes like `PolyHasher`, but every constellation key is 0.
+    struct ZeroHasher;
+
+    impl Hasher for ZeroHasher {
+        const TAG: u32 = 0x5a45_524f;
+        fn extend(&self, prefix: u64, byte: u8) -> u64 { PolyHasher.extend(prefix, byte) }
+        fn step(&self, c: u64) -> u64 { PolyHasher.step(c) }
+        fn window(&self, hi: u64, lo: u64, c: u64) -> u64 { PolyHasher.window(hi, lo, c) }
+        fn combine(&self, _h1: u64, _h2: u64, _d: u64) -> u64 { 0 }
+    }
+
+    #[test]
+    fn keys_hashing_to_zero_are_still_found() {
+        let text = b"zero is a key like any other; the empty slot marker is not a key".repeat(3);
+        let idx = CsiIndex::build_with_hasher(&text, &CsiConfig::default(), ZeroHasher);
+        assert!(idx.flat.iter().all(|fi| fi.keys.iter().filter(|&&k| k != 0).eq([&ZERO_KEY])));
+        assert_eq!(idx.validate(), Ok(()));
+        let poly = CsiIndex::build(&text);
+        for (a, b) in [(0, 30), (12, 70), (100, 190)] {
+            assert!(!poly.search(&text[a..b]).is_empty());
+            assert_eq!(idx.search(&text[a..b]), poly.search(&text[a..b]));
+        }
+        assert_eq!(idx.search(b"zero is a key like any other; the empty slot").len(), 3);
+    }
+
+    #[test]
+    fn zero_keys_survive_append_packing_and_serialization() {
+        let text = b"zero is a key like any other; the empty slot marker is not a key".repeat(6);
+        let poly = CsiIndex::build(&text);
+        let config = CsiConfig { pack_postings: Some(16), ..CsiConfig::default() };
+        let mut idx = CsiIndex::build_with_hasher(&text[..100], &config, ZeroHasher);
+        idx.append(&text[100..]);
+        // every posting sits in the one bucket, now packed
+        assert!(idx.flat.iter().all(|fi| fi.packed.len() == 1 && fi.packed[0].0 == ZERO_KEY));
+        assert_eq!(idx.validate(), Ok(()));
+        let mut bytes = Vec::new();
+        idx.write_index(&mut bytes).unwrap();
+        let back = CsiIndex::read_index(&bytes[..], ZeroHasher).unwrap();
+        assert_eq!(back.validate(), Ok(()));
+        for (a, b) in [(0, 30), (12, 70), (300, 380)] {
+            assert_eq!(back.search(&text[a..b]), poly.search(&text[a..b]));
+            assert_eq!(idx.count(&text[a..b]), poly.count(&text[a..b]));
+        }
+    }
+
+    #[test]
+    fn clustered_keys_spill_to_overflow_and_stay_searchable() {
+        let mut x = 0x2545f4914f6cdd1du64;
+        let text: Vec<u8> = (0..3000).map(|_| { x ^= x << 13; x ^= x >> 7; x ^= x << 17; (x >> 56) as u8 }).collect();
+        let mut idx = CsiIndex::build_with_hasher(&text, &CsiConfig::default(), ClusterHasher);
+        for occ in idx.occupancy_report() {
+            assert_eq!(occ.occupied_slots, MAX_PROBE);
+            assert!(occ.overflowed > 1000);
+        }
+        idx.validate().unwrap();
+        let summary = idx.index_summary();
+        assert_eq!(summary, CsiIndex::build(&text).index_summary());
+        for start in [0, 17, 1500, 2900] {
+            let pat = &text[start..start + 40];
+            assert_eq!(idx.search(pat), [start]);
+        }
+        let mut absent = text[100..140].to_vec();
+        absent[20] ^= 0x55;
+        assert!(idx.search(&absent).is_empty());
+
+        idx.truncate(2000);
+        idx.validate().unwrap();
+        assert!(idx.search(&text[1950..1990]) == [1950] && idx.search(&text[2500..2540]).is_empty());
+        idx.compact();
+        idx.validate().unwrap();
+        assert_eq!(idx.search(&text[1950..1990]), [1950]);
+    }
+
+    #[test]
+    fn load_factor_sizes_tables_exactly() {
+        let mut x = 0x2545f4914f6cdd1du64;
+        let text: Vec<u8> = (0..5000).map(|_| { x ^= x << 13; x ^= x >> 7; x ^= x << 17; (x >> 59) as u8 + b'A' }).collect();
+        let pow2 = CsiIndex::build(&text);
+        let mut dense = CsiIndexBuilder::new().load_factor(0.75).build(&text);
+        for (&d, fi) in dense.gaps.iter().zip(&dense.flat) {
+            let m = text.len() + 1 - dense.k - d;
+            assert_eq!(fi.table_size, (m as f64 / 0.75).ceil() as usize);
+            assert!(!fi.table_size.is_power_of_two());
+        }
+        assert!(dense.heap_bytes() < pow2.heap_bytes());
+        assert_eq!(dense.validate(), Ok(()));
+        assert!(dense.occupancy_report().iter().all(|o| o.max_probe < MAX_PROBE && o.load_factor > 0.7));
+        for (a, b) in [(0, 40), (1234, 1300), (4950, 5000)] {
+            assert_eq!(dense.search(&text[a..b]), pow2.search(&text[a..b]));
+        }
+        assert!(dense.search(b"not in the text at all, lowercase").is_empty());
+
+        let sizes: Vec<usize> = dense.flat.iter().map(|fi| fi.table_size).collect();
+        dense.compact();
+        assert_eq!(dense.flat.iter().map(|fi| fi.table_size).collect::<Vec<_>>(), sizes);
+        let loose = CsiIndexBuilder::new().load_factor(1.5).build(&text);
+        assert_eq!(loose.flat[0].table_size, pow2.flat[0].table_size);
+        // loads so small their tables would overflow `usize` fail, not panic
+        for tiny in [1e-300, f64::MIN_POSITIVE, 0.009] {
+            let config = CsiConfig { load_factor: Some(tiny), ..CsiConfig::default() };
+            assert_eq!(CsiIndex::try_build_with_config(&text, &config).err(), Some(CsiIndexError::LoadFactor));
+        }
+        let floor = CsiIndexBuilder::new().load_factor(MIN_LOAD).build(&text[..500]);
+        assert_eq!(floor.search(&text[100..140]), CsiIndex::build(&text[..500]).search(&text[100..140]));
+    }
+
+    #[test]
+    fn tiny_texts_build_and_search_exactly() {
+        let mut x = 0x9e3779b97f4a7c15u64;
+        let bytes: Vec<u8> = (0..80).map(|_| { x ^= x << 13; x ^= x >> 7; x ^= x << 17; (x >> 60) as u8 + b'a' }).collect();
+        for n in 0..=bytes.len() {
+            let text = &bytes[..n];
+            let idx = CsiIndex::build(text);
+            idx.validate().unwrap();
+            // tables only for gaps that fit, bar the first
+            assert!(idx.gaps[1..].iter().all(|&d| idx.k + d <= n));
+            for (&d, fi) in idx.gaps.iter().zip(&idx.flat) {
+                let m = (n + 1).saturating_sub(idx.k + d);
+                assert_eq!(fi.table_size, if m == 0 { 1 } else { (2 * m).next_power_of_two().max(16) });
+            }
+            for start in 0..n {
+                for len in 1..=n - start {
+                    if len < idx.min_pattern_len() && len != n {
+                        continue;
+                    }
+                    let pat = &text[start..start + len];
+                    let naive: Vec<usize> = (0..=n - len).filter(|&i| &text[i..i + len] == pat).collect();
+                    assert_eq!(idx.search(pat), naive, "n={n} start={start} len={len}");
+                }
+            }
+            if n < bytes.len() {
+                assert!(idx.search(&bytes[..n + 1]).is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn strided_index_keeps_recall_above_min_len() {
+        let mut x = 0x2545f4914f6cdd1du64;
+        let text: Vec<u8> = (0..5000).map(|_| { x ^= x << 13; x ^= x >> 7; x ^= x << 17; b"abcdefgh"[(x >> 61) as usize] }).collect();
+        let dense = CsiIndex::build(&text);
+        let sparse = CsiIndex::build_with_config(&text, &CsiConfig { stride: 4, ..CsiConfig::default() });
+        assert_eq!(sparse.min_pattern_len(), dense.min_pattern_len() + 3);
+        assert!(sparse.index_summary().total_postings * 3 < dense.index_summary().total_postings);
+        sparse.validate().unwrap();
+        let min = sparse.min_pattern_len();
+        for start in [0, 1, 2, 3, 777, 1234, 4000] {
+            for len in [min, min + 1, 40] {
+                let pat = &text[start..start + len];
+                let naive: Vec<usize> = (0..=text.len() - len).filter(|&i| &text[i..i + len] == pat).collect();
+                assert_eq!(sparse.search(pat), naive);
+                let unverified = sparse.search_unverified(pat);
+                assert!(naive.iter().all(|o| unverified.contains(o)));
+                assert!(sparse.estimate_candidates(pat) >= naive.len());
+                assert!(sparse.search_with_support(pat).iter().any(|(o, _)| *o == start));
+            }
+        }
+        let short = &text[10..10 + min - 1];
+        assert!(sparse.search(short).contains(&10));
+    }
+
+    #[test]
+    fn rebuild_matches_fresh_build_and_reuses_tables() {
+        let low = b"aaab aaab aaab abab".repeat(40);
+        let high: Vec<u8> = (0u32..1500).map(|i| (i.wrapping_mul(2654435761) >> 9) as u8).collect();
+        let mut idx = CsiIndex::build(&low);
+        for (data, config) in [
+            (&high[..], CsiConfig::default()),
+            (&high[200..], CsiConfig::default()),
+            (&low[..], CsiConfig { max_gaps: Some(2), stride: 2, ..CsiConfig::default() }),
+            (&high[..700], CsiConfig { keep_powers: Some(true), ..CsiConfig::default() }),
+        ] {
+            let keys = idx.flat[0].keys.as_ptr();
+            let reusable = idx.flat[0].keys.capacity() >= table_size_for(data.len(), None);
+            idx.rebuild(data, &config);
+            let fresh = CsiIndex::build_with_config(data, &config);
+            assert_eq!((idx.k, &idx.gaps, idx.stride), (fresh.k, &fresh.gaps, fresh.stride));
+            assert_eq!(idx.index_summary(), fresh.index_summary());
+            assert_eq!(idx.powers(), fresh.powers());
+            assert_eq!(idx.validate(), Ok(()));
+            for (a, b) in [(0, 30), (100, 140), (333, 400)] {
+                assert_eq!(idx.search(&data[a..b]), fresh.search(&data[a..b]));
+            }
+            if reusable {
+                assert_eq!(idx.flat[0].keys.as_ptr(), keys);
+            }
+        }
+    }
+
+    #[test]
+    fn short_patterns_fall_back_to_a_scan() {
+        let text = b"scan the short ones; scan the short ones too; aaaa".repeat(4);
+        let naive = |pat: &[u8]| -> Vec<usize> {
+            (0..=text.len() - pat.len()).filter(|&i| text[i..].starts_with(pat)).collect()
+        };
+        let strided = CsiIndexBuilder::new().stride(2).build(&text);
+        for idx in [CsiIndex::build(&text), strided] {
+            let min = idx.min_pattern_len();
+            for pat in [&text[..idx.k], &text[..min - 1], &text[21..21 + min - 1], b"s", b"aa", b"zq"] {
+                let want = naive(pat);
+                assert_eq!(idx.search(pat), want, "{:?}", String::from_utf8_lossy(pat));
+                assert_eq!(idx.count(pat), want.len());
+            }
+            assert_eq!(idx.search(b""), (0..=text.len()).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn short_pattern_scan_can_be_turned_off() {
+        let text = b"only the index answers; only the index answers here".repeat(4);
+        let idx = CsiIndexBuilder::new().scan_short_patterns(false).build(&text);
+        let full = CsiIndex::build(&text);
+        let short = &text[..idx.min_pattern_len() - 1];
+        assert!(full.search(short).len() > 1);
+        assert_eq!((idx.search(short), idx.count(short), idx.estimate_candidates(short)), (vec![], 0, 0));
+        assert!(idx.search_approx(short, 1).is_empty());
+        let long = &text[..idx.min_pattern_len()];
+        assert_eq!(idx.search(long), full.search(long));
+
+        let mut bytes = Vec::new();
+        idx.write_index(&mut bytes).unwrap();
+        let loaded = CsiIndex::read_index(&bytes[..], PolyHasher).unwrap();
+        assert!(loaded.search(short).is_empty());
+    }
+
+    #[test]
+    fn match_bytes_is_bounds_checked() {
+        let text = b"render the matched bytes; render the matched bytes again".to_vec();
+        let idx = CsiIndex::build(&text);
+        let pat = b"render the matched bytes";
+        for off in idx.search(pat) {
+            assert_eq!(idx.match_bytes(off, pat.len()), Some(&pat[..]));
+        }
+        assert_eq!(idx.text(), &text[..]);
+        assert_eq!(idx.match_bytes(text.len(), 0), Some(&b""[..]));
+        assert_eq!(idx.match_bytes(text.len() - 1, 2), None);
+        assert_eq!(idx.match_bytes(usize::MAX, 2), None);
+        assert!(idx.has_text() && CsiIndex::build(b"").has_text());
+
+        // a sidecar's text is empty for want of one, not because it's empty
+        let mut bytes = Vec::new();
+        idx.write_sidecar(&mut bytes).unwrap();
+        let sidecar = CsiIndex::read_sidecar(&bytes[..], PolyHasher).unwrap();
+        assert!(!sidecar.has_text() && sidecar.text().is_empty());
+        assert_eq!(sidecar.match_bytes(0, pat.len()), None);
+    }
+
+    #[test]
+    fn pattern_spanning_whole_text() {
+        let text = b"the pattern is the entire text, exactly".to_vec();
+        let idx = CsiIndex::build(&text);
+        assert_eq!(idx.search(&text), vec![0]);
+        assert_eq!(idx.search(&text[..text.len() - 1]), vec![0]);
+        assert_eq!(idx.search(&text[1..]), vec![1]);
+        let mut longer = text.clone();
+        longer.push(b'!');
+        assert!(idx.search(&longer).is_empty());
+        let mut off_by_one = text.clone();
+        *off_by_one.last_mut().unwrap() = b'?';
+        assert!(idx.search(&off_by_one).is_empty());
+        assert_eq!(CsiIndex::build(b"").search(b""), vec![0]);
+    }
+
+    #[test]
+    fn compact_reclaims_truncated_postings() {
+        let text = b"compact this sentence now; compact this sentence now; and drop the rest of it here".to_vec();
+        let mut idx = CsiIndex::build(&text);
+        idx.truncate(54);
+        let pats = [&text[..26], &text[3..40], &text[27..54]];
+        let before: Vec<Vec<usize>> = pats.iter().map(|p| idx.search(p)).collect();
+        let stale: usize = idx.flat.iter().map(|f| f.offs.len()).sum();
+
+        idx.compact();
+        let after: Vec<Vec<usize>> = pats.iter().map(|p| idx.search(p)).collect();
+        assert_eq!(before, after);
+        assert_eq!(idx.validate(), Ok(()));
+        let packed: usize = idx.flat.iter().map(|f| f.offs.len()).sum();
+        assert!(packed < stale);
+        assert_eq!(packed, idx.index_summary().total_postings);
+    }
+
+    #[test]
+    fn best_gaps_agrees_with_full_search() {
+        let text = b"one long pattern over several gap tables, one long pattern over several gap tables!".repeat(3);
+        let idx = CsiIndex::build(&text);
+        for pat in [&text[..80], &text[7..70], &text[40..120]] {
+            let full = idx.search(pat);
+            assert!(!full.is_empty());
+            for g in 0..=idx.gaps.len() {
+                assert_eq!(idx.search_best_gaps(pat, g), full);
+            }
+        }
+    }
+
+    #[test]
+    fn planner_stops_probing_once_a_list_is_short() {
+        let text: Vec<u8> = (0..3000).flat_map(|i| format!("id={} kind={} ", i, i % 4).into_bytes()).collect();
+        let tiers = EntropyTiers::fixed(4, vec![4, 8, 16, 32]);
+        let idx = CsiIndexBuilder::new().tiers(tiers.clone()).build(&text);
+        let raw = CsiIndexBuilder::new().tiers(tiers).verify(false).build(&text);
+        let lists = |idx: &CsiIndex, pat: &[u8]| {
+            let php = prefix_hash(&idx.hasher, pat);
+            let planned = idx.phase_lists_hashed(pat.len(), &php).unwrap();
+            (planned[0].1.len(), idx.phase_lists(pat).unwrap()[0].1.len())
+        };
+        // a rare pattern is settled by its first gap, a common one needs all
+        let rare: &[u8] = b"id=1234 kind=2 id=1235 kind=3 id=1236 kind=0 ";
+        let common: &[u8] = b" kind=1 id=2";
+        assert_eq!(lists(&idx, rare), (1, 4));
+        assert_eq!(lists(&idx, common), (2, 2));
+        assert_eq!(lists(&raw, rare), (4, 4));
+        for pat in [rare, common, b"kind=3 id=2999 kind=3 ", b"id=77 kind=1 id=78"] {
+            let naive: Vec<usize> = (0..=text.len() - pat.len()).filter(|&i| text[i..].starts_with(pat)).collect();
+            assert_eq!(idx.search(pat), naive);
+            assert_eq!((idx.count(pat), idx.contains(pat)), (naive.len(), !naive.is_empty()));
+            assert_eq!(idx.search_iter(pat).collect::<Vec<_>>(), naive);
+            assert!(idx.search_unverified(pat).len() >= naive.len());
+        }
+    }
+
+    #[test]
+    fn empty_pattern_and_degenerate_text_contract() {
+        let text = b"short text";
+        let idx = CsiIndex::build(text);
+        assert_eq!(idx.search(b""), (0..=text.len()).collect::<Vec<_>>());
+        assert_eq!(idx.search_by(b"", |a, b| a == b), idx.search(b""));
+
+        let empty = CsiIndex::build(b"");
+        assert_eq!(empty.search(b""), vec![0]);
+        assert!(empty.search(&[b'x'; 40]).is_empty());
+
+        // uniform input: one giant bucket per gap, still exact
+        let same = vec![b'z'; 500];
+        let idx = CsiIndex::build(&same);
+        assert_eq!(idx.validate(), Ok(()));
+        assert!(idx.index_summary().per_gap.iter().all(|&(_, distinct, _)| distinct == 1));
+        assert_eq!(idx.search(&[b'z'; 100]), (0..=400).collect::<Vec<_>>());
+        assert_eq!(idx.search(&[b'z'; 500]), vec![0]);
+        assert!(idx.search(&[b'z'; 501]).is_empty());
+    }
+
+    #[test]
+    fn custom_thresholds_pick_the_expected_tier() {
+        let text = b"entropy tiers are now caller-tunable, not hard-coded";
+        let ent = compute_entropy(text);
+        let params = || [(7, vec![9]), (3, vec![3, 5]), (2, vec![2])];
+        assert_eq!(CsiIndex::build_auto_with_thresholds(text, ent + 1.0, ent + 2.0, params()).k, 7);
+        let mid = CsiIndex::build_auto_with_thresholds(text, ent - 1.0, ent + 1.0, params());
+        assert_eq!((mid.k, mid.gaps.clone()), (3, vec![3, 5]));
+        assert_eq!(CsiIndex::build_auto_with_thresholds(text, 0.0, ent, params()).k, 2);
+        assert_eq!(mid.search(b"tiers are now caller"), vec![8]);
+
+        let default = CsiIndex::build(text);
+        let (k, mut gaps) = EntropyTiers::default().choose(ent);
+        // gaps too wide for this text get no table
+        gaps.retain(|&d| k + d <= text.len());
+        assert_eq!((default.k, default.gaps), (k, gaps));
     }
-    n
 }
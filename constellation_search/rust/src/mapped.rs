@@ -0,0 +1,259 @@
+// src/mapped.rs
+//! Indexes over memory‐mapped files: tables built over a mapped text and
+//! verifying against the mapping instead of a private copy, or a whole
+//! serialized index searched in place.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+use memmap2::Mmap;
+
+use crate::array::{Array, Word};
+use crate::sidecar::{get, Copied, Source};
+use crate::{CsiConfig, CsiIndex, CsiIndexError, Hasher, PolyHasher, Spare};
+
+/// Gap tables plus the mapping they were built over; see
+/// `CsiIndex::build_from_file`.
+///
+/// The mapping is shared with the file, not a snapshot of it. If another
+/// process rewrites the file, searches verify against the new bytes through
+/// tables keyed by the old ones and can miss or report wrong matches;
+/// `check_source` detects that. If the file is truncated, touching the lost
+/// pages raises `SIGBUS` and kills the process. Only map files nobody
+/// modifies while the index is alive.
+pub struct MmapSourceIndex {
+    index: CsiIndex, // built over `map`; its own text is left empty
+    map:   Mmap,
+}
+
+impl CsiIndex {
+    /// Map the file at `path` and build tables over it, keeping the mapping
+    /// for verification instead of copying the file into the index. Heap use
+    /// is the gap tables alone; the text stays in the page cache.
+    ///
+    /// Case folding, newline normalization and masks rewrite the text, so
+    /// there would be nothing to map; configs using them, or table sizes too
+    /// small for the file, are refused with `InvalidInput`. Errors opening
+    /// or mapping the file pass through.
+    pub fn build_from_file(path: &Path, config: &CsiConfig) -> io::Result<MmapSourceIndex> {
+        if config.rewrites_text() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, CsiIndexError::RewritesText));
+        }
+        let file = File::open(path)?;
+        // SAFETY: see the hazards on `MmapSourceIndex`; the caller keeps the
+        // file unmodified while the index is alive.
+        let map = unsafe { Mmap::map(&file)? };
+        let index = CsiIndex::build_phased((&map[..]).into(), config, PolyHasher, None, None, Spare::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok(MmapSourceIndex { index, map })
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Map a whole index from `write_index` (or `csi_save`), keyed by
+    /// `hasher`, and search its tables and text in place: nothing is copied
+    /// or re‐hashed, so opening costs a pass over the tables for the
+    /// structural checks `read_index` makes, at page‐cache speed. Heap use is
+    /// the bookkeeping and hash powers alone, and writes through `append`,
+    /// `truncate` or a rebuild copy the arrays they touch out of the mapping.
+    ///
+    /// Where the target can't read the file's words as stored (big‐endian or
+    /// 32‐bit), the arrays are copied as `read_index` would. The hazards of
+    /// `MmapSourceIndex` apply: only map files nobody modifies while the
+    /// index is alive. Errors as from `read_index`, or from opening the file.
+    pub fn open_mmap(path: &Path, hasher: H) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: see the hazards on `MmapSourceIndex`; the caller keeps the
+        // file unmodified while the index is alive.
+        let map = Arc::new(unsafe { Mmap::map(&file)? });
+        Self::read_format(&mut InPlace { map, pos: 0 }, hasher, true)
+    }
+}
+
+/// A cursor over a mapped index file that leaves arrays in the mapping
+struct InPlace {
+    map: Arc<Mmap>,
+    pos: usize,
+}
+
+impl InPlace {
+    /// `len` elements of `T` in the `bytes` bytes at the cursor, past which
+    /// it moves; if the target can't read them in place, the cursor goes
+    /// back to `from` and `copy` reads them instead.
+    fn in_place<T: Word>(&mut self, from: usize, len: usize, bytes: Option<usize>,
+                         copy: impl FnOnce(&mut Copied<&mut Self>) -> io::Result<Array<T>>) -> io::Result<Array<T>> {
+        let at = self.pos;
+        self.pos = bytes.and_then(|b| b.checked_add(at)).filter(|&end| end <= self.map.len())
+            .ok_or(io::ErrorKind::UnexpectedEof)?;
+        match Array::mapped(&self.map, at, len) {
+            Some(array) => Ok(array),
+            None => {
+                self.pos = from;
+                copy(&mut Copied(self))
+            }
+        }
+    }
+
+    /// A `put_words` length prefix.
+    fn count(&mut self) -> io::Result<usize> {
+        usize::try_from(get(self)?).map_err(|_| io::ErrorKind::UnexpectedEof.into())
+    }
+}
+
+impl Read for InPlace {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let rest = self.map.get(self.pos..).unwrap_or_default();
+        let n = rest.len().min(buf.len());
+        buf[..n].copy_from_slice(&rest[..n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl Source for InPlace {
+    fn words(&mut self) -> io::Result<Array<u64>> {
+        let from = self.pos;
+        let len = self.count()?;
+        self.in_place(from, len, len.checked_mul(8), |r| r.words())
+    }
+
+    fn sizes(&mut self) -> io::Result<Array<usize>> {
+        let from = self.pos;
+        let len = self.count()?;
+        self.in_place(from, len, len.checked_mul(8), |r| r.sizes())
+    }
+
+    fn packed(&mut self, len: usize) -> io::Result<Array<u16>> {
+        let from = self.pos;
+        let words = self.count()?;
+        if words != len.div_ceil(4) {
+            // let the copying reader report it
+            self.pos = from;
+            return Copied(self).packed(len);
+        }
+        self.in_place(from, len, words.checked_mul(8), |r| r.packed(len))
+    }
+
+    fn halves(&mut self, len: usize) -> io::Result<Array<u32>> {
+        let from = self.pos;
+        let words = self.count()?;
+        if words != len.div_ceil(2) {
+            self.pos = from;
+            return Copied(self).halves(len);
+        }
+        self.in_place(from, len, words.checked_mul(8), |r| r.halves(len))
+    }
+
+    fn text(&mut self, len: usize) -> io::Result<Array<u8>> {
+        let from = self.pos;
+        self.in_place(from, len, len.checked_next_multiple_of(8), |r| r.text(len))
+    }
+}
+
+impl MmapSourceIndex {
+    /// The mapped file contents, which searches verify against.
+    pub fn text(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// Sorted offsets of every exact occurrence of `pat` in the file, as
+    /// `CsiIndex::search` would report them.
+    pub fn search(&self, pat: &[u8]) -> Vec<usize> {
+        self.index.search_over(pat, &self.map)
+    }
+
+    /// See `CsiIndex::k`.
+    pub fn k(&self) -> usize {
+        self.index.k()
+    }
+
+    /// See `CsiIndex::gaps`.
+    pub fn gaps(&self) -> &[usize] {
+        self.index.gaps()
+    }
+
+    /// Heap bytes held by the tables; the mapping is not counted.
+    pub fn heap_bytes(&self) -> usize {
+        self.index.heap_bytes()
+    }
+
+    /// Rehash the mapping and compare it with what the tables were built
+    /// over: `CsiIndexError::TextMismatch` if the file changed since. O(n);
+    /// it cannot catch a change made while it runs.
+    pub fn check_source(&self) -> Result<(), CsiIndexError> {
+        let digest = self.map.iter().fold(0, |h, &b| self.index.hasher.extend(h, b));
+        if self.map.len() == self.index.text_len && digest == self.index.digest {
+            Ok(())
+        } else {
+            Err(CsiIndexError::TextMismatch)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+
+    use crate::{CaseFold, CsiConfig, CsiIndex, PolyHasher};
+
+    #[test]
+    fn mapped_file_searches_like_an_owned_index() {
+        let text = b"map the corpus, index the map, verify against the map; map the corpus".repeat(30);
+        let path = std::env::temp_dir().join(format!("csi-mapped-{}.txt", std::process::id()));
+        std::fs::File::create(&path).unwrap().write_all(&text).unwrap();
+
+        let owned = CsiIndex::build(&text);
+        let mapped = CsiIndex::build_from_file(&path, &CsiConfig::default()).unwrap();
+        assert_eq!((mapped.text(), mapped.k(), mapped.gaps()), (&text[..], owned.k(), owned.gaps()));
+        for pat in [&text[..40], &text[25..90], b"verify against the map; map th", b"not in the corpus at all"] {
+            assert_eq!(mapped.search(pat), owned.search(pat));
+        }
+        assert_eq!(mapped.search(b"").len(), text.len() + 1);
+        assert!(mapped.heap_bytes() + text.len() <= owned.heap_bytes());
+        assert_eq!(mapped.check_source(), Ok(()));
+
+        let folding = CsiConfig { case_fold: CaseFold::Unicode, ..CsiConfig::default() };
+        let refused = CsiIndex::build_from_file(&path, &folding).err().unwrap();
+        assert_eq!(refused.kind(), io::ErrorKind::InvalidInput);
+        std::fs::remove_file(&path).unwrap();
+        assert!(CsiIndex::build_from_file(&path, &CsiConfig::default()).is_err());
+    }
+
+    #[test]
+    fn mapped_index_file_searches_in_place() {
+        let text = b"open the index, search the mapping, copy nothing; open the index".repeat(40);
+        let config = CsiConfig { fingerprints: true, ..CsiConfig::default() };
+        let built = CsiIndex::build_with_config(&text, &config);
+        let path = std::env::temp_dir().join(format!("csi-open-{}.idx", std::process::id()));
+        let mut bytes = Vec::new();
+        built.write_index(&mut bytes).unwrap();
+        std::fs::write(&path, &bytes).unwrap();
+
+        let mut opened = CsiIndex::open_mmap(&path, PolyHasher).unwrap();
+        let heap = opened.mem_usage();
+        assert_eq!((heap.text, heap.slots, heap.postings), (0, 0, 0));
+        assert_eq!((opened.text(), opened.k(), opened.gaps()), (&text[..], built.k(), built.gaps()));
+        assert_eq!(opened.validate(), Ok(()));
+        for pat in [&text[..40], &text[19..83], b"copy nothing; open the index, search", b"never written to the file"] {
+            assert_eq!(opened.search(pat), built.search(pat));
+        }
+        // writes move what they touch onto the heap and leave the file alone
+        opened.append(b" and append");
+        assert_eq!(opened.search(b"open the index and append"), vec![text.len() - 14]);
+        assert_eq!(std::fs::read(&path).unwrap(), bytes);
+
+        std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        assert!(CsiIndex::open_mmap(&path, PolyHasher).is_err());
+
+        // a text length too large to pad to a word, just before the text
+        let at = bytes.windows(text.len()).position(|w| w == text).unwrap() - 8;
+        assert_eq!(bytes[at..at + 8], (text.len() as u64).to_le_bytes());
+        let mut corrupt = bytes.clone();
+        corrupt[at..at + 8].copy_from_slice(&(u64::MAX - 2).to_le_bytes());
+        std::fs::write(&path, &corrupt).unwrap();
+        assert!(CsiIndex::open_mmap(&path, PolyHasher).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+// src/mask.rs
+//! Periodic don't‐care bytes for fixed‐layout records.
+//!
+//! A `ByteMask` marks positions `ignored` within every `period`‐byte frame
+//! of the text, counted from text offset 0: byte `o` is a don't‐care when
+//! `o % period` is in `ignored`. Those bytes are zeroed in the indexed text
+//! (so `text()` shows them as 0), which takes them out of every hash. A
+//! search then can't know a pattern's alignment up front, so it runs once per
+//! frame phase `r`, zeroing the pattern bytes that would land on ignored
+//! positions if it started at an offset `≡ r (mod period)`, and keeps the
+//! matches with that phase. Verification compares the zeroed forms, so
+//! ignored bytes match anything.
+
+use alloc::vec::Vec;
+
+use crate::{verify, CsiIndex, Hasher};
+
+/// Byte positions ignored in every `period`‐byte frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByteMask {
+    period:  usize,
+    ignored: Vec<bool>, // len = period
+}
+
+impl ByteMask {
+    /// Ignore frame positions `ignored` (each `< period`).
+    ///
+    /// Panics if `period` is 0 or a position is out of range.
+    pub fn new(period: usize, ignored: &[usize]) -> Self {
+        assert!(period > 0, "mask period must be non‐zero");
+        let mut flags = vec![false; period];
+        for &p in ignored {
+            assert!(p < period, "masked position {p} outside period {period}");
+            flags[p] = true;
+        }
+        ByteMask { period, ignored: flags }
+    }
+
+    /// Whether text offset `off` is a don't‐care.
+    pub fn ignores(&self, off: usize) -> bool {
+        self.ignored[off % self.period]
+    }
+
+    /// Zero the don't‐care bytes of `data`, taken to start at text offset
+    /// `start`.
+    pub(crate) fn apply(&self, data: &mut [u8], start: usize) {
+        let mut p = start % self.period;
+        for b in data {
+            if self.ignored[p] {
+                *b = 0;
+            }
+            p = if p + 1 == self.period { 0 } else { p + 1 };
+        }
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// `search` for a masked index: one pass per frame phase.
+    pub(crate) fn search_masked(&self, pat: &[u8], mask: &ByteMask) -> Vec<usize> {
+        let eq = verify::same;
+        let mut out: Vec<usize> = Vec::new();
+        let mut phased = pat.to_vec();
+        for r in 0..mask.period {
+            phased.copy_from_slice(pat);
+            mask.apply(&mut phased, r);
+            out.extend(self.search_lists(&phased, usize::MAX, &eq)
+                .into_iter()
+                .filter(|&off| off % mask.period == r));
+        }
+        out.sort_unstable();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ByteMask;
+    use crate::{CsiConfig, CsiIndex};
+
+    /// 16‐byte records: 12 bytes of payload, then a 4‐byte checksum.
+    fn record(payload: &[u8; 12], sum: u32) -> Vec<u8> {
+        let mut r = payload.to_vec();
+        r.extend_from_slice(&sum.to_le_bytes());
+        r
+    }
+
+    #[test]
+    fn masked_checksums_are_dont_cares() {
+        let payloads: [&[u8; 12]; 4] = [b"alpha-record", b"bravo-record", b"charlie-rec!", b"delta-record"];
+        let mut text = Vec::new();
+        for round in 0..3u32 {
+            for (i, p) in payloads.iter().enumerate() {
+                text.extend(record(p, round * 1000 + i as u32 * 7919));
+            }
+        }
+        let mask = ByteMask::new(16, &[12, 13, 14, 15]);
+        let cfg = CsiConfig { mask: Some(mask.clone()), ..CsiConfig::default() };
+        let idx = CsiIndex::build_with_config(&text, &cfg);
+
+        // two records with made‐up checksums still match every round
+        let mut pat = record(payloads[1], 0xdead_beef);
+        pat.extend(record(payloads[2], 42));
+        assert_eq!(idx.search(&pat), [16, 80, 144]);
+        assert!(CsiIndex::build(&text).search(&pat).is_empty());
+
+        // unaligned start: from the middle of a payload across a checksum
+        let unaligned = [&b"vo-record"[..], &[9, 9, 9, 9], b"charlie-rec!"].concat();
+        assert_eq!(idx.search(&unaligned), [19, 83, 147]);
+
+        // payload differences still reject
+        let mut wrong = pat.clone();
+        wrong[3] = b'X';
+        assert!(idx.search(&wrong).is_empty());
+        assert!(mask.ignores(31) && !mask.ignores(32));
+        assert_eq!(idx.text()[12..16], [0, 0, 0, 0]);
+    }
+}
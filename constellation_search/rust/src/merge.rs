@@ -0,0 +1,251 @@
+// src/merge.rs
+//! One index from several built over consecutive shards of a text.
+//!
+//! Every gap table is laid out afresh from the parts' postings, shifted by
+//! the bytes of the parts before them, plus the constellations straddling
+//! a seam between two parts, which only hashing the bytes around the seam
+//! finds. A part that doesn't start on a multiple of the stride indexed
+//! offsets off the merged index's grid, so its span is hashed as well.
+//! Nothing else is re‐hashed: the tables are rebuilt from stored keys, as
+//! `append` re‐slots them.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::hash::{fingerprint, prefix_hash, window_const};
+use crate::{CsiIndex, CsiIndexError, FlatIndex, Hasher, TableParams, SATURATED};
+use crate::tombstone::insert_dead;
+
+impl<H: Hasher> CsiIndex<H> {
+    /// One index over the concatenated texts of `parts`, in order, that
+    /// searches like an index built over that text in one pass; offsets,
+    /// slice bounds, CRLF positions and invalidated ranges are shifted by the bytes before
+    /// their part.
+    ///
+    /// The parts must share every build option and the hasher's seed, and
+    /// `k` and the gaps with them, so fix those (`CsiIndexBuilder::params`)
+    /// rather than leave each shard to its entropy. Each must hold its own
+    /// text, without a mask or merged duplicate slices, and document IDs
+    /// carry over only if every part has them. Otherwise, or without any
+    /// parts, `CsiIndexError::IncompatibleParts`. A `\r` ending one part
+    /// and a `\n` starting the next stay two bytes, as newline
+    /// normalization only joins them within a text; the line index is kept
+    /// if every part has one.
+    pub fn merge(parts: Vec<CsiIndex<H>>) -> Result<Self, CsiIndexError> {
+        Self::check_mergeable(parts.iter())?;
+        let gaps = merged_gaps(&parts.iter().collect::<Vec<_>>()).unwrap_or_default().to_vec();
+        let first = &parts[0];
+        let bases: Vec<usize> = parts.iter().scan(0, |at, part| {
+            *at += part.text_len;
+            Some(*at - part.text_len)
+        }).collect();
+        let text: Vec<u8> = parts.iter().flat_map(|part| part.text.iter().copied()).collect();
+        let n = text.len();
+        let p = first.table_params();
+        let flat = gaps.iter().enumerate().map(|(g, &d)| {
+            let ts = match first.fixed {
+                true => parts.iter().filter_map(|part| part.flat.get(g)).map(|fi| fi.table_size).max().unwrap_or(0),
+                false => p.slots(n, d),
+            };
+            merged_table(&parts, &bases, &text, g, d, ts, p)
+        }).collect();
+
+        fn rebased(v: &[usize], base: usize) -> impl Iterator<Item = usize> + '_ {
+            v.iter().map(move |&o| o + base)
+        }
+        let (mut crlf, mut bounds, mut ids) = (Vec::new(), Vec::new(), Vec::new());
+        let slices = parts.iter().any(|part| !part.bounds.is_empty());
+        for (part, &base) in parts.iter().zip(&bases) {
+            crlf.extend(rebased(&part.crlf, base));
+            if slices {
+                bounds.extend(rebased(if part.bounds.is_empty() { &[0] } else { &part.bounds }, base));
+            }
+            ids.extend_from_slice(&part.ids);
+        }
+        let lines = parts.iter().all(|part| part.lines.is_some()).then(|| {
+            parts.iter().zip(&bases).flat_map(|(part, &base)| rebased(part.lines.as_deref().unwrap_or_default(), base)).collect()
+        });
+        let mut dead = Vec::new();
+        for (part, &base) in parts.iter().zip(&bases) {
+            for r in &part.dead {
+                insert_dead(&mut dead, r.start + base..r.end + base);
+            }
+        }
+        let mut pw = first.pw.clone();
+        if parts.iter().any(|part| part.pw.len() > part.k + 1) {
+            while pw.len() <= n {
+                pw.push(first.hasher.step(pw[pw.len() - 1]));
+            }
+        } else {
+            pw.truncate(first.k + 1);
+        }
+        let digest = text[first.text_len..].iter().fold(first.digest, |h, &b| first.hasher.extend(h, b));
+        let (k, cap, stride, window, verify, fold, newlines) =
+            (first.k, first.cap, first.stride, first.window, first.verify, first.fold, first.newlines);
+        let (load, fixed, fps, scan_short, wide, pack) = (first.load, first.fixed, first.fps, first.scan_short, first.wide, first.pack);
+        let hasher = parts.into_iter().next().map(|part| part.hasher).expect("checked above");
+        Ok(CsiIndex {
+            k, gaps, flat, text: text.into(), pw, bounds, aliases: Default::default(), ids, cap, stride, window, verify,
+            fold, newlines, crlf, lines, dead, mask: None, digest, text_len: n, load, fixed, fps, scan_short, wide, pack, hasher,
+        })
+    }
+
+    /// `Err(IncompatibleParts)` where `merge` would refuse `parts`.
+    pub(crate) fn check_mergeable<'a>(parts: impl Iterator<Item = &'a Self>) -> Result<(), CsiIndexError>
+    where
+        H: 'a,
+    {
+        let parts: Vec<&Self> = parts.collect();
+        let gaps = merged_gaps(&parts).ok_or(CsiIndexError::IncompatibleParts)?;
+        let shape = |part: &Self| (
+            (part.k, part.stride, part.window, part.cap, part.pack, part.load.map(f64::to_bits)),
+            (part.fps, part.wide, part.verify, part.scan_short, part.fixed, part.fold, part.newlines),
+            (part.hasher.seed(), part.ids.is_empty()),
+        );
+        let fits = |part: &Self| part.mask.is_none() && part.aliases.starts.is_empty() && part.has_text()
+            // a text too short for a gap's constellations drops its table
+            && gaps.starts_with(&part.gaps) && gaps.get(part.gaps.len()).is_none_or(|&d| part.k + d > part.text_len);
+        match parts.iter().all(|&part| fits(part) && shape(part) == shape(parts[0])) {
+            true => Ok(()),
+            false => Err(CsiIndexError::IncompatibleParts),
+        }
+    }
+}
+
+/// The longest of the parts' gap lists, which the others should start
+/// with; `None` without parts.
+fn merged_gaps<'a, H: Hasher>(parts: &[&'a CsiIndex<H>]) -> Option<&'a [usize]> {
+    parts.iter().map(|part| &part.gaps[..]).max_by_key(|gaps| gaps.len())
+}
+
+/// Gap table `g`, of gap `d`, over `text`, the texts of `parts`
+/// concatenated at `bases`, laid out over `ts` slots.
+fn merged_table<H: Hasher>(parts: &[CsiIndex<H>], bases: &[usize], text: &[u8], g: usize, d: usize, ts: usize, p: TableParams)
+                           -> FlatIndex {
+    let (h, k) = (&parts[0].hasher, p.k);
+    // (key, offset, fingerprint); packed lists kept none, and buckets that
+    // large are packed again
+    let mut postings = Vec::new();
+    let mut saturated = Vec::new();
+    // start offsets no part indexed on the merged grid
+    let mut unindexed: Vec<Range<usize>> = Vec::new();
+    let mut covered = 0;
+    for (part, &base) in parts.iter().zip(bases) {
+        if !base.is_multiple_of(p.stride) {
+            continue;
+        }
+        // a part without the table has no constellations of its gap
+        for (fi, (key, start, len)) in part.flat.get(g).into_iter().flat_map(|fi| fi.entries().map(move |e| (fi, e))) {
+            if len != SATURATED {
+                let fps = fi.fps.get(start..start + len);
+                for (i, o) in fi.offs.list(start..start + len).iter().enumerate() {
+                    postings.push((key, base + o, fps.map_or(0, |f| f[i])));
+                }
+            } else if let Some(list) = fi.packed_list(key) {
+                postings.extend(list.iter().map(|o| (key, base + o, 0)));
+            } else {
+                saturated.push(key);
+            }
+        }
+        if base > covered {
+            unindexed.push(covered..base);
+        }
+        covered = covered.max(base + p.starts(part.text_len, d));
+    }
+    let m = p.starts(text.len(), d);
+    if m > covered {
+        unindexed.push(covered..m);
+    }
+    let (pk, c) = (parts[0].pw[k], window_const(h, k + d));
+    // a short last part leaves starts between parts that no constellation
+    // fits after, so spans stop at `m`
+    for span in unindexed.into_iter().map(|r| r.start..r.end.min(m)).filter(|r| !r.is_empty()) {
+        // hashed from a stride‐aligned start, so keys land on the grid, and
+        // on past the last start to its syncmer's end
+        let from = span.start / p.stride * p.stride;
+        let ph = prefix_hash(h, &text[from..span.end - 1 + (k + d).max(p.reach())]);
+        p.each_key(h, &ph, pk, d, span.end - from, |i, key| {
+            if from + i >= span.start {
+                let fp = if p.fps { fingerprint(h.window(ph[i + k + d], ph[i], c)) } else { 0 };
+                postings.push((key, from + i, fp));
+            }
+        });
+    }
+    postings.sort_unstable();
+    saturated.sort_unstable();
+    saturated.dedup();
+    let added: Vec<(u64, usize)> = postings.iter().map(|&(key, o, _)| (key, o)).collect();
+    let fps: Vec<u16> = postings.iter().map(|e| e.2).collect();
+    // keys a part saturated hold more than the cap in the whole text too
+    let none = FlatIndex { overflow: saturated.into_iter().map(|key| (key, 0, SATURATED)).collect(), ..FlatIndex::default() };
+    none.merged(&added, p.fps.then_some(&fps[..]), ts, p)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CsiIndex, CsiIndexBuilder, CsiIndexError, EntropyTiers};
+
+    #[test]
+    fn merged_shards_search_like_one_build() {
+        let text: Vec<u8> = (0..1200).flat_map(|i| format!("{:03} req={} ok\r\n", i % 170, i * 7 % 31).into_bytes()).collect();
+        let tiers = EntropyTiers::fixed(4, vec![4, 8, 16]);
+        let builders = [
+            CsiIndexBuilder::new().tiers(tiers.clone()),
+            CsiIndexBuilder::new().tiers(tiers.clone()).stride(3).fingerprints(true).max_postings_per_key(60),
+            CsiIndexBuilder::new().tiers(tiers.clone()).pack_postings(40).track_lines(true).keep_powers(true),
+            CsiIndexBuilder::new().tiers(tiers.clone()).normalize_newlines(true),
+            CsiIndexBuilder::new().tiers(tiers).syncmer_window(6).fingerprints(true),
+        ];
+        // seams inside records, off the stride grid, and a shard narrower
+        // than the widest constellation
+        let cuts = [0, 1001, 4003, 4013, 9000, text.len()];
+        for builder in builders {
+            let whole = builder.build(&text);
+            let shards: Vec<&[u8]> = cuts.windows(2).map(|w| &text[w[0]..w[1]]).collect();
+            let parts: Vec<CsiIndex> = shards.iter().map(|s| builder.build(s)).collect();
+            let merged = CsiIndex::merge(parts).unwrap();
+            assert_eq!(merged.validate(), Ok(()));
+            assert_eq!(merged.text(), whole.text());
+            assert_eq!(merged.index_summary(), whole.index_summary());
+            for at in (0..whole.text().len() - 30).step_by(29) {
+                for len in [4, 12, 23] {
+                    let pat = &whole.text()[at..at + len];
+                    assert_eq!(merged.search(pat), whole.search(pat), "{:?}", String::from_utf8_lossy(pat));
+                }
+            }
+            let end = merged.text().len();
+            assert_eq!(merged.resolve_position(end), whole.resolve_position(end));
+            assert_eq!(merged.original_offset(end), whole.original_offset(end));
+            assert_eq!(merged.powers().len(), whole.powers().len());
+        }
+        let a = CsiIndexBuilder::new().params(4, vec![4, 8]).build(&text[..500]);
+        let b = CsiIndexBuilder::new().params(4, vec![4, 16]).build(&text[500..]);
+        assert_eq!(CsiIndex::merge(vec![a, b]).err(), Some(CsiIndexError::IncompatibleParts));
+        assert_eq!(CsiIndex::<crate::PolyHasher>::merge(Vec::new()).err(), Some(CsiIndexError::IncompatibleParts));
+    }
+
+    #[test]
+    fn short_last_shards_merge_like_one_build() {
+        let text: Vec<u8> = (0..59).map(|i| b"abcdefghijklmnopqrstuvw"[i * 7 % 23]).collect();
+        let builders = [
+            CsiIndexBuilder::new().params(3, vec![2, 5, 9]),
+            CsiIndexBuilder::new().params(3, vec![2, 5, 9]).stride(2).fingerprints(true),
+            CsiIndexBuilder::new().params(3, vec![2, 5, 9]).syncmer_window(3),
+        ];
+        for builder in builders {
+            let whole = builder.build(&text);
+            // an empty last shard, one byte, and one byte short of the
+            // widest constellation
+            for tail in [0, 1, 3 + 9 - 2] {
+                let cut = text.len() - tail;
+                let merged = CsiIndex::merge(vec![builder.build(&text[..cut]), builder.build(&text[cut..])]).unwrap();
+                assert_eq!(merged.validate(), Ok(()));
+                assert_eq!(merged.index_summary(), whole.index_summary(), "tail {tail}");
+                for at in 0..text.len() - 12 {
+                    let pat = &text[at..at + 12];
+                    assert_eq!(merged.search(pat), whole.search(pat), "tail {tail}, at {at}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+// src/newline.rs
+//! CRLF → LF normalization and the offset map back to the source bytes,
+//! and line/column positions.
+//!
+//! Unlike case folding this changes the text's length, so offsets reported
+//! by searches are positions in the normalized text; `original_offset` maps
+//! them back using the recorded positions of the dropped `\r` bytes.
+//! Positions count lines in the indexed text, where a collapsed `\r\n` is
+//! one `\n`, from the line index of `CsiConfig::track_lines` if kept.
+
+use alloc::vec::Vec;
+
+use crate::{CsiIndex, Hasher};
+
+/// `data` with every `\r\n` collapsed to `\n`, plus the normalized position
+/// of each `\n` that lost its `\r` (ascending); `None` if there's no CRLF.
+pub(crate) fn strip_crlf(data: &[u8]) -> Option<(Vec<u8>, Vec<usize>)> {
+    if !data.windows(2).any(|w| w == b"\r\n") {
+        return None;
+    }
+    let mut out = Vec::with_capacity(data.len());
+    let mut lfs = Vec::new();
+    for (i, &b) in data.iter().enumerate() {
+        if b == b'\r' && data.get(i + 1) == Some(&b'\n') {
+            lfs.push(out.len());
+        } else {
+            out.push(b);
+        }
+    }
+    Some((out, lfs))
+}
+
+/// `data` with every `\r\n` collapsed to `\n`, in place.
+pub(crate) fn strip_crlf_in_place(data: &mut Vec<u8>) {
+    let mut kept = 0;
+    for i in 0..data.len() {
+        // `kept <= i`, so the byte after `i` is still the original
+        if data[i] != b'\r' || data.get(i + 1) != Some(&b'\n') {
+            data[kept] = data[i];
+            kept += 1;
+        }
+    }
+    data.truncate(kept);
+}
+
+/// Offsets of the `\n`s in `data`, plus `base`.
+pub(crate) fn line_breaks(data: &[u8], base: usize) -> impl Iterator<Item = usize> + '_ {
+    data.iter().enumerate().filter(|&(_, &b)| b == b'\n').map(move |(i, _)| base + i)
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// 1‐based line and byte column of text offset `offset`; an offset at
+    /// a `\n` is the last column of its line, and `text().len()` a
+    /// position past the last line break.
+    ///
+    /// Without `CsiConfig::track_lines` this counts the `\n`s ahead of
+    /// `offset` in the stored text, so it costs a scan and sees masked
+    /// line breaks as blanked. Panics if `offset` is past the end of the
+    /// text, or if the index keeps neither a line index nor its text.
+    pub fn resolve_position(&self, offset: usize) -> (usize, usize) {
+        assert!(offset <= self.text_len, "offset {offset} past the end of the text");
+        let (line, start) = match &self.lines {
+            Some(lines) => match lines.partition_point(|&p| p < offset) {
+                0 => (0, 0),
+                i => (i, lines[i - 1] + 1),
+            },
+            None => {
+                let ahead = &self.text[..offset];
+                (line_breaks(ahead, 0).count(), ahead.iter().rposition(|&b| b == b'\n').map_or(0, |p| p + 1))
+            }
+        };
+        (line + 1, offset - start + 1)
+    }
+
+    /// `search`, each match as `(line, column, offset)`, its position as
+    /// `resolve_position` gives it. Without a line index the text is
+    /// scanned once, up to the last match.
+    pub fn search_lines(&self, pat: &[u8]) -> Vec<(usize, usize, usize)> {
+        let hits = self.search(pat);
+        if self.lines.is_some() {
+            return hits.into_iter().map(|off| {
+                let (line, col) = self.resolve_position(off);
+                (line, col, off)
+            }).collect();
+        }
+        // matches ascend, so each scan picks up where the last one stopped
+        let (mut line, mut start, mut at) = (1, 0, 0);
+        hits.into_iter().map(|off| {
+            for p in line_breaks(&self.text[at..off], at) {
+                (line, start) = (line + 1, p + 1);
+            }
+            at = off;
+            (line, off - start + 1, off)
+        }).collect()
+    }
+
+    /// Source‐byte offset of normalized offset `off`; the identity unless
+    /// the index was built with `CsiConfig::normalize_newlines`. An offset
+    /// at a collapsed line break maps to its `\n`, not the `\r` before it.
+    pub fn original_offset(&self, off: usize) -> usize {
+        off + self.crlf.partition_point(|&p| p <= off)
+    }
+
+    /// Inverse of `original_offset`: the normalized position of source
+    /// offset `orig` (a dropped `\r` maps to its `\n`).
+    pub(crate) fn normalized_offset(&self, orig: usize) -> usize {
+        // the i‐th dropped `\r` sat at source offset crlf[i] + i
+        let dropped = self.crlf.iter().enumerate()
+            .take_while(|&(i, &p)| p + i < orig)
+            .count();
+        orig - dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CsiConfig, CsiIndex, CsiIndexBuilder};
+
+    #[test]
+    fn crlf_text_matches_lf_query_at_source_offset() {
+        let src = b"header line\r\nthe quick fox\r\njumps over it\r\nmiddle\r\nthe quick fox\njumps over it\r\nend".to_vec();
+        let cfg = CsiConfig { normalize_newlines: true, ..CsiConfig::default() };
+        let idx = CsiIndex::build_with_config(&src, &cfg);
+        let hits = idx.search(b"the quick fox\njumps over it");
+        let orig: Vec<usize> = hits.iter().map(|&o| idx.original_offset(o)).collect();
+        assert_eq!(orig, [13, 51]);
+        assert_eq!(idx.search(b"the quick fox\r\njumps over it"), hits);
+        assert_eq!(&src[orig[0]..orig[0] + 15], b"the quick fox\r\n");
+        for (o, &orig) in hits.iter().zip(&orig) {
+            assert_eq!(idx.normalized_offset(orig), *o);
+        }
+        let plain = CsiIndex::build(&src);
+        assert_eq!(plain.search(b"the quick fox\njumps over it").iter().map(|&o| plain.original_offset(o)).collect::<Vec<_>>(), [51]);
+    }
+
+    #[test]
+    fn positions_count_lines_with_or_without_a_line_index() {
+        let src: Vec<u8> = (0..300).flat_map(|i| format!("line {i}: status={}\r\n{}", i % 7, "\n".repeat(i % 3)).into_bytes()).collect();
+        let untracked = CsiIndexBuilder::new().normalize_newlines(true).build(&src);
+        let tracked = CsiIndexBuilder::new().normalize_newlines(true).track_lines(true).build(&src);
+        let text = tracked.text();
+        assert_eq!(tracked.resolve_position(0), (1, 1));
+        assert_eq!(tracked.resolve_position(text.iter().position(|&b| b == b'\n').unwrap() + 1), (2, 1));
+        for off in (0..=text.len()).step_by(7).chain([text.len() - 1, text.len()]) {
+            let (line, col) = tracked.resolve_position(off);
+            assert_eq!(untracked.resolve_position(off), (line, col));
+            let start = text[..off].iter().rposition(|&b| b == b'\n').map_or(0, |p| p + 1);
+            assert_eq!((line, col), (1 + text[..off].iter().filter(|&&b| b == b'\n').count(), off - start + 1));
+        }
+        let pat = b": status=3\n";
+        let hits = tracked.search_lines(pat);
+        assert_eq!(hits.len(), tracked.count(pat));
+        assert_eq!(untracked.search_lines(pat), hits);
+        assert!(hits.iter().all(|&(line, col, off)| tracked.resolve_position(off) == (line, col)));
+
+        // the line index follows the text through appends, truncation and sidecars
+        let builder = CsiIndexBuilder::new().normalize_newlines(true).track_lines(true);
+        let mut grown = builder.build(&src[..1001]);
+        grown.append(&src[1001..]);
+        assert_eq!(grown.search_lines(pat), hits);
+        grown.truncate(text.len() / 2);
+        let cut = builder.build(&text[..text.len() / 2]);
+        assert_eq!(grown.search_lines(pat), cut.search_lines(pat));
+        let mut bytes = Vec::new();
+        tracked.write_sidecar(&mut bytes).unwrap();
+        let sidecar = CsiIndex::read_sidecar(&bytes[..], crate::PolyHasher).unwrap();
+        assert!(sidecar.text().is_empty());
+        for &(line, col, off) in &hits {
+            assert_eq!(sidecar.resolve_position(off), (line, col));
+        }
+    }
+}
@@ -0,0 +1,292 @@
+// src/packed.rs
+//! Delta‐encoded posting lists for buckets above `CsiConfig::pack_postings`.
+//!
+//! A packed list stores each posting as the LEB128 varint of its distance
+//! from the previous one (the first from 0), so the long runs a repetitive
+//! text puts in a few buckets cost a byte or two per posting instead of
+//! four (eight past 4 GiB). Every `BLOCK`th posting is also kept as a skip entry, its value
+//! and the byte offset of the varint after it, which lets `retain` jump
+//! over blocks no candidate falls in and decode only the rest.
+
+use alloc::vec::Vec;
+
+use crate::array::Array;
+use crate::{FlatIndex, SATURATED};
+
+/// Postings per skip entry
+const BLOCK: usize = 64;
+
+/// A sorted posting list, delta‐encoded
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Packed {
+    pub(crate) len:   usize,
+    pub(crate) bytes: Array<u8>,
+    // `(value, end)` pairs, flattened, for postings 0, BLOCK, 2 * BLOCK..:
+    // the posting and the byte offset just past its varint
+    pub(crate) skips: Array<usize>,
+}
+
+impl Packed {
+    /// `list`, which must be strictly ascending, packed.
+    pub(crate) fn new(list: &[usize]) -> Self {
+        let mut packer = Packer::with_capacity(list.len());
+        for &p in list {
+            packer.push(p);
+        }
+        packer.finish()
+    }
+
+    /// The postings in ascending order. Stops early on malformed bytes,
+    /// which `validate` catches by comparing the count with `len`.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut cursor = Cursor { at: 0, value: 0 };
+        (0..self.len).map_while(move |_| cursor.step(&self.bytes))
+    }
+
+    /// Keep the entries of sorted `acc` that are postings of this list,
+    /// decoding only the blocks some entry lands in and galloping over the
+    /// skip entries between them.
+    pub(crate) fn retain(&self, acc: &mut Vec<usize>) {
+        let heads = self.skips.len() / 2;
+        let head = |b: usize| self.skips[2 * b];
+        // `next` is the block after the cursor's; `cursor.value` the last
+        // posting decoded, `left` how many of the block remain
+        let (mut next, mut cursor, mut left) = (0, Cursor { at: 0, value: 0 }, 0);
+        let mut done = heads == 0;
+        acc.retain(|&x| {
+            if done {
+                return false;
+            }
+            if x < cursor.value {
+                return false;
+            }
+            // jump to the last block starting at or before `x`, if past ours
+            let past = self.past(next, x);
+            match (past > next).then(|| past - 1) {
+                Some(b) => {
+                    cursor = Cursor { at: self.skips[2 * b + 1], value: head(b) };
+                    left = (self.len - b * BLOCK).min(BLOCK) - 1;
+                    next = b + 1;
+                }
+                // below the first posting
+                None if next == 0 => return false,
+                None => {}
+            }
+            while cursor.value < x {
+                if left == 0 {
+                    // past this block and short of the next one's head
+                    done = next == heads;
+                    return false;
+                }
+                match cursor.step(&self.bytes) {
+                    Some(_) => left -= 1,
+                    None => {
+                        done = true;
+                        return false;
+                    }
+                }
+            }
+            cursor.value == x
+        });
+    }
+
+    /// First block at or after `from` whose head exceeds `x` (or the block
+    /// count): doubling steps, then a binary search inside the last, as
+    /// `gallop` does over a plain list.
+    fn past(&self, from: usize, x: usize) -> usize {
+        let heads = self.skips.len() / 2;
+        let head = |b: usize| self.skips[2 * b];
+        let (mut lo, mut hi, mut step) = (from, from, 1);
+        while hi < heads && head(hi) <= x {
+            lo = hi + 1;
+            hi += step;
+            step *= 2;
+        }
+        hi = hi.min(heads);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if head(mid) <= x { lo = mid + 1 } else { hi = mid }
+        }
+        lo
+    }
+
+    /// Bytes allocated on the heap; 0 while mapped.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.bytes.capacity() + self.skips.capacity() * 8
+    }
+}
+
+impl FlatIndex {
+    /// Move every bucket holding more than `threshold` postings (and not
+    /// saturated) out of `offs` into `packed`, closing up the space left.
+    /// Buckets must be laid out in slot order, then overflow order, as
+    /// builds and appends lay them out.
+    pub(crate) fn pack(&mut self, threshold: usize) {
+        if threshold == SATURATED {
+            return;
+        }
+        let slots = self.keys.len();
+        let (mut removed, fps) = (0, !self.fps.is_empty());
+        for b in 0..slots + self.overflow.len() {
+            let (key, start, len) = match b.checked_sub(slots) {
+                None => (self.keys[b], self.starts.get(b), self.lens.get(b)),
+                Some(e) => self.overflow[e],
+            };
+            if key == 0 || len == SATURATED || len <= threshold && removed == 0 {
+                continue;
+            }
+            let (start, len) = if len > threshold {
+                self.packed.push((key, Packed::new(&self.offs.list(start..start + len).to_vec())));
+                removed += len;
+                (0, SATURATED)
+            } else {
+                self.offs.copy_within(start..start + len, start - removed);
+                if fps {
+                    self.fps.to_mut().copy_within(start..start + len, start - removed);
+                }
+                (start - removed, len)
+            };
+            match b.checked_sub(slots) {
+                None => {
+                    self.starts.set(b, start);
+                    self.lens.set(b, len);
+                }
+                Some(e) => self.overflow[e] = (key, start, len),
+            }
+        }
+        if removed == 0 {
+            return;
+        }
+        let kept = self.offs.len() - removed;
+        self.offs.truncate(kept);
+        if fps {
+            self.fps.to_mut().truncate(kept);
+            self.fps.to_mut().shrink_to_fit();
+        }
+        self.packed.sort_unstable_by_key(|e| e.0);
+    }
+}
+
+/// A `Packed` list encoded a posting at a time, for lists that are never
+/// held plain
+pub(crate) struct Packer {
+    len:   usize,
+    prev:  usize,
+    bytes: Vec<u8>,
+    skips: Vec<usize>,
+}
+
+impl Packer {
+    /// An empty list, with room for `len` postings of a byte or two.
+    pub(crate) fn with_capacity(len: usize) -> Self {
+        Packer { len: 0, prev: 0, bytes: Vec::with_capacity(len * 2), skips: Vec::with_capacity(2 * len.div_ceil(BLOCK)) }
+    }
+
+    /// Append `p`, which must exceed every posting pushed before.
+    pub(crate) fn push(&mut self, p: usize) {
+        let mut delta = p - self.prev;
+        while delta >= 0x80 {
+            self.bytes.push(delta as u8 | 0x80);
+            delta >>= 7;
+        }
+        self.bytes.push(delta as u8);
+        if self.len.is_multiple_of(BLOCK) {
+            self.skips.extend([p, self.bytes.len()]);
+        }
+        (self.len, self.prev) = (self.len + 1, p);
+    }
+
+    pub(crate) fn finish(self) -> Packed {
+        Packed { len: self.len, bytes: self.bytes.into(), skips: self.skips.into() }
+    }
+}
+
+/// A decoding position: the byte offset of the next varint and the
+/// posting decoded last
+struct Cursor {
+    at:    usize,
+    value: usize,
+}
+
+impl Cursor {
+    /// Decode the next posting, or `None` if the bytes run out mid‐varint
+    /// or it overflows.
+    #[inline(always)]
+    fn step(&mut self, bytes: &[u8]) -> Option<usize> {
+        let mut delta = 0usize;
+        for shift in (0..usize::BITS).step_by(7) {
+            let b = *bytes.get(self.at)?;
+            self.at += 1;
+            delta |= ((b & 0x7f) as usize).checked_shl(shift)?;
+            if b < 0x80 {
+                self.value = self.value.checked_add(delta)?;
+                return Some(self.value);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Packed;
+    use crate::{CsiIndex, CsiIndexBuilder, EntropyTiers, PolyHasher};
+
+    #[test]
+    fn packed_lists_decode_and_filter_like_the_plain_ones() {
+        let list: Vec<usize> = (0..1000usize).map(|i| i * 13 + i * i % 13 + (i / 100) * 50_000).collect();
+        let packed = Packed::new(&list);
+        assert_eq!(packed.iter().collect::<Vec<_>>(), list);
+        assert!(packed.bytes.len() < 2 * list.len());
+
+        let probes: Vec<Vec<usize>> = vec![
+            Vec::new(),
+            (0..500_000).step_by(3).collect(),
+            list.iter().step_by(97).copied().collect(),
+            vec![list[63], list[64], list[65], list[999], list[999] + 1],
+            // far apart, so the skips are galloped over
+            vec![0, list[1], list[640], list[641] - 1, list[900]],
+        ];
+        for mut acc in probes {
+            let want: Vec<usize> = acc.iter().copied().filter(|x| list.binary_search(x).is_ok()).collect();
+            packed.retain(&mut acc);
+            assert_eq!(acc, want);
+        }
+        assert_eq!(Packed::new(&[]).iter().count(), 0);
+    }
+
+    #[test]
+    fn packed_buckets_search_like_plain_ones_in_less_memory() {
+        // a few records repeated many times, so most postings sit in huge buckets
+        let text: Vec<u8> = (0..4000).flat_map(|i| format!("GET /a/{} 200\n", i % 5 + (i == 1234) as usize * 10).into_bytes()).collect();
+        let plain = CsiIndexBuilder::new().tiers(EntropyTiers::fixed(4, vec![4, 8])).stride(2).build(&text);
+        let builder = CsiIndexBuilder::new().tiers(EntropyTiers::fixed(4, vec![4, 8])).stride(2).pack_postings(100);
+        let packed = builder.build(&text);
+        assert!(packed.flat.iter().all(|fi| !fi.packed.is_empty()));
+        // against 32‐bit plain postings
+        assert!(packed.mem_usage().postings * 3 < plain.mem_usage().postings * 2);
+        assert_eq!(packed.validate(), Ok(()));
+        assert_eq!(packed.index_summary(), plain.index_summary());
+        let pats: [&[u8]; 5] = [b"GET /a/3 200\nGET", b"/a/14 200", b"200\nGET /a/2", b"GET /a/4 201", b"a/1 200\nGET /a/2 200"];
+        let check = |idx: &CsiIndex| for pat in pats {
+            let want = plain.search(pat);
+            assert_eq!(idx.search(pat), want, "{:?}", String::from_utf8_lossy(pat));
+            assert_eq!(idx.search_iter(pat).collect::<Vec<_>>(), want);
+            assert_eq!((idx.count(pat), idx.contains(pat)), (want.len(), !want.is_empty()));
+            assert_eq!(idx.search_in(pat, 1000..30_000), plain.search_in(pat, 1000..30_000));
+        };
+        check(&packed);
+
+        let mut bytes = Vec::new();
+        packed.write_index(&mut bytes).unwrap();
+        check(&CsiIndex::read_index(&bytes[..], PolyHasher).unwrap());
+
+        let mut grown = builder.build(&text[..text.len() / 3]);
+        grown.append(&text[text.len() / 3..]);
+        assert_eq!(grown.validate(), Ok(()));
+        check(&grown);
+        grown.truncate(20_000);
+        assert_eq!(grown.validate(), Ok(()));
+        assert_eq!(grown.search(pats[0]), plain.search(pats[0]).into_iter().filter(|&o| o + pats[0].len() <= 20_000).collect::<Vec<_>>());
+    }
+}
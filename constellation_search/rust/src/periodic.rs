@@ -0,0 +1,104 @@
+// src/periodic.rs
+//! Fast path for self‐overlapping (periodic) patterns like "aaaa" or "abab".
+//!
+//! Such patterns produce dense runs of candidates spaced one period apart.
+//! Once an occurrence at `off` is verified, the one at `off + p` already
+//! agrees on its first `m - p` bytes, so only the trailing `p` bytes need
+//! comparing. A run of r occurrences then costs O(m + r·p) instead of
+//! O(r·m); non‐periodic patterns never take this path.
+
+use alloc::vec::Vec;
+
+use crate::{CsiIndex, Hasher};
+
+/// Smallest period of `pat` from the KMP failure function (`pat.len()` if
+/// the pattern doesn't overlap itself).
+pub(crate) fn pattern_period(pat: &[u8]) -> usize {
+    pattern_period_in(pat, &mut Vec::new())
+}
+
+/// `pattern_period`, building the failure function in `fail`.
+pub(crate) fn pattern_period_in(pat: &[u8], fail: &mut Vec<usize>) -> usize {
+    let m = pat.len();
+    if m == 0 {
+        return 0;
+    }
+    fail.clear();
+    fail.resize(m, 0);
+    let mut j = 0;
+    for i in 1..m {
+        while j > 0 && pat[i] != pat[j] {
+            j = fail[j - 1];
+        }
+        if pat[i] == pat[j] {
+            j += 1;
+        }
+        fail[i] = j;
+    }
+    m - fail[m - 1]
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Exact search for a pattern with period `p` (`2p <= pat.len()`),
+    /// verifying run continuations on their last `p` bytes only.
+    pub(crate) fn search_periodic(&self, pat: &[u8], p: usize) -> Vec<usize> {
+        let m = pat.len();
+        let n = self.text.len();
+        let tail = &pat[m - p..];
+        let mut out = Vec::new();
+        let mut last: Option<usize> = None;
+        let mut check = |off: usize| {
+            if off + m > n {
+                return;
+            }
+            let hit = match last {
+                Some(prev) if prev + p == off => self.text[off + m - p..off + m] == *tail,
+                _ => self.text[off..off + m] == *pat,
+            };
+            if hit {
+                if self.live(off, m) {
+                    out.push(off);
+                }
+                last = Some(off);
+            }
+        };
+        match self.candidates(pat, usize::MAX) {
+            Some(acc) => acc.into_iter().for_each(&mut check),
+            None => (0..=n.saturating_sub(m)).for_each(&mut check),
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pattern_period;
+    use crate::CsiIndex;
+
+    fn naive(text: &[u8], pat: &[u8]) -> Vec<usize> {
+        (0..=text.len().saturating_sub(pat.len()))
+            .filter(|&i| text[i..].starts_with(pat))
+            .collect()
+    }
+
+    #[test]
+    fn period_of_common_shapes() {
+        assert_eq!(pattern_period(b"aaaa"), 1);
+        assert_eq!(pattern_period(b"abababab"), 2);
+        assert_eq!(pattern_period(b"abcabcab"), 3);
+        assert_eq!(pattern_period(b"abcd"), 4);
+    }
+
+    #[test]
+    fn periodic_patterns_match_naive_scan() {
+        let mut text = b"a".repeat(300);
+        text.extend_from_slice(b"b-abababababababababababababababab-abab-");
+        text.extend(b"xyz".repeat(40));
+        text.extend(b"a".repeat(50));
+        let idx = CsiIndex::build(&text);
+        let pats: [&[u8]; 4] = [&[b'a'; 40], &b"ab".repeat(14), &b"xyz".repeat(9), &text[290..330]];
+        for pat in pats {
+            assert_eq!(idx.search(pat), naive(&text, pat));
+        }
+    }
+}
@@ -0,0 +1,114 @@
+// src/presence.rs
+//! Existence‐only index: one bitset per gap, no offsets and no text.
+//!
+//! Each constellation key sets one bit in its gap's bitset; a query "might
+//! be present" if every usable gap's bit is set. This is a Bloom filter with
+//! one hash per gap, so false positives are possible and false negatives are
+//! not. With `b` bits per indexed position a gap's bit is spuriously set with
+//! probability about `1 - e^(-1/b)` (≈6% at the default 16), and a pattern
+//! spanning `g` gaps needs all `g` to collide. `false_positive_rate` reports
+//! the actual fill‐based figure for the built index.
+
+use alloc::vec::Vec;
+
+use crate::hash::window_const;
+use crate::{compute_entropy, key_at, prefix_hash, CaseFold, CsiConfig, PolyHasher};
+
+/// Bit‐packed constellation membership sets for "does X occur anywhere"
+pub struct PresenceIndex {
+    k:    usize,
+    gaps: Vec<usize>,
+    pk:   u64,
+    fold: CaseFold,
+    bits: Vec<Vec<u64>>, // one word array per gap, length a power of two
+}
+
+impl PresenceIndex {
+    /// Build with 16 bits per indexed position.
+    pub fn build(data: &[u8]) -> Self {
+        Self::build_with(data, &CsiConfig::default(), 16)
+    }
+
+    /// Build using `config`'s entropy tiers and gap limit, sizing each
+    /// bitset to about `bits_per_key` bits per indexed position.
+    pub fn build_with(data: &[u8], config: &CsiConfig, bits_per_key: usize) -> Self {
+        let folded = config.case_fold.apply(data);
+        let data = &folded[..];
+        let (k, mut gaps) = config.tiers.choose(compute_entropy(data));
+        gaps.sort_unstable();
+        gaps.dedup();
+        if let Some(keep) = config.max_gaps {
+            gaps.truncate(keep.max(1));
+        }
+        let ph = prefix_hash(&PolyHasher, data);
+        let pk = window_const(&PolyHasher, k);
+        let n = data.len();
+        let bits = gaps.iter().map(|&d| {
+            let m = if n >= k + d { n - (k + d) + 1 } else { 0 };
+            let nbits = (m * bits_per_key.max(1)).next_power_of_two().max(64);
+            let mut words = vec![0u64; nbits / 64];
+            for i in 0..m {
+                let b = key_at(&PolyHasher, &ph, pk, i, k, d) as usize & (nbits - 1);
+                words[b / 64] |= 1 << (b % 64);
+            }
+            words
+        }).collect();
+        PresenceIndex { k, gaps, pk, fold: config.case_fold, bits }
+    }
+
+    /// False if `pat` certainly doesn't occur; true if it might.
+    ///
+    /// Patterns shorter than `k + gaps[0] + k` carry no constellation to
+    /// check, so they always report true.
+    pub fn might_contain(&self, pat: &[u8]) -> bool {
+        let pat = self.fold.apply(pat);
+        let m = pat.len();
+        if m < self.k + self.gaps[0] + self.k {
+            return true;
+        }
+        let php = prefix_hash(&PolyHasher, &pat);
+        self.gaps.iter().zip(&self.bits)
+            .filter(|&(&d, _)| d + self.k <= m)
+            .all(|(&d, words)| {
+                let b = key_at(&PolyHasher, &php, self.pk, 0, self.k, d) as usize & (words.len() * 64 - 1);
+                words[b / 64] & (1 << (b % 64)) != 0
+            })
+    }
+
+    /// Chance that a pattern using every gap passes `might_contain` without
+    /// occurring: the product of each bitset's fill ratio.
+    pub fn false_positive_rate(&self) -> f64 {
+        self.bits.iter().map(|w| {
+            let set: u32 = w.iter().map(|x| x.count_ones()).sum();
+            set as f64 / (w.len() * 64) as f64
+        }).product()
+    }
+
+    /// Heap bytes held by the bitsets.
+    pub fn heap_bytes(&self) -> usize {
+        self.bits.iter().map(|w| w.capacity() * 8).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PresenceIndex;
+    use crate::CsiIndex;
+
+    #[test]
+    fn presence_has_no_false_negatives() {
+        let text: Vec<u8> = (0u32..5000).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let p = PresenceIndex::build(&text);
+        for start in (0..4900).step_by(97) {
+            assert!(p.might_contain(&text[start..start + 60]));
+        }
+        let absent = (0..200).filter(|&s| {
+            let pat: Vec<u8> = (0u8..60).map(|b| b.wrapping_mul(s as u8 | 1).wrapping_add(s as u8)).collect();
+            !p.might_contain(&pat)
+        }).count();
+        assert!(absent > 190);
+        assert!(p.false_positive_rate() < 0.01);
+        let full = CsiIndex::build(&text);
+        assert!(p.heap_bytes() < full.index_summary().total_postings * 8);
+    }
+}
@@ -0,0 +1,123 @@
+// src/python.rs
+//! Python bindings over PyO3 (feature `python`): a `ConstellationIndex`
+//! class in an extension module named `csi`, so Python code gets lists of
+//! offsets back without sizing `csi_search` buffers through ctypes.
+//!
+//! Builds and searches release the GIL. Saved, serialized and pickled
+//! indexes all use the `write_index` format, so a pickle restores without
+//! rebuilding. The tests embed an interpreter, so they link libpython and
+//! run without the `extension-module` feature.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::PathBuf;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyBytesMethods};
+
+use crate::{CsiIndex, PolyHasher};
+
+/// An index over a copy of a `bytes` text
+#[pyclass(name = "ConstellationIndex", module = "csi")]
+pub struct ConstellationIndex {
+    inner: CsiIndex,
+}
+
+#[pymethods]
+impl ConstellationIndex {
+    /// `ConstellationIndex(data)` is `build(data)`; with no argument, an
+    /// index over no text, which is what unpickling starts from.
+    #[new]
+    #[pyo3(signature = (data = None))]
+    fn new(py: Python<'_>, data: Option<&Bound<'_, PyBytes>>) -> Self {
+        Self::build(py, data.map_or(&[], |b| b.as_bytes()))
+    }
+
+    /// Index `data`, as `CsiIndex::build`.
+    #[staticmethod]
+    fn build(py: Python<'_>, data: &[u8]) -> Self {
+        ConstellationIndex { inner: py.allow_threads(|| CsiIndex::build(data)) }
+    }
+
+    /// Sorted offsets of every occurrence of `pat`.
+    fn search(&self, py: Python<'_>, pat: &[u8]) -> Vec<usize> {
+        py.allow_threads(|| self.inner.search(pat))
+    }
+
+    /// Number of occurrences of `pat`.
+    fn count(&self, py: Python<'_>, pat: &[u8]) -> usize {
+        py.allow_threads(|| self.inner.count(pat))
+    }
+
+    /// The index serialized as `CsiIndex::write_index` lays it out.
+    fn to_bytes<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let mut out = Vec::new();
+        self.inner.write_index(&mut out)?;
+        Ok(PyBytes::new(py, &out))
+    }
+
+    /// An index from `to_bytes`; `ValueError` if `data` isn't one.
+    #[staticmethod]
+    fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let inner = CsiIndex::read_index(data, PolyHasher).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(ConstellationIndex { inner })
+    }
+
+    /// Write `to_bytes` to the file at `path`, replacing it; `OSError` on
+    /// failure.
+    fn save(&self, path: PathBuf) -> PyResult<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.inner.write_index(&mut w)?;
+        w.flush()?;
+        Ok(())
+    }
+
+    /// The index `save` wrote to `path`.
+    #[staticmethod]
+    fn load(path: PathBuf) -> PyResult<Self> {
+        Ok(ConstellationIndex { inner: CsiIndex::read_index(BufReader::new(File::open(path)?), PolyHasher)? })
+    }
+
+    fn __getstate__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        self.to_bytes(py)
+    }
+
+    fn __setstate__(&mut self, state: &[u8]) -> PyResult<()> {
+        *self = Self::from_bytes(state)?;
+        Ok(())
+    }
+}
+
+#[pymodule]
+fn csi(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<ConstellationIndex>()
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::prelude::*;
+    use pyo3::types::PyBytesMethods;
+
+    use super::ConstellationIndex;
+
+    #[test]
+    fn unpickled_index_searches_like_the_pickled_one() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let text: Vec<u8> = (0..3000).flat_map(|i| format!("{} GET /Item/{} 200\n", i % 97, i % 11).into_bytes()).collect();
+            let idx = ConstellationIndex::build(py, &text);
+            let state = idx.__getstate__(py).unwrap();
+            // unpickling calls `__setstate__` on an index built from nothing
+            let mut back = ConstellationIndex::new(py, None);
+            assert!(back.search(py, b"GET /Item/3 200").is_empty());
+            back.__setstate__(state.as_bytes()).unwrap();
+            for pat in [&b"GET /Item/3 200"[..], b"200\n5", b"GET", b"not in the log", b""] {
+                assert_eq!(back.search(py, pat), idx.search(py, pat));
+                assert_eq!(back.count(py, pat), idx.count(py, pat));
+            }
+            assert!(!back.search(py, b"GET /Item/3 200").is_empty());
+            assert!(back.__setstate__(b"not an index").is_err());
+        });
+    }
+}
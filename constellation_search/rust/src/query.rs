@@ -0,0 +1,1217 @@
+// src/query.rs
+//! Search variants derived from the sorted, verified result list.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::hash::{key_at, prefix_hash};
+use crate::array::List;
+use crate::{gallop, verify, visit_kway, visit_sorted, CsiIndex, CsiIndexError, Hasher};
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Matches kept greedily left to right, each at least `min_gap` bytes
+    /// after the previously kept one (the first match is always kept).
+    ///
+    /// Unlike non‐overlapping search, the spacing is caller‐defined rather
+    /// than `pat.len()`.
+    pub fn search_spaced(&self, pat: &[u8], min_gap: usize) -> Vec<usize> {
+        let mut kept: Vec<usize> = Vec::new();
+        for off in self.search(pat) {
+            if kept.last().is_none_or(|&prev| off - prev >= min_gap) {
+                kept.push(off);
+            }
+        }
+        kept
+    }
+
+    /// `search` with `origin` added to every offset, for an index covering
+    /// `parent[origin..]` of some larger buffer. Verification still runs
+    /// against the index's own text; only the reported coordinates move.
+    pub fn search_with_origin(&self, pat: &[u8], origin: usize) -> Vec<usize> {
+        let mut hits = self.search(pat);
+        for off in &mut hits {
+            *off += origin;
+        }
+        hits
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Distance from each match to the next, in match order: one fewer
+    /// entry than `search(pat)`, empty below two matches. Overlapping
+    /// matches give intervals shorter than the pattern.
+    pub fn match_intervals(&self, pat: &[u8]) -> Vec<usize> {
+        self.search(pat).windows(2).map(|w| w[1] - w[0]).collect()
+    }
+
+    /// The dominant spacing of `pat`'s matches, if they are near‐periodic:
+    /// the most common interval (the smallest on a tie), provided it
+    /// accounts for more than half of at least two intervals. Occasional
+    /// dropouts or extra matches leave the period intact; spacings with no
+    /// majority give `None`.
+    pub fn detect_period(&self, pat: &[u8]) -> Option<usize> {
+        let mut iv = self.match_intervals(pat);
+        if iv.len() < 2 {
+            return None;
+        }
+        iv.sort_unstable();
+        let (mut best, mut count) = (iv[0], 0);
+        for run in iv.chunk_by(|a, b| a == b) {
+            if run.len() > count {
+                (best, count) = (run[0], run.len());
+            }
+        }
+        (2 * count > iv.len()).then_some(best)
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Smallest match offset strictly greater than `after`, for cursor‐style
+    /// iteration. Pass `usize::MAX` to start from offset 0.
+    ///
+    /// Binary‐searches the sorted candidate list and verifies forward from
+    /// there, so resuming doesn't re‐verify earlier matches.
+    pub fn find_next(&self, pat: &[u8], after: usize) -> Option<usize> {
+        let pat = &*self.normalize(pat);
+        let from = after.wrapping_add(1);
+        let n = self.text.len();
+        if let Some(mask) = &self.mask {
+            return self.search_masked(pat, mask).into_iter().find(|&off| off >= from);
+        }
+        if pat.is_empty() {
+            return (from <= n).then_some(from);
+        }
+        let eq = verify::same;
+        match self.candidates(pat, usize::MAX) {
+            Some(acc) => {
+                let i = acc.partition_point(|&o| o < from);
+                acc[i..].iter().copied().find(|&off| self.verify_by(off, pat, &eq))
+            }
+            None => (from..=n.saturating_sub(pat.len()))
+                .find(|&off| self.verify_by(off, pat, &eq)),
+        }
+    }
+
+    /// Match offset closest to `pos` by absolute distance, the later one on
+    /// a tie; `None` if there are no matches. `pos` may lie anywhere,
+    /// including past the end of the text.
+    pub fn nearest_match(&self, pat: &[u8], pos: usize) -> Option<usize> {
+        let hits = self.search(pat);
+        let i = hits.partition_point(|&o| o < pos);
+        match (i.checked_sub(1).map(|j| hits[j]), hits.get(i).copied()) {
+            (Some(before), Some(after)) => Some(if pos - before < after - pos { before } else { after }),
+            (before, after) => after.or(before),
+        }
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Matches in ascending offset order, found lazily as the iterator is
+    /// advanced: posting lists are intersected and candidates verified on
+    /// demand, so `take(10)` pays for about ten matches, not all of them.
+    /// Masked and periodic patterns are answered in full first, as in
+    /// `search_iter_rev`.
+    pub fn search_iter<'a>(&'a self, pat: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+        let pat = self.normalize(pat);
+        let (cands, verified) = self.candidate_iter(&pat);
+        cands.filter(move |&off| verified || self.verify_by(off, &pat, &verify::same))
+    }
+
+    /// Ascending candidates for normalized `pat`, intersected lazily where
+    /// `streamed` can, and whether they're already verified.
+    pub(crate) fn candidate_iter<'a>(&'a self, pat: &[u8]) -> (impl Iterator<Item = usize> + use<'a, H>, bool) {
+        let stream = self.streamed(pat);
+        let (cands, scan, verified) = if stream.is_some() { (Vec::new(), 0..0, false) } else { self.deferred(pat) };
+        (stream.into_iter().flatten().chain(cands).chain(scan), verified)
+    }
+
+    /// Matches in descending offset order, verified lazily as the iterator
+    /// is advanced, so taking the last few of a huge result set only pays
+    /// for those.
+    ///
+    /// The candidate list is still generated and sorted up front (it's an
+    /// intersection); only verification is deferred. Masked and periodic
+    /// patterns are answered in full first, as in `search_visit`.
+    pub fn search_iter_rev<'a>(&'a self, pat: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+        let pat = self.normalize(pat);
+        let (cands, scan, verified) = self.deferred(&pat);
+        cands.into_iter().rev().chain(scan.rev()).filter(move |&off| {
+            verified || self.verify_by(off, &pat, &verify::same)
+        })
+    }
+
+    /// Candidates for normalized `pat`, intersected as they're taken, where
+    /// `deferred` would intersect posting lists; `None` on its other paths.
+    fn streamed(&self, pat: &[u8]) -> Option<Merged<'_>> {
+        let p = crate::periodic::pattern_period(pat);
+        if self.mask.is_some() || pat.is_empty() || pat.len() >= self.text.len()
+            || self.verify && p > 0 && 2 * p <= pat.len() {
+            return None;
+        }
+        let php = prefix_hash(&self.hasher, pat);
+        let phases = self.phase_lists_hashed(pat.len(), &php)?;
+        // packed lists are only filtered against, which `deferred` does
+        if phases.iter().any(|(_, _, packed)| !packed.is_empty()) {
+            return None;
+        }
+        let heads = phases.into_iter().map(|(phase, mut lists, _)| {
+            lists.sort_unstable_by_key(|l| l.len());
+            let fp = lists.first().and_then(|&l| self.span_check(&php, phase, l));
+            let mut common = Common { pos: vec![0; lists.len().saturating_sub(1)], lists, at: 0, fp };
+            (common.offset(phase), phase, common)
+        }).collect();
+        Some(Merged { heads })
+    }
+
+    /// Work the lazy iterators do up front for normalized `pat`: sorted
+    /// candidates, positions to scan after them, and whether both are
+    /// already verified.
+    fn deferred(&self, pat: &[u8]) -> (Vec<usize>, core::ops::Range<usize>, bool) {
+        let n = self.text.len();
+        let p = crate::periodic::pattern_period(pat);
+        if let Some(mask) = &self.mask {
+            (self.search_masked(pat, mask), 0..0, true)
+        } else if pat.is_empty() {
+            (Vec::new(), 0..n + 1, true)
+        } else if pat.len() >= n {
+            (if *pat == self.text[..] && self.live(0, n) { vec![0] } else { Vec::new() }, 0..0, true)
+        } else if self.verify && p > 0 && 2 * p <= pat.len() {
+            (self.search_periodic(pat, p), 0..0, true)
+        } else {
+            match self.candidates(pat, usize::MAX) {
+                Some(acc) => (acc, 0..0, false),
+                None => (Vec::new(), 0..n - pat.len() + 1, false),
+            }
+        }
+    }
+
+    /// Largest match offset, verifying candidates from the end.
+    pub fn find_last(&self, pat: &[u8]) -> Option<usize> {
+        self.search_iter_rev(pat).next()
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Call `f` with each match offset in ascending order until it returns
+    /// false, without collecting a result vector.
+    ///
+    /// Matches come from `search_iter`, so stopping early skips both the
+    /// intersection and the verify work for the rest. Masked and periodic
+    /// patterns still take their fast paths, which find all matches up
+    /// front.
+    pub fn search_visit<F: FnMut(usize) -> bool>(&self, pat: &[u8], mut f: F) {
+        self.search_iter(pat).take_while(|&off| f(off)).for_each(drop);
+    }
+
+    /// Matches number `offset` to `offset + limit` (exclusive) in ascending
+    /// order: `search(pat)[offset..][..limit]`, clamped, for paging.
+    ///
+    /// Intersection and verification stop at the page's last match, as in
+    /// `search_iter`; the `offset` matches before it are still found. For
+    /// deep pages, resuming with `find_next` from the previous page's last
+    /// offset skips even those.
+    pub fn search_limited(&self, pat: &[u8], offset: usize, limit: usize) -> Vec<usize> {
+        self.search_iter(pat).skip(offset).take(limit).collect()
+    }
+
+    /// `search` under `mode`: every match, or with
+    /// `MatchMode::NonOverlapping` the leftmost ones that don't overlap.
+    pub fn search_mode(&self, pat: &[u8], mode: MatchMode) -> Vec<usize> {
+        if mode == MatchMode::All {
+            return self.search(pat);
+        }
+        let mut hits = Vec::new();
+        self.visit_mode(pat, mode, |off| {
+            hits.push(off);
+            true
+        });
+        hits
+    }
+
+    /// `search_mode(pat, mode).len()`, streamed like `count` for every mode.
+    pub fn count_mode(&self, pat: &[u8], mode: MatchMode) -> usize {
+        if mode == MatchMode::All {
+            return self.count(pat);
+        }
+        let mut n = 0;
+        self.visit_mode(pat, mode, |_| {
+            n += 1;
+            true
+        });
+        n
+    }
+
+    /// `search_visit` over the matches `mode` keeps.
+    pub(crate) fn visit_mode<F: FnMut(usize) -> bool>(&self, pat: &[u8], mode: MatchMode, mut f: F) {
+        // offsets are into the normalized text, so is the span they cover
+        let m = self.normalize(pat).len().max(1);
+        let mut free = 0;
+        self.search_visit(pat, |off| match mode {
+            MatchMode::NonOverlapping if off < free => true,
+            MatchMode::NonOverlapping => {
+                free = off + m;
+                f(off)
+            }
+            MatchMode::All => f(off),
+        });
+    }
+}
+
+/// Which matches `search_mode` and `count_mode` report
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MatchMode {
+    /// Every occurrence, overlapping ones included, as `search` reports them
+    #[default]
+    All,
+    /// Leftmost non‐overlapping occurrences: scanning left to right, each
+    /// match kept starts at or past the end of the one before, so "aa" is
+    /// found twice in "aaaaa", as a find‐and‐replace pass would see it
+    NonOverlapping,
+}
+
+/// Offsets in every one of `lists`, shortest first, ascending: what
+/// `visit_kway` visits, found one per `next`. Entries whose fingerprint
+/// isn't `fp`'s are skipped.
+struct Common<'a> {
+    lists: Vec<List<'a>>,
+    pos:   Vec<usize>, // cursor in each list after the first
+    at:    usize,      // cursor in the first
+    fp:    Option<(&'a [u16], u16)>,
+}
+
+impl Common<'_> {
+    /// The next common posting as a match offset, `phase` bytes before it.
+    fn offset(&mut self, phase: usize) -> Option<usize> {
+        self.find_map(|p| p.checked_sub(phase))
+    }
+}
+
+impl Iterator for Common<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let Common { lists, pos, at, fp } = self;
+        let (&first, rest) = lists.split_first()?;
+        while *at < first.len() {
+            let v = first.get(*at);
+            let mut target = v;
+            for (&l, p) in rest.iter().zip(pos.iter_mut()) {
+                *p = gallop(l, *p, target);
+                if *p == l.len() {
+                    *at = first.len();
+                    return None;
+                }
+                let x = l.get(*p);
+                if x > target {
+                    target = x;
+                    break;
+                }
+            }
+            if target == v {
+                *at += 1;
+                if fp.is_none_or(|(fps, want)| fps[*at - 1] == want) {
+                    return Some(v);
+                }
+            } else {
+                *at = gallop(first, *at, target);
+            }
+        }
+        None
+    }
+}
+
+/// The phases' `Common` streams merged into ascending match offsets; each
+/// head is `(next offset, phase, stream)`
+struct Merged<'a> {
+    heads: Vec<(Option<usize>, usize, Common<'a>)>,
+}
+
+impl Iterator for Merged<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        // a phase's postings sit at multiples of the stride, so no two
+        // phases yield the same offset
+        let head = self.heads.iter_mut().filter(|h| h.0.is_some()).min_by_key(|h| h.0)?;
+        let off = head.0;
+        head.0 = head.2.offset(head.1);
+        off
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// `search(pat).len()` without building the result: candidates are
+    /// verified and counted as the intersection yields them, so neither
+    /// the candidate nor the match list is allocated (only a few
+    /// per‐call vectors of list references remain). Verification still
+    /// runs, so hash collisions aren't counted.
+    pub fn count(&self, pat: &[u8]) -> usize {
+        let pat = &*self.normalize(pat);
+        let n = self.text.len();
+        if let Some(mask) = &self.mask {
+            return self.search_masked(pat, mask).len();
+        }
+        if pat.is_empty() {
+            return n + 1;
+        }
+        let p = crate::periodic::pattern_period(pat);
+        if self.verify && p > 0 && 2 * p <= pat.len() {
+            return self.search_periodic(pat, p).len();
+        }
+        let eq = verify::same;
+        let php = prefix_hash(&self.hasher, pat);
+        let Some(mut phases) = self.phase_lists_hashed(pat.len(), &php) else {
+            return (0..=n.saturating_sub(pat.len())).filter(|&off| self.verify_by(off, pat, &eq)).count();
+        };
+        if phases.iter().any(|(_, _, packed)| !packed.is_empty()) {
+            return self.search_lists(pat, usize::MAX, &eq).len();
+        }
+        let mut count = 0;
+        for (phase, lists, _) in &mut phases {
+            lists.sort_unstable_by_key(|l| l.len());
+            let fp = self.span_check(&php, *phase, lists[0]);
+            let mut hit = |i: usize, p: usize| {
+                if fp.is_none_or(|(fps, want)| fps[i] == want)
+                    && p.checked_sub(*phase).is_some_and(|off| self.verify_by(off, pat, &eq)) {
+                    count += 1;
+                }
+            };
+            match lists[..] {
+                [one] => one.iter().enumerate().for_each(|(i, p)| hit(i, p)),
+                [a, b] => visit_sorted(a, b, hit),
+                _ => visit_kway(lists, hit),
+            }
+        }
+        count
+    }
+
+    /// Matches lying wholly inside `range` of the text: offsets `o` of
+    /// `search(pat)` with `range.start <= o` and `o + pat.len() <=
+    /// range.end`. Each posting list is cut to the window by binary search
+    /// before the intersection, so the cost follows the postings inside it,
+    /// not in the whole text. Masked and periodic patterns are searched in
+    /// full and then filtered.
+    pub fn search_in(&self, pat: &[u8], range: Range<usize>) -> Vec<usize> {
+        let norm = &*self.normalize(pat);
+        let m = norm.len();
+        let end = range.end.min(self.text.len());
+        if range.start.saturating_add(m) > end {
+            return Vec::new();
+        }
+        let last = end - m;
+        let p = crate::periodic::pattern_period(norm);
+        if self.mask.is_some() || m == 0 || self.verify && p > 0 && 2 * p <= m {
+            let hits = self.search(pat);
+            let (lo, hi) = (hits.partition_point(|&o| o < range.start), hits.partition_point(|&o| o <= last));
+            return hits[lo..hi].to_vec();
+        }
+        let eq = verify::same;
+        let php = prefix_hash(&self.hasher, norm);
+        let Some(mut phases) = self.phase_lists_hashed(m, &php) else {
+            return (range.start..=last).filter(|&off| self.verify_by(off, norm, &eq)).collect();
+        };
+        if phases.iter().any(|(_, _, packed)| !packed.is_empty()) {
+            let hits = self.search_lists(norm, usize::MAX, &eq);
+            let (lo, hi) = (hits.partition_point(|&o| o < range.start), hits.partition_point(|&o| o <= last));
+            return hits[lo..hi].to_vec();
+        }
+        let mut out = Vec::new();
+        for (phase, lists, _) in &mut phases {
+            // a match at `o` is posted at `o + phase`
+            let (lo, hi) = (range.start + *phase, last + *phase);
+            for l in lists.iter_mut() {
+                *l = l.slice(l.partition_point(|q| q < lo)..l.partition_point(|q| q <= hi));
+            }
+            lists.sort_unstable_by_key(|l| l.len());
+            let fp = self.span_check(&php, *phase, lists[0]);
+            let mut hit = |i: usize, q: usize| {
+                if fp.is_none_or(|(fps, want)| fps[i] == want) && self.verify_by(q - *phase, norm, &eq) {
+                    out.push(q - *phase);
+                }
+            };
+            match lists[..] {
+                [one] => one.iter().enumerate().for_each(|(i, q)| hit(i, q)),
+                [a, b] => visit_sorted(a, b, hit),
+                _ => visit_kway(lists, hit),
+            }
+        }
+        if phases.len() > 1 {
+            out.sort_unstable();
+        }
+        out
+    }
+
+    /// `count(pat) > 0`, returning at the first verified match: each phase
+    /// walks its shortest posting list and binary‐searches the others for
+    /// each entry, so no intersection is built. Masked and periodic
+    /// patterns are answered in full first, as in `search_visit`.
+    pub fn contains(&self, pat: &[u8]) -> bool {
+        let norm = &*self.normalize(pat);
+        let p = crate::periodic::pattern_period(norm);
+        if self.mask.is_some() || norm.is_empty() || self.verify && p > 0 && 2 * p <= norm.len() {
+            return self.search_iter(pat).next().is_some();
+        }
+        let pat = norm;
+        let eq = verify::same;
+        let php = prefix_hash(&self.hasher, pat);
+        let Some(mut phases) = self.phase_lists_hashed(pat.len(), &php) else {
+            return (0..=self.text.len().saturating_sub(pat.len())).any(|off| self.verify_by(off, pat, &eq));
+        };
+        if phases.iter().any(|(_, _, packed)| !packed.is_empty()) {
+            return !self.search_lists(pat, usize::MAX, &eq).is_empty();
+        }
+        phases.iter_mut().any(|(phase, lists, _)| {
+            lists.sort_unstable_by_key(|l| l.len());
+            let fp = self.span_check(&php, *phase, lists[0]);
+            lists[0].iter().enumerate().any(|(i, pos)| {
+                fp.is_none_or(|(fps, want)| fps[i] == want)
+                    && lists[1..].iter().all(|l| l.binary_search(pos).is_ok())
+                    && pos.checked_sub(*phase).is_some_and(|off| self.verify_by(off, pat, &eq))
+            })
+        })
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Like `search`, but verifies candidates against `text`, after checking
+    /// that it hashes to the text the index was built over; a different
+    /// length or hash yields `CsiIndexError::TextMismatch` instead of
+    /// matches taken from stale tables.
+    ///
+    /// `text` must be in indexed form (what `text()` returns: folded,
+    /// newline‐normalized, masked). The check rehashes all of it, O(n).
+    pub fn search_with_text(&self, pat: &[u8], text: &[u8]) -> Result<Vec<usize>, CsiIndexError> {
+        let digest = text.iter().fold(0, |h, &b| self.hasher.extend(h, b));
+        if text.len() != self.text_len || digest != self.digest {
+            return Err(CsiIndexError::TextMismatch);
+        }
+        if self.mask.is_some() {
+            return Ok(self.search(pat));
+        }
+        Ok(self.search_over(pat, text))
+    }
+
+    /// `search` verifying against `text` rather than the stored text, which
+    /// the caller vouches is the indexed text; no mask handling.
+    pub(crate) fn search_over(&self, pat: &[u8], text: &[u8]) -> Vec<usize> {
+        let pat = &*self.normalize(pat);
+        let n = text.len();
+        if pat.is_empty() {
+            return (0..=n).collect();
+        }
+        let fits = |&off: &usize| text.get(off..off + pat.len()) == Some(pat) && self.live(off, pat.len());
+        match self.candidates(pat, usize::MAX) {
+            Some(acc) => acc.into_iter().filter(fits).collect(),
+            None => (0..=n.saturating_sub(pat.len())).filter(fits).collect(),
+        }
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Call `f` with each offset, ascending, where `pat` occurs in
+    /// `big_text`: a text the index was not built over, such as the full
+    /// stream an index over a sample was tuned on.
+    ///
+    /// The stored postings are not used, only `k`, the gaps and the hasher.
+    /// One pass rolls the constellation key of the widest gap that fits in
+    /// `pat` over `big_text`, keeping `O(k + gap)` state, and compares `pat`
+    /// at each position whose key matches. `pat` is folded and
+    /// newline‐normalized as in `search`, but `big_text` is taken as is and
+    /// no mask is applied. A pattern too short for any gap is compared at
+    /// every position.
+    pub fn scan_external<F: FnMut(usize)>(&self, big_text: &[u8], pat: &[u8], mut f: F) {
+        let pat = &*self.normalize(pat);
+        let (k, m, n) = (self.k, pat.len(), big_text.len());
+        if m > n {
+            return;
+        }
+        let fits = |&off: &usize| verify::same(&big_text[off..off + m], pat);
+        let Some(&d) = self.gaps.iter().rev().find(|&&d| d + k <= m) else {
+            (0..=n - m).filter(fits).for_each(f);
+            return;
+        };
+        let (pk, span) = (self.pw[k], d + k);
+        let want = key_at(&self.hasher, &prefix_hash(&self.hasher, &pat[..span]), pk, 0, k, d);
+        // prefix states of the last `span + 1` positions; `ring[i % len]` is
+        // the state after `big_text[..i]`
+        let mut ring = vec![0u64; span + 1];
+        let at = |ring: &[u64], i: usize| ring[i % ring.len()];
+        let mut state = 0;
+        for (i, &b) in big_text.iter().enumerate() {
+            state = self.hasher.extend(state, b);
+            ring[(i + 1) % (span + 1)] = state;
+            let Some(off) = (i + 1).checked_sub(span) else { continue };
+            let h1 = self.hasher.window(at(&ring, off + k), at(&ring, off), pk);
+            let h2 = self.hasher.window(at(&ring, off + span), at(&ring, off + d), pk);
+            if self.hasher.combine(h1, h2, d as u64) == want && off + m <= n && fits(&off) {
+                f(off);
+            }
+        }
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Offsets of `a`, ascending, with a match of `b` starting at most
+    /// `window` bytes after them (`off_a <= off_b <= off_a + window`), so
+    /// `b` may overlap `a`. Only `a`'s offsets are returned, and a `b`
+    /// before `a` doesn't count.
+    ///
+    /// Both patterns are searched once and the sorted lists merged with a
+    /// pointer into `b`'s matches that only moves forward.
+    pub fn search_cooccur(&self, a: &[u8], b: &[u8], window: usize) -> Vec<usize> {
+        let bs = self.search(b);
+        let mut j = 0;
+        self.search(a).into_iter().filter(|&off| {
+            j += bs[j..].partition_point(|&ob| ob < off);
+            bs.get(j).is_some_and(|&ob| ob - off <= window)
+        }).collect()
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Each match offset with a copy of the text around it, from `before`
+    /// bytes ahead of the match to `after` bytes past its end, clamped to
+    /// the text, as in a grep with context.
+    ///
+    /// Windows are cut from the indexed text (see `text()`) and measured
+    /// with the normalized pattern's length. An index without stored text
+    /// (from `read_sidecar`) has no matches here; slice the external text
+    /// around the offsets of `search_with_text` instead.
+    pub fn search_context(&self, pat: &[u8], before: usize, after: usize) -> Vec<(usize, Vec<u8>)> {
+        let len = self.normalize(pat).len();
+        let n = self.text.len();
+        self.search(pat).into_iter().map(|off| {
+            let lo = off.saturating_sub(before);
+            let hi = (off + len).saturating_add(after).min(n);
+            (off, self.text[lo..hi].to_vec())
+        }).collect()
+    }
+
+    /// `search_context` without the copies: each `Match` borrows its
+    /// window from the index's text, so a caller can display results
+    /// without keeping the corpus.
+    pub fn search_with_context(&self, pat: &[u8], before: usize, after: usize) -> Vec<Match<'_>> {
+        let len = self.normalize(pat).len();
+        let n = self.text.len();
+        self.search(pat).into_iter().map(|offset| {
+            let start = offset.saturating_sub(before);
+            let end = (offset + len).saturating_add(after).min(n);
+            Match { offset, len, start, context: &self.text[start..end] }
+        }).collect()
+    }
+}
+
+/// A match with the text around it, from `CsiIndex::search_with_context`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match<'a> {
+    /// Offset of the match in the indexed text
+    pub offset:  usize,
+    /// Length of the match (the normalized pattern's)
+    pub len:     usize,
+    /// Offset of `context` in the indexed text
+    pub start:   usize,
+    /// The match and up to `before` and `after` bytes either side of it
+    pub context: &'a [u8],
+}
+
+impl<'a> Match<'a> {
+    /// The matched bytes within `context`.
+    pub fn matched(&self) -> &'a [u8] {
+        let at = self.offset - self.start;
+        &self.context[at..at + self.len]
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Write matches into `out` in ascending order: `Ok(n)` if all `n` fit,
+    /// otherwise `Err(total)` with the first `out.len()` written.
+    ///
+    /// Matches stream straight into `out` (via `search_visit`), so no result
+    /// vector is allocated; retry with `total` slots to get the rest.
+    pub fn search_into_slice(&self, pat: &[u8], out: &mut [usize]) -> Result<usize, usize> {
+        let mut total = 0;
+        self.search_visit(pat, |off| {
+            if let Some(slot) = out.get_mut(total) {
+                *slot = off;
+            }
+            total += 1;
+            true
+        });
+        if total <= out.len() { Ok(total) } else { Err(total) }
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Leftmost offset and length of the longest prefix of `pat` that
+    /// occurs, or `None` if not even its first byte does: where a delta
+    /// encoder would copy from, or how much of a block a near‐duplicate
+    /// shares. A full match reports `(first offset, pat.len())`.
+    ///
+    /// Prefixes are probed from `min_pattern_len` up, doubling past each
+    /// one found, and each hit is extended by comparing the text after it
+    /// with the rest of `pat`; the first absent prefix bounds a binary
+    /// search of the lengths between. That is O(log m) searches, most of
+    /// them cut short by an absent constellation, and a pattern copied
+    /// whole from the text takes one. Shorter prefixes have no
+    /// constellation and are found by scanning the text.
+    pub fn longest_match_at(&self, pat: &[u8]) -> Option<(usize, usize)> {
+        let pat = &*self.normalize(pat);
+        let (n, minlen) = (self.text.len(), self.min_pattern_len());
+        let first = |len: usize| -> Option<usize> {
+            let pre = &pat[..len];
+            if len < minlen {
+                (0..=n.checked_sub(len)?).find(|&off| &self.text[off..off + len] == pre && self.live(off, len))
+            } else {
+                self.search(pre).first().copied()
+            }
+        };
+        // how far the prefix found at `off` with length `len` runs on
+        let extend = |off: usize, len: usize| {
+            let run = self.text.get(off..).unwrap_or_default().iter().zip(pat).take_while(|(a, b)| a == b).count();
+            if run > len && self.live(off, run) { run } else { len }
+        };
+        // prefix `lo` occurs (leftmost at `best` once lo > 0, if `exact`);
+        // none longer than `hi` does
+        let (mut lo, mut hi, mut best, mut exact) = (0, pat.len(), None, true);
+        let mut probe = minlen.min(hi);
+        while lo < hi {
+            let Some(off) = first(probe) else {
+                hi = probe - 1;
+                break;
+            };
+            best = Some(off);
+            lo = extend(off, probe);
+            exact = lo == probe;
+            probe = lo.saturating_mul(2).min(hi);
+        }
+        while lo < hi {
+            let mid = lo + (hi - lo).div_ceil(2);
+            match first(mid) {
+                Some(off) => {
+                    best = Some(off);
+                    lo = extend(off, mid);
+                    exact = lo == mid;
+                }
+                None => hi = mid - 1,
+            }
+        }
+        // an extended hit need not be the leftmost of its length
+        if !exact && lo > 0 {
+            best = first(lo);
+        }
+        best.map(|off| (off, lo))
+    }
+
+    /// `longest_match_at`, under its earlier name.
+    pub fn longest_prefix_match(&self, pat: &[u8]) -> Option<(usize, usize)> {
+        self.longest_match_at(pat)
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Matches as a bitset over the text: bit `off % 64` of word `off / 64`
+    /// is set for every match start `off`. One bit per text byte and one
+    /// for the end, where the empty pattern matches too, so it beats the
+    /// offset list once more than one start in 64 matches.
+    pub fn search_mask(&self, pat: &[u8]) -> Vec<u64> {
+        let mut mask = vec![0u64; (self.text.len() + 1).div_ceil(64)];
+        self.search_visit(pat, |off| {
+            mask[off / 64] |= 1 << (off % 64);
+            true
+        });
+        mask
+    }
+
+    /// Like `search_mask`, but with every byte a match covers set, ready to
+    /// use as a highlight mask; overlapping matches merge. Only text bytes
+    /// can be covered, so there's no bit for the end.
+    pub fn search_span_mask(&self, pat: &[u8]) -> Vec<u64> {
+        let len = self.normalize(pat).len();
+        let mut mask = vec![0u64; self.text.len().div_ceil(64)];
+        self.search_visit(pat, |off| {
+            set_span(&mut mask, off, off + len);
+            true
+        });
+        mask
+    }
+}
+
+/// Set bits `lo..hi` of `mask`, a word at a time.
+pub fn set_span(mask: &mut [u64], lo: usize, hi: usize) {
+    if lo >= hi {
+        return;
+    }
+    let (first, last) = (lo / 64, (hi - 1) / 64);
+    let head = !0u64 << (lo % 64);
+    let tail = !0u64 >> (63 - (hi - 1) % 64);
+    if first == last {
+        mask[first] |= head & tail;
+        return;
+    }
+    mask[first] |= head;
+    mask[first + 1..last].fill(!0);
+    mask[last] |= tail;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CsiIndex, CsiIndexBuilder, CsiIndexError};
+    use super::{set_span, MatchMode};
+
+    #[test]
+    fn find_next_walks_matches_in_order() {
+        let text = b"the cursor target is here; the cursor target is here; not it; the cursor target is here".to_vec();
+        let idx = CsiIndex::build(&text);
+        let pat = b"the cursor target is here";
+        let mut seen = Vec::new();
+        let mut at = usize::MAX;
+        while let Some(off) = idx.find_next(pat, at) {
+            seen.push(off);
+            at = off;
+        }
+        assert_eq!(seen, idx.search(pat));
+        assert_eq!(seen.len(), 3);
+        assert_eq!(idx.find_next(pat, 0), Some(27));
+        assert_eq!(idx.find_next(pat, seen[2]), None);
+    }
+
+    #[test]
+    fn nearest_match_picks_the_closer_neighbour() {
+        let pat = b"jump target for the cursor";
+        let mut text = Vec::new();
+        for filler in [10usize, 30, 30] {
+            text.extend(std::iter::repeat_n(b'-', filler));
+            text.extend_from_slice(pat);
+        }
+        let idx = CsiIndex::build(&text);
+        assert_eq!(idx.search(pat), [10, 66, 122]);
+        assert_eq!(idx.nearest_match(pat, 0), Some(10));
+        assert_eq!(idx.nearest_match(pat, 37), Some(10));
+        assert_eq!(idx.nearest_match(pat, 38), Some(66));
+        assert_eq!(idx.nearest_match(pat, 39), Some(66));
+        assert_eq!(idx.nearest_match(pat, 122), Some(122));
+        assert_eq!(idx.nearest_match(pat, usize::MAX), Some(122));
+        assert_eq!(idx.nearest_match(b"no such target in the text", 50), None);
+    }
+
+    #[test]
+    fn reverse_iteration_mirrors_search() {
+        let text = b"newest results first, newest results first; aaaaaaaaaaaaaaaaaaaaaaaaaaaa; newest results first".to_vec();
+        let idx = CsiIndex::build(&text);
+        let pats: [&[u8]; 5] = [b"newest results first", b"aaaaaaaaaaaaaaaaaaaaaaa", b"", b"nowhere to be found here", &text];
+        for pat in pats {
+            let fwd: Vec<usize> = idx.search(pat).into_iter().rev().collect();
+            assert_eq!(idx.search_iter_rev(pat).collect::<Vec<_>>(), fwd);
+            assert_eq!(idx.find_last(pat), fwd.first().copied());
+        }
+        assert_eq!(idx.search_iter_rev(b"newest results first").take(2).collect::<Vec<_>>(), [74, 22]);
+    }
+
+    #[test]
+    fn search_visit_matches_search_and_stops() {
+        let text = b"visit me right now please; visit me right now please again; visit me right now please".to_vec();
+        let idx = CsiIndex::build(&text);
+        for pat in [&b"visit me right now please"[..], b"visit me right now please again; visit", b"", b"zzzzzzzzzzzzzzzzzzzz"] {
+            let mut seen = Vec::new();
+            idx.search_visit(pat, |off| { seen.push(off); true });
+            assert_eq!(seen, idx.search(pat));
+        }
+        assert_eq!(idx.search(b"visit me right now please").len(), 3);
+        let mut first = Vec::new();
+        idx.search_visit(b"visit me right now please", |off| { first.push(off); false });
+        assert_eq!(first.len(), 1);
+    }
+
+    #[test]
+    fn search_with_text_rejects_stale_text() {
+        let text = b"the text as it was when indexed; the text as it was when indexed".to_vec();
+        let mut idx = CsiIndex::build(&text);
+        let pat = b"the text as it was when";
+        assert_eq!(idx.search_with_text(pat, &text), Ok(idx.search(pat)));
+        let mut edited = text.clone();
+        edited[40] = b'X';
+        assert_eq!(idx.search_with_text(pat, &edited), Err(CsiIndexError::TextMismatch));
+        assert_eq!(idx.search_with_text(pat, &text[..60]), Err(CsiIndexError::TextMismatch));
+        idx.truncate(60);
+        assert_eq!(idx.search_with_text(pat, &text[..60]), Ok(vec![0, 33]));
+    }
+
+    #[test]
+    fn origin_shifts_offsets_only() {
+        let parent = b"....header....region marker number one; region marker number one; tail".to_vec();
+        let origin = 14;
+        let idx = CsiIndex::build(&parent[origin..]);
+        let pat = b"region marker number one";
+        let hits = idx.search_with_origin(pat, origin);
+        assert_eq!(hits, [14, 40]);
+        assert!(hits.iter().all(|&o| &parent[o..o + pat.len()] == pat));
+        assert!(idx.search_with_origin(b"region marker number two", origin).is_empty());
+    }
+
+    #[test]
+    fn search_into_slice_reports_overflow() {
+        let text = b"slot filler text here; slot filler text here; slot filler text here".to_vec();
+        let idx = CsiIndex::build(&text);
+        let pat = b"slot filler text here";
+        let mut exact = [usize::MAX; 3];
+        assert_eq!(idx.search_into_slice(pat, &mut exact), Ok(3));
+        assert_eq!(exact, [0, 23, 46]);
+        let mut roomy = [usize::MAX; 5];
+        assert_eq!(idx.search_into_slice(pat, &mut roomy), Ok(3));
+        assert_eq!(roomy[3..], [usize::MAX; 2]);
+        let mut small = [usize::MAX; 2];
+        assert_eq!(idx.search_into_slice(pat, &mut small), Err(3));
+        assert_eq!(small, [0, 23]);
+        assert_eq!(idx.search_into_slice(pat, &mut []), Err(3));
+        assert_eq!(idx.search_into_slice(b"absent pattern text!!", &mut []), Ok(0));
+    }
+
+    #[test]
+    fn longest_prefix_match_finds_divergence_point() {
+        let text = b"prefix search should stop exactly where the text diverges from the query".to_vec();
+        let idx = CsiIndex::build(&text);
+        assert_eq!(idx.longest_prefix_match(b"search should stop exactly where the text ends"), Some((7, 42)));
+        assert_eq!(idx.longest_prefix_match(b"the query"), Some((63, 9)));
+        assert_eq!(idx.longest_prefix_match(b"stXYZ"), Some((21, 2)));
+        assert_eq!(idx.longest_prefix_match(b"#nothing"), None);
+        assert_eq!(idx.longest_prefix_match(b""), None);
+    }
+
+    #[test]
+    fn longest_match_at_agrees_with_a_scan() {
+        let text: Vec<u8> = (0..3000).flat_map(|i| format!("{} GET /item/{} 200\n", i % 97, i % 11).into_bytes()).collect();
+        let scan = |pat: &[u8]| -> Option<(usize, usize)> {
+            let len = |off: usize| text[off..].iter().zip(pat).take_while(|(a, b)| a == b).count();
+            let best = (0..text.len()).map(len).max().filter(|&l| l > 0)?;
+            Some(((0..text.len()).find(|&off| len(off) == best).unwrap(), best))
+        };
+        let idx = CsiIndex::build(&text);
+        let mut block = text[5000..9000].to_vec();
+        block[3500] = b'#';
+        for pat in [&text[40_000..41_000], &block[..], b"5 GET /item/3 200\n17 PUT", b"GET /item/10 404", b"\n96 GET /item/2 ", b"#"] {
+            assert_eq!(idx.longest_match_at(pat), scan(pat), "{}", pat.len());
+        }
+        // the leftmost of the longest, though a shorter prefix occurs earlier
+        let mut idx = CsiIndex::build(b"abcdefgh_____abcdefghijklmnop____abcdefghijklmnopqrstuvwxyz");
+        assert_eq!(idx.longest_match_at(b"abcdefghijklmnopqrstuvwxyz0123"), Some((33, 26)));
+        assert_eq!(idx.longest_match_at(b"abcdefghijklmnopQ"), Some((13, 16)));
+
+        // deleted bytes aren't copied from
+        idx.invalidate_range(53..54);
+        assert_eq!(idx.longest_match_at(b"abcdefghijklmnopqrstuvwxyz0123"), Some((33, 20)));
+    }
+
+    #[test]
+    fn non_overlapping_mode_keeps_the_leftmost_matches() {
+        let text = b"xxabababab yy abab ab abababab zz aaaaa aaaaa".repeat(30);
+        let idx = CsiIndexBuilder::new().params(2, vec![1, 2]).build(&text);
+        for pat in [&b"abab"[..], b"aa", b"ab ab", b"aaaaa aaaaa", b"never"] {
+            let all = idx.search(pat);
+            assert_eq!(idx.search_mode(pat, MatchMode::All), all);
+            // the greedy scan a replace pass would make
+            let mut want: Vec<usize> = Vec::new();
+            for &off in &all {
+                if want.last().is_none_or(|&prev| off >= prev + pat.len()) {
+                    want.push(off);
+                }
+            }
+            assert_eq!(idx.search_mode(pat, MatchMode::NonOverlapping), want, "{:?}", String::from_utf8_lossy(pat));
+            assert_eq!(idx.count_mode(pat, MatchMode::NonOverlapping), want.len());
+            assert_eq!(idx.count_mode(pat, MatchMode::All), all.len());
+        }
+        let one = &text[..45];
+        assert_eq!(idx.search_mode(b"abab", MatchMode::NonOverlapping)[..2], [2, 6]);
+        assert_eq!(CsiIndex::build(one).count_mode(b"aa", MatchMode::NonOverlapping), 4);
+    }
+
+    #[test]
+    fn search_spaced_regular_and_irregular() {
+        let marker = b"<<periodic-marker-x>>";
+        let mut text = Vec::new();
+        for gap in [0usize, 40, 40, 5, 40, 12, 40] {
+            text.extend(std::iter::repeat_n(b'.', gap));
+            text.extend_from_slice(marker);
+        }
+        let idx = CsiIndex::build(&text);
+        let all = idx.search(marker);
+        assert_eq!(all, vec![0, 61, 122, 148, 209, 242, 303]);
+        assert_eq!(idx.search_spaced(marker, 0), all);
+        assert_eq!(idx.search_spaced(marker, 61), vec![0, 61, 122, 209, 303]);
+        assert_eq!(idx.search_spaced(marker, 100), vec![0, 122, 242]);
+    }
+
+    #[test]
+    fn search_mask_agrees_with_offsets() {
+        let pat = b"highlight this phrase";
+        let mut text = b"some lead-in text before the first one ".to_vec();
+        for filler in ["", " and ", "xx", &".".repeat(70)] {
+            text.extend_from_slice(pat);
+            text.extend_from_slice(filler.as_bytes());
+        }
+        let idx = CsiIndex::build(&text);
+        let hits = idx.search(pat);
+        assert_eq!(hits.len(), 4);
+        let bits = |m: &[u64]| -> Vec<usize> {
+            (0..text.len()).filter(|&i| m[i / 64] >> (i % 64) & 1 == 1).collect()
+        };
+        let mask = idx.search_mask(pat);
+        assert_eq!(mask.len(), (text.len() + 1).div_ceil(64));
+        assert_eq!(bits(&mask), hits);
+        let covered: Vec<usize> = (0..text.len())
+            .filter(|&i| hits.iter().any(|&o| (o..o + pat.len()).contains(&i)))
+            .collect();
+        assert_eq!(bits(&idx.search_span_mask(pat)), covered);
+        assert!(idx.search_mask(b"not anywhere in the text").iter().all(|&w| w == 0));
+
+        // the empty pattern's match at the end of the text has a bit, also
+        // when the text fills its last word or is empty
+        for n in [0, 1, 63, 64, 128, text.len()] {
+            let idx = CsiIndex::build(&text[..n]);
+            let mask = idx.search_mask(b"");
+            let ones: usize = mask.iter().map(|w| w.count_ones() as usize).sum();
+            assert_eq!((mask.len(), ones), ((n + 1).div_ceil(64), n + 1), "{n}");
+            assert_eq!(mask[n / 64] >> (n % 64) & 1, 1);
+            assert!(idx.search_span_mask(b"").iter().all(|&w| w == 0));
+            assert_eq!(idx.search_span_mask(&text[..n]).iter().map(|w| w.count_ones() as usize).sum::<usize>(), n);
+        }
+
+        let mut m = [0u64; 3];
+        set_span(&mut m, 60, 130);
+        set_span(&mut m, 5, 5);
+        assert_eq!(m, [0xf << 60, !0, 0b11]);
+    }
+
+    #[test]
+    fn intervals_and_period_of_a_fixed_marker() {
+        let marker = b"<sync-word-0x7e7e-0x7e7e>";
+        let mut text = Vec::new();
+        for (i, gap) in [0usize, 46, 46, 46, 13, 46, 46].into_iter().enumerate() {
+            text.extend((0..gap).map(|j| b"abcdefghij"[(i + j) % 10]));
+            text.extend_from_slice(marker);
+        }
+        let idx = CsiIndex::build(&text);
+        let step = 46 + marker.len();
+        assert_eq!(idx.match_intervals(marker), [step, step, step, 13 + marker.len(), step, step]);
+        assert_eq!(idx.detect_period(marker), Some(step));
+
+        let mut text = Vec::new();
+        for gap in [0usize, 20, 20, 35, 50, 35] {
+            text.extend(std::iter::repeat_n(b'.', gap));
+            text.extend_from_slice(marker);
+        }
+        let irregular = CsiIndex::build(&text);
+        assert_eq!(irregular.match_intervals(marker).len(), 5);
+        assert_eq!(irregular.detect_period(marker), None);
+        assert!(idx.match_intervals(b"<sync-word-0x0000-0x0000>").is_empty());
+        assert_eq!(idx.detect_period(b"<sync-word-0x0000-0x0000>"), None);
+    }
+
+    #[test]
+    fn scan_external_matches_outside_the_sample() {
+        let sample = b"sensor 7 ok; sensor 9 ok; sensor 3 drift detected; ".repeat(4);
+        let idx = CsiIndex::build(&sample);
+        let mut big = Vec::new();
+        for i in 0..300u32 {
+            big.extend_from_slice(format!("sensor {} ok; ", i % 11).as_bytes());
+            if i % 37 == 5 {
+                big.extend_from_slice(b"sensor 12 drift detected; ");
+            }
+        }
+        let naive = |pat: &[u8]| -> Vec<usize> {
+            (0..=big.len() - pat.len()).filter(|&o| big[o..].starts_with(pat)).collect()
+        };
+        for pat in [&b"sensor 12 drift detected"[..], b"ok; sensor 10 ok; sensor", b"fault", b"4 ok"] {
+            let mut got = Vec::new();
+            idx.scan_external(&big, pat, |off| got.push(off));
+            assert_eq!(got, naive(pat));
+        }
+        let mut got = Vec::new();
+        idx.scan_external(&big, b"sensor 12 drift detected", |off| got.push(off));
+        assert_eq!(got.len(), 8);
+        assert!(idx.search(b"sensor 12 drift detected").is_empty());
+    }
+
+    #[test]
+    fn search_context_clamps_at_both_ends() {
+        let text = b"ERROR: disk quota exceeded on /var; INFO: rotated logs; ERROR: disk quota exceeded on /home";
+        let pat = b"ERROR: disk quota exceeded on /";
+        let idx = CsiIndex::build(text);
+        assert!(pat.len() >= idx.min_pattern_len());
+        let hits = idx.search_context(pat, 6, 5);
+        assert_eq!(hits, [
+            (0, b"ERROR: disk quota exceeded on /var; ".to_vec()),
+            (56, b"logs; ERROR: disk quota exceeded on /home".to_vec()),
+        ]);
+        assert_eq!(idx.search_context(pat, 0, 0)[1], (56, pat.to_vec()));
+        assert_eq!(idx.search_context(pat, usize::MAX, usize::MAX)[0].1, text);
+        assert!(idx.search_context(b"ERROR: disk quota exceeded on /usr", 3, 3).is_empty());
+
+        let hits = idx.search_with_context(pat, 6, 5);
+        assert_eq!(hits.iter().map(|m| (m.offset, m.context.to_vec())).collect::<Vec<_>>(), idx.search_context(pat, 6, 5));
+        assert_eq!(hits[1].start, 50);
+        assert!(hits.iter().all(|m| m.matched() == pat && m.len == pat.len()));
+        assert_eq!(idx.search_with_context(pat, usize::MAX, usize::MAX)[1].context, text);
+    }
+
+    #[test]
+    fn cooccur_keeps_a_with_b_shortly_after() {
+        let (a, b): (&[u8], &[u8]) = (b"<request id=4711 user=admin>", b"<response status=500 error>");
+        let mut text = Vec::new();
+        // b right after a, b 40 bytes after a, b just before a, then a alone
+        for gap in [0usize, 40] {
+            text.extend_from_slice(a);
+            text.extend(std::iter::repeat_n(b'.', gap));
+            text.extend_from_slice(b);
+            text.extend_from_slice(b" ---------- ");
+        }
+        text.extend_from_slice(b);
+        text.extend_from_slice(a);
+        text.extend_from_slice(b" ---------------------------------------------------------------------- ");
+        text.extend_from_slice(a);
+        let idx = CsiIndex::build(&text);
+        let (offs_a, offs_b) = (idx.search(a), idx.search(b));
+        assert_eq!((offs_a.len(), offs_b.len()), (4, 3));
+
+        assert_eq!(idx.search_cooccur(a, b, a.len()), [offs_a[0]]);
+        assert_eq!(idx.search_cooccur(a, b, a.len() + 40), offs_a[..2]);
+        assert_eq!(idx.search_cooccur(a, b, text.len()), offs_a[..2]);
+        assert_eq!(idx.search_cooccur(a, a, 0), offs_a);
+        assert_eq!(idx.search_cooccur(b, a, 27), [offs_b[2]]);
+        assert!(idx.search_cooccur(a, b"<response status=200 ok>xxx", text.len()).is_empty());
+    }
+
+    #[test]
+    fn count_and_contains_agree_with_search() {
+        let text = b"count me in; count me out; count me in again, count me in".repeat(7);
+        let strided = CsiIndexBuilder::new().stride(3).build(&text);
+        for idx in [CsiIndex::build(&text), strided] {
+            for pat in [&b"count me in"[..], b"count me in; count me out", b"me out; count me in again,", b"", b"in; in; in; in; in; in;"] {
+                assert_eq!(idx.count(pat), idx.search(pat).len());
+                assert_eq!(idx.contains(pat), !idx.search(pat).is_empty());
+            }
+            assert!(!idx.contains(b"count me in; count me in; count me in"));
+        }
+        let idx = CsiIndex::build(&text);
+        assert_eq!(idx.count(b"count me in; count me out"), 7);
+        let h = crate::csi_new(text.as_ptr(), text.len());
+        let pat = b"count me in again, count me in";
+        assert_eq!(crate::csi_count(h, pat.as_ptr(), pat.len()), 7);
+        assert_eq!(crate::csi_count(h, std::ptr::null(), 3), 0);
+        assert!(crate::csi_contains(h, pat.as_ptr(), pat.len()));
+        assert!(!crate::csi_contains(h, b"count me out again".as_ptr(), 18));
+        assert!(!crate::csi_contains(std::ptr::null(), pat.as_ptr(), pat.len()));
+        crate::csi_free(h);
+    }
+
+    #[test]
+    fn search_in_keeps_matches_inside_the_window() {
+        let text: Vec<u8> = (0..900).flat_map(|i| format!("day{:02} GET /a/{} 200\n", i / 100, i % 11).into_bytes()).collect();
+        let builders = [
+            CsiIndexBuilder::new(),
+            CsiIndexBuilder::new().stride(2).fingerprints(true),
+            CsiIndexBuilder::new().max_postings_per_key(4),
+        ];
+        for builder in builders {
+            let idx = builder.build(&text);
+            for pat in [&b"GET /a/3 200\nday0"[..], b" 200\nday04 GET /a/", b"0", b"", b"zzzzzzzzzzzzzzzzzzzz"] {
+                let all = idx.search(pat);
+                for range in [0..text.len(), 1000..5000, 4321..4330, 9000..text.len() + 50, std::ops::Range { start: 300, end: 200 }] {
+                    let want: Vec<usize> = all.iter().copied()
+                        .filter(|&o| o >= range.start && o + pat.len() <= range.end.min(text.len()))
+                        .collect();
+                    assert_eq!(idx.search_in(pat, range.clone()), want, "{range:?}");
+                }
+            }
+        }
+        let h = crate::csi_new(text.as_ptr(), text.len());
+        let pat = b"day05 GET /a/";
+        let mut out = [0usize; 2];
+        let want = CsiIndex::build(&text).search_in(pat, 10_000..11_000);
+        assert_eq!(crate::csi_search_range(h, pat.as_ptr(), pat.len(), 10_000, 11_000, out.as_mut_ptr(), 2), want.len());
+        assert_eq!(out, want[..2]);
+        crate::csi_free(h);
+    }
+
+    #[test]
+    fn search_iter_streams_the_intersection_in_order() {
+        let text: Vec<u8> = (0..1500).flat_map(|i| format!("evt={} user={:02} ok;", i % 40, i % 17).into_bytes()).collect();
+        let builders = [
+            CsiIndexBuilder::new(),
+            CsiIndexBuilder::new().stride(3),
+            CsiIndexBuilder::new().stride(2).fingerprints(true).max_postings_per_key(60),
+            CsiIndexBuilder::new().syncmer_window(6),
+        ];
+        for builder in builders {
+            let idx = builder.build(&text);
+            for pat in [&b"evt=12 user=05 ok;"[..], b"user=03 ok;evt=3", b" ok;evt=1", b"evt=99 user=00 ok;"] {
+                let all = idx.search(pat);
+                assert_eq!(idx.search_iter(pat).collect::<Vec<_>>(), all);
+                assert_eq!(idx.search_iter(pat).take(3).collect::<Vec<_>>(), all[..all.len().min(3)]);
+                let mut seen = Vec::new();
+                idx.search_visit(pat, |off| { seen.push(off); seen.len() < 5 });
+                assert_eq!(seen, all[..all.len().min(5)]);
+            }
+        }
+    }
+
+    #[test]
+    fn search_limited_pages_through_search() {
+        let text: Vec<u8> = (0..800).flat_map(|i| format!("row {:03} kind={} ", i, i % 3).into_bytes()).collect();
+        let idx = CsiIndexBuilder::new().max_postings_per_key(50).build(&text);
+        for pat in [&b" kind=1 row "[..], b"kind=2 row 1", b"row 5", b"", b"kind=7 row 000"] {
+            let all = idx.search(pat);
+            for (offset, limit) in [(0, 10), (10, 10), (all.len().saturating_sub(3), 10), (all.len(), 5), (5, 0), (0, usize::MAX)] {
+                let want = &all[offset.min(all.len())..][..limit.min(all.len().saturating_sub(offset))];
+                assert_eq!(idx.search_limited(pat, offset, limit), want, "{offset} {limit}");
+            }
+        }
+    }
+
+    #[test]
+    fn search_iter_is_lazy_search_and_shares_across_threads() {
+        fn shared<T: Send + Sync>(_: &T) {}
+        let text = b"thread one reads, thread two reads, thread three reads the index".repeat(9);
+        let idx = std::sync::Arc::new(CsiIndex::build(&text));
+        shared(&idx);
+        let pats: [&[u8]; 4] = [b"thread two reads, thread three", b"reads", b"", b"nobody writes to this index"];
+        let workers: Vec<_> = (0..4).map(|_| {
+            let idx = std::sync::Arc::clone(&idx);
+            std::thread::spawn(move || pats.iter().map(|p| idx.search_iter(p).collect::<Vec<_>>()).collect::<Vec<_>>())
+        }).collect();
+        let want: Vec<Vec<usize>> = pats.iter().map(|p| idx.search(p)).collect();
+        for w in workers {
+            assert_eq!(w.join().unwrap(), want);
+        }
+        assert_eq!(idx.search_iter(b"thread").nth(1), Some(18));
+    }
+
+    #[test]
+    fn contains_answers_edge_patterns_as_search_does() {
+        let text = b"keep this line; drop this line; keep this line once more".to_vec();
+        let occurs = |idx: &CsiIndex, pat: &[u8]| !idx.search(pat).is_empty();
+        let mut idx = CsiIndex::build(&text);
+        let short = &b"line"[..];
+        assert!(short.len() < idx.min_pattern_len());
+        let longer = [&text[..], b"!"].concat();
+        for pat in [&b""[..], short, b"lime", &text, &longer, b"drop this line; keep", b"drop this line; keeps"] {
+            assert_eq!(idx.contains(pat), occurs(&idx, pat), "{}", String::from_utf8_lossy(pat));
+        }
+        assert!(idx.contains(b"") && idx.contains(short) && idx.contains(&text) && !idx.contains(&longer));
+
+        // the only match tombstoned, or only some of several
+        idx.invalidate_range(16..31);
+        for (pat, want) in [(&b"drop this line; keep"[..], false), (b"drop", false), (b"keep this line", true), (b"this line", true)] {
+            assert_eq!(idx.contains(pat), want, "{}", String::from_utf8_lossy(pat));
+            assert_eq!(idx.contains(pat), occurs(&idx, pat));
+        }
+        assert!(!idx.contains(&text));
+
+        // short patterns aren't scanned for when that's turned off
+        let idx = CsiIndexBuilder::new().scan_short_patterns(false).build(&text);
+        assert_eq!((idx.contains(short), idx.contains(b"")), (occurs(&idx, short), occurs(&idx, b"")));
+        assert!(!idx.contains(short));
+        // nor does an empty text hold anything but the empty pattern
+        let empty = CsiIndex::build(b"");
+        assert!(empty.contains(b"") && !empty.contains(b"x"));
+    }
+}
@@ -0,0 +1,154 @@
+// src/regex.rs
+//! Regex search (feature `regex`) with the index as a prefilter.
+//!
+//! The longest run of literal bytes that every match must contain is taken
+//! from the parsed regex and searched for. Each occurrence bounds where a
+//! match through it can lie: by the most bytes the regex can match before
+//! and after the run, or by the occurrence's line where that is unbounded
+//! and the regex can't match a `\n`. The regex engine then runs only over
+//! those windows, merged, while seeing the whole text for anchors and word
+//! boundaries. A regex without such a run, or with windows that can't be
+//! bounded, is run over the whole text.
+
+use alloc::{borrow::Cow, string::ToString, vec::Vec};
+use core::ops::Range;
+
+use regex_automata::meta::Regex;
+use regex_automata::Input;
+use regex_syntax::hir::{Class, Hir, HirKind};
+
+use crate::newline::line_breaks;
+use crate::{CsiIndex, CsiIndexError, Hasher};
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Leftmost‐first, non‐overlapping matches of regex `pattern` in the
+    /// indexed text, ascending, as the regex engine's `find_iter` reports
+    /// them over the whole text.
+    ///
+    /// The regex runs over the text as indexed, folded or normalized, so
+    /// write it for that text. Only literal bytes outside alternations and
+    /// repetitions prefilter: a case‐insensitive or alternating pattern
+    /// gets the engine's plain speed. `CsiIndexError::Regex` if `pattern`
+    /// doesn't compile.
+    pub fn search_regex(&self, pattern: &str) -> Result<Vec<Range<usize>>, CsiIndexError> {
+        let hir = regex_syntax::parse(pattern).map_err(|e| CsiIndexError::Regex(e.to_string()))?;
+        let re = Regex::builder().build_from_hir(&hir).map_err(|e| CsiIndexError::Regex(e.to_string()))?;
+        let text = &self.text[..];
+        let windows = self.regex_windows(&hir).unwrap_or_else(|| core::iter::once(0..text.len()).collect());
+        let mut found = Vec::new();
+        for span in windows {
+            found.extend(re.find_iter(Input::new(text).range(span)).map(|m| m.range()).filter(|r| self.live(r.start, r.len())));
+        }
+        Ok(found)
+    }
+
+    /// Disjoint, ascending spans of the text that hold every match of
+    /// `hir`, or `None` if only the whole text is known to.
+    fn regex_windows(&self, hir: &Hir) -> Option<Vec<Range<usize>>> {
+        let mut pieces = Vec::new();
+        flatten(hir, &mut pieces);
+        // the longest run of adjacent literals: its first piece, the piece
+        // after it and its bytes
+        let mut best: Option<(usize, usize, Vec<u8>)> = None;
+        let mut i = 0;
+        while i < pieces.len() {
+            let (mut j, mut run) = (i, Vec::new());
+            while let Some(HirKind::Literal(lit)) = pieces.get(j).map(|h| h.kind()) {
+                run.extend_from_slice(&lit.0);
+                j += 1;
+            }
+            if run.len() > best.as_ref().map_or(0, |b| b.2.len()) {
+                best = Some((i, j, run));
+            }
+            i = j.max(i + 1);
+        }
+        let (first, end, lit) = best?;
+        let most = |hs: &[&Hir]| hs.iter().try_fold(0usize, |n, h| n.checked_add(h.properties().maximum_len()?));
+        let (before, after) = (most(&pieces[..first]), most(&pieces[end..]));
+        let by_line = before.is_none() || after.is_none();
+        if by_line && crosses_lines(hir) {
+            return None;
+        }
+        let breaks: Cow<'_, [usize]> = match &self.lines {
+            _ if !by_line => Cow::Borrowed(&[]),
+            Some(lines) => Cow::Borrowed(lines),
+            None => Cow::Owned(line_breaks(&self.text, 0).collect()),
+        };
+        let n = self.text.len();
+        let mut windows: Vec<Range<usize>> = Vec::new();
+        for off in self.search(&lit) {
+            let lo = match before {
+                Some(b) => off.saturating_sub(b),
+                None => match breaks.partition_point(|&p| p < off) {
+                    0 => 0,
+                    i => breaks[i - 1] + 1,
+                },
+            };
+            let hi = match after {
+                Some(a) => (off + lit.len()).saturating_add(a).min(n),
+                None => breaks.get(breaks.partition_point(|&p| p < off + lit.len())).map_or(n, |&p| p),
+            };
+            match windows.last_mut() {
+                Some(w) if lo <= w.end => w.end = w.end.max(hi),
+                _ => windows.push(lo..hi),
+            }
+        }
+        Some(windows)
+    }
+}
+
+/// `hir`'s top‐level sequence, through concatenations and groups.
+fn flatten<'h>(hir: &'h Hir, out: &mut Vec<&'h Hir>) {
+    match hir.kind() {
+        HirKind::Concat(hs) => hs.iter().for_each(|h| flatten(h, out)),
+        HirKind::Capture(c) => flatten(&c.sub, out),
+        _ => out.push(hir),
+    }
+}
+
+/// Whether `hir` can match a `\n`.
+fn crosses_lines(hir: &Hir) -> bool {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => false,
+        HirKind::Literal(lit) => lit.0.contains(&b'\n'),
+        HirKind::Class(Class::Unicode(c)) => c.ranges().iter().any(|r| r.start() <= '\n' && '\n' <= r.end()),
+        HirKind::Class(Class::Bytes(c)) => c.ranges().iter().any(|r| r.start() <= b'\n' && b'\n' <= r.end()),
+        HirKind::Repetition(r) => crosses_lines(&r.sub),
+        HirKind::Capture(c) => crosses_lines(&c.sub),
+        HirKind::Concat(hs) | HirKind::Alternation(hs) => hs.iter().any(crosses_lines),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex_automata::meta::Regex;
+
+    use crate::{CsiIndex, CsiIndexBuilder, CsiIndexError};
+
+    #[test]
+    fn prefiltered_regex_matches_what_the_engine_finds_in_the_whole_text() {
+        let text: Vec<u8> = (0..500).flat_map(|i| {
+            let level = ["INFO", "WARN", "ERROR"][i * 7 % 3];
+            format!("{i:04} {level} disk {} quota host-{};\n", i * 37 % 1000, i % 23).into_bytes()
+        }).collect();
+        let idx = CsiIndex::build(&text);
+        let lines = CsiIndexBuilder::new().track_lines(true).build(&text);
+        let pats = [
+            r"\d{4} ERROR disk \d+",       // bounded on both sides
+            r"ERROR disk.*host-1\d;",      // unbounded, but within a line
+            r"\bWARN disk (\d+) quota",    // anchored on a word boundary
+            r"quota host-7;\s+\d+ INFO",   // may cross lines: the whole text
+            r"[0-9]+ (INFO|WARN)",         // no literal run
+            r"quota host-99;",             // no match
+        ];
+        for pat in pats {
+            let want: Vec<_> = Regex::new(pat).unwrap().find_iter(&text[..]).map(|m| m.range()).collect();
+            assert_eq!(idx.search_regex(pat).unwrap(), want, "{pat}");
+            assert_eq!(lines.search_regex(pat).unwrap(), want, "{pat}");
+        }
+        let errors = idx.search_regex(r"ERROR disk \d+ quota").unwrap();
+        assert_eq!(errors.len(), idx.count(b" ERROR disk "));
+        assert!(errors.iter().all(|r| text[r.clone()].starts_with(b"ERROR disk ")));
+        assert!(matches!(idx.search_regex("(disk"), Err(CsiIndexError::Regex(_))));
+    }
+}
@@ -0,0 +1,248 @@
+// src/scored.rs
+//! Soft matching: which gaps' constellations support each candidate offset.
+//!
+//! `search` intersects posting lists, so any missing constellation kills the
+//! query. Here the lists are merged as a union instead, keeping track of
+//! which gaps vouched for each offset. A true occurrence is supported by
+//! every usable gap; partial support points at offsets where the pattern
+//! differs from the text (and which anchors the difference hit). Candidates
+//! are not verified.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::cmp::Reverse;
+
+use crate::{key_at, prefix_hash, CsiIndex, Hasher, Probe};
+
+/// Result order for `search_scored`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OrderBy {
+    /// Ascending offset, straight from the merge
+    #[default]
+    Position,
+    /// Descending support, equal scores by ascending offset (so the order
+    /// is fully determined and pages over it are stable); costs an extra sort
+    ScoreDesc,
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// `(gap, phase, posting list)` for every gap usable at each phase of
+    /// `pat` (only phase 0 unless strided, its first syncmer with those) whose constellation there is
+    /// present and unsaturated; entries are `phase` bytes past the offset
+    /// they support.
+    fn support_lists(&self, pat: &[u8]) -> Vec<(usize, usize, Cow<'_, [usize]>)> {
+        let m = pat.len();
+        if m < self.min_pattern_len() {
+            return Vec::new();
+        }
+        let php = prefix_hash(&self.hasher, pat);
+        let pk = self.pw[self.k];
+        self.phases(&php)
+            .flat_map(|phase| self.gaps.iter().zip(&self.flat).map(move |(&d, fi)| (phase, d, fi)))
+            .filter(|&(phase, d, _)| phase + d + self.k <= m)
+            .filter_map(|(phase, d, fi)| {
+                fi.lookup(key_at(&self.hasher, &php, pk, phase, self.k, d)).postings().map(|l| (d, phase, l))
+            })
+            .collect()
+    }
+
+    /// Every offset supported by at least one gap, with the gaps (ascending)
+    /// whose constellation matched there, in offset order.
+    ///
+    /// Costs a sort over the union of the posting lists rather than an
+    /// intersection, so it's noticeably more expensive than `search` when
+    /// lists are long.
+    pub fn search_with_support(&self, pat: &[u8]) -> Vec<(usize, Vec<usize>)> {
+        let pat = &*self.normalize(pat);
+        let mut pairs: Vec<(usize, usize)> = Vec::new();
+        for (d, phase, list) in self.support_lists(pat) {
+            pairs.extend(list.iter().filter_map(|&p| p.checked_sub(phase)).map(|off| (off, d)));
+        }
+        pairs.sort_unstable();
+        let mut out: Vec<(usize, Vec<usize>)> = Vec::new();
+        for (off, d) in pairs {
+            match out.last_mut() {
+                Some((o, gaps)) if *o == off => gaps.push(d),
+                _ => out.push((off, vec![d])),
+            }
+        }
+        out
+    }
+
+    /// How many distinct constellations of `pat`, taken at every position
+    /// and every usable gap, occur anywhere in the text (saturated buckets
+    /// count as present).
+    ///
+    /// Nothing is intersected, so this is a bag‐of‐constellations overlap
+    /// signal, not evidence of a match: unrelated text sharing enough
+    /// `k`‐grams at the right distances scores too. One bucket probe per
+    /// distinct constellation; 0 below `min_pattern_len`.
+    pub fn shared_constellations(&self, pat: &[u8]) -> usize {
+        let pat = &*self.normalize(pat);
+        let m = pat.len();
+        if m < self.min_pattern_len() {
+            return 0;
+        }
+        let php = prefix_hash(&self.hasher, pat);
+        let pk = self.pw[self.k];
+        let mut keys = Vec::new();
+        let mut shared = 0;
+        for (&d, fi) in self.gaps.iter().zip(&self.flat).filter(|&(&d, _)| d + self.k <= m) {
+            keys.clear();
+            keys.extend((0..=m - d - self.k).map(|i| key_at(&self.hasher, &php, pk, i, self.k, d)));
+            keys.sort_unstable();
+            keys.dedup();
+            shared += keys.iter().filter(|&&key| !matches!(fi.lookup(key), Probe::Missing)).count();
+        }
+        shared
+    }
+
+    /// `(offset, supporting gap count)` for every offset with any support,
+    /// in the requested order.
+    ///
+    /// `Position` is the merge's natural output; `ScoreDesc` re‐sorts it by
+    /// `(Reverse(score), offset)`, so equal scores come out in ascending
+    /// offset order. Unverified, like `search_with_support`.
+    pub fn search_scored(&self, pat: &[u8], order: OrderBy) -> Vec<(usize, usize)> {
+        let mut out: Vec<(usize, usize)> = self.search_with_support(pat)
+            .into_iter()
+            .map(|(off, gaps)| (off, gaps.len()))
+            .collect();
+        if order == OrderBy::ScoreDesc {
+            out.sort_unstable_by_key(|&(off, score)| (Reverse(score), off));
+        }
+        out
+    }
+
+    /// `(offset, weight)` for every offset with any support, heaviest
+    /// first, equal weights by ascending offset.
+    ///
+    /// Each supporting constellation contributes `1 / len`, `len` being its
+    /// posting‐list length, so one constellation's weight is shared among
+    /// all the places it occurs: an offset confirmed by a single rare
+    /// constellation can outrank one confirmed by several common ones, which
+    /// a raw gap count (`search_scored`) can't express. An offset confirmed
+    /// by every usable gap scores at most their count. Unverified.
+    pub fn search_weighted(&self, pat: &[u8]) -> Vec<(usize, f64)> {
+        let pat = &*self.normalize(pat);
+        let mut pairs: Vec<(usize, f64)> = Vec::new();
+        for (_, phase, list) in self.support_lists(pat) {
+            let w = 1.0 / list.len() as f64;
+            pairs.extend(list.iter().filter_map(|&p| p.checked_sub(phase)).map(|off| (off, w)));
+        }
+        pairs.sort_unstable_by_key(|&(off, _)| off);
+        let mut out: Vec<(usize, f64)> = Vec::new();
+        for (off, w) in pairs {
+            match out.last_mut() {
+                Some((o, sum)) if *o == off => *sum += w,
+                _ => out.push((off, w)),
+            }
+        }
+        out.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OrderBy;
+    use crate::{CsiConfig, CsiIndex, EntropyTiers};
+
+    #[test]
+    fn scored_orders_by_position_or_score() {
+        let text = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ-_+=".repeat(3);
+        let idx = CsiIndex::build(&text);
+        let mut pat = text[3..3 + 50].to_vec();
+        pat[40] = b'!';
+        let by_pos = idx.search_scored(&pat, OrderBy::Position);
+        assert!(by_pos.windows(2).all(|w| w[0].0 < w[1].0));
+        let ranked = idx.search_scored(&pat, OrderBy::ScoreDesc);
+        assert!(ranked.windows(2).all(|w| w[0].1 > w[1].1 || (w[0].1 == w[1].1 && w[0].0 < w[1].0)));
+        let mut resorted = ranked.clone();
+        resorted.sort_unstable();
+        assert_eq!(resorted, by_pos);
+    }
+
+    #[test]
+    fn rare_confirmation_outweighs_common() {
+        let config = CsiConfig {
+            tiers: EntropyTiers::fixed(4, vec![4, 8, 16, 32]),
+            ..CsiConfig::default()
+        };
+        let pat = &b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ"[..50];
+        // gap 32's second anchor broken: three common constellations confirm
+        let mut common = pat.to_vec();
+        common[32] = b'!';
+        // gaps 4, 8 and 16 broken: only gap 32, seen once, confirms
+        let mut rare = pat.to_vec();
+        for i in [4, 8, 16] {
+            rare[i] = b'!';
+        }
+        let mut text = Vec::new();
+        for _ in 0..30 {
+            text.extend_from_slice(&common);
+            text.extend_from_slice(b"~~~~~~~~");
+        }
+        let at = text.len();
+        text.extend_from_slice(&rare);
+        let idx = CsiIndex::build_with_config(&text, &config);
+
+        let weighted = idx.search_weighted(pat);
+        assert_eq!(weighted.len(), 31);
+        assert_eq!(weighted[0], (at, 1.0));
+        assert!((weighted[1].1 - 3.0 / 30.0).abs() < 1e-12 && weighted[1].0 == 0);
+        let by_count = idx.search_scored(pat, OrderBy::ScoreDesc);
+        assert_eq!((by_count[0].1, by_count.last().unwrap()), (3, &(at, 1)));
+    }
+
+    #[test]
+    fn shared_constellations_grade_overlap() {
+        let text = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ-_+=".repeat(2);
+        let idx = CsiIndex::build(&text);
+        let pat = text[3..3 + 50].to_vec();
+        let all: usize = idx.gaps.iter().filter(|&&d| d + idx.k <= 50).map(|&d| 50 - d - idx.k + 1).sum();
+        assert_eq!(idx.shared_constellations(&pat), all);
+        let mut edited = pat.clone();
+        edited[25] = b'!';
+        let some = idx.shared_constellations(&edited);
+        assert!(some > 0 && some < all);
+        assert_eq!(idx.shared_constellations(&[b'!'; 50]), 0);
+        assert_eq!(idx.shared_constellations(&pat[..idx.min_pattern_len() - 1]), 0);
+    }
+
+    #[test]
+    fn equal_scores_rank_by_offset() {
+        let block = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ-_+=";
+        let l = block.len();
+        let mut text = block.repeat(4);
+        let idx = CsiIndex::build(&text);
+        let usable: Vec<usize> = idx.gaps.iter().copied().filter(|&d| d + idx.k <= 50).collect();
+        let (full, big) = (usable.len(), *usable.last().unwrap());
+        // copies 1 and 3 lose the largest usable gap's second anchor
+        for copy in [1, 3] {
+            text[copy * l + 3 + big] = b'!';
+        }
+        let idx = CsiIndex::build(&text);
+        let ranked = idx.search_scored(&text[3..3 + 50], OrderBy::ScoreDesc);
+        assert_eq!(ranked, [(3, full), (3 + 2 * l, full), (3 + l, full - 1), (3 + 3 * l, full - 1)]);
+    }
+
+    #[test]
+    fn support_pinpoints_the_mutated_anchor() {
+        let text = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ-_+=".repeat(2);
+        let idx = CsiIndex::build(&text);
+        let pat = &text[3..3 + 50];
+        let usable: Vec<usize> = idx.gaps.iter().copied().filter(|&d| d + idx.k <= pat.len()).collect();
+
+        let exact = idx.search_with_support(pat);
+        assert!(exact.contains(&(3, usable.clone())));
+
+        // corrupt the second anchor of the largest usable gap only
+        let big = *usable.last().unwrap();
+        let mut mutated = pat.to_vec();
+        mutated[big] = b'!';
+        let support = idx.search_with_support(&mutated);
+        let at3 = &support.iter().find(|(o, _)| *o == 3).unwrap().1;
+        assert_eq!(at3, &usable[..usable.len() - 1]);
+        assert!(idx.search(&mutated).is_empty());
+    }
+}
@@ -0,0 +1,275 @@
+// src/scratch.rs
+//! Searches into buffers the caller keeps: `search_with` answers as
+//! `search` does, but the folded pattern, its prefix hashes, the posting
+//! lists and the intersections all live in a `SearchScratch`, so once its
+//! vectors have grown to a workload's patterns a search allocates nothing.
+//!
+//! Masked indexes and periodic patterns (see `periodic`) still take
+//! `search`'s allocating routes; only their results land in `out`.
+
+use alloc::vec::Vec;
+
+use crate::array::List;
+use crate::hash::key_at;
+use crate::packed::Packed;
+use crate::{periodic, verify, visit_kway_in, visit_sorted, CsiIndex, Hasher, Probe};
+
+/// Buffers for `CsiIndex::search_with`, reusable across queries and
+/// across indexes: nothing in them borrows an index between searches
+#[derive(Default)]
+pub struct SearchScratch {
+    pat:    Vec<u8>,  // the normalized pattern
+    php:    Vec<u64>, // its prefix states
+    fail:   Vec<usize>, // failure function, for the pattern's period
+    // the storage of a search's posting lists, always empty here; `lend`
+    // retypes it to the index's lifetime for the search
+    lists:  Vec<List<'static>>,
+    packed: Vec<&'static Packed>,
+    pos:    Vec<usize>, // k‐way cursors
+    hits:   Vec<usize>, // one phase's intersection
+    acc:    Vec<usize>, // every phase's candidates
+}
+
+/// `v`'s allocation as an empty vector of another element type.
+///
+/// No element is ever converted: `v` is cleared first, so the collect
+/// only reuses the buffer, which `Vec`'s in‐place collect does for
+/// elements of one layout, as lists of any two lifetimes are.
+fn lend<T, U>(mut v: Vec<T>) -> Vec<U> {
+    v.clear();
+    v.into_iter().map(|_| unreachable!()).collect()
+}
+
+impl SearchScratch {
+    /// Empty buffers, which the first searches grow.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// `search`, writing the matches to `out` (cleared first) and keeping
+    /// every intermediate buffer in `scratch`, so repeated queries don't
+    /// allocate once the buffers are large enough.
+    pub fn search_with(&self, pat: &[u8], scratch: &mut SearchScratch, out: &mut Vec<usize>) {
+        out.clear();
+        let mut buf = core::mem::take(&mut scratch.pat);
+        self.normalize_into(pat, &mut buf);
+        self.search_scratch(&buf, scratch, out);
+        scratch.pat = buf;
+    }
+
+    /// `search` of the normalized `pat`.
+    fn search_scratch(&self, pat: &[u8], s: &mut SearchScratch, out: &mut Vec<usize>) {
+        let n = self.text.len();
+        if let Some(mask) = &self.mask {
+            out.extend(self.search_masked(pat, mask));
+            return;
+        }
+        if pat.len() >= n {
+            if pat == &self.text[..] && self.live(0, pat.len()) {
+                out.push(0);
+            }
+            return;
+        }
+        let p = periodic::pattern_period_in(pat, &mut s.fail);
+        if self.verify && p > 0 && 2 * p <= pat.len() {
+            out.extend(self.search_periodic(pat, p));
+            return;
+        }
+        if pat.is_empty() {
+            out.extend(0..=n);
+            return;
+        }
+        s.php.clear();
+        s.php.push(0);
+        for (i, &b) in pat.iter().enumerate() {
+            let h = self.hasher.extend(s.php[i], b);
+            s.php.push(h);
+        }
+        let keep = |&off: &usize| self.verify_by(off, pat, &verify::same);
+        if self.candidates_scratch(pat.len(), s) {
+            out.extend(s.acc.iter().copied().filter(keep));
+        } else {
+            out.extend((0..=n - pat.len()).filter(keep));
+        }
+    }
+
+    /// `candidates_hashed` with every list and the search cutoff, into
+    /// `s.acc`; `false` where that returns `None`.
+    fn candidates_scratch(&self, m: usize, s: &mut SearchScratch) -> bool {
+        s.acc.clear();
+        if m < self.min_pattern_len() {
+            return !self.scan_short;
+        }
+        let (mut lists, mut packed) = (lend(core::mem::take(&mut s.lists)), lend(core::mem::take(&mut s.packed)));
+        let found = self.candidates_in(m, s, &mut lists, &mut packed);
+        (s.lists, s.packed) = (lend(lists), lend(packed));
+        found
+    }
+
+    /// `candidates_scratch` once past the short patterns, collecting each
+    /// phase's lists in `lists` and `packed`.
+    fn candidates_in<'a>(&'a self, m: usize, s: &mut SearchScratch, lists: &mut Vec<List<'a>>, packed: &mut Vec<&'a Packed>) -> bool {
+        let SearchScratch { php, pos, hits, acc, .. } = s;
+        let (pk, cutoff) = (self.pw[self.k], self.plan_cutoff());
+        let mut phases = 0;
+        'phase: for phase in self.phases(php) {
+            lists.clear();
+            packed.clear();
+            for (idx, &d) in self.gaps.iter().enumerate() {
+                if phase + d + self.k <= m {
+                    match self.flat[idx].lookup(key_at(&self.hasher, php, pk, phase, self.k, d)) {
+                        Probe::Missing => continue 'phase,
+                        Probe::Saturated => {}
+                        Probe::List(l) => lists.push(l),
+                        Probe::Packed(l) => packed.push(l),
+                    }
+                    if lists.last().is_some_and(|l| l.len() < cutoff) {
+                        break;
+                    }
+                }
+            }
+            if lists.is_empty() && packed.is_empty() {
+                return false;
+            }
+            phases += 1;
+            lists.sort_unstable_by_key(|l| l.len());
+            packed.sort_unstable_by_key(|l| l.len);
+            hits.clear();
+            // packed lists left to narrow `hits` with
+            let rest = match lists.first() {
+                None => {
+                    hits.extend(packed[0].iter());
+                    &packed[1..]
+                }
+                Some(&first) => {
+                    let check = self.span_check(php, phase, first);
+                    let mut keep = |i: usize, p: usize| if check.is_none_or(|(fps, want)| fps[i] == want) { hits.push(p) };
+                    match lists[..] {
+                        [one] => one.iter().enumerate().for_each(|(i, p)| keep(i, p)),
+                        [a, b] => visit_sorted(a, b, keep),
+                        _ => visit_kway_in(lists, pos, keep),
+                    }
+                    &packed[..]
+                }
+            };
+            for list in rest {
+                list.retain(hits);
+            }
+            acc.extend(hits.iter().filter_map(|&p| p.checked_sub(phase)));
+        }
+        if phases > 1 {
+            acc.sort_unstable();
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    use super::{lend, SearchScratch};
+    use crate::array::List;
+    use crate::{ByteMask, CaseFold, CsiIndex, CsiIndexBuilder, EntropyTiers};
+
+    /// The system allocator, counting this thread's allocations
+    struct Counting;
+
+    thread_local! {
+        static ALLOCS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCS.with(|n| n.set(n.get() + 1));
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static COUNTING: Counting = Counting;
+
+    fn log() -> Vec<u8> {
+        (0..4000).flat_map(|i| format!("{} GET /Item/{} 200\r\n", i % 97, i % 11).into_bytes()).collect()
+    }
+
+    const PATTERNS: [&[u8]; 9] = [
+        b"GET /Item/3 200", b"200\r\n5", b"GET", b"/item/7 200\r\n4", b"", b"ab", b"abababababab",
+        b"not in the log at all", b"0 GET /Item/10 200\r\n",
+    ];
+
+    #[test]
+    fn search_with_matches_search() {
+        let text = log();
+        let builders = [
+            CsiIndexBuilder::new(),
+            CsiIndexBuilder::new().tiers(EntropyTiers::fixed(4, vec![4, 8])).stride(3),
+            CsiIndexBuilder::new().fingerprints(true).pack_postings(64),
+            CsiIndexBuilder::new().tiers(EntropyTiers::fixed(3, vec![3, 7])).pack_postings(16).stride(2),
+            CsiIndexBuilder::new().case_fold(CaseFold::Ascii).normalize_newlines(true),
+            CsiIndexBuilder::new().verify(false),
+            CsiIndexBuilder::new().tiers(EntropyTiers::fixed(4, vec![4, 8])).syncmer_window(4),
+            CsiIndexBuilder::new().mask(ByteMask::new(5, &[0])),
+        ];
+        let mut out = vec![7];
+        for builder in builders {
+            let mut idx = builder.build(&text);
+            let mut scratch = SearchScratch::new();
+            for pat in PATTERNS {
+                idx.search_with(pat, &mut scratch, &mut out);
+                assert_eq!(out, idx.search(pat), "{}", String::from_utf8_lossy(pat));
+            }
+            idx.invalidate_range(100..900);
+            let mut scratch = SearchScratch::new();
+            idx.search_with(b"GET /Item/3 200", &mut scratch, &mut out);
+            assert_eq!(out, idx.search(b"GET /Item/3 200"));
+        }
+        let tiny = CsiIndex::build(b"tiny");
+        let mut scratch = SearchScratch::new();
+        tiny.search_with(b"tiny", &mut scratch, &mut out);
+        assert_eq!(out, [0]);
+        tiny.search_with(b"longer than the text", &mut scratch, &mut out);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn warm_scratch_searches_allocate_nothing() {
+        let text = log();
+        let idx = CsiIndexBuilder::new().tiers(EntropyTiers::fixed(4, vec![4, 8])).stride(2).build(&text);
+        let (mut scratch, mut out) = (SearchScratch::new(), Vec::new());
+        let pats: [&[u8]; 3] = [b"GET /Item/3 200", b"200\r\n5", b"not in the log at all"];
+        for pat in pats {
+            idx.search_with(pat, &mut scratch, &mut out);
+        }
+        let before = ALLOCS.with(Cell::get);
+        for pat in pats {
+            idx.search_with(pat, &mut scratch, &mut out);
+        }
+        assert_eq!(ALLOCS.with(Cell::get), before);
+    }
+
+    #[test]
+    fn one_scratch_outlives_the_indexes_it_searches() {
+        let (mut scratch, mut out) = (SearchScratch::new(), Vec::new());
+        for text in [log(), log().to_ascii_uppercase()] {
+            let idx = CsiIndex::build(&text);
+            idx.search_with(b"200\r\n5", &mut scratch, &mut out);
+            assert_eq!(out, idx.search(b"200\r\n5"));
+        }
+        // and its list buffers keep their allocation across the lending
+        let mut lists: Vec<List<'static>> = Vec::with_capacity(16);
+        lists.push((&[1usize, 2]).into());
+        let at = lists.as_ptr() as usize;
+        let lent: Vec<List<'_>> = lend(lists);
+        assert!(lent.is_empty() && lent.capacity() >= 16 && lent.as_ptr() as usize == at);
+        let back: Vec<List<'static>> = lend(lent);
+        assert_eq!(back.as_ptr() as usize, at);
+    }
+}
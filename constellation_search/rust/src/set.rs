@@ -0,0 +1,106 @@
+// src/set.rs
+//! Many indexes behind one query entry point, as for one index per shard or
+//! tenant: a search fans out to every index and the matches come back
+//! tagged with the shard they're in.
+
+use alloc::vec::Vec;
+
+use crate::{CsiIndex, Hasher, PolyHasher};
+
+/// Indexes searched together, numbered from 0 in the order they're added;
+/// see `search`
+pub struct CsiIndexSet<H = PolyHasher> {
+    shards: Vec<CsiIndex<H>>,
+}
+
+impl<H> Default for CsiIndexSet<H> {
+    fn default() -> Self {
+        CsiIndexSet { shards: Vec::new() }
+    }
+}
+
+impl<H: Hasher> CsiIndexSet<H> {
+    /// An empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `index` as the next shard and return its ID.
+    pub fn add(&mut self, index: CsiIndex<H>) -> usize {
+        self.shards.push(index);
+        self.shards.len() - 1
+    }
+
+    /// Number of shards.
+    pub fn len(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.is_empty()
+    }
+
+    /// Shard `id`'s index, if there is one.
+    pub fn shard(&self, id: usize) -> Option<&CsiIndex<H>> {
+        self.shards.get(id)
+    }
+
+    /// `(shard ID, offset)` of every match of `pat` in every shard, by
+    /// shard and then offset: each shard's `search` in turn.
+    pub fn search(&self, pat: &[u8]) -> Vec<(usize, usize)> {
+        let mut out = Vec::new();
+        for (id, idx) in self.shards.iter().enumerate() {
+            out.extend(idx.search(pat).into_iter().map(|off| (id, off)));
+        }
+        out
+    }
+
+    /// `search`, with the shards searched concurrently on the current rayon
+    /// pool; the matches come back in the same order.
+    #[cfg(feature = "rayon")]
+    pub fn par_search(&self, pat: &[u8]) -> Vec<(usize, usize)> {
+        use rayon::prelude::*;
+
+        let hits: Vec<Vec<usize>> = self.shards.par_iter().map(|idx| idx.search(pat)).collect();
+        hits.into_iter().enumerate().flat_map(|(id, offs)| offs.into_iter().map(move |off| (id, off))).collect()
+    }
+
+    /// Matches of `pat` over every shard.
+    pub fn count(&self, pat: &[u8]) -> usize {
+        self.shards.iter().map(|idx| idx.count(pat)).sum()
+    }
+}
+
+impl<H: Hasher> FromIterator<CsiIndex<H>> for CsiIndexSet<H> {
+    fn from_iter<I: IntoIterator<Item = CsiIndex<H>>>(iter: I) -> Self {
+        CsiIndexSet { shards: iter.into_iter().collect() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CsiIndexSet;
+    use crate::{CaseFold, CsiConfig, CsiIndex};
+
+    #[test]
+    fn search_tags_matches_with_their_shard() {
+        let texts = [&b"tenant one says hello, then hello again"[..], b"nothing to see", b"hello from tenant three"];
+        let mut set = CsiIndexSet::new();
+        for t in texts {
+            set.add(CsiIndex::build(t));
+        }
+        assert_eq!((set.len(), set.shard(1).map(|s| s.text()), set.shard(3).is_none()), (3, Some(texts[1]), true));
+        assert_eq!(set.search(b"hello"), [(0, 16), (0, 28), (2, 0)]);
+        assert_eq!(set.count(b"hello"), 3);
+        assert_eq!(set.search(b"tenant t"), [(2, 11)]);
+        assert!(set.search(b"absent from all").is_empty());
+        #[cfg(feature = "rayon")]
+        assert_eq!(set.par_search(b"hello"), set.search(b"hello"));
+
+        // each shard answers under its own config
+        let folded = CsiConfig { case_fold: CaseFold::Ascii, ..CsiConfig::default() };
+        let set: CsiIndexSet = [CsiIndex::build(b"Hello"), CsiIndex::build_with_config(b"HELLO", &folded)].into_iter().collect();
+        assert_eq!(set.search(b"hello"), [(1, 0)]);
+        assert!(CsiIndexSet::<crate::PolyHasher>::new().search(b"x").is_empty());
+    }
+}
@@ -0,0 +1,630 @@
+// src/sidecar.rs
+//! Serialized indexes: an "index sidecar" of the tables alone, shipped apart
+//! from its text, or a whole index with text and hash powers.
+//!
+//! Layout, all integers little‐endian: the magic (`CSIS` for a sidecar,
+//! `CSII` for a whole index), a `u32` format version and the hasher's `u32`
+//! `TAG`; then `u64` scalars (the hasher's seed, `k`, stride, syncmer window, posting cap, packing threshold,
+//! text length, text digest), one byte each for the search flags (bit 0 verify, bit 1 short
+//! patterns not scanned, bit 2 wide slots), case folding, newline handling (bit 0
+//! CRLF normalization, bit 1 a line index) and fingerprints, and for a byte map the
+//! 256‐byte table; then the gaps, slice bounds, CRLF positions, duplicate‐slice groups,
+//! document IDs, line breaks and invalidated ranges (start and end pairs) as
+//! length‐prefixed `u64` arrays; and per gap the
+//! table size followed by its keys, the slot width in bits (32 or 64), its
+//! starts and lens at that width (two `u32`s to a word, low first), the
+//! posting width likewise and the postings at it (32‐bit ones after their
+//! count), fingerprints (four `u16`s to a word, low first) and overflow
+//! triples, each length‐prefixed, then the number of packed lists and for
+//! each its key, posting count, length‐prefixed bytes zero‐padded to a word
+//! and skip pairs. A whole index continues with the length‐prefixed text
+//! bytes, zero‐padded to a word, the hash powers, a byte for forced table
+//! sizes and the load factor's `f64` bits (all ones for none).
+//!
+//! Every array thus starts on a word boundary of the file, so a mapped
+//! whole index can be searched in place (`CsiIndex::open_mmap`).
+
+use std::io::{self, Read, Write};
+
+use crate::array::{Array, Offsets, Slots};
+use crate::corpus::Aliases;
+use crate::packed::Packed;
+use crate::hash::window_const;
+use crate::{ByteMap, CaseFold, CsiIndex, FlatIndex, Hasher, MIN_LOAD};
+
+const MAGIC: &[u8; 4] = b"CSIS";
+const MAGIC_FULL: &[u8; 4] = b"CSII";
+const VERSION: u32 = 11;
+
+/// Largest `k` or syncmer window a header may give for a shorter text: no
+/// tier picks either past a few dozen, and both size allocations
+const MAX_SPAN: usize = 1 << 16;
+
+/// Where `read_format` takes the tables and text from: the scalars and
+/// bookkeeping are always read, the big arrays may be left in place
+pub(crate) trait Source: Read {
+    /// A `put_words` array.
+    fn words(&mut self) -> io::Result<Array<u64>>;
+    /// A `put_words` array of sizes.
+    fn sizes(&mut self) -> io::Result<Array<usize>>;
+    /// `len` `u16`s packed four to a word, as a `put_words` array.
+    fn packed(&mut self, len: usize) -> io::Result<Array<u16>>;
+    /// `len` `u32`s packed two to a word, as a `put_words` array.
+    fn halves(&mut self, len: usize) -> io::Result<Array<u32>>;
+    /// `len` bytes and their padding to a word.
+    fn text(&mut self, len: usize) -> io::Result<Array<u8>>;
+}
+
+/// A reader whose arrays are copied onto the heap
+pub(crate) struct Copied<R>(pub(crate) R);
+
+impl<R: Read> Read for Copied<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl<R: Read> Source for Copied<R> {
+    fn words(&mut self) -> io::Result<Array<u64>> {
+        get_words(self).map(Array::from)
+    }
+
+    fn sizes(&mut self) -> io::Result<Array<usize>> {
+        get_sizes(self).map(Array::from)
+    }
+
+    fn packed(&mut self, len: usize) -> io::Result<Array<u16>> {
+        let packed = get_words(self)?;
+        if packed.len() != len.div_ceil(4) {
+            return Err(bad("fingerprints don't cover the postings"));
+        }
+        Ok(packed.iter().flat_map(|&w| (0..4).map(move |i| (w >> (16 * i)) as u16)).take(len).collect())
+    }
+
+    fn halves(&mut self, len: usize) -> io::Result<Array<u32>> {
+        let packed = get_words(self)?;
+        if packed.len() != len.div_ceil(2) {
+            return Err(bad("slot array doesn't cover the table"));
+        }
+        Ok(packed.iter().flat_map(|&w| [w as u32, (w >> 32) as u32]).take(len).collect())
+    }
+
+    fn text(&mut self, len: usize) -> io::Result<Array<u8>> {
+        let mut text = Vec::with_capacity(len.min(1 << 20));
+        self.by_ref().take(len as u64).read_to_end(&mut text)?;
+        if text.len() != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.read_exact(&mut [0u8; 7][..padding(len)])?;
+        Ok(text.into())
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Write every gap table and the settings needed to search with them,
+    /// but not the text, hash powers or mask.
+    ///
+    /// A sidecar is only usable against the exact text it was built over:
+    /// `read_sidecar` yields an index that answers `search_with_text`, which
+    /// checks the supplied text's length and hash before using the tables.
+    /// Masked indexes can't be written (their search needs the stored
+    /// text) and fail with `InvalidInput`.
+    pub fn write_sidecar<W: Write>(&self, w: W) -> io::Result<()> {
+        self.write_format(w, false)
+    }
+
+    /// Write the whole index: everything `write_sidecar` does plus the text
+    /// and hash powers, so `read_index` can restore it without hashing
+    /// anything. Masked indexes fail with `InvalidInput`, as there.
+    pub fn write_index<W: Write>(&self, w: W) -> io::Result<()> {
+        self.write_format(w, true)
+    }
+
+    fn write_format<W: Write>(&self, mut w: W, full: bool) -> io::Result<()> {
+        if self.mask.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "masked indexes have no serialized form"));
+        }
+        self.write_head(&mut w, full)?;
+        for fi in &self.flat {
+            put_table_head(&mut w, fi)?;
+            match &fi.offs {
+                Offsets::Narrow(offs) => {
+                    put(&mut w, 32)?;
+                    put(&mut w, offs.len() as u64)?;
+                    put_halves(&mut w, offs.iter().copied(), offs.len())?;
+                }
+                Offsets::Wide(offs) => {
+                    put(&mut w, 64)?;
+                    put_words(&mut w, offs.iter().map(|&x| x as u64), offs.len())?;
+                }
+            }
+            let packed = fi.fps.chunks(4).map(|c| c.iter().rev().fold(0, |w, &f| w << 16 | f as u64));
+            put_words(&mut w, packed, fi.fps.len().div_ceil(4))?;
+            put_table_tail(&mut w, fi)?;
+        }
+        if full {
+            self.write_tail(&mut w, &self.text, self.pw.iter().copied(), self.pw.len())?;
+        }
+        w.flush()
+    }
+
+    /// Everything before the gap tables: magic, scalars and bookkeeping.
+    pub(crate) fn write_head<W: Write>(&self, w: &mut W, full: bool) -> io::Result<()> {
+        w.write_all(if full { MAGIC_FULL } else { MAGIC })?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&H::TAG.to_le_bytes())?;
+        put(w, self.hasher.seed())?;
+        for x in [self.k, self.stride, self.window, self.cap, self.pack, self.text_len] {
+            put(w, x as u64)?;
+        }
+        put(w, self.digest)?;
+        let fold = match self.fold {
+            CaseFold::None => 0,
+            CaseFold::Unicode => 1,
+            CaseFold::Ascii => 2,
+            CaseFold::Map(_) => 3,
+        };
+        let search = self.verify as u8 | (!self.scan_short as u8) << 1 | (self.wide as u8) << 2;
+        let newlines = self.newlines as u8 | (self.lines.is_some() as u8) << 1;
+        w.write_all(&[search, fold, newlines, self.fps as u8])?;
+        if let CaseFold::Map(map) = &self.fold {
+            w.write_all(map.table())?;
+        }
+        let none = Vec::new();
+        let lines = self.lines.as_ref().unwrap_or(&none);
+        for v in [&self.gaps, &self.bounds, &self.crlf, &self.aliases.starts, &self.aliases.docs, &self.ids, lines] {
+            put_words(w, v.iter().map(|&x| x as u64), v.len())?;
+        }
+        put_words(w, self.dead.iter().flat_map(|r| [r.start as u64, r.end as u64]), 2 * self.dead.len())
+    }
+
+    /// Everything after the gap tables of a whole index: `text`, the
+    /// `len` hash powers `pw`, and the table sizing.
+    pub(crate) fn write_tail<W: Write>(&self, w: &mut W, text: &[u8], pw: impl Iterator<Item = u64>, len: usize) -> io::Result<()> {
+        put(w, text.len() as u64)?;
+        w.write_all(text)?;
+        w.write_all(&[0; 7][..padding(text.len())])?;
+        put_words(w, pw, len)?;
+        w.write_all(&[self.fixed as u8])?;
+        put(w, self.load.map_or(u64::MAX, f64::to_bits))
+    }
+
+    /// Load a sidecar from `write_sidecar`, keyed by `hasher`. The index has
+    /// no text: search it only with `search_with_text`, passing the text it
+    /// was built over (in indexed form, as `text()` returned it).
+    ///
+    /// `InvalidData` if the input isn't a sidecar, was written by another
+    /// hasher (per `Hasher::TAG`) or another format version, or its tables
+    /// are malformed; the structure is checked so that lookups stay in
+    /// bounds, but keys can't be re‐hashed without the text.
+    pub fn read_sidecar<R: Read>(r: R, hasher: H) -> io::Result<Self> {
+        Self::read_format(&mut Copied(r), hasher, false)
+    }
+
+    /// Load an index from `write_index`, keyed by `hasher`, searchable like
+    /// the one written. Nothing is re‐hashed: the structure is checked as in
+    /// `read_sidecar`, and the text only for its length, so run `validate`
+    /// to check it against the tables. `InvalidData` (or `UnexpectedEof` if
+    /// truncated) where `read_sidecar` would fail.
+    pub fn read_index<R: Read>(r: R, hasher: H) -> io::Result<Self> {
+        Self::read_format(&mut Copied(r), hasher, true)
+    }
+
+    pub(crate) fn read_format<S: Source>(r: &mut S, hasher: H, full: bool) -> io::Result<Self> {
+        let mut head = [0u8; 12];
+        r.read_exact(&mut head)?;
+        if &head[..4] != if full { MAGIC_FULL } else { MAGIC } {
+            return Err(bad(if full { "not a serialized index" } else { "not a sidecar" }));
+        }
+        if head[4..8] != VERSION.to_le_bytes() {
+            return Err(bad("unsupported version"));
+        }
+        if head[8..12] != H::TAG.to_le_bytes() {
+            return Err(bad("tables keyed by a different hasher"));
+        }
+        let seed = get(r)?;
+        let hasher = if seed == hasher.seed() {
+            hasher
+        } else {
+            hasher.reseeded(seed).ok_or_else(|| bad("tables keyed under a seed the hasher can't take"))?
+        };
+        let (k, stride, window) = (get_size(r)?, get_size(r)?, get_size(r)?);
+        let (cap, pack) = (get_size(r)?, get_size(r)?);
+        let text_len = get_size(r)?;
+        let digest = get(r)?;
+        let mut flags = [0u8; 4];
+        r.read_exact(&mut flags)?;
+        if flags[0] > 7 {
+            return Err(bad("unknown search flags"));
+        }
+        if flags[2] > 3 {
+            return Err(bad("unknown newline handling"));
+        }
+        let fold = match flags[1] {
+            0 => CaseFold::None,
+            1 => CaseFold::Unicode,
+            2 => CaseFold::Ascii,
+            3 => {
+                let mut table = [0u8; 256];
+                r.read_exact(&mut table)?;
+                CaseFold::Map(ByteMap::checked(table).ok_or_else(|| bad("byte map isn't idempotent"))?)
+            }
+            _ => return Err(bad("unknown case folding")),
+        };
+        let (gaps, bounds, crlf) = (get_sizes(r)?, get_sizes(r)?, get_sizes(r)?);
+        if !header_fits(k, stride, window, text_len, &gaps) {
+            return Err(bad("malformed header"));
+        }
+        let aliases = Aliases { starts: get_sizes(r)?, docs: get_sizes(r)? };
+        let ids = get_sizes(r)?;
+        let lines = get_sizes(r)?;
+        let lines = (flags[2] & 2 != 0).then_some(lines);
+        let dead = get_sizes(r)?;
+        if dead.len() % 2 != 0 {
+            return Err(bad("truncated invalidated ranges"));
+        }
+        let dead: Vec<_> = dead.chunks(2).map(|r| r[0]..r[1]).collect();
+        let mut flat = Vec::with_capacity(gaps.len());
+        for _ in &gaps {
+            let table_size = get_size(r)?;
+            let keys = r.words()?;
+            let (starts, lens) = match get(r)? {
+                32 => (Slots::Narrow(r.halves(table_size)?), Slots::Narrow(r.halves(table_size)?)),
+                64 => (Slots::Wide(r.sizes()?), Slots::Wide(r.sizes()?)),
+                _ => return Err(bad("unknown slot width")),
+            };
+            let offs = match get(r)? {
+                32 => Offsets::Narrow(get_size(r).and_then(|len| r.halves(len))?),
+                64 => Offsets::Wide(r.sizes()?),
+                _ => return Err(bad("unknown posting width")),
+            };
+            let fps = r.packed(if flags[3] != 0 { offs.len() } else { 0 })?;
+            let triples = get_words(r)?;
+            if triples.len() % 3 != 0 {
+                return Err(bad("truncated overflow table"));
+            }
+            let overflow = triples.chunks(3)
+                .map(|t| Ok((t[0], size(t[1])?, size(t[2])?)))
+                .collect::<io::Result<_>>()?;
+            let mut packed = Vec::new();
+            for _ in 0..get(r)? {
+                let (key, len) = (get(r)?, get_size(r)?);
+                let bytes = get_size(r).and_then(|n| r.text(n))?;
+                packed.push((key, Packed { len, bytes, skips: r.sizes()? }));
+            }
+            flat.push(FlatIndex { table_size, keys, starts, lens, offs, fps, overflow, packed });
+        }
+        // what `locate` and `search_docs` index with unchecked assumptions
+        let ascending = |v: &[usize]| v.windows(2).all(|w| w[0] <= w[1]) && v.last().is_none_or(|&x| x <= text_len);
+        let groups = &aliases.starts;
+        if window > 1 && stride > 1 || !ascending(&bounds) || !ascending(&crlf) || lines.as_deref().is_some_and(|l| !ascending(l))
+            || !(groups.is_empty() || groups.len() == bounds.len() + 1 && groups[0] == 0
+                && groups.windows(2).all(|w| w[0] < w[1]) && groups[bounds.len()] == aliases.docs.len())
+            || !(ids.is_empty() || ids.len() >= aliases.docs.iter().max().map_or(bounds.len(), |&d| d + 1))
+            || !dead.iter().all(|r| r.start < r.end && r.end <= text_len)
+            || !dead.windows(2).all(|w| w[0].end < w[1].start) {
+            return Err(bad("malformed slice bookkeeping"));
+        }
+        let (text, pw, fixed, load) = if full {
+            let len = get_size(r)?;
+            let text = r.text(len)?;
+            let pw = get_words(r)?;
+            let mut fixed = [0u8; 1];
+            r.read_exact(&mut fixed)?;
+            let load = Some(f64::from_bits(get(r)?)).filter(|l| !l.is_nan());
+            if text.len() != text_len {
+                return Err(bad("text length differs from the tables'"));
+            }
+            if load.is_some_and(|l| l > 0.0 && l < MIN_LOAD) {
+                return Err(bad("load factor below the floor"));
+            }
+            (text, pw, fixed[0] != 0, load)
+        } else {
+            let mut pw = Vec::new();
+            pw.try_reserve_exact(k + 1).map_err(|_| bad("hash powers too large"))?;
+            pw.extend((0..=k).map(|len| window_const(&hasher, len)));
+            (Array::default(), pw, true, None)
+        };
+        let idx = CsiIndex {
+            k, gaps, flat, text, pw, bounds, aliases, ids, cap, stride, window,
+            verify: flags[0] & 1 != 0, fold, newlines: flags[2] & 1 != 0, crlf, lines, dead, mask: None,
+            digest, text_len, load, fixed, fps: flags[3] != 0, scan_short: flags[0] & 2 == 0,
+            wide: flags[0] & 4 != 0, pack, hasher,
+        };
+        idx.check_tables(text_len, None).map_err(|e| bad(&e.to_string()))?;
+        Ok(idx)
+    }
+}
+
+/// Whether a header's shape can be sized and summed without overflow: `k`
+/// and the window at most `MAX_SPAN` or the text length, gaps strictly
+/// ascending, and the stride, the text and every `k + gap` within
+/// `isize::MAX`, so adding one to an offset can't wrap. A gap may still
+/// exceed the text: the smallest always stays, and `truncate` keeps them all.
+fn header_fits(k: usize, stride: usize, window: usize, text_len: usize, gaps: &[usize]) -> bool {
+    let (most, span) = (text_len.max(MAX_SPAN), |x: usize| x <= isize::MAX as usize);
+    (1..=most).contains(&k) && (1..=most).contains(&window) && stride > 0 && span(stride) && span(text_len)
+        && !gaps.is_empty() && gaps.windows(2).all(|w| w[0] < w[1])
+        && gaps.iter().all(|&d| k.checked_add(d).is_some_and(span))
+}
+
+fn bad(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("sidecar: {what}"))
+}
+
+fn size(x: u64) -> io::Result<usize> {
+    usize::try_from(x).map_err(|_| bad("size overflow"))
+}
+
+/// A gap table up to its postings: size, keys, slot width, starts and lens.
+pub(crate) fn put_table_head<W: Write>(w: &mut W, fi: &FlatIndex) -> io::Result<()> {
+    put(w, fi.table_size as u64)?;
+    put_words(w, fi.keys.iter().copied(), fi.keys.len())?;
+    if let (Slots::Narrow(starts), Slots::Narrow(lens)) = (&fi.starts, &fi.lens) {
+        put(w, 32)?;
+        for v in [starts, lens] {
+            put_halves(w, v.iter().copied(), v.len())?;
+        }
+    } else {
+        put(w, 64)?;
+        for v in [&fi.starts, &fi.lens] {
+            put_words(w, v.iter().map(|x| x as u64), v.len())?;
+        }
+    }
+    Ok(())
+}
+
+/// A gap table past its fingerprints: overflow triples and packed lists.
+pub(crate) fn put_table_tail<W: Write>(w: &mut W, fi: &FlatIndex) -> io::Result<()> {
+    let triples = fi.overflow.iter().flat_map(|&(key, start, len)| [key, start as u64, len as u64]);
+    put_words(w, triples, 3 * fi.overflow.len())?;
+    put(w, fi.packed.len() as u64)?;
+    for (key, list) in &fi.packed {
+        for x in [*key, list.len as u64, list.bytes.len() as u64] {
+            put(w, x)?;
+        }
+        w.write_all(&list.bytes)?;
+        w.write_all(&[0; 7][..padding(list.bytes.len())])?;
+        put_words(w, list.skips.iter().map(|&x| x as u64), list.skips.len())?;
+    }
+    Ok(())
+}
+
+/// Zero bytes that pad `len` bytes to a word.
+pub(crate) fn padding(len: usize) -> usize {
+    len.next_multiple_of(8) - len
+}
+
+pub(crate) fn put<W: Write>(w: &mut W, x: u64) -> io::Result<()> {
+    w.write_all(&x.to_le_bytes())
+}
+
+pub(crate) fn get<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut b = [0u8; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
+
+/// `len`, then the `len` words of `words`, a block at a time.
+pub(crate) fn put_words<W: Write>(w: &mut W, words: impl Iterator<Item = u64>, len: usize) -> io::Result<()> {
+    put(w, len as u64)?;
+    let mut buf = Vec::with_capacity(8 * 1024);
+    for x in words {
+        buf.extend_from_slice(&x.to_le_bytes());
+        if buf.len() == buf.capacity() {
+            w.write_all(&buf)?;
+            buf.clear();
+        }
+    }
+    w.write_all(&buf)
+}
+
+/// The `len` values of `v` two to a word, low first, as a `put_words` array.
+pub(crate) fn put_halves<W: Write>(w: &mut W, v: impl Iterator<Item = u32>, len: usize) -> io::Result<()> {
+    let mut v = v.fuse();
+    let words = core::iter::from_fn(|| Some(v.next()? as u64 | (v.next().unwrap_or(0) as u64) << 32));
+    put_words(w, words, len.div_ceil(2))
+}
+
+/// A `put_words` array. Grows as words arrive, so a corrupt length runs
+/// out of input instead of reserving memory up front.
+fn get_words<R: Read>(r: &mut R) -> io::Result<Vec<u64>> {
+    let len = get(r)?;
+    let mut v = Vec::with_capacity(len.min(1 << 16) as usize);
+    for _ in 0..len {
+        v.push(get(r)?);
+    }
+    Ok(v)
+}
+
+fn get_size<R: Read>(r: &mut R) -> io::Result<usize> {
+    size(get(r)?)
+}
+
+fn get_sizes<R: Read>(r: &mut R) -> io::Result<Vec<usize>> {
+    get_words(r)?.into_iter().map(size).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::array::{Offsets, Slots};
+    use crate::{CsiConfig, CsiIndex, CsiIndexBuilder, CsiIndexError, EntropyTiers, PolyHasher};
+
+    #[test]
+    fn sidecar_round_trips_with_external_text() {
+        let text = b"ship the tables here and the text there; ship the tables twice".repeat(20);
+        let built = CsiIndex::build_with_config(&text, &CsiConfig { stride: 2, max_postings_per_key: Some(30), ..CsiConfig::default() });
+        let mut bytes = Vec::new();
+        built.write_sidecar(&mut bytes).unwrap();
+        assert!(bytes.len() < built.heap_bytes());
+
+        let side = CsiIndex::read_sidecar(&bytes[..], PolyHasher).unwrap();
+        assert!(matches!(side.flat[0].lens, Slots::Narrow(_)));
+        for pat in [&text[..30], &text[40..100], b"ship the tables twice", b"not shipped anywhere at all"] {
+            assert_eq!(side.search_with_text(pat, &text), Ok(built.search(pat)));
+        }
+        let mut other = text.clone();
+        other[5] ^= 1;
+        assert_eq!(side.search_with_text(&text[..30], &other), Err(CsiIndexError::TextMismatch));
+        assert_eq!(side.search_with_text(&text[..30], &text[1..]), Err(CsiIndexError::TextMismatch));
+
+        let kind = |b: &[u8]| CsiIndex::read_sidecar(b, PolyHasher).err().map(|e| e.kind());
+        assert_eq!(kind(&bytes[..bytes.len() - 1]), Some(io::ErrorKind::UnexpectedEof));
+        let mut tagged = bytes.clone();
+        tagged[8] ^= 1;
+        assert_eq!(kind(&tagged), Some(io::ErrorKind::InvalidData));
+        let mut corrupt = bytes.clone();
+        let at = bytes.len() - 32; // the last postings' word; no fingerprints, overflow or packed lists follow
+        corrupt[at..at + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(kind(&corrupt), Some(io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn forced_table_sizes_serialize_identically() {
+        let text = b"golden files want the same bytes from every build of the same text".repeat(12);
+        let auto = CsiIndex::build(&text);
+        let sizes: Vec<usize> = auto.flat.iter().map(|fi| 4 * fi.table_size).collect();
+        let sidecar = |idx: &CsiIndex| {
+            let mut bytes = Vec::new();
+            idx.write_sidecar(&mut bytes).unwrap();
+            bytes
+        };
+        let a = CsiIndexBuilder::new().table_sizes(sizes.clone()).build(&text);
+        let b = CsiIndexBuilder::new().table_sizes(sizes.clone()).threads(4).try_build(&text).unwrap();
+        assert_eq!(a.flat.iter().map(|fi| fi.table_size).collect::<Vec<_>>(), sizes);
+        assert_eq!(sidecar(&a), sidecar(&b));
+        assert_ne!(sidecar(&a), sidecar(&auto));
+        assert_eq!(a.search(&text[3..50]), auto.search(&text[3..50]));
+
+        let mut small = sizes.clone();
+        small[0] = auto.flat[0].table_size / 4;
+        let min = (text.len() + 1 - auto.k - auto.gaps[0]).next_power_of_two();
+        let gap = auto.gaps[0];
+        assert_eq!(CsiIndexBuilder::new().table_sizes(small).try_build(&text).err(), Some(CsiIndexError::TableSize { gap, min }));
+        let mut odd = sizes.clone();
+        odd[0] += 1;
+        assert!(CsiIndexBuilder::new().table_sizes(odd).try_build(&text).is_err());
+        assert!(CsiIndexBuilder::new().table_sizes(sizes[..1].to_vec()).try_build(&text).is_err());
+    }
+
+    #[test]
+    fn whole_index_round_trips_without_rehashing() {
+        let docs: [&[u8]; 3] = [b"first document, first words here", b"second one differs a little", b"first document, first words here"];
+        let config = CsiConfig {
+            dedup: true, keep_powers: Some(true), load_factor: Some(0.6), fingerprints: true, wide_slots: true,
+            ..CsiConfig::default()
+        };
+        let built = CsiIndex::build_from_slices(&docs, &config);
+        let mut bytes = Vec::new();
+        built.write_index(&mut bytes).unwrap();
+        let mut loaded = CsiIndex::read_index(&bytes[..], PolyHasher).unwrap();
+        assert_eq!((loaded.text(), loaded.powers()), (built.text(), built.powers()));
+        assert_eq!(loaded.search_docs(b"first document, first words"), built.search_docs(b"first document, first words"));
+        assert_eq!(loaded.validate(), Ok(()));
+        let fps = |idx: &CsiIndex| idx.flat.iter().map(|fi| fi.fps.clone()).collect::<Vec<_>>();
+        assert!(fps(&loaded) == fps(&built) && !built.flat[0].fps.is_empty());
+        loaded.compact();
+        assert_eq!(loaded.occupancy_report(), built.occupancy_report());
+        assert_eq!(fps(&loaded), fps(&built));
+        assert!(matches!(loaded.flat[0].lens, Slots::Wide(_)));
+
+        assert!(CsiIndex::read_sidecar(&bytes[..], PolyHasher).is_err());
+        let kind = |b: &[u8]| CsiIndex::read_index(b, PolyHasher).err().map(|e| e.kind());
+        assert_eq!(kind(&bytes[..bytes.len() - 9]), Some(io::ErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn narrow_tables_search_like_wide_ones_and_round_trip() {
+        let text: Vec<u8> = (0..900).flat_map(|i| format!("row {} of {} says {}; ", i % 97, i % 13, i * 31 % 211).into_bytes()).collect();
+        let builders = [
+            CsiIndexBuilder::new(),
+            CsiIndexBuilder::new().stride(2).fingerprints(true).max_postings_per_key(200),
+            CsiIndexBuilder::new().pack_postings(30).syncmer_window(4),
+        ];
+        let pats: Vec<&[u8]> = vec![&text[..40], &text[1000..1030], &text[5000..5200], b"row 5 of 5 says", b"says 7; row", b"not in the rows at all"];
+        for b in builders {
+            let narrow = b.clone().build(&text);
+            let wide = b.wide_slots(true).build(&text);
+            assert!(narrow.flat.iter().all(|fi| matches!((&fi.lens, &fi.offs), (Slots::Narrow(_), Offsets::Narrow(_)))));
+            assert!(wide.flat.iter().all(|fi| matches!((&fi.lens, &fi.offs), (Slots::Wide(_), Offsets::Wide(_)))));
+            assert!(narrow.mem_usage().postings < wide.mem_usage().postings);
+            for &pat in &pats {
+                assert_eq!(narrow.search(pat), wide.search(pat));
+                assert_eq!(narrow.search_unverified(pat), wide.search_unverified(pat));
+                assert_eq!(narrow.count(pat), wide.count(pat));
+                assert_eq!(narrow.contains(pat), wide.contains(pat));
+                assert_eq!(narrow.search_in(pat, 3000..9000), wide.search_in(pat, 3000..9000));
+                assert_eq!(narrow.search_iter(pat).collect::<Vec<_>>(), wide.search(pat));
+                assert_eq!(narrow.posting_list(pat, 0), wide.posting_list(pat, 0));
+            }
+
+            // both widths read back as written, from a sidecar or a whole index
+            for idx in [&narrow, &wide] {
+                let (mut side, mut whole) = (Vec::new(), Vec::new());
+                idx.write_sidecar(&mut side).unwrap();
+                idx.write_index(&mut whole).unwrap();
+                let side = CsiIndex::read_sidecar(&side[..], PolyHasher).unwrap();
+                let whole = CsiIndex::read_index(&whole[..], PolyHasher).unwrap();
+                for back in [&side, &whole] {
+                    assert!(back.flat.iter().zip(&idx.flat).all(|(a, b)| (&a.starts, &a.lens, &a.offs) == (&b.starts, &b.lens, &b.offs)));
+                }
+                assert_eq!(whole.validate(), Ok(()));
+                for &pat in &pats {
+                    assert_eq!(side.search_with_text(pat, &text), Ok(wide.search(pat)));
+                    assert_eq!(whole.search(pat), wide.search(pat));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn corrupt_headers_fail_to_read() {
+        let text = b"headers from a hostile file; headers from a hostile file".repeat(8);
+        let idx = CsiIndexBuilder::new().tiers(EntropyTiers::fixed(4, vec![5, 9])).build(&text);
+        let (mut side, mut whole) = (Vec::new(), Vec::new());
+        idx.write_sidecar(&mut side).unwrap();
+        idx.write_index(&mut whole).unwrap();
+        // `u64` fields: k at 20, stride 28, window 36, text length 60, the
+        // gap count 80 and the gaps from 88
+        let field = |at: usize| u64::from_le_bytes(side[at..at + 8].try_into().unwrap());
+        assert_eq!([20, 28, 36, 60, 80, 88, 96].map(field), [4, 1, 1, text.len() as u64, 2, 5, 9]);
+        let huge = u64::MAX / 2;
+        let patches: [&[(usize, u64)]; 12] = [
+            &[(20, huge)], &[(20, 0)], &[(20, 1 << 20)],
+            &[(28, 0)], &[(28, u64::MAX)], &[(36, huge)],
+            &[(60, u64::MAX)],
+            // a gap that overflows `k + gap`, and one past the text that
+            // still has postings
+            &[(88, u64::MAX - 2)], &[(96, huge)], &[(96, 1 << 40)],
+            // unsorted and repeated gaps
+            &[(88, 9), (96, 5)], &[(96, 5)],
+        ];
+        for (bytes, full) in [(&side, false), (&whole, true)] {
+            for patch in patches {
+                let mut bad = bytes.clone();
+                for &(at, v) in patch {
+                    bad[at..at + 8].copy_from_slice(&v.to_le_bytes());
+                }
+                let read = if full { CsiIndex::read_index(&bad[..], PolyHasher) } else { CsiIndex::read_sidecar(&bad[..], PolyHasher) };
+                assert_eq!(read.err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData), "{patch:?}");
+            }
+        }
+
+        // a gap past the text on its own is what `truncate` leaves, and reads
+        let mut cut = idx;
+        cut.truncate(7);
+        let mut bytes = Vec::new();
+        cut.write_index(&mut bytes).unwrap();
+        assert_eq!(CsiIndex::read_index(&bytes[..], PolyHasher).unwrap().gaps(), [5, 9]);
+
+        // nor may a load factor that `try_build` would have refused
+        let loaded = CsiIndexBuilder::new().load_factor(0.5).build(&text);
+        let mut bytes = Vec::new();
+        loaded.write_index(&mut bytes).unwrap();
+        let at = bytes.windows(8).rposition(|w| w == 0.5f64.to_bits().to_le_bytes()).unwrap();
+        bytes[at..at + 8].copy_from_slice(&1e-300f64.to_bits().to_le_bytes());
+        assert_eq!(CsiIndex::read_index(&bytes[..], PolyHasher).err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+    }
+}
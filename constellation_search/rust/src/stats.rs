@@ -0,0 +1,882 @@
+// src/stats.rs
+//! Introspection over the built tables, for capacity planning.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+use crate::hash::{key_at, prefix_hash};
+use crate::packed::Packed;
+use crate::{periodic, verify, CsiIndex, FlatIndex, Hasher, Probe, SATURATED};
+
+/// Crate‐level totals across every gap table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexSummary {
+    pub text_len: usize,
+    pub total_distinct_constellations: usize,
+    pub total_postings: usize,
+    /// `(gap, distinct constellations, postings)` in gap order
+    pub per_gap: Vec<(usize, usize, usize)>,
+}
+
+/// Hash‐table health of one gap's bucket table
+#[derive(Debug, Clone, PartialEq)]
+pub struct GapOccupancy {
+    pub gap:            usize,
+    pub table_size:     usize,
+    pub occupied_slots: usize,
+    /// `occupied_slots / table_size`
+    pub load_factor:    f64,
+    /// Longest distance, in slots, from a key's home slot to where linear
+    /// probing placed it (0 = every key sits in its home slot)
+    pub max_probe:      usize,
+    /// Keys that found no free slot within the probe limit and went to the
+    /// table's sorted overflow area instead (normally 0)
+    pub overflowed:     usize,
+}
+
+/// Shape of one gap table, from `CsiIndex::stats`
+#[derive(Debug, Clone, PartialEq)]
+pub struct GapStats {
+    pub gap:               usize,
+    pub table_size:        usize,
+    /// Occupied slots over `table_size`
+    pub load_factor:       f64,
+    /// As `GapOccupancy::max_probe`
+    pub max_probe:         usize,
+    /// Postings of the longest bucket still holding them; a bucket near
+    /// the text's length marks a constellation too common to filter on
+    pub largest_bucket:    usize,
+    /// Buckets whose postings were dropped at build (see
+    /// `CsiConfig::max_postings_per_key`)
+    pub saturated_buckets: usize,
+    pub postings:          usize,
+    /// Heap bytes of the table's slots, postings, fingerprints and overflow
+    pub heap_bytes:        usize,
+}
+
+/// Per‐gap table shapes plus totals, from `CsiIndex::stats`
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexStats {
+    /// In gap order
+    pub per_gap:        Vec<GapStats>,
+    pub total_postings: usize,
+    /// As `CsiIndex::heap_bytes`, text and bookkeeping included
+    pub heap_bytes:     usize,
+}
+
+/// Outcome of looking one pattern constellation up in its gap table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GapLookup {
+    /// Not indexed: no occurrence of the pattern starts at this phase
+    Missing,
+    /// Indexed, but with postings dropped at build (see
+    /// `CsiConfig::max_postings_per_key`); adds no filtering
+    Saturated,
+    /// Indexed, with this many postings
+    Postings(usize),
+}
+
+impl From<&Probe<'_>> for GapLookup {
+    fn from(probe: &Probe<'_>) -> Self {
+        match probe {
+            Probe::Missing => GapLookup::Missing,
+            Probe::Saturated => GapLookup::Saturated,
+            Probe::List(l) => GapLookup::Postings(l.len()),
+            Probe::Packed(l) => GapLookup::Postings(l.len),
+        }
+    }
+}
+
+/// One constellation lookup of a pattern, from `CsiIndex::explain_miss`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GapProbe {
+    /// Pattern offset of the constellation (always 0 unless strided, or the
+    /// pattern's first syncmer)
+    pub phase:  usize,
+    pub gap:    usize,
+    pub lookup: GapLookup,
+}
+
+/// Every constellation lookup a search of one pattern makes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissReport {
+    /// By phase, then gap; unlike `search`, lookups carry on past a
+    /// missing constellation
+    pub probes: Vec<GapProbe>,
+    /// Per phase, the first gap whose constellation is missing, where
+    /// `search` gives up on that phase, or `None` if all are present.
+    /// Empty for patterns shorter than `min_pattern_len`.
+    pub breaking_gaps: Vec<Option<usize>>,
+}
+
+/// How one search narrowed its candidates, from `CsiIndex::search_verbose`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SearchDiag {
+    /// Postings of the pattern's constellation per gap, in gap order and
+    /// summed over phases; 0 for a gap that is missing, saturated or wider
+    /// than the pattern
+    pub per_gap_candidates: Vec<usize>,
+    /// Candidates left after intersecting (every start position when the
+    /// search falls back to scanning)
+    pub intersected: usize,
+    /// Candidates that passed verification, i.e. the matches
+    pub verified: usize,
+    /// `intersected - verified`: constellation hits whose bytes differ
+    pub rejected_by_verify: usize,
+}
+
+/// How a search was answered, in `QueryMetrics`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum QueryRoute {
+    /// Posting lists intersected into candidates
+    #[default]
+    Tables,
+    /// Every position verified: the pattern is shorter than
+    /// `min_pattern_len` or all its constellations are saturated
+    Scan,
+    /// A periodic pattern, found from its period's runs (see `search`)
+    Periodic,
+    /// Answered without candidates: an empty pattern, one as long as the
+    /// text, or a masked index
+    Direct,
+}
+
+/// What one search did and how long each stage took, from
+/// `CsiIndex::search_with_metrics`; the times are all zero without the
+/// `std` feature, which has the clock
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct QueryMetrics {
+    pub route:       QueryRoute,
+    /// Bytes of the pattern after folding and newline normalization
+    pub pattern_len: usize,
+    /// Phases whose every looked‐up constellation was present
+    pub phases:      usize,
+    /// Posting lists intersected, over those phases
+    pub lists:       usize,
+    /// Postings in those lists
+    pub postings:    usize,
+    /// Offsets verified against the text (0 on `Periodic` and `Direct`)
+    pub candidates:  usize,
+    /// Matches returned
+    pub matches:     usize,
+    /// Hashing the pattern and probing the gap tables
+    pub lookup:      Duration,
+    /// Intersecting the posting lists
+    pub intersect:   Duration,
+    /// Verifying candidates, or answering on the other routes
+    pub verify:      Duration,
+    /// The whole search, normalization included
+    pub total:       Duration,
+}
+
+impl QueryMetrics {
+    /// Candidates verification turned down: offsets whose constellations
+    /// matched while other bytes, or by hash collision the anchors, differ.
+    /// Many of them make a pattern worth a look (or a wider gap).
+    pub fn false_candidates(&self) -> usize {
+        self.candidates.saturating_sub(self.matches)
+    }
+}
+
+/// Heap bytes held by each part of an index, from `CsiIndex::mem_usage`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemUsage {
+    /// The stored text, as indexed (folded, normalized or masked)
+    pub text:        usize,
+    /// Hash powers: `B^0..=B^k`, or one per text byte if kept
+    pub powers:      usize,
+    /// Every gap table's slot arrays (keys, bucket starts and lengths), 16
+    /// bytes per slot (24 with `CsiConfig::wide_slots` or 4 Gi postings);
+    /// table sizing leaves a share of them empty
+    pub slots:       usize,
+    /// Postings, 4 bytes each (8 with `CsiConfig::wide_slots` or a text of
+    /// 4 GiB or more), plus their fingerprints if kept
+    pub postings:    usize,
+    /// Overflow tables
+    pub overflow:    usize,
+    /// CRLF positions, slice bounds, duplicate‐slice groups and document IDs
+    pub bookkeeping: usize,
+}
+
+impl MemUsage {
+    /// Sum of every component: `CsiIndex::heap_bytes`.
+    pub fn total(&self) -> usize {
+        self.text + self.powers + self.slots + self.postings + self.overflow + self.bookkeeping
+    }
+}
+
+/// Stands in for `std::time::Instant` without `std`: every phase takes no
+/// time
+#[cfg(not(feature = "std"))]
+#[derive(Clone, Copy)]
+pub(crate) struct Instant;
+
+#[cfg(not(feature = "std"))]
+impl Instant {
+    pub(crate) fn now() -> Self {
+        Instant
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+/// Wall time of each build phase, from `CsiIndex::build_timed`; all zero
+/// without the `std` feature, which has the clock
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BuildTimings {
+    /// Entropy measurement and tier/gap selection
+    pub entropy:     Duration,
+    /// Prefix hashes and hash powers over the text
+    pub prefix_hash: Duration,
+    /// One table build per gap, in gap order
+    pub per_gap:     Vec<Duration>,
+}
+
+/// Where a pattern's matches fall in the text; all zero when there are none
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MatchStats {
+    pub count: usize,
+    pub first: usize,
+    pub last:  usize,
+    /// `last - first`
+    pub span:  usize,
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Aggregate constellation and posting counts over all gap tables.
+    pub fn index_summary(&self) -> IndexSummary {
+        let per_gap: Vec<(usize, usize, usize)> = self.gaps.iter()
+            .zip(&self.flat)
+            .map(|(&d, fi)| (d, fi.entries().count(), fi.offs.len() + fi.packed.iter().map(|(_, l)| l.len).sum::<usize>()))
+            .collect();
+        IndexSummary {
+            text_len: self.text.len(),
+            total_distinct_constellations: per_gap.iter().map(|g| g.1).sum(),
+            total_postings: per_gap.iter().map(|g| g.2).sum(),
+            per_gap,
+        }
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Heap bytes held by the index: text, hash powers, newline and slice
+    /// bookkeeping, and every gap table with its fingerprints.
+    pub fn heap_bytes(&self) -> usize {
+        self.mem_usage().total()
+    }
+
+    /// `heap_bytes` by component, counting allocated capacity, to weigh
+    /// the tables against the text copy.
+    pub fn mem_usage(&self) -> MemUsage {
+        fn words(v: &Vec<usize>) -> usize {
+            v.capacity() * 8
+        }
+        let tables = |f: fn([usize; 3]) -> usize| self.flat.iter().map(|fi| f(fi.heap_parts())).sum();
+        MemUsage {
+            text:        self.text.capacity(),
+            powers:      self.pw.capacity() * 8,
+            slots:       tables(|p| p[0]),
+            postings:    tables(|p| p[1]),
+            overflow:    tables(|p| p[2]),
+            bookkeeping: words(&self.crlf) + self.lines.as_ref().map_or(0, words) + words(&self.bounds) + words(&self.ids) + self.aliases.heap_bytes()
+                + self.dead.capacity() * 16,
+        }
+    }
+
+    /// Occupancy and worst probe distance of every gap table, in gap order,
+    /// from one walk over each table's keys.
+    pub fn occupancy_report(&self) -> Vec<GapOccupancy> {
+        self.gaps.iter().zip(&self.flat).map(|(&gap, fi)| {
+            let ts = fi.table_size;
+            let (mut occupied_slots, mut max_probe) = (0, 0);
+            for (slot, &key) in fi.keys.iter().enumerate() {
+                if key != 0 {
+                    occupied_slots += 1;
+                    max_probe = max_probe.max((slot + ts - fi.home(key)) % ts);
+                }
+            }
+            GapOccupancy {
+                gap,
+                table_size: fi.table_size,
+                occupied_slots,
+                load_factor: occupied_slots as f64 / fi.table_size as f64,
+                max_probe,
+                overflowed: fi.overflow.len(),
+            }
+        }).collect()
+    }
+
+    /// Every gap table's size, load, worst probe, largest and saturated
+    /// buckets, postings and heap bytes, for tuning `k` and the gaps and
+    /// spotting inputs that defeat the filter. Walks every table once.
+    pub fn stats(&self) -> IndexStats {
+        let summary = self.index_summary();
+        let per_gap: Vec<GapStats> = self.occupancy_report().into_iter().zip(&self.flat).zip(&summary.per_gap)
+            .map(|((occ, fi), &(_, _, postings))| {
+                let lens = fi.entries().map(|(key, _, len)| match fi.packed_list(key) {
+                    Some(list) if len == SATURATED => list.len,
+                    _ => len,
+                });
+                let (mut largest_bucket, mut saturated_buckets) = (0, 0);
+                for len in lens {
+                    if len == SATURATED {
+                        saturated_buckets += 1;
+                    } else {
+                        largest_bucket = largest_bucket.max(len);
+                    }
+                }
+                GapStats {
+                    gap: occ.gap,
+                    table_size: occ.table_size,
+                    load_factor: occ.load_factor,
+                    max_probe: occ.max_probe,
+                    largest_bucket,
+                    saturated_buckets,
+                    postings,
+                    heap_bytes: fi.heap_parts().iter().sum(),
+                }
+            })
+            .collect();
+        IndexStats { per_gap, total_postings: summary.total_postings, heap_bytes: self.heap_bytes() }
+    }
+}
+
+impl FlatIndex {
+    /// Heap bytes of the slot arrays, the postings with their fingerprints
+    /// and packed lists, and the overflow table.
+    fn heap_parts(&self) -> [usize; 3] {
+        [
+            self.keys.capacity() * 8 + self.starts.heap_bytes() + self.lens.heap_bytes(),
+            self.offs.heap_bytes() + self.fps.capacity() * 2
+                + self.packed.iter().map(|(_, l)| l.heap_bytes()).sum::<usize>() + self.packed.capacity() * size_of::<(u64, Packed)>(),
+            self.overflow.capacity() * 24,
+        ]
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Read every gap table (keys, bucket starts and lengths, postings,
+    /// fingerprints, overflow) and
+    /// the text front to back, returning a wrapping sum of what was read.
+    ///
+    /// Meant for benchmark harnesses, to start timed searches from warm
+    /// caches; the checksum keeps the compiler from eliding the reads and is
+    /// identical across builds of the same text and config. Costs one
+    /// sequential pass over the whole index; no search calls it.
+    pub fn prewarm(&self) -> u64 {
+        let mut sum = 0u64;
+        for fi in &self.flat {
+            sum = fi.keys.iter().fold(sum, |s, &k| s.wrapping_add(k));
+            sum = fi.starts.iter().chain(fi.lens.iter()).chain(fi.offs.all().iter())
+                .fold(sum, |s, x| s.wrapping_add(x as u64));
+            sum = fi.fps.iter().fold(sum, |s, &f| s.wrapping_add(f as u64));
+            sum = fi.overflow.iter().fold(sum, |s, &(key, start, len)| {
+                s.wrapping_add(key).wrapping_add(start as u64).wrapping_add(len as u64)
+            });
+            sum = fi.packed.iter().flat_map(|(_, l)| l.bytes.iter())
+                .fold(sum, |s, &b| s.wrapping_add(b as u64));
+        }
+        self.text.iter().fold(sum, |s, &b| s.wrapping_add(b as u64))
+    }
+}
+
+/// Shape and per‐gap occupancy; the text and postings are left out.
+impl<H: Hasher> fmt::Debug for CsiIndex<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CsiIndex")
+            .field("text_len", &self.text.len())
+            .field("k", &self.k)
+            .field("gaps", &self.gaps)
+            .field("occupancy", &self.occupancy_report())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Run one search and summarize how its matches are distributed.
+    pub fn match_stats(&self, pat: &[u8]) -> MatchStats {
+        let hits = self.search(pat);
+        match (hits.first(), hits.last()) {
+            (Some(&first), Some(&last)) =>
+                MatchStats { count: hits.len(), first, last, span: last - first },
+            _ => MatchStats::default(),
+        }
+    }
+
+    /// Upper bound on the candidates `search(pat)` would verify: the shortest
+    /// usable posting list (summed over phases on a strided index), found
+    /// with one bucket probe per gap and no intersection or verify.
+    ///
+    /// 0 if a constellation is absent; every start position if the pattern
+    /// is too short to index or all its constellations are saturated (those
+    /// searches fall back to scanning), except that short patterns estimate
+    /// 0 on an index built without `CsiConfig::scan_short_patterns`.
+    pub fn estimate_candidates(&self, pat: &[u8]) -> usize {
+        let pat = &*self.normalize(pat);
+        let n = self.text.len();
+        if pat.is_empty() {
+            return n + 1;
+        }
+        match self.phase_lists(pat) {
+            None => (n + 1).saturating_sub(pat.len()),
+            Some(phases) => phases.iter()
+                .map(|(_, lists, packed)| lists.iter().map(|l| l.len()).chain(packed.iter().map(|l| l.len)).min().unwrap_or(0))
+                .sum(),
+        }
+    }
+
+    /// Each lookup `search(pat)` makes while gathering candidates, with
+    /// the first missing gap per phase: why a pattern has no matches, or
+    /// which gaps do the filtering when it has some.
+    ///
+    /// The pattern is normalized as in `search` but not masked. A phase
+    /// whose constellations all miss only means no occurrence starts at
+    /// that phase's offset; the pattern has no candidates once every phase
+    /// has a breaking gap.
+    pub fn explain_miss(&self, pat: &[u8]) -> MissReport {
+        let pat = &*self.normalize(pat);
+        let m = pat.len();
+        let mut report = MissReport { probes: Vec::new(), breaking_gaps: Vec::new() };
+        if m < self.min_pattern_len() {
+            return report;
+        }
+        let (php, pk) = (prefix_hash(&self.hasher, pat), self.pw[self.k]);
+        for phase in self.phases(&php) {
+            let mut breaking = None;
+            for (idx, &gap) in self.gaps.iter().enumerate().filter(|&(_, &d)| phase + d + self.k <= m) {
+                let lookup = GapLookup::from(&self.flat[idx].lookup(key_at(&self.hasher, &php, pk, phase, self.k, gap)));
+                if lookup == GapLookup::Missing && breaking.is_none() {
+                    breaking = Some(gap);
+                }
+                report.probes.push(GapProbe { phase, gap, lookup });
+            }
+            report.breaking_gaps.push(breaking);
+        }
+        report
+    }
+
+    /// `search(pat)` together with what it did: the route it took, the
+    /// lists and postings it intersected, the candidates it verified and
+    /// the time spent at each stage. Takes the same steps as `search`, plus
+    /// the clock readings, so it can stand in for `search` wherever slow or
+    /// collision‐heavy queries should be logged.
+    pub fn search_with_metrics(&self, pat: &[u8]) -> (Vec<usize>, QueryMetrics) {
+        let started = Instant::now();
+        let pat = &*self.normalize(pat);
+        let (n, m) = (self.text.len(), pat.len());
+        let mut mx = QueryMetrics { pattern_len: m, ..QueryMetrics::default() };
+        let p = periodic::pattern_period(pat);
+        let hits = if self.mask.is_some() || m == 0 || m >= n || self.verify && p > 0 && 2 * p <= m {
+            let t = Instant::now();
+            let (route, hits) = match &self.mask {
+                Some(mask) => (QueryRoute::Direct, self.search_masked(pat, mask)),
+                None if m == 0 => (QueryRoute::Direct, (0..=n).collect()),
+                // only offset 0 can fit, as in `search`
+                None if m >= n => {
+                    let whole = pat == &self.text[..] && self.live(0, m);
+                    (QueryRoute::Direct, whole.then_some(0).into_iter().collect())
+                }
+                None => (QueryRoute::Periodic, self.search_periodic(pat, p)),
+            };
+            mx.route = route;
+            mx.verify = t.elapsed();
+            hits
+        } else {
+            let t = Instant::now();
+            let php = prefix_hash(&self.hasher, pat);
+            let phases = self.probe_phases(m, &php, self.plan_cutoff());
+            mx.lookup = t.elapsed();
+            let t = Instant::now();
+            let candidates = phases.map(|mut phases| {
+                mx.phases = phases.len();
+                for (_, lists, packed) in &phases {
+                    mx.lists += lists.len() + packed.len();
+                    mx.postings += lists.iter().map(|l| l.len()).sum::<usize>() + packed.iter().map(|l| l.len).sum::<usize>();
+                }
+                self.intersect_phases(&php, &mut phases, usize::MAX)
+            });
+            mx.intersect = t.elapsed();
+            let t = Instant::now();
+            let keep = |&off: &usize| self.verify_by(off, pat, &verify::same);
+            let hits: Vec<usize> = match candidates {
+                Some(acc) => {
+                    mx.candidates = acc.len();
+                    acc.into_iter().filter(keep).collect()
+                }
+                None => {
+                    mx.route = QueryRoute::Scan;
+                    mx.candidates = n - m + 1;
+                    (0..=n - m).filter(keep).collect()
+                }
+            };
+            mx.verify = t.elapsed();
+            hits
+        };
+        mx.matches = hits.len();
+        mx.total = started.elapsed();
+        (hits, mx)
+    }
+
+    /// `search(pat)` together with counts of what each stage kept. Slower
+    /// than `search`: candidates are always intersected in full (no
+    /// periodic shortcut) and every table is probed again for the counts.
+    ///
+    /// On a masked index only the result is known; `intersected` and
+    /// `verified` both report the match count.
+    pub fn search_verbose(&self, pat: &[u8]) -> (Vec<usize>, SearchDiag) {
+        let mut per_gap_candidates = vec![0; self.gaps.len()];
+        for probe in self.explain_miss(pat).probes {
+            if let (GapLookup::Postings(c), Some(g)) = (probe.lookup, self.gaps.iter().position(|&d| d == probe.gap)) {
+                per_gap_candidates[g] += c;
+            }
+        }
+        let pat = &*self.normalize(pat);
+        let n = self.text.len();
+        let candidates: Vec<usize> = if let Some(mask) = &self.mask {
+            self.search_masked(pat, mask)
+        } else if pat.is_empty() {
+            (0..=n).collect()
+        } else {
+            self.candidates(pat, usize::MAX)
+                .unwrap_or_else(|| (0..=n.saturating_sub(pat.len())).collect())
+        };
+        let intersected = candidates.len();
+        let eq = verify::same;
+        let hits: Vec<usize> = if self.mask.is_some() || pat.is_empty() {
+            candidates
+        } else {
+            candidates.into_iter().filter(|&off| self.verify_by(off, pat, &eq)).collect()
+        };
+        let verified = hits.len();
+        let diag = SearchDiag { per_gap_candidates, intersected, verified, rejected_by_verify: intersected - verified };
+        (hits, diag)
+    }
+
+    /// Modelled chance that some gap table holds two distinct
+    /// constellations under one key, which would let unverified searches
+    /// (`search_unverified`, or `CsiConfig::verify` off) return offsets
+    /// whose bytes differ from the pattern.
+    ///
+    /// Assumes keys are uniform and independent over all 2⁶⁴ values; a
+    /// table with `D` distinct keys then collides with probability about
+    /// `1 - exp(-D(D-1)/2⁶⁵)`, combined over tables. Computed from stored
+    /// counts, not measured. It doesn't cover saturated patterns, whose
+    /// unverified results are every position.
+    /// Needs `std` for `f64::exp_m1`.
+    #[cfg(feature = "std")]
+    pub fn estimated_false_positive_rate(&self) -> f64 {
+        let pairs: f64 = self.flat.iter()
+            .map(|fi| {
+                let d = fi.entries().count() as f64;
+                d * (d - 1.0).max(0.0) / 2.0
+            })
+            .sum();
+        -(-pairs / 2f64.powi(64)).exp_m1()
+    }
+
+    /// `search(pat)`, but only if `estimate_candidates(pat)` is at most
+    /// `max_candidates`; `None` otherwise, having done no intersection or
+    /// verify. The table probes are repeated when the search runs, which is
+    /// cheap next to the work a rejected query avoids.
+    pub fn search_if_selective(&self, pat: &[u8], max_candidates: usize) -> Option<Vec<usize>> {
+        (self.estimate_candidates(pat) <= max_candidates).then(|| self.search(pat))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GapLookup, MatchStats, QueryRoute};
+    use crate::{CsiConfig, CsiIndex, CsiIndexBuilder, CsiIndexError, EntropyTiers};
+
+    #[test]
+    fn estimate_bounds_candidate_count() {
+        let text = b"estimate me before searching; estimate me before searching; estimate me".to_vec();
+        let idx = CsiIndex::build(&text);
+        let pat = b"estimate me before searching";
+        let est = idx.estimate_candidates(pat);
+        assert!(est >= idx.search(pat).len() && est >= 2);
+        assert_eq!(idx.estimate_candidates(b"nowhere in this text at all"), 0);
+        assert_eq!(idx.estimate_candidates(b"est"), text.len() + 1 - 3);
+        assert_eq!(idx.estimate_candidates(b""), text.len() + 1);
+        let capped = CsiIndex::build_with_config(&text, &CsiConfig { max_postings_per_key: Some(0), ..CsiConfig::default() });
+        assert_eq!(capped.estimate_candidates(pat), text.len() + 1 - pat.len());
+    }
+
+    #[test]
+    fn false_positive_rate_follows_the_birthday_bound() {
+        let small = CsiIndex::build(b"a short text has very few constellations to collide");
+        let text: Vec<u8> = (0u32..50_000).map(|i| (i.wrapping_mul(2654435761) >> 11) as u8).collect();
+        let large = CsiIndex::build(&text);
+        let (p_small, p_large) = (small.estimated_false_positive_rate(), large.estimated_false_positive_rate());
+        assert!(0.0 < p_small && p_small < p_large && p_large < 1e-9);
+        let pairs: f64 = large.index_summary().per_gap.iter()
+            .map(|&(_, d, _)| (d * (d - 1) / 2) as f64)
+            .sum();
+        assert!((p_large / (pairs / 2f64.powi(64)) - 1.0).abs() < 1e-6);
+        assert_eq!(CsiIndex::build(b"").estimated_false_positive_rate(), 0.0);
+    }
+
+    #[test]
+    fn unselective_queries_are_refused() {
+        let mut text = b"spam ".repeat(400);
+        text.extend_from_slice(b"the one rare needle in the haystack");
+        text.extend(b"spam ".repeat(400));
+        let idx = CsiIndex::build(&text);
+        let common = &text[..40];
+        assert!(idx.estimate_candidates(common) > 100);
+        assert_eq!(idx.search_if_selective(common, 100), None);
+        assert_eq!(idx.search_if_selective(common, text.len()), Some(idx.search(common)));
+        assert_eq!(idx.search_if_selective(b"the one rare needle in the haystack", 100), Some(vec![2000]));
+        assert_eq!(idx.search_if_selective(b"", 10), None);
+    }
+
+    #[test]
+    fn mem_usage_sums_capacities() {
+        use std::mem::size_of;
+
+        let text = b"weigh the tables against the text; weigh them twice".repeat(20);
+        let config = CsiConfig { fingerprints: true, normalize_newlines: true, ..CsiConfig::default() };
+        let idx = CsiIndex::build_with_config(&text, &config);
+        let mem = idx.mem_usage();
+        let slots: usize = idx.flat.iter().map(|fi| fi.table_size * (size_of::<u64>() + 2 * size_of::<u32>())).sum();
+        let fps: usize = idx.flat.iter().map(|fi| fi.fps.capacity() * size_of::<u16>()).sum();
+        let postings = idx.index_summary().total_postings * size_of::<u32>() + fps;
+        assert_eq!((mem.text, mem.powers), (idx.text.capacity(), idx.pw.capacity() * size_of::<u64>()));
+        assert_eq!((mem.slots, mem.postings), (slots, postings));
+
+        // 64‐bit slots cost half as much again, 64‐bit postings twice as
+        // much, and search the same
+        let wide = CsiIndex::build_with_config(&text, &CsiConfig { wide_slots: true, ..config.clone() });
+        assert_eq!(wide.mem_usage().slots, slots / 2 * 3);
+        assert_eq!(wide.mem_usage().postings, 2 * postings - fps);
+        assert_eq!(wide.search(&text[40..80]), idx.search(&text[40..80]));
+        assert!(idx.flat.iter().all(|fi| fi.table_size >= 2 * fi.entries().count()));
+        assert_eq!(mem.total(), idx.heap_bytes());
+
+        let h = crate::csi_new(text.as_ptr(), text.len());
+        assert_eq!(crate::csi_mem_usage(h), unsafe { &(*h).inner }.heap_bytes());
+        crate::csi_free(h);
+        assert_eq!(crate::csi_mem_usage(std::ptr::null()), 0);
+    }
+
+    #[test]
+    fn stats_report_each_table_and_sum_to_the_totals() {
+        // one record repeated, then a run of a single byte: a few huge buckets
+        let mut text = b"GET /index.html 200\n".repeat(300);
+        text.extend(std::iter::repeat_n(b'z', 2000));
+        let idx = CsiIndex::build_with_config(&text, &CsiConfig { max_postings_per_key: Some(1000), ..CsiConfig::default() });
+        let s = idx.stats();
+        let occ = idx.occupancy_report();
+        assert_eq!(s.per_gap.len(), idx.gaps.len());
+        for ((g, o), fi) in s.per_gap.iter().zip(&occ).zip(&idx.flat) {
+            assert_eq!((g.gap, g.table_size, g.load_factor, g.max_probe), (o.gap, o.table_size, o.load_factor, o.max_probe));
+            assert!(g.largest_bucket >= 290 && g.largest_bucket <= 1000);
+            // the run of `z`s keys one constellation per gap, too common to keep
+            assert!(g.saturated_buckets >= 1);
+            assert!(g.postings < text.len() && g.heap_bytes >= fi.keys.len() * 8);
+        }
+        assert_eq!(s.total_postings, s.per_gap.iter().map(|g| g.postings).sum::<usize>());
+        assert_eq!(s.per_gap.iter().map(|g| g.heap_bytes).sum::<usize>(), {
+            let m = idx.mem_usage();
+            m.slots + m.postings + m.overflow
+        });
+        assert_eq!(s.heap_bytes, idx.heap_bytes());
+
+        let h = crate::csi_new(text.as_ptr(), text.len());
+        let mut out = vec![crate::CsiGapStats::default(); 8];
+        let n = crate::csi_stats(h, out.as_mut_ptr(), 1);
+        assert_eq!(n, s.per_gap.len());
+        let g = &s.per_gap[0];
+        assert_eq!((out[0].gap, out[0].table_size, out[0].max_probe), (g.gap, g.table_size, g.max_probe));
+        assert_eq!(out[1], crate::CsiGapStats::default());
+        assert_eq!(crate::csi_stats(h, std::ptr::null_mut(), 0), n);
+        crate::csi_free(h);
+        assert_eq!(crate::csi_stats(std::ptr::null(), out.as_mut_ptr(), 8), 0);
+    }
+
+    #[test]
+    fn budgeted_build_respects_budget() {
+        let text: Vec<u8> = (0u32..6000).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let full = CsiIndex::build(&text);
+        let all = full.heap_bytes();
+        let pat = &text[1000..1050];
+        assert_eq!(CsiIndex::build_within_budget(&text, all).unwrap().gaps, full.gaps);
+
+        let tight = all - 1;
+        let idx = CsiIndex::build_within_budget(&text, tight).unwrap();
+        assert_eq!(idx.gaps[..], full.gaps[..full.gaps.len() - 1]);
+        assert!(idx.heap_bytes() <= tight);
+        assert_eq!(idx.search(pat), full.search(pat));
+
+        let one = CsiIndex::build_within_budget(&text, all / full.gaps.len() + text.len()).unwrap();
+        assert!(one.gaps.len() == 1 && one.search(pat) == full.search(pat));
+        match CsiIndex::build_within_budget(&text, text.len()) {
+            Err(CsiIndexError::OverBudget { needed }) => {
+                assert!(needed > text.len());
+                assert!(CsiIndex::build_within_budget(&text, needed).is_ok());
+            }
+            other => panic!("expected OverBudget, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn prewarm_checksum_is_reproducible() {
+        let text = b"warm the tables before timing; warm the tables before timing".to_vec();
+        let a = CsiIndex::build(&text);
+        assert_eq!(a.prewarm(), CsiIndex::build(&text).prewarm());
+        let mut b = CsiIndex::build(&text);
+        b.truncate(40);
+        assert_ne!(a.prewarm(), b.prewarm());
+    }
+
+    #[test]
+    fn occupancy_report_tracks_tables() {
+        let text: Vec<u8> = (0u32..4000).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let idx = CsiIndex::build(&text);
+        let report = idx.occupancy_report();
+        let summary = idx.index_summary();
+        assert_eq!(report.len(), idx.gaps.len());
+        for (occ, &(gap, distinct, _)) in report.iter().zip(&summary.per_gap) {
+            assert_eq!((occ.gap, occ.occupied_slots), (gap, distinct));
+            assert!(occ.load_factor > 0.0 && occ.load_factor <= 0.5);
+            assert!(occ.max_probe < occ.table_size);
+        }
+        assert!(format!("{idx:?}").contains("max_probe"));
+    }
+
+    #[test]
+    fn build_timed_matches_plain_build() {
+        let text: Vec<u8> = (0u32..20_000).map(|i| (i.wrapping_mul(2654435761) >> 11) as u8).collect();
+        let (idx, t) = CsiIndex::build_timed(&text, &CsiConfig::default());
+        assert_eq!(t.per_gap.len(), idx.gaps.len());
+        assert_eq!(idx.index_summary(), CsiIndex::build(&text).index_summary());
+        assert_eq!(idx.search(&text[500..540]), CsiIndex::build(&text).search(&text[500..540]));
+    }
+
+    #[test]
+    fn match_stats_summarizes_hits() {
+        let text = b"-signature-block-01- filler filler -signature-block-01- more -signature-block-01-";
+        let idx = CsiIndex::build(text);
+        let s = idx.match_stats(b"-signature-block-01-");
+        assert_eq!(s, MatchStats { count: 3, first: 0, last: 61, span: 61 });
+        assert_eq!(idx.match_stats(b"-signature-block-02-"), MatchStats::default());
+    }
+
+    #[test]
+    fn summary_totals_match_per_gap() {
+        let text = b"summary over a short text, a short text indeed".repeat(3);
+        let idx = CsiIndex::build(&text);
+        let s = idx.index_summary();
+        assert_eq!(s.text_len, text.len());
+        assert_eq!(s.per_gap.len(), idx.gaps.len());
+        for &(d, distinct, postings) in &s.per_gap {
+            assert_eq!(postings, text.len() - (idx.k + d) + 1);
+            assert!(distinct > 0 && distinct <= postings);
+        }
+        assert_eq!(s.total_postings, s.per_gap.iter().map(|g| g.2).sum::<usize>());
+    }
+
+    #[test]
+    fn explain_miss_pinpoints_the_breaking_gap() {
+        let mut x = 0x9e3779b97f4a7c15u64;
+        let text: Vec<u8> = (0..3000).map(|_| { x ^= x << 13; x ^= x >> 7; x ^= x << 17; (x >> 59) as u8 + b'a' }).collect();
+        let idx = CsiIndex::build(&text);
+        let (k, len) = (idx.k, idx.min_pattern_len() + 10);
+        let found = idx.explain_miss(&text[700..700 + len]);
+        assert_eq!(found.breaking_gaps, [None]);
+        assert!(found.probes.iter().all(|p| p.lookup == GapLookup::Postings(1)));
+
+        // flip the last byte of the widest fitting gap's target window
+        let widest = *idx.gaps.iter().filter(|&&d| d + k <= len).max().unwrap();
+        let at = widest + k - 1;
+        let mut pat = text[700..700 + len].to_vec();
+        pat[at] ^= 0x20;
+        let breaking = idx.gaps.iter().copied().find(|&d| d <= at && at < d + k);
+        let report = idx.explain_miss(&pat);
+        assert_eq!(report.breaking_gaps, [breaking]);
+        assert!(breaking.is_some() && idx.search(&pat).is_empty());
+        for p in &report.probes {
+            let covered = p.gap <= at && at < p.gap + k;
+            assert_eq!(p.lookup == GapLookup::Missing, covered, "gap {}", p.gap);
+        }
+        assert!(idx.explain_miss(&pat[..k]).probes.is_empty());
+    }
+
+    #[test]
+    fn verbose_search_counts_rejected_candidates() {
+        let mut x = 0x9e3779b97f4a7c15u64;
+        let mut text: Vec<u8> = (0..3000).map(|_| { x ^= x << 13; x ^= x >> 7; x ^= x << 17; (x >> 59) as u8 + b'a' }).collect();
+        let probe = CsiIndex::build(&text);
+        // bytes past the widest anchor window aren't hashed by any gap
+        let len = probe.gaps.last().unwrap() + probe.k + 6;
+        let mut decoy = text[100..100 + len].to_vec();
+        decoy[len - 1] ^= 0x20;
+        text[2000..2000 + len].copy_from_slice(&decoy);
+        let idx = CsiIndex::build(&text);
+        assert_eq!((idx.k, &idx.gaps), (probe.k, &probe.gaps));
+
+        let pat = &text[100..100 + len];
+        let (hits, diag) = idx.search_verbose(pat);
+        assert_eq!(hits, idx.search(pat));
+        assert_eq!(hits, [100]);
+        assert_eq!(diag.verified, hits.len());
+        assert_eq!(diag.rejected_by_verify, diag.intersected - diag.verified);
+        assert_eq!((diag.intersected, diag.rejected_by_verify), (2, 1));
+        assert!(diag.per_gap_candidates.iter().all(|&c| c == 2));
+
+        let (none, diag) = idx.search_verbose(b"zzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzz");
+        assert!(none.is_empty());
+        assert_eq!((diag.intersected, diag.verified, diag.rejected_by_verify), (0, 0, 0));
+    }
+
+    #[test]
+    fn metrics_follow_the_route_a_search_takes() {
+        let mut text: Vec<u8> = (0..2000).flat_map(|i| format!("key:{i:04}val:data").into_bytes()).collect();
+        text.extend_from_slice(b"abababababababababab");
+        let idx = CsiIndexBuilder::new().tiers(EntropyTiers::fixed(4, vec![8])).build(&text);
+        let metrics = |pat: &[u8]| {
+            let (hits, mx) = idx.search_with_metrics(pat);
+            assert_eq!(hits, idx.search(pat), "{}", String::from_utf8_lossy(pat));
+            assert_eq!((mx.pattern_len, mx.matches), (pat.len(), hits.len()));
+            assert!(mx.total >= mx.lookup + mx.intersect + mx.verify);
+            mx
+        };
+        let mx = metrics(b"key:0042val:data");
+        assert_eq!((mx.route, mx.phases, mx.lists, mx.postings), (QueryRoute::Tables, 1, 1, 2000));
+        assert_eq!((mx.candidates, mx.false_candidates()), (2000, 1999));
+        let mx = metrics(b"key:9999val:data");
+        assert_eq!((mx.route, mx.candidates, mx.matches), (QueryRoute::Tables, 2000, 0));
+        let mx = metrics(b"nothing like it anywhere");
+        assert_eq!((mx.route, mx.phases, mx.candidates), (QueryRoute::Tables, 0, 0));
+        let mx = metrics(b"val:");
+        assert_eq!((mx.route, mx.candidates), (QueryRoute::Scan, text.len() - 3));
+        assert_eq!(metrics(b"abababab").route, QueryRoute::Periodic);
+        assert_eq!(metrics(b"").route, QueryRoute::Direct);
+        assert_eq!(metrics(&text).route, QueryRoute::Direct);
+    }
+
+    /// Every record shares the anchors, so a plain index verifies all 2000
+    /// of them; span fingerprints leave the one match (plus a 1‐in‐65536
+    /// collision now and then) for the byte compare.
+    #[test]
+    fn fingerprints_reject_candidates_before_verify() {
+        let text: Vec<u8> = (0..2000).flat_map(|i| format!("key:{i:04}val:data").into_bytes()).collect();
+        let tiers = EntropyTiers::fixed(4, vec![8]);
+        let plain = CsiIndexBuilder::new().tiers(tiers.clone()).build(&text);
+        let printed = CsiIndexBuilder::new().tiers(tiers).fingerprints(true).build(&text);
+        let pat = b"key:0042val:data";
+        let ((hits, without), (fp_hits, with)) = (plain.search_verbose(pat), printed.search_verbose(pat));
+        assert_eq!((hits, fp_hits), (vec![42 * 16], vec![42 * 16]));
+        assert_eq!((without.intersected, without.rejected_by_verify), (2000, 1999));
+        assert!(with.intersected <= 3 && with.rejected_by_verify + 1 == with.intersected);
+        assert_eq!((printed.count(pat), printed.search(b"val:data")), (1, plain.search(b"val:data")));
+        assert_eq!(printed.heap_bytes() - plain.heap_bytes(), printed.flat[0].fps.capacity() * 2);
+        assert_eq!(printed.validate(), Ok(()));
+    }
+}
@@ -0,0 +1,216 @@
+// src/store.rs
+//! Writer/many‐reader holder that swaps in rebuilt indexes without locking.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use arc_swap::ArcSwap;
+
+use crate::{CsiConfig, CsiIndex};
+
+/// Current index behind an atomic pointer; readers never block a rebuild.
+///
+/// `load` hands out an `Arc` snapshot, so a reader keeps using the index it
+/// started with even if `rebuild` swaps a newer one in meanwhile; the old
+/// index is freed when the last snapshot drops.
+pub struct CsiStore {
+    current: ArcSwap<CsiIndex>,
+    config:  CsiConfig,
+}
+
+impl CsiStore {
+    /// Build the initial index from `data`; later rebuilds reuse `config`.
+    pub fn new(data: &[u8], config: CsiConfig) -> Self {
+        let index = CsiIndex::build_with_config(data, &config);
+        CsiStore { current: ArcSwap::from_pointee(index), config }
+    }
+
+    /// Snapshot of the index to query.
+    pub fn load(&self) -> Arc<CsiIndex> {
+        self.current.load_full()
+    }
+
+    /// Build a fresh index over `data` and publish it atomically.
+    ///
+    /// The build runs on the calling thread before the swap, so readers
+    /// keep the previous index for its whole duration.
+    pub fn rebuild(&self, data: &[u8]) {
+        self.replace(CsiIndex::build_with_config(data, &self.config));
+    }
+
+    /// Publish an index built elsewhere, returning the one it replaced.
+    pub fn replace(&self, index: CsiIndex) -> Arc<CsiIndex> {
+        self.current.swap(Arc::new(index))
+    }
+}
+
+/// Index over a growing prefix of its input, extended on a background
+/// thread; see `CsiIndex::build_progressive`.
+///
+/// Guarantees:
+/// - every snapshot (`load`, or the one `search` uses) is exactly the index
+///   `build_with_config` would produce over `data[..len]` for some published
+///   `len`, so no offset it returns reaches past that prefix;
+/// - published prefixes only grow, and the last one is all of `data`;
+/// - readers never block on the worker, which only ever swaps a pointer.
+///
+/// `indexed_len` and the offsets are in index coordinates
+/// (after any newline normalization), like `CsiIndex::text`. A pattern
+/// straddling the current end of the prefix isn't found until a longer
+/// prefix is published. Dropping the handle cancels the unfinished build.
+pub struct ProgressiveIndex {
+    shared: Arc<Progress>,
+    worker: Option<JoinHandle<()>>,
+}
+
+struct Progress {
+    current: ArcSwap<CsiIndex>,
+    total:   usize,
+    done:    AtomicBool,
+    stop:    AtomicBool,
+}
+
+impl CsiIndex {
+    /// Index `data[..first]` on this thread (so the handle is queryable at
+    /// once), then keep building over prefixes of twice the previous length
+    /// on a background thread, publishing each as it completes, until all
+    /// of `data` is indexed.
+    ///
+    /// Each step is a full, cancellable build over its prefix rather than an
+    /// `append` to the last: appending keeps the first prefix's `k` and
+    /// gaps, and a short prefix has dropped every gap too wide for it, so
+    /// the final index wouldn't match one built over `data`. With doubling
+    /// the builds' total cost stays within about twice that of the last.
+    pub fn build_progressive(data: Vec<u8>, config: CsiConfig, first: usize) -> ProgressiveIndex {
+        let data: Arc<[u8]> = data.into();
+        let total = data.len();
+        let mut len = first.max(1).min(total);
+        let shared = Arc::new(Progress {
+            current: ArcSwap::from_pointee(CsiIndex::build_with_config(&data[..len], &config)),
+            total,
+            done: AtomicBool::new(len == total),
+            stop: AtomicBool::new(false),
+        });
+        let worker = (len < total).then(|| {
+            let shared = Arc::clone(&shared);
+            std::thread::spawn(move || {
+                while len < total {
+                    len = len.saturating_mul(2).min(total);
+                    let keep_going = |_, _| !shared.stop.load(Ordering::Relaxed);
+                    match CsiIndex::build_cancellable(&data[..len], &config, keep_going) {
+                        Ok(idx) => shared.current.store(Arc::new(idx)),
+                        Err(_) => return,
+                    }
+                }
+                shared.done.store(true, Ordering::Release);
+            })
+        });
+        ProgressiveIndex { shared, worker }
+    }
+}
+
+impl ProgressiveIndex {
+    /// Snapshot of the most recently published prefix index.
+    pub fn load(&self) -> Arc<CsiIndex> {
+        self.shared.current.load_full()
+    }
+
+    /// Length of the text the current snapshot covers.
+    pub fn indexed_len(&self) -> usize {
+        self.shared.current.load().text().len()
+    }
+
+    /// Length of the input being indexed, in input bytes.
+    pub fn total_len(&self) -> usize {
+        self.shared.total
+    }
+
+    /// Whether the whole input has been indexed and published.
+    pub fn is_complete(&self) -> bool {
+        self.shared.done.load(Ordering::Acquire)
+    }
+
+    /// `search` over the current snapshot; every offset lies in
+    /// `0..=indexed_len()` as of that snapshot.
+    pub fn search(&self, pat: &[u8]) -> Vec<usize> {
+        self.shared.current.load().search(pat)
+    }
+
+    /// Wait for the background build and return the index over all of the
+    /// input.
+    pub fn wait(mut self) -> Arc<CsiIndex> {
+        if let Some(worker) = self.worker.take() {
+            // a panicking build leaves the last good prefix published
+            let _ = worker.join();
+        }
+        self.load()
+    }
+}
+
+impl Drop for ProgressiveIndex {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CsiStore;
+    use crate::{CsiConfig, CsiIndex};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn progressive_never_reports_past_indexed_prefix() {
+        let unit = b"progressively indexed text, one line after another.\n";
+        let data: Vec<u8> = unit.repeat(2000);
+        let pat = b"indexed text, one line after";
+        let full = CsiIndex::build(&data).search(pat);
+        let prog = CsiIndex::build_progressive(data.clone(), CsiConfig::default(), 1000);
+        let mut seen = 0;
+        while !prog.is_complete() {
+            let snap = prog.load();
+            let hits = snap.search(pat);
+            assert!(hits.iter().all(|&o| o + pat.len() <= snap.text().len()));
+            assert_eq!(hits[..], full[..hits.len()]);
+            assert!(snap.text().len() >= seen);
+            seen = snap.text().len();
+        }
+        assert_eq!(prog.indexed_len(), prog.total_len());
+        assert_eq!(prog.wait().search(pat), full);
+
+        let tiny = CsiIndex::build_progressive(data[..100].to_vec(), CsiConfig::default(), 1 << 20);
+        assert!(tiny.is_complete() && tiny.indexed_len() == 100);
+        drop(CsiIndex::build_progressive(data, CsiConfig::default(), 64));
+    }
+
+    #[test]
+    fn readers_see_whole_indexes_across_swaps() {
+        let old: Vec<u8> = b"the quick brown fox jumps over the lazy dog. ".repeat(40);
+        let new: Vec<u8> = b"pack my box with five dozen liquor jugs now. ".repeat(40);
+        let store = CsiStore::new(&old, CsiConfig::default());
+        let held = store.load();
+        let done = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    while !done.load(Ordering::Relaxed) {
+                        let idx = store.load();
+                        let a = idx.search(b"quick brown fox jumps").len();
+                        let b = idx.search(b"five dozen liquor jugs").len();
+                        assert!((a, b) == (40, 0) || (a, b) == (0, 40));
+                    }
+                });
+            }
+            for i in 0..10 {
+                store.rebuild(if i % 2 == 0 { &new } else { &old });
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(held.search(b"quick brown fox jumps").len(), 40);
+        assert_eq!(store.load().search(b"quick brown fox jumps").len(), 40);
+    }
+}
@@ -0,0 +1,136 @@
+// src/syncmer.rs
+//! Closed syncmers, the starts a `CsiConfig::syncmer_window` index keeps.
+//!
+//! Start `i` is a syncmer if, of the hashes of the `w` k‐mers starting at
+//! `i..i + w`, the first or the last is the smallest. That only depends on
+//! the `k + w - 1` bytes from `i`, so a pattern's syncmers are its
+//! occurrences' syncmers at the same offsets, and any `w` consecutive
+//! starts hold one: of the k‐mers at `i..i + 2w - 1`, the leftmost
+//! smallest is the first of the window at it if that starts by `i + w - 1`,
+//! and otherwise the last of the window `w - 1` before it.
+
+use alloc::collections::VecDeque;
+
+use crate::Hasher;
+
+/// Odd multiplier scattering the k‐mer hashes before they're compared, so
+/// which k‐mers win doesn't follow the order of the hash's values
+const MIX: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// The syncmer starts over prefix states `ph`, ascending, as far as `ph`
+/// reaches to decide them
+pub(crate) struct Syncmers<'a, H> {
+    h:      &'a H,
+    ph:     &'a [u64],
+    pk:     u64, // window constant for `k`
+    k:      usize,
+    w:      usize,
+    at:     usize, // next start to decide
+    pushed: usize, // k‐mers seen so far
+    // the k‐mers from `at` on that no later one undercuts, ascending in
+    // hash: the front is the window's smallest
+    mins:   VecDeque<(usize, u64)>,
+}
+
+impl<'a, H: Hasher> Syncmers<'a, H> {
+    pub(crate) fn new(h: &'a H, ph: &'a [u64], pk: u64, k: usize, w: usize) -> Self {
+        Syncmers { h, ph, pk, k, w, at: 0, pushed: 0, mins: VecDeque::with_capacity(w) }
+    }
+
+    /// The scattered hash of the k‐mer at `j`.
+    fn value(&self, j: usize) -> u64 {
+        self.h.window(self.ph[j + self.k], self.ph[j], self.pk).wrapping_mul(MIX)
+    }
+}
+
+impl<H: Hasher> Iterator for Syncmers<'_, H> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        loop {
+            let last = self.at + self.w - 1;
+            if last + self.k >= self.ph.len() {
+                return None;
+            }
+            while self.pushed <= last {
+                let v = self.value(self.pushed);
+                while self.mins.back().is_some_and(|&(_, b)| b > v) {
+                    self.mins.pop_back();
+                }
+                self.mins.push_back((self.pushed, v));
+                self.pushed += 1;
+            }
+            while self.mins.front().is_some_and(|&(j, _)| j < self.at) {
+                self.mins.pop_front();
+            }
+            let i = self.at;
+            self.at += 1;
+            // the window's last k‐mer went in last, so it's at the back
+            let (min, back) = (self.mins[0].1, self.mins[self.mins.len() - 1].1);
+            if back == min || self.value(i) == min {
+                return Some(i);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Syncmers, MIX};
+    use crate::hash::{prefix_hash, window_const};
+    use crate::{CsiConfig, CsiIndex, CsiIndexBuilder, EntropyTiers, Hasher, PolyHasher};
+
+    fn noise(seed: u64, len: usize) -> Vec<u8> {
+        let mut x = seed;
+        (0..len).map(|_| { x ^= x << 13; x ^= x >> 7; x ^= x << 17; b"acgt"[(x >> 62) as usize] }).collect()
+    }
+
+    #[test]
+    fn syncmers_are_local_and_never_far_apart() {
+        let (h, k, w) = (PolyHasher, 5, 8);
+        let text = noise(7, 5000);
+        let ph = prefix_hash(&h, &text);
+        let pk = window_const(&h, k);
+        let value = |j: usize| h.window(ph[j + k], ph[j], pk).wrapping_mul(MIX);
+        let got: Vec<usize> = Syncmers::new(&h, &ph, pk, k, w).collect();
+        let want: Vec<usize> = (0..=text.len() - (k + w - 1)).filter(|&i| {
+            let min = (i..i + w).map(value).min().unwrap();
+            value(i) == min || value(i + w - 1) == min
+        }).collect();
+        assert_eq!(got, want);
+        assert!(got.windows(2).all(|p| p[1] - p[0] <= w) && got[0] < w);
+        // about 2 / (w + 1) of the starts
+        assert!(got.len() * (w + 1) < 3 * text.len(), "{}", got.len());
+
+        // and the same from any start
+        let sub = prefix_hash(&h, &text[1234..]);
+        let shifted: Vec<usize> = Syncmers::new(&h, &sub, pk, k, w).map(|i| i + 1234).collect();
+        assert_eq!(shifted, got.iter().copied().filter(|&i| i >= 1234).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn syncmer_index_is_smaller_and_exact() {
+        let text: Vec<u8> = (0..30).flat_map(|i| [noise(i, 2000), format!(" record {i} ends ").into_bytes()].concat()).collect();
+        let tiers = EntropyTiers::fixed(6, vec![6, 12]);
+        let dense = CsiIndexBuilder::new().tiers(tiers.clone()).build(&text);
+        let sparse = CsiIndexBuilder::new().tiers(tiers).syncmer_window(10).build(&text);
+        let postings = |idx: &CsiIndex| idx.stats().total_postings;
+        assert!(postings(&sparse) * 4 < postings(&dense), "{} vs {}", postings(&sparse), postings(&dense));
+        assert_eq!(sparse.min_pattern_len(), 9 + 6 + 6 + 6);
+        for (at, len) in [(0, 40), (777, 27), (15_000, 200), (41_000, 64), (text.len() - 30, 30), (2000, 15)] {
+            let pat = &text[at..at + len];
+            assert_eq!(sparse.search(pat), dense.search(pat), "{at} {len}");
+            assert_eq!(sparse.count(pat), dense.count(pat));
+        }
+        assert_eq!(sparse.search(b" record 17 ends acg"), dense.search(b" record 17 ends acg"));
+        assert!(sparse.search(b"nothing like this in the text").is_empty());
+
+        // the window reaches the file and back
+        let mut bytes = Vec::new();
+        sparse.write_index(&mut bytes).unwrap();
+        let back = CsiIndex::read_index(&mut &bytes[..], PolyHasher).unwrap();
+        assert_eq!(back.search(&text[9000..9100]), dense.search(&text[9000..9100]));
+        let other = CsiIndex::build_with_config(&text, &CsiConfig { syncmer_window: Some(1), ..CsiConfig::default() });
+        assert_eq!(other.min_pattern_len(), CsiIndex::build(&text).min_pattern_len());
+    }
+}
@@ -0,0 +1,136 @@
+// src/text.rs
+//! Search over UTF‐8 text by character: matches that start and end on
+//! character boundaries, reported at character offsets as well as byte
+//! offsets.
+//!
+//! Exact matches of a `&str` in a `str` always fall on boundaries; the
+//! check matters once a `ByteMap` fold or a mask rewrites bytes inside
+//! characters. Boundaries are those of the source text, kept as a bitset
+//! over the indexed text with a running count per word, so a character
+//! offset is a popcount.
+//!
+//! Unicode normalization (NFC) is not applied: that needs the Unicode
+//! composition tables, which this crate doesn't carry. Precomposed and
+//! decomposed spellings of a character match only themselves.
+
+use alloc::vec::Vec;
+
+use crate::{CaseFold, CsiConfig, CsiIndex};
+
+/// A `CsiIndex` over a `str` that answers `&str` patterns by character;
+/// see `search_str`
+pub struct TextIndex {
+    index:  CsiIndex,
+    starts: Vec<u64>,   // bit `i`: byte `i` of the indexed text starts a character
+    chars:  Vec<usize>, // characters before byte `64 * i`
+}
+
+impl TextIndex {
+    /// Index `text` byte‐exactly.
+    pub fn new(text: &str) -> Self {
+        Self::with_config(text, &CsiConfig::default())
+    }
+
+    /// Index `text` with Unicode simple case folding (`CaseFold::Unicode`),
+    /// so patterns match it in any case.
+    pub fn folded(text: &str) -> Self {
+        Self::with_config(text, &CsiConfig { case_fold: CaseFold::Unicode, ..CsiConfig::default() })
+    }
+
+    /// Index `text` with `config`. Offsets refer to the indexed text, which
+    /// is shorter than `text` if newlines are normalized; characters are
+    /// `text`'s even where a `ByteMap` fold or mask rewrites their bytes.
+    pub fn with_config(text: &str, config: &CsiConfig) -> Self {
+        let index = CsiIndex::build_with_config(text.as_bytes(), config);
+        let src = text.as_bytes();
+        // the bytes CRLF normalization looked at, which come folded
+        let folded = if config.normalize_newlines { config.case_fold.apply(src) } else { src.into() };
+        let mut starts = vec![0u64; index.text().len().div_ceil(64)];
+        let mut at = 0;
+        for (i, &b) in src.iter().enumerate() {
+            if config.normalize_newlines && folded[i] == b'\r' && folded.get(i + 1) == Some(&b'\n') {
+                continue;
+            }
+            starts[at / 64] |= (!continuation(b) as u64) << (at % 64);
+            at += 1;
+        }
+        debug_assert_eq!(at, index.text().len());
+        let mut chars = Vec::with_capacity(starts.len() + 1);
+        chars.push(0);
+        for w in &starts {
+            chars.push(chars[chars.len() - 1] + w.count_ones() as usize);
+        }
+        TextIndex { index, starts, chars }
+    }
+
+    /// `(char_offset, byte_offset)` of every occurrence of `pat` starting
+    /// and ending on a character boundary, ascending. The empty pattern
+    /// occurs at every boundary, the end of the text included.
+    pub fn search_str(&self, pat: &str) -> Vec<(usize, usize)> {
+        let m = self.index.normalize(pat.as_bytes()).len();
+        let mut hits = self.index.search(pat.as_bytes());
+        hits.retain(|&off| self.boundary(off) && self.boundary(off + m));
+        hits.into_iter().map(|off| (self.char_offset(off), off)).collect()
+    }
+
+    /// Characters of the indexed text before byte `off`, counting one that
+    /// `off` falls inside. Panics if `off` is past the end of the text.
+    pub fn char_offset(&self, off: usize) -> usize {
+        let n = self.index.text().len();
+        assert!(off <= n, "byte offset {off} past the end of the text");
+        let below = self.starts.get(off / 64).map_or(0, |w| w & ((1 << (off % 64)) - 1));
+        self.chars[off / 64] + below.count_ones() as usize
+    }
+
+    /// The underlying index, for byte‐level queries.
+    pub fn index(&self) -> &CsiIndex {
+        &self.index
+    }
+
+    /// Whether byte `at` of the indexed text starts a character or is its end.
+    fn boundary(&self, at: usize) -> bool {
+        at == self.index.text().len() || self.starts[at / 64] >> (at % 64) & 1 == 1
+    }
+}
+
+fn continuation(b: u8) -> bool {
+    b & 0xc0 == 0x80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TextIndex;
+    use crate::{ByteMap, CaseFold, CsiConfig};
+
+    #[test]
+    fn matches_report_char_and_byte_offsets() {
+        let text: String = (0..400).map(|i| format!("naïve café №{} ünïcödé — ", i % 13)).collect();
+        let idx = TextIndex::new(&text);
+        for pat in ["café №7 ünï", "ünïcödé — naïve", "— naïve café №12", "no such thing here"] {
+            let want: Vec<(usize, usize)> = text.match_indices(pat).map(|(b, _)| (text[..b].chars().count(), b)).collect();
+            assert_eq!(idx.search_str(pat), want, "{pat}");
+        }
+        assert_eq!(idx.char_offset(text.len()), text.chars().count());
+        let empty = idx.search_str("");
+        assert_eq!(empty.len(), text.chars().count() + 1);
+        assert!(empty.iter().all(|&(c, b)| text.is_char_boundary(b) && text[..b].chars().count() == c));
+
+        let folded = TextIndex::folded(&text);
+        assert_eq!(folded.search_str("CAFÉ №7 ÜNÏ"), idx.search_str("café №7 ünï"));
+        assert!(idx.search_str("CAFÉ №7 ÜNÏ").is_empty());
+    }
+
+    #[test]
+    fn matches_inside_characters_are_dropped() {
+        // 'é' is C3 A9; mapping C3 to A9 makes every byte of "éé" alike
+        let map = ByteMap::new(core::array::from_fn(|b| if b == 0xc3 { 0xa9 } else { b as u8 }));
+        let config = CsiConfig { case_fold: CaseFold::Map(map), ..CsiConfig::default() };
+        let idx = TextIndex::with_config("ééé", &config);
+        assert_eq!(idx.index().search("é".as_bytes()), [0, 1, 2, 3, 4]);
+        assert_eq!(idx.search_str("é"), [(0, 0), (1, 2), (2, 4)]);
+        assert_eq!(idx.char_offset(3), 2);
+
+        let crlf = TextIndex::with_config("a\r\nb\r\nb", &CsiConfig { normalize_newlines: true, ..CsiConfig::default() });
+        assert_eq!(crlf.search_str("\r\nb"), [(1, 1), (3, 3)]);
+    }
+}
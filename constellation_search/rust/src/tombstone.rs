@@ -0,0 +1,179 @@
+// src/tombstone.rs
+//! Deleted ranges of the indexed text.
+//!
+//! `invalidate_range` only records the range: searches drop every match
+//! that overlaps one, so deleting is O(log r) in the `r` ranges already
+//! recorded and the tables are untouched. `compact` later rebuilds the
+//! tables without the postings whose constellations overlap a deleted
+//! range. The text itself is kept either way, so offsets stay put.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::hash::{fingerprint, window_const};
+use crate::{CsiIndex, FlatIndex, Hasher, TableParams, SATURATED};
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Mark `range` of the indexed text (offsets as `search` reports them)
+    /// deleted: no search reports a match overlapping it from now on.
+    ///
+    /// The range is clipped to the text; overlapping and adjacent ranges
+    /// are joined. The empty pattern still matches at every offset.
+    /// Postings stay in the tables until `compact`.
+    pub fn invalidate_range(&mut self, range: Range<usize>) {
+        let range = range.start.min(self.text_len)..range.end.min(self.text_len);
+        insert_dead(&mut self.dead, range);
+    }
+
+    /// The deleted ranges, ascending and disjoint.
+    pub fn invalidated(&self) -> &[Range<usize>] {
+        &self.dead
+    }
+
+    /// Whether `text[off..off + m]` overlaps no deleted range.
+    #[inline(always)]
+    pub(crate) fn live(&self, off: usize, m: usize) -> bool {
+        self.dead.is_empty() || {
+            let at = self.dead.partition_point(|r| r.end <= off);
+            self.dead.get(at).is_none_or(|r| r.start >= off + m)
+        }
+    }
+
+    /// Drop deleted ranges at or past `new_len`, as `truncate` does the text.
+    pub(crate) fn truncate_dead(&mut self, new_len: usize) {
+        self.dead.truncate(self.dead.partition_point(|r| r.start < new_len));
+        if let Some(last) = self.dead.last_mut() {
+            last.end = last.end.min(new_len);
+        }
+    }
+
+    /// Take every posting whose constellation overlaps a deleted range out
+    /// of the freshly rebuilt tables, re‐laying out those that lose any.
+    /// `ph` is the text's prefix hash.
+    pub(crate) fn drop_dead(&mut self, ph: &[u64], p: TableParams) {
+        let (h, k) = (&self.hasher, self.k);
+        for (&d, fi) in self.gaps.iter().zip(&mut self.flat) {
+            let span = k + d;
+            let dead = |o: usize| {
+                let at = self.dead.partition_point(|r| r.end <= o);
+                self.dead.get(at).is_some_and(|r| r.start < o + span)
+            };
+            let mut postings = Vec::new();
+            let mut saturated = Vec::new();
+            let mut dropped = false;
+            for (key, start, len) in fi.entries() {
+                let list: Vec<usize> = match fi.packed_list(key) {
+                    Some(list) if len == SATURATED => list.iter().collect(),
+                    _ if len == SATURATED => {
+                        saturated.push(key);
+                        continue;
+                    }
+                    _ => fi.offs.list(start..start + len).to_vec(),
+                };
+                let before = postings.len();
+                postings.extend(list.iter().filter(|&&o| !dead(o)).map(|&o| (key, o)));
+                dropped |= postings.len() - before < list.len();
+            }
+            if !dropped {
+                continue;
+            }
+            postings.sort_unstable();
+            // packed lists kept no fingerprints, and one may shrink below the
+            // packing threshold, so take them all from the text again
+            let c = window_const(h, span);
+            let fps = p.fps.then(|| postings.iter().map(|&(_, o)| fingerprint(h.window(ph[o + span], ph[o], c))).collect::<Vec<_>>());
+            let none = FlatIndex { overflow: saturated.into_iter().map(|key| (key, 0, SATURATED)).collect(), ..FlatIndex::default() };
+            *fi = none.merged(&postings, fps.as_deref(), fi.table_size, p);
+        }
+    }
+}
+
+/// Add `range` to `dead`, keeping it ascending, disjoint and without
+/// adjacent ranges; empty ranges are ignored.
+pub(crate) fn insert_dead(dead: &mut Vec<Range<usize>>, range: Range<usize>) {
+    if range.is_empty() {
+        return;
+    }
+    // ranges that overlap or touch `range`
+    let lo = dead.partition_point(|r| r.end < range.start);
+    let hi = dead.partition_point(|r| r.start <= range.end);
+    let (start, end) = match lo < hi {
+        true => (dead[lo].start.min(range.start), dead[hi - 1].end.max(range.end)),
+        false => (range.start, range.end),
+    };
+    dead.splice(lo..hi, core::iter::once(start..end));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::insert_dead;
+    use crate::{CsiIndex, CsiIndexBuilder, EntropyTiers, PolyHasher};
+
+    #[test]
+    fn inserted_ranges_stay_sorted_and_joined() {
+        let mut dead = Vec::new();
+        for r in [10..20, 40..50, 5..5, 30..35, 20..22, 33..41, 0..3] {
+            insert_dead(&mut dead, r);
+        }
+        assert_eq!(dead, [0..3, 10..22, 30..50]);
+        insert_dead(&mut dead, 70..80);
+        insert_dead(&mut dead, 2..60);
+        assert_eq!(dead, [0..60, 70..80]);
+    }
+
+    #[test]
+    fn invalidated_ranges_hide_matches_until_and_after_compaction() {
+        let text: Vec<u8> = (0..600).flat_map(|i| format!("user={} action=login ok\n", i % 40).into_bytes()).collect();
+        let builders = [
+            CsiIndexBuilder::new().tiers(EntropyTiers::fixed(4, vec![4, 8])),
+            CsiIndexBuilder::new().tiers(EntropyTiers::fixed(4, vec![4, 8])).stride(2).fingerprints(true).pack_postings(30),
+            CsiIndexBuilder::new().tiers(EntropyTiers::fixed(4, vec![4, 8])).syncmer_window(6),
+        ];
+        let dead = [1000..1500, 2990..3010, 9000..12_000];
+        let pats: [&[u8]; 5] = [b"action=login ok\nuser=7 ", b"user=12 act", b"login", b"ok\nuser=3", b"=login ok\nuser=39 action"];
+        for builder in builders {
+            let whole = builder.build(&text);
+            let mut idx = builder.build(&text);
+            for r in dead.clone() {
+                idx.invalidate_range(r);
+            }
+            let live = |o: usize, m: usize| dead.iter().all(|r| o + m <= r.start || o >= r.end);
+            let check = |idx: &CsiIndex| for pat in pats {
+                let want: Vec<usize> = whole.search(pat).into_iter().filter(|&o| live(o, pat.len())).collect();
+                assert!(want.len() < whole.search(pat).len());
+                assert_eq!(idx.search(pat), want, "{:?}", String::from_utf8_lossy(pat));
+                assert_eq!(idx.search_iter(pat).collect::<Vec<_>>(), want);
+                assert_eq!((idx.count(pat), idx.contains(pat)), (want.len(), !want.is_empty()));
+                assert_eq!(idx.search_in(pat, 500..20_000), whole.search_in(pat, 500..20_000).into_iter()
+                    .filter(|&o| live(o, pat.len())).collect::<Vec<_>>());
+            };
+            check(&idx);
+
+            let postings = idx.index_summary().total_postings;
+            idx.compact();
+            assert_eq!(idx.validate(), Ok(()));
+            assert!(idx.index_summary().total_postings < postings);
+            assert_eq!(idx.text(), whole.text());
+            check(&idx);
+
+            let mut bytes = Vec::new();
+            idx.write_index(&mut bytes).unwrap();
+            let loaded = CsiIndex::read_index(&bytes[..], PolyHasher).unwrap();
+            assert_eq!(loaded.invalidated(), dead);
+            check(&loaded);
+        }
+    }
+
+    #[test]
+    fn truncating_clips_the_invalidated_ranges() {
+        let mut idx = CsiIndex::build(b"keep this part, drop that part, and lose the tail entirely");
+        idx.invalidate_range(16..31);
+        idx.invalidate_range(40..100);
+        idx.invalidate_range(0..4);
+        assert_eq!(idx.invalidated(), [0..4, 16..31, 40..58]);
+        assert!(idx.search(b"that part").is_empty());
+        idx.truncate(20);
+        assert_eq!(idx.invalidated(), [0..4, 16..20]);
+        assert_eq!(idx.search(b"this part"), [5]);
+    }
+}
@@ -0,0 +1,144 @@
+// src/validate.rs
+//! Internal consistency checks for a built (or loaded) index.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::fmt;
+
+use crate::packed::Packed;
+use crate::{key_at, prefix_hash, slot_key, CsiIndex, Hasher, Probe, SATURATED};
+
+/// First invariant violation found by `CsiIndex::validate`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// `k` is zero, there are no gaps, or gaps and tables disagree in count
+    Shape,
+    /// table size is zero, an array (fingerprints included) has the wrong
+    /// length, or the overflow table isn't sorted by key
+    TableSize { gap: usize },
+    /// a bucket's `starts + lens` runs past `offs`, or a packed list doesn't
+    /// decode to its length; here and below, slots from the table size up
+    /// number the overflow table's entries and then the packed lists
+    BucketOutOfRange { gap: usize, slot: usize },
+    /// a bucket's offsets are not strictly ascending
+    UnsortedPostings { gap: usize, slot: usize },
+    /// a posting leaves no room for its constellation in the text
+    OffsetOutOfRange { gap: usize, offset: usize },
+    /// a posting's constellation does not hash back to its bucket
+    KeyMismatch { gap: usize, offset: usize },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ValidationError::Shape => write!(f, "invalid k/gap configuration"),
+            ValidationError::TableSize { gap } => write!(f, "gap {gap}: bad table size"),
+            ValidationError::BucketOutOfRange { gap, slot } =>
+                write!(f, "gap {gap}: bucket {slot} exceeds posting storage"),
+            ValidationError::UnsortedPostings { gap, slot } =>
+                write!(f, "gap {gap}: bucket {slot} postings not sorted"),
+            ValidationError::OffsetOutOfRange { gap, offset } =>
+                write!(f, "gap {gap}: offset {offset} outside text"),
+            ValidationError::KeyMismatch { gap, offset } =>
+                write!(f, "gap {gap}: constellation at {offset} does not hash to its bucket"),
+        }
+    }
+}
+
+impl core::error::Error for ValidationError {}
+
+impl<H: Hasher> CsiIndex<H> {
+    /// Check every table invariant, re‐hashing the first posting of each
+    /// bucket against the text. Linear in text length plus table size.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        self.check_tables(self.text.len(), Some(&prefix_hash(&self.hasher, &self.text)))
+    }
+
+    /// `validate` against a text of length `n` with prefix states `ph`; the
+    /// key re‐hash is skipped without them, leaving the structural checks
+    /// that keep lookups in bounds.
+    pub(crate) fn check_tables(&self, n: usize, ph: Option<&[u64]>) -> Result<(), ValidationError> {
+        if self.k == 0 || self.gaps.is_empty() || self.gaps.len() != self.flat.len()
+            || self.pw.len() <= self.k {
+            return Err(ValidationError::Shape);
+        }
+        let pk = self.pw[self.k];
+        for (&gap, fi) in self.gaps.iter().zip(&self.flat) {
+            let ts = fi.table_size;
+            if ts == 0 || fi.keys.len() != ts
+                || fi.starts.len() != ts || fi.lens.len() != ts
+                || fi.fps.len() != if self.fps { fi.offs.len() } else { 0 }
+                || fi.overflow.windows(2).any(|w| w[0].0 >= w[1].0)
+                || fi.packed.windows(2).any(|w| w[0].0 >= w[1].0) {
+                return Err(ValidationError::TableSize { gap });
+            }
+            let slots = (0..ts).map(|slot| (slot, fi.keys[slot], fi.starts.get(slot), fi.lens.get(slot)));
+            let spilled = fi.overflow.iter().enumerate().map(|(i, &(key, start, len))| (ts + i, key, start, len));
+            let mut buckets = Vec::new();
+            for (slot, key, start, len) in slots.chain(spilled) {
+                if key == 0 || len == SATURATED {
+                    continue;
+                }
+                if start.checked_add(len).is_none_or(|end| end > fi.offs.len()) {
+                    return Err(ValidationError::BucketOutOfRange { gap, slot });
+                }
+                let list = fi.offs.list(start..start + len);
+                buckets.push((slot, key, Some(start), list.widened()));
+            }
+            let first = ts + fi.overflow.len();
+            for (slot, (key, packed)) in (first..).zip(&fi.packed) {
+                let list: Vec<usize> = packed.iter().collect();
+                if list.windows(2).any(|w| w[0] >= w[1]) {
+                    return Err(ValidationError::UnsortedPostings { gap, slot });
+                }
+                // skip entries included, since `retain` trusts them
+                if list.len() != packed.len || Packed::new(&list) != *packed {
+                    return Err(ValidationError::BucketOutOfRange { gap, slot });
+                }
+                buckets.push((slot, *key, None, Cow::Owned(list)));
+            }
+            for (slot, key, start, list) in buckets {
+                if list.windows(2).any(|w| w[0] >= w[1]) {
+                    return Err(ValidationError::UnsortedPostings { gap, slot });
+                }
+                if let Some(&offset) = list.iter().find(|&&o| self.k.checked_add(gap).and_then(|span| o.checked_add(span)).is_none_or(|end| end > n)) {
+                    return Err(ValidationError::OffsetOutOfRange { gap, offset });
+                }
+                if let (Some(&offset), Some(ph)) = (list.first(), ph) {
+                    let rekey = slot_key(key_at(&self.hasher, ph, pk, offset, self.k, gap));
+                    let found = match fi.lookup(rekey) {
+                        Probe::List(l) => start.is_some() && fi.offs.position(l) == start && l.len() == list.len(),
+                        Probe::Packed(p) => slot >= first && core::ptr::eq(p, &fi.packed[slot - first].1),
+                        _ => false,
+                    };
+                    if rekey != key || !found {
+                        return Err(ValidationError::KeyMismatch { gap, offset });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ValidationError;
+    use crate::array::Offsets;
+    use crate::CsiIndex;
+
+    #[test]
+    fn fresh_index_validates_and_corruption_is_caught() {
+        let text = b"validate me, validate me twice, and then validate me once more".to_vec();
+        let mut idx = CsiIndex::build(&text);
+        assert_eq!(idx.validate(), Ok(()));
+
+        let mut offs = idx.flat[0].offs.all().to_vec();
+        *offs.last_mut().unwrap() = text.len();
+        idx.flat[0].offs = Offsets::new(offs, true);
+        assert!(idx.validate().is_err());
+
+        let mut idx = CsiIndex::build(&text);
+        idx.text[0] ^= 0xff;
+        assert!(matches!(idx.validate(), Err(ValidationError::KeyMismatch { offset: 0, .. })));
+    }
+}
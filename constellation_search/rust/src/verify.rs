@@ -0,0 +1,71 @@
+// src/verify.rs
+//! Candidate verification: is `text[off..off + m]` the pattern?
+//!
+//! On collision‐heavy text most candidates survive the intersection only to
+//! fail here, and a wrong candidate usually differs near one end of the
+//! pattern. So patterns of 16 bytes or more compare their first and last 16
+//! bytes a vector at a time (SSE2 on x86_64, NEON on aarch64, both part of
+//! the baseline, so there is nothing to detect at run time) before handing
+//! the middle to the slice `==`, whose `memcmp` picks the widest vectors the
+//! CPU has. Shorter patterns go straight to `==`.
+
+/// `a == b`, rejecting on the first or last 16 bytes before the rest.
+#[inline(always)]
+pub(crate) fn same(a: &[u8], b: &[u8]) -> bool {
+    match (a.first_chunk(), b.first_chunk(), a.last_chunk(), b.last_chunk()) {
+        (Some(ah), Some(bh), Some(at), Some(bt)) if a.len() == b.len() => {
+            // the ends overlap below 32 bytes, leaving no middle
+            let mid = 16..a.len().max(32) - 16;
+            eq16(ah, bh) && eq16(at, bt) && a[mid.clone()] == b[mid]
+        }
+        _ => a == b,
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+fn eq16(a: &[u8; 16], b: &[u8; 16]) -> bool {
+    use core::arch::x86_64::{_mm_cmpeq_epi8, _mm_loadu_si128, _mm_movemask_epi8};
+    // SAFETY: both arrays are 16 bytes and the loads are unaligned
+    unsafe {
+        let (x, y) = (_mm_loadu_si128(a.as_ptr().cast()), _mm_loadu_si128(b.as_ptr().cast()));
+        _mm_movemask_epi8(_mm_cmpeq_epi8(x, y)) == 0xffff
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+#[inline(always)]
+fn eq16(a: &[u8; 16], b: &[u8; 16]) -> bool {
+    use core::arch::aarch64::{vceqq_u8, vld1q_u8, vminvq_u8};
+    // SAFETY: both arrays are 16 bytes and the loads are unaligned
+    unsafe { vminvq_u8(vceqq_u8(vld1q_u8(a.as_ptr()), vld1q_u8(b.as_ptr()))) == 0xff }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+#[inline(always)]
+fn eq16(a: &[u8; 16], b: &[u8; 16]) -> bool {
+    a == b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::same;
+
+    #[test]
+    fn same_agrees_with_slice_equality_at_every_length_and_position() {
+        let a: Vec<u8> = (0..100u8).map(|i| i.wrapping_mul(37)).collect();
+        for m in 0..=a.len() {
+            let pat = &a[..m];
+            assert!(same(pat, pat));
+            // one wrong byte anywhere, including inside the overlapping ends
+            for at in 0..m {
+                let mut b = pat.to_vec();
+                b[at] ^= 0x80;
+                assert!(!same(pat, &b), "m {m}, byte {at}");
+            }
+            if m > 0 {
+                assert!(!same(pat, &pat[..m - 1]));
+            }
+        }
+    }
+}
@@ -0,0 +1,59 @@
+// src/wasm.rs
+//! Browser bindings over `wasm-bindgen` (feature `wasm`), for searching
+//! downloaded text client‐side.
+//!
+//! Byte arguments arrive from JS as `Uint8Array`s and offsets go back as a
+//! `Uint32Array`, which holds any offset into a wasm32 memory. The C ABI in
+//! `ffi` is compiled out on wasm32, so nothing here touches raw pointers.
+
+use wasm_bindgen::prelude::*;
+
+use crate::CsiIndex;
+
+/// An index owned from JS, built over a copy of its text
+#[wasm_bindgen]
+pub struct WasmCsiIndex {
+    inner: CsiIndex,
+}
+
+#[wasm_bindgen]
+impl WasmCsiIndex {
+    /// Index `text`, as `CsiIndex::build`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(text: &[u8]) -> WasmCsiIndex {
+        WasmCsiIndex { inner: CsiIndex::build(text) }
+    }
+
+    /// Sorted offsets of every occurrence of `pat`, as `CsiIndex::search`.
+    /// Offsets are truncated to 32 bits, which only loses any on a 64‐bit
+    /// host indexing 4 GiB or more.
+    pub fn search(&self, pat: &[u8]) -> Vec<u32> {
+        self.inner.search(pat).into_iter().map(|o| o as u32).collect()
+    }
+
+    /// Number of occurrences of `pat`, as `CsiIndex::count`.
+    pub fn count(&self, pat: &[u8]) -> usize {
+        self.inner.count(pat)
+    }
+
+    /// Whether `pat` occurs at all, as `CsiIndex::contains`.
+    pub fn contains(&self, pat: &[u8]) -> bool {
+        self.inner.contains(pat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WasmCsiIndex;
+
+    #[test]
+    fn wasm_index_searches_like_the_rust_one() {
+        let text = b"fetched over the wire, searched in the page; fetched once".repeat(30);
+        let (js, idx) = (WasmCsiIndex::new(&text), crate::CsiIndex::build(&text));
+        for pat in [&b"searched in the page; fetched"[..], b"wire", b"not in the text at all"] {
+            let want: Vec<u32> = idx.search(pat).into_iter().map(|o| o as u32).collect();
+            assert_eq!(js.search(pat), want);
+            assert_eq!((js.count(pat), js.contains(pat)), (want.len(), !want.is_empty()));
+        }
+    }
+}